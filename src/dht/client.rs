@@ -0,0 +1,171 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tokio::net::UdpSocket;
+use tokio::time::timeout;
+
+use super::krpc::{NodeId, Query, Response};
+use super::token::TokenCache;
+
+/// How long a node gets to answer a query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest KRPC message read; real ones stay well below a typical MTU.
+const MAX_MESSAGE: usize = 2048;
+
+/// Sends KRPC queries from one UDP socket and announces torrents to the nodes that handed
+/// out write tokens.
+#[derive(Debug)]
+pub struct DhtClient {
+    socket: UdpSocket,
+    id: NodeId,
+    tokens: TokenCache,
+}
+
+impl DhtClient {
+    pub fn new(socket: UdpSocket, id: NodeId) -> Self {
+        Self {
+            socket,
+            id,
+            tokens: TokenCache::new(),
+        }
+    }
+
+    pub fn tokens(&self) -> &TokenCache {
+        &self.tokens
+    }
+
+    /// Sends `query` to `node` and waits for the response with the same transaction id;
+    /// stray datagrams are skipped.
+    pub async fn query(&self, node: SocketAddr, query: &Query) -> Result<Response> {
+        let transaction_id = transaction_id()?;
+        self.socket
+            .send_to(&query.encode(&transaction_id), node)
+            .await
+            .with_context(|| format!("Failed to query DHT node {node}"))?;
+
+        let mut buffer = vec![0; MAX_MESSAGE];
+        timeout(QUERY_TIMEOUT, async {
+            loop {
+                let (read, from) = self.socket.recv_from(&mut buffer).await?;
+                if from != node {
+                    continue;
+                }
+                if let Ok(response) = Response::decode(&buffer[..read])
+                    && response.transaction_id == transaction_id
+                {
+                    return Ok(response);
+                }
+            }
+        })
+        .await
+        .with_context(|| format!("DHT node {node} did not answer"))?
+    }
+
+    /// Asks every node in `nodes` for peers of `info_hash`, keeping their write tokens, and
+    /// returns the peers found. Nodes that do not answer are skipped.
+    pub async fn get_peers(
+        &mut self,
+        nodes: &[SocketAddr],
+        info_hash: [u8; 20],
+    ) -> Vec<SocketAddr> {
+        let query = Query::GetPeers {
+            id: self.id,
+            info_hash,
+        };
+        let mut peers = Vec::new();
+        for node in nodes {
+            if let Ok(response) = self.query(*node, &query).await {
+                self.tokens.record(*node, &response, Instant::now());
+                peers.extend(response.peers);
+            }
+        }
+        peers
+    }
+
+    /// Announces `port` for `info_hash` to the `limit` closest nodes with a valid token;
+    /// `implied_port` lets them take the source port instead, for a port behind NAT.
+    /// Returns the nodes that acknowledged.
+    pub async fn announce(
+        &mut self,
+        info_hash: [u8; 20],
+        port: u16,
+        implied_port: bool,
+        limit: usize,
+    ) -> Vec<SocketAddr> {
+        let now = Instant::now();
+        self.tokens.prune(now);
+        let mut announced = Vec::new();
+        for (node, query) in
+            self.tokens
+                .announces(self.id, info_hash, port, implied_port, limit, now)
+        {
+            match self.query(node, &query).await {
+                Ok(_) => announced.push(node),
+                // A rejected token is useless; a fresh one comes with the next get_peers.
+                Err(_) => self.tokens.forget(&node),
+            }
+        }
+        announced
+    }
+}
+
+fn transaction_id() -> Result<Vec<u8>> {
+    let mut bytes = [0; 2];
+    getrandom::fill(&mut bytes).context("No randomness for a transaction id")?;
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::encoder::Value;
+
+    /// Answers `get_peers` with one peer and a token, and `announce_peer` only when it
+    /// carries that token; returns the node's address.
+    async fn fake_node() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = vec![0; MAX_MESSAGE];
+            while let Ok((read, from)) = socket.recv_from(&mut buffer).await {
+                let query = &buffer[..read];
+                let contains = |needle: &[u8]| query.windows(needle.len()).any(|w| w == needle);
+                let start = query.windows(5).position(|w| w == b"1:t2:").unwrap() + 5;
+                let transaction_id = &query[start..start + 2];
+                let body = if contains(b"9:get_peers") {
+                    Value::dict()
+                        .with("id", [9u8; 20].as_slice())
+                        .with("token", "secret")
+                        .with("values", vec![[10u8, 0, 0, 1, 0x1a, 0xe1].as_slice()])
+                } else if contains(b"5:token6:secret") && contains(b"12:implied_porti1e") {
+                    Value::dict().with("id", [9u8; 20].as_slice())
+                } else {
+                    continue;
+                };
+                let response = Value::dict()
+                    .with("r", body)
+                    .with("t", transaction_id)
+                    .with("y", "r")
+                    .encode();
+                socket.send_to(&response, from).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn announces_with_the_token_from_get_peers() {
+        let node = fake_node().await;
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut client = DhtClient::new(socket, [1; 20]);
+
+        let peers = client.get_peers(&[node], [2; 20]).await;
+        assert_eq!(peers, ["10.0.0.1:6881".parse::<SocketAddr>().unwrap()]);
+        assert_eq!(
+            client.tokens().get(&node, Instant::now()),
+            Some(b"secret".as_slice())
+        );
+        assert_eq!(client.announce([2; 20], 6881, true, 8).await, [node]);
+    }
+}
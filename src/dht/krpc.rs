@@ -0,0 +1,239 @@
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+
+use crate::file::encoder::Value;
+use crate::peer::Peer;
+
+pub type NodeId = [u8; 20];
+
+/// Length of a compact IPv4 node entry: id, address, port.
+const COMPACT_NODE_LEN: usize = 26;
+
+/// Outgoing KRPC queries (BEP 5).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    Ping {
+        id: NodeId,
+    },
+    FindNode {
+        id: NodeId,
+        target: NodeId,
+    },
+    GetPeers {
+        id: NodeId,
+        info_hash: [u8; 20],
+    },
+    AnnouncePeer {
+        id: NodeId,
+        info_hash: [u8; 20],
+        port: u16,
+        /// Write token from the node's earlier `get_peers` response.
+        token: Vec<u8>,
+        /// Asks the node to use the packet's source port instead of `port`, which is what a
+        /// NAT actually exposes when the listen port is not forwarded.
+        implied_port: bool,
+    },
+}
+
+impl Query {
+    fn method(&self) -> &'static str {
+        match self {
+            Query::Ping { .. } => "ping",
+            Query::FindNode { .. } => "find_node",
+            Query::GetPeers { .. } => "get_peers",
+            Query::AnnouncePeer { .. } => "announce_peer",
+        }
+    }
+
+    pub fn encode(&self, transaction_id: &[u8]) -> Vec<u8> {
+        let args = match self {
+            Query::Ping { id } => Value::dict().with("id", id.as_slice()),
+            Query::FindNode { id, target } => Value::dict()
+                .with("id", id.as_slice())
+                .with("target", target.as_slice()),
+            Query::GetPeers { id, info_hash } => Value::dict()
+                .with("id", id.as_slice())
+                .with("info_hash", info_hash.as_slice()),
+            Query::AnnouncePeer {
+                id,
+                info_hash,
+                port,
+                token,
+                implied_port,
+            } => Value::dict()
+                .with("id", id.as_slice())
+                .with("implied_port", u64::from(*implied_port))
+                .with("info_hash", info_hash.as_slice())
+                .with("port", u64::from(*port))
+                .with("token", token.as_slice()),
+        };
+
+        Value::dict()
+            .with("a", args)
+            .with("q", self.method())
+            .with("t", transaction_id)
+            .with("y", "q")
+            .encode()
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RawResponseBody {
+    #[serde(default)]
+    id: ByteBuf,
+    #[serde(default)]
+    token: ByteBuf,
+    #[serde(default)]
+    values: Vec<ByteBuf>,
+    #[serde(default)]
+    nodes: ByteBuf,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawResponse {
+    t: ByteBuf,
+    y: ByteBuf,
+    #[serde(default)]
+    r: RawResponseBody,
+}
+
+/// A decoded KRPC response; which fields are set depends on the query it answers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub transaction_id: Vec<u8>,
+    pub id: NodeId,
+    /// Write token handed out by `get_peers`, required to `announce_peer` to the same node.
+    pub token: Option<Vec<u8>>,
+    pub peers: Vec<SocketAddr>,
+    pub nodes: Vec<(NodeId, SocketAddr)>,
+}
+
+impl Response {
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let raw: RawResponse = bendy::serde::from_bytes(bytes)?;
+        match raw.y.as_slice() {
+            b"r" => {}
+            b"e" => bail!("DHT node answered with an error"),
+            other => bail!(
+                "Unexpected KRPC message type {:?}",
+                String::from_utf8_lossy(other)
+            ),
+        }
+
+        let id = raw
+            .r
+            .id
+            .as_slice()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("DHT response has an invalid node id"))?;
+
+        let peers = raw
+            .r
+            .values
+            .iter()
+            .filter_map(|value| Peer::from_compact(value))
+            .map(|peer| peer.addr)
+            .collect();
+
+        if !raw.r.nodes.len().is_multiple_of(COMPACT_NODE_LEN) {
+            bail!("Compact node list has {} bytes", raw.r.nodes.len());
+        }
+        let nodes = raw
+            .r
+            .nodes
+            .chunks_exact(COMPACT_NODE_LEN)
+            .map(|node| {
+                let id = node[..20].try_into().expect("slice is 20 bytes");
+                (id, compact_addr(&node[20..]))
+            })
+            .collect();
+
+        Ok(Response {
+            transaction_id: raw.t.into_vec(),
+            id,
+            token: (!raw.r.token.is_empty()).then(|| raw.r.token.into_vec()),
+            peers,
+            nodes,
+        })
+    }
+}
+
+fn compact_addr(bytes: &[u8]) -> SocketAddr {
+    let ip = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
+    let port = u16::from_be_bytes([bytes[4], bytes[5]]);
+    SocketAddr::V4(SocketAddrV4::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Examples from BEP 5.
+
+    #[test]
+    fn encodes_queries() {
+        let get_peers = Query::GetPeers {
+            id: *b"abcdefghij0123456789",
+            info_hash: *b"mnopqrstuvwxyz123456",
+        };
+        assert_eq!(
+            get_peers.encode(b"aa"),
+            b"d1:ad2:id20:abcdefghij01234567899:info_hash20:mnopqrstuvwxyz123456e1:q9:get_peers1:t2:aa1:y1:qe"
+        );
+
+        let announce = Query::AnnouncePeer {
+            id: *b"abcdefghij0123456789",
+            info_hash: *b"mnopqrstuvwxyz123456",
+            port: 6881,
+            token: b"aoeusnth".to_vec(),
+            implied_port: true,
+        };
+        assert_eq!(
+            announce.encode(b"aa"),
+            b"d1:ad2:id20:abcdefghij012345678912:implied_porti1e9:info_hash20:mnopqrstuvwxyz1234564:porti6881e5:token8:aoeusnthe1:q13:announce_peer1:t2:aa1:y1:qe"
+        );
+    }
+
+    #[test]
+    fn decodes_get_peers_responses() {
+        let response = Response::decode(
+            b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:axje.u6:idhtnmee1:t2:aa1:y1:re",
+        )
+        .unwrap();
+        assert_eq!(response.transaction_id, b"aa");
+        assert_eq!(response.id, *b"abcdefghij0123456789");
+        assert_eq!(response.token.as_deref(), Some(b"aoeusnth".as_slice()));
+        assert_eq!(
+            response.peers,
+            [
+                "97.120.106.101:11893".parse::<SocketAddr>().unwrap(),
+                "105.100.104.116:28269".parse().unwrap()
+            ]
+        );
+
+        let nodes = Response::decode(
+            b"d1:rd2:id20:abcdefghij01234567895:nodes26:mnopqrstuvwxyz123456axje.ue1:t2:aa1:y1:re",
+        )
+        .unwrap();
+        assert_eq!(nodes.token, None);
+        assert_eq!(
+            nodes.nodes,
+            [(
+                *b"mnopqrstuvwxyz123456",
+                "97.120.106.101:11893".parse().unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_errors_and_broken_node_lists() {
+        assert!(Response::decode(b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee").is_err());
+        assert!(
+            Response::decode(b"d1:rd2:id20:abcdefghij01234567895:nodes3:abce1:t2:aa1:y1:re")
+                .is_err()
+        );
+    }
+}
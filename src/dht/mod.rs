@@ -0,0 +1,7 @@
+pub mod client;
+pub mod krpc;
+pub mod token;
+
+pub use client::DhtClient;
+pub use krpc::{NodeId, Query, Response};
+pub use token::{TOKEN_LIFETIME, TokenCache};
@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use super::krpc::{NodeId, Query, Response};
+
+/// Nodes accept tokens for up to ten minutes (BEP 5); stay below that so announces made just
+/// before expiry still arrive in time.
+pub const TOKEN_LIFETIME: Duration = Duration::from_secs(8 * 60);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CachedToken {
+    node: NodeId,
+    token: Vec<u8>,
+    received: Instant,
+}
+
+/// Write tokens collected from `get_peers` responses for one info hash.
+#[derive(Debug, Default, Clone)]
+pub struct TokenCache {
+    tokens: HashMap<SocketAddr, CachedToken>,
+}
+
+impl TokenCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers the token of a `get_peers` response; responses without one are ignored.
+    pub fn record(&mut self, from: SocketAddr, response: &Response, now: Instant) {
+        if let Some(token) = &response.token {
+            self.tokens.insert(
+                from,
+                CachedToken {
+                    node: response.id,
+                    token: token.clone(),
+                    received: now,
+                },
+            );
+        }
+    }
+
+    /// Token for `node`, unless it has expired.
+    pub fn get(&self, node: &SocketAddr, now: Instant) -> Option<&[u8]> {
+        self.tokens
+            .get(node)
+            .filter(|cached| now.duration_since(cached.received) < TOKEN_LIFETIME)
+            .map(|cached| cached.token.as_slice())
+    }
+
+    pub fn forget(&mut self, node: &SocketAddr) {
+        self.tokens.remove(node);
+    }
+
+    /// Drops expired tokens.
+    pub fn prune(&mut self, now: Instant) {
+        self.tokens
+            .retain(|_, cached| now.duration_since(cached.received) < TOKEN_LIFETIME);
+    }
+
+    /// Builds `announce_peer` queries for the `limit` valid tokens whose nodes are closest to
+    /// `info_hash`; announcing with an expired token is silently dropped by the node.
+    pub fn announces(
+        &self,
+        id: NodeId,
+        info_hash: [u8; 20],
+        port: u16,
+        implied_port: bool,
+        limit: usize,
+        now: Instant,
+    ) -> Vec<(SocketAddr, Query)> {
+        let mut valid = self
+            .tokens
+            .iter()
+            .filter(|(_, cached)| now.duration_since(cached.received) < TOKEN_LIFETIME)
+            .collect::<Vec<_>>();
+        valid.sort_by_key(|(_, cached)| distance(&cached.node, &info_hash));
+
+        valid
+            .into_iter()
+            .take(limit)
+            .map(|(addr, cached)| {
+                let query = Query::AnnouncePeer {
+                    id,
+                    info_hash,
+                    port,
+                    token: cached.token.clone(),
+                    implied_port,
+                };
+                (*addr, query)
+            })
+            .collect()
+    }
+}
+
+/// XOR distance between two ids, comparable as a big-endian number.
+pub fn distance(a: &NodeId, b: &[u8; 20]) -> [u8; 20] {
+    let mut distance = [0; 20];
+    for (byte, (a, b)) in distance.iter_mut().zip(a.iter().zip(b)) {
+        *byte = a ^ b;
+    }
+    distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(id: u8, token: Option<&[u8]>) -> Response {
+        Response {
+            transaction_id: b"aa".to_vec(),
+            id: [id; 20],
+            token: token.map(<[u8]>::to_vec),
+            peers: Vec::new(),
+            nodes: Vec::new(),
+        }
+    }
+
+    fn node(port: u16) -> SocketAddr {
+        ([10, 0, 0, 1], port).into()
+    }
+
+    #[test]
+    fn tokens_expire() {
+        let mut cache = TokenCache::new();
+        let start = Instant::now();
+        cache.record(node(1), &response(1, Some(b"one")), start);
+        cache.record(node(2), &response(2, None), start);
+        assert_eq!(cache.get(&node(1), start), Some(b"one".as_slice()));
+        assert_eq!(cache.get(&node(2), start), None);
+
+        let expired = start + TOKEN_LIFETIME;
+        assert_eq!(cache.get(&node(1), expired), None);
+        assert!(
+            cache
+                .announces([0; 20], [0; 20], 6881, false, 8, expired)
+                .is_empty()
+        );
+        cache.prune(expired);
+        assert!(cache.tokens.is_empty());
+    }
+
+    #[test]
+    fn announces_to_the_closest_nodes_with_their_tokens() {
+        let mut cache = TokenCache::new();
+        let now = Instant::now();
+        cache.record(node(1), &response(0xf0, Some(b"far")), now);
+        cache.record(node(2), &response(0x01, Some(b"near")), now);
+        cache.record(node(3), &response(0x0f, Some(b"middle")), now);
+
+        let announces = cache.announces([7; 20], [0; 20], 6881, true, 2, now);
+        let expected = [
+            (node(2), b"near".as_slice()),
+            (node(3), b"middle".as_slice()),
+        ];
+        assert_eq!(announces.len(), expected.len());
+        for ((addr, query), (expected_addr, expected_token)) in announces.iter().zip(expected) {
+            assert_eq!(*addr, expected_addr);
+            assert_eq!(
+                *query,
+                Query::AnnouncePeer {
+                    id: [7; 20],
+                    info_hash: [0; 20],
+                    port: 6881,
+                    token: expected_token.to_vec(),
+                    implied_port: true,
+                }
+            );
+        }
+    }
+}
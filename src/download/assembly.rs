@@ -0,0 +1,242 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{Result, bail};
+
+use super::attribution::{PieceAttribution, PieceSource};
+use super::partial::{BLOCK_SIZE, PartialPiece};
+use super::storage::write_piece;
+use super::verify::{PieceState, PieceStates};
+use crate::file::TorrentFile;
+use crate::peer::{AbuseGuard, Admission, Offense};
+
+/// What adding a block did to its piece.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlockOutcome {
+    /// More blocks are missing.
+    Pending,
+    /// The last block arrived and the piece matches its hash.
+    Verified(VerifiedPiece),
+    /// The last block arrived but the piece does not match its hash. Its blocks were dropped
+    /// so every one is requested again.
+    Failed {
+        piece: usize,
+        /// Every source that supplied a block of the bad piece.
+        sources: Vec<PieceSource>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedPiece {
+    pub index: usize,
+    pub data: Vec<u8>,
+    pub sources: Vec<PieceSource>,
+}
+
+#[derive(Debug, Clone)]
+struct InFlightPiece {
+    partial: PartialPiece,
+    /// Source of every received block, by block number.
+    sources: Vec<Option<PieceSource>>,
+}
+
+impl InFlightPiece {
+    fn contributors(&self) -> Vec<PieceSource> {
+        let mut sources = self.sources.iter().flatten().cloned().collect::<Vec<_>>();
+        sources.sort();
+        sources.dedup();
+        sources
+    }
+}
+
+/// Collects the blocks of pieces being downloaded and checks each piece against its SHA-1
+/// once complete.
+///
+/// Remembers which source sent every block, so a piece that fails its hash check can be
+/// blamed on the sources that took part.
+#[derive(Debug, Clone)]
+pub struct PieceAssembler {
+    hashes: Vec<[u8; 20]>,
+    piece_length: u64,
+    total_length: u64,
+    pieces: BTreeMap<usize, InFlightPiece>,
+}
+
+impl PieceAssembler {
+    pub fn new(torrent: &TorrentFile) -> Self {
+        Self {
+            hashes: torrent.piece_hashes().to_vec(),
+            piece_length: torrent.piece_length(),
+            total_length: torrent.total_length(),
+            pieces: BTreeMap::new(),
+        }
+    }
+
+    /// Starts from pieces resumed with [`super::partial::load_partials`]; blocks saved before
+    /// the restart have no known source.
+    pub fn resume(&mut self, partials: Vec<PartialPiece>) {
+        for partial in partials {
+            let sources = vec![None; partial.block_count()];
+            self.pieces
+                .insert(partial.index(), InFlightPiece { partial, sources });
+        }
+    }
+
+    /// Pieces with at least one block but not complete yet.
+    pub fn in_flight(&self) -> impl Iterator<Item = &PartialPiece> {
+        self.pieces.values().map(|piece| &piece.partial)
+    }
+
+    pub fn get(&self, index: usize) -> Option<&PartialPiece> {
+        self.pieces.get(&index).map(|piece| &piece.partial)
+    }
+
+    /// Drops a piece's blocks, e.g. when it was completed some other way.
+    pub fn discard(&mut self, index: usize) {
+        self.pieces.remove(&index);
+    }
+
+    /// Adds a block from `source`. Errors mean the block does not fit the piece, which is a
+    /// protocol violation rather than bad data.
+    pub fn add_block(
+        &mut self,
+        source: PieceSource,
+        index: usize,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<BlockOutcome> {
+        let Some(expected) = self.hashes.get(index).copied() else {
+            bail!("Block for piece {index}, which does not exist");
+        };
+        let length = self.piece_size(index);
+        let piece = self.pieces.entry(index).or_insert_with(|| {
+            let partial = PartialPiece::new(index, length);
+            let sources = vec![None; partial.block_count()];
+            InFlightPiece { partial, sources }
+        });
+
+        piece.partial.add_block(offset, data)?;
+        piece.sources[offset / BLOCK_SIZE] = Some(source);
+        if !piece.partial.is_complete() {
+            return Ok(BlockOutcome::Pending);
+        }
+
+        if piece.partial.verify(&expected) {
+            let piece = self.pieces.remove(&index).expect("piece is in flight");
+            let sources = piece.contributors();
+            return Ok(BlockOutcome::Verified(VerifiedPiece {
+                index,
+                data: piece.partial.into_data(),
+                sources,
+            }));
+        }
+
+        let sources = piece.contributors();
+        piece.partial.reset();
+        piece.sources.iter_mut().for_each(|source| *source = None);
+        Ok(BlockOutcome::Failed {
+            piece: index,
+            sources,
+        })
+    }
+
+    fn piece_size(&self, index: usize) -> usize {
+        let start = index as u64 * self.piece_length;
+        self.piece_length
+            .min(self.total_length.saturating_sub(start)) as usize
+    }
+}
+
+/// Writes a verified piece under `root`, then marks it verified and remembers its sources.
+pub fn store_verified(
+    torrent: &TorrentFile,
+    root: &Path,
+    piece: &VerifiedPiece,
+    states: &mut PieceStates,
+    attribution: &mut PieceAttribution,
+) -> Result<()> {
+    write_piece(torrent, root, piece.index, &piece.data)?;
+    states.set(piece.index, PieceState::Verified);
+    attribution.record(piece.index, piece.sources.clone());
+    Ok(())
+}
+
+/// Strikes every peer that contributed to a piece that failed its hash check, and returns the
+/// addresses that are banned as a result and should be disconnected.
+///
+/// With several contributors the culprit is unknown, so all of them are struck; an honest
+/// peer only gets banned if it keeps sharing pieces with bad ones.
+pub fn penalize(guard: &mut AbuseGuard, sources: &[PieceSource], now: Instant) -> Vec<IpAddr> {
+    sources
+        .iter()
+        .filter_map(|source| match source {
+            PieceSource::Peer(addr) => Some(addr.ip()),
+            PieceSource::WebSeed(_) => None,
+        })
+        .filter(|ip| {
+            matches!(
+                guard.record_offense(*ip, Offense::CorruptData, now),
+                Admission::Banned { .. }
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+
+    fn peer(port: u16) -> PieceSource {
+        PieceSource::Peer(([10, 0, 0, 1], port).into())
+    }
+
+    #[test]
+    fn verifies_a_piece_once_every_block_arrived() {
+        let synthetic = SyntheticTorrent::single("assembly", 64 * 1024, 32 * 1024);
+        let mut assembler = PieceAssembler::new(&synthetic.torrent);
+        let piece = synthetic.piece(1);
+
+        let outcome = assembler
+            .add_block(peer(1), 1, BLOCK_SIZE, &piece[BLOCK_SIZE..])
+            .unwrap();
+        assert_eq!(outcome, BlockOutcome::Pending);
+        let outcome = assembler
+            .add_block(peer(2), 1, 0, &piece[..BLOCK_SIZE])
+            .unwrap();
+        assert_eq!(
+            outcome,
+            BlockOutcome::Verified(VerifiedPiece {
+                index: 1,
+                data: piece.to_vec(),
+                sources: vec![peer(1), peer(2)],
+            })
+        );
+        assert!(assembler.get(1).is_none());
+    }
+
+    #[test]
+    fn bad_pieces_are_dropped_and_blamed() {
+        let synthetic = SyntheticTorrent::single("assembly", 64 * 1024, 32 * 1024);
+        let mut assembler = PieceAssembler::new(&synthetic.torrent);
+        let piece = synthetic.piece(0);
+
+        assembler
+            .add_block(peer(1), 0, 0, &piece[..BLOCK_SIZE])
+            .unwrap();
+        let outcome = assembler
+            .add_block(peer(2), 0, BLOCK_SIZE, &vec![0; BLOCK_SIZE])
+            .unwrap();
+        assert_eq!(
+            outcome,
+            BlockOutcome::Failed {
+                piece: 0,
+                sources: vec![peer(1), peer(2)],
+            }
+        );
+        let partial = assembler.get(0).unwrap();
+        assert_eq!(partial.missing_blocks().count(), 2);
+    }
+}
@@ -0,0 +1,237 @@
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+
+use crate::format::hex;
+use crate::session;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PieceSource {
+    Peer(SocketAddr),
+    WebSeed(String),
+}
+
+impl fmt::Display for PieceSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PieceSource::Peer(addr) => write!(f, "peer {addr}"),
+            PieceSource::WebSeed(url) => write!(f, "webseed {url}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceContribution {
+    /// Every source that supplied at least one block of the piece.
+    pub sources: Vec<PieceSource>,
+    pub verified_at: SystemTime,
+}
+
+impl PieceSource {
+    fn parse(text: &str) -> Result<Self> {
+        match text.split_once(' ') {
+            Some(("peer", addr)) => Ok(PieceSource::Peer(
+                addr.parse()
+                    .with_context(|| format!("Invalid peer {addr}"))?,
+            )),
+            Some(("webseed", url)) => Ok(PieceSource::WebSeed(url.to_string())),
+            _ => bail!("Unknown piece source {text:?}"),
+        }
+    }
+}
+
+/// Remembers which sources supplied each verified piece of a torrent.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PieceAttribution {
+    pieces: BTreeMap<usize, PieceContribution>,
+}
+
+impl PieceAttribution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where the sources of the torrent with `info_hash` are kept, as CSV.
+    pub fn path(info_hash: &[u8; 20]) -> Option<PathBuf> {
+        session::dir().map(|dir| dir.join("pieces").join(format!("{}.csv", hex(info_hash))))
+    }
+
+    /// Loads the sources saved for a torrent; none are known until a piece was stored.
+    pub fn load(info_hash: &[u8; 20]) -> Result<Self> {
+        let Some(path) = Self::path(info_hash) else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        Self::import_csv(content.as_slice())
+            .with_context(|| format!("Invalid piece sources {path:?}"))
+    }
+
+    /// Saves the sources; web seed URLs may carry credentials, so on Unix only the owner
+    /// can read them.
+    pub fn save(&self, info_hash: &[u8; 20]) -> Result<()> {
+        let path = Self::path(info_hash).context("No data directory available")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut csv = Vec::new();
+        self.export_csv(&mut csv)?;
+        session::write_private(&path, &csv)
+    }
+
+    pub fn record(&mut self, piece: usize, mut sources: Vec<PieceSource>) {
+        sources.sort();
+        sources.dedup();
+        self.pieces.insert(
+            piece,
+            PieceContribution {
+                sources,
+                verified_at: SystemTime::now(),
+            },
+        );
+    }
+
+    /// Forgets a piece, e.g. when a recheck demotes it.
+    pub fn forget(&mut self, piece: usize) {
+        self.pieces.remove(&piece);
+    }
+
+    pub fn get(&self, piece: usize) -> Option<&PieceContribution> {
+        self.pieces.get(&piece)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &PieceContribution)> {
+        self.pieces
+            .iter()
+            .map(|(piece, contribution)| (*piece, contribution))
+    }
+
+    /// Pieces each source contributed to, most prolific sources first.
+    pub fn by_source(&self) -> Vec<(PieceSource, Vec<usize>)> {
+        let mut grouped: HashMap<&PieceSource, Vec<usize>> = HashMap::new();
+        for (piece, contribution) in &self.pieces {
+            for source in &contribution.sources {
+                grouped.entry(source).or_default().push(*piece);
+            }
+        }
+
+        let mut grouped: Vec<_> = grouped
+            .into_iter()
+            .map(|(source, pieces)| (source.clone(), pieces))
+            .collect();
+        grouped.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(&b.0)));
+        grouped
+    }
+
+    /// Writes `piece,verified_at,source` rows, one per contributing source. Sources with
+    /// commas or quotes, e.g. web seed URLs, are quoted.
+    pub fn export_csv(&self, mut out: impl Write) -> Result<()> {
+        writeln!(out, "piece,verified_at,source")?;
+        for (piece, contribution) in &self.pieces {
+            let verified_at = contribution
+                .verified_at
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs())
+                .unwrap_or_default();
+            for source in &contribution.sources {
+                writeln!(
+                    out,
+                    "{piece},{verified_at},{}",
+                    csv_field(&source.to_string())
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads rows written by [`Self::export_csv`].
+    pub fn import_csv(input: impl BufRead) -> Result<Self> {
+        let mut attribution = Self::default();
+        for (number, line) in input.lines().enumerate().skip(1) {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let mut fields = line.splitn(3, ',');
+            let (Some(piece), Some(verified_at), Some(source)) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                bail!("Line {} has too few fields", number + 1);
+            };
+            let piece = piece
+                .parse()
+                .with_context(|| format!("Invalid piece on line {}", number + 1))?;
+            let verified_at = UNIX_EPOCH
+                + Duration::from_secs(
+                    verified_at
+                        .parse()
+                        .with_context(|| format!("Invalid time on line {}", number + 1))?,
+                );
+            let source = PieceSource::parse(&unquote(source)?)?;
+            let contribution =
+                attribution
+                    .pieces
+                    .entry(piece)
+                    .or_insert_with(|| PieceContribution {
+                        sources: Vec::new(),
+                        verified_at,
+                    });
+            contribution.sources.push(source);
+        }
+        Ok(attribution)
+    }
+}
+
+/// Quotes a CSV field holding a separator, a quote or a line break, doubling its quotes.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Reverses [`csv_field`] for the last field of a row.
+fn unquote(field: &str) -> Result<Cow<'_, str>> {
+    match field.strip_prefix('"') {
+        None => Ok(Cow::Borrowed(field)),
+        Some(quoted) => {
+            let inner = quoted
+                .strip_suffix('"')
+                .context("Unterminated quoted field")?;
+            Ok(Cow::Owned(inner.replace("\"\"", "\"")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quotes_sources_with_commas_and_quotes() {
+        let mut attribution = PieceAttribution::new();
+        let url = "http://seed.example/a,b/\"c\"";
+        attribution.record(0, vec![PieceSource::WebSeed(url.to_string())]);
+        attribution.record(1, vec![PieceSource::Peer("10.0.0.1:6881".parse().unwrap())]);
+
+        let mut csv = Vec::new();
+        attribution.export_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let rows = csv.lines().collect::<Vec<_>>();
+        assert!(rows[1].ends_with(",\"webseed http://seed.example/a,b/\"\"c\"\"\""));
+        assert!(rows[2].ends_with(",peer 10.0.0.1:6881"));
+
+        let imported = PieceAttribution::import_csv(csv.as_bytes()).unwrap();
+        assert_eq!(imported.by_source(), attribution.by_source());
+    }
+}
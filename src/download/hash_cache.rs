@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use sha1::{Digest, Sha1};
+
+use crate::file::encoder::Value;
+use crate::session;
+
+/// Name of the cache file in the session directory.
+pub const HASH_CACHE_FILE: &str = "hash-cache";
+
+/// Bytes of one stored entry, see [`Key::encode`].
+const RECORD_LEN: usize = 68;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HashCacheConfig {
+    pub enabled: bool,
+    /// Pieces remembered at most, about 68 bytes each on disk; the least recently used go
+    /// first.
+    pub max_entries: usize,
+}
+
+impl Default for HashCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_entries: 100_000,
+        }
+    }
+}
+
+impl HashCacheConfig {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.enabled
+            .then(|| session::dir().map(|dir| dir.join(HASH_CACHE_FILE)))
+            .flatten()
+    }
+}
+
+/// A file as it is on disk right now: the same file that was not written since has the same
+/// identity, whatever it is called and whichever torrent it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileIdentity {
+    device: u64,
+    inode: u64,
+    len: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl FileIdentity {
+    /// `None` where files have no stable identity or no modification time, which keeps
+    /// them out of the cache.
+    pub fn of(file: &File) -> Option<Self> {
+        let metadata = file.metadata().ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let (device, inode) = device_and_inode(&metadata)?;
+        Some(Self {
+            device,
+            inode,
+            len: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn device_and_inode(metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn device_and_inode(_metadata: &fs::Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Key {
+    file: FileIdentity,
+    offset: u64,
+    length: u32,
+}
+
+impl Key {
+    fn encode(&self, hash: &[u8; 20], out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.file.device.to_be_bytes());
+        out.extend_from_slice(&self.file.inode.to_be_bytes());
+        out.extend_from_slice(&self.file.len.to_be_bytes());
+        out.extend_from_slice(&self.file.modified_secs.to_be_bytes());
+        out.extend_from_slice(&self.file.modified_nanos.to_be_bytes());
+        out.extend_from_slice(&self.offset.to_be_bytes());
+        out.extend_from_slice(&self.length.to_be_bytes());
+        out.extend_from_slice(hash);
+    }
+
+    fn decode(record: &[u8]) -> (Self, [u8; 20]) {
+        let u64_at = |at: usize| u64::from_be_bytes(record[at..at + 8].try_into().unwrap());
+        let u32_at = |at: usize| u32::from_be_bytes(record[at..at + 4].try_into().unwrap());
+        let key = Self {
+            file: FileIdentity {
+                device: u64_at(0),
+                inode: u64_at(8),
+                len: u64_at(16),
+                modified_secs: u64_at(24),
+                modified_nanos: u32_at(32),
+            },
+            offset: u64_at(36),
+            length: u32_at(44),
+        };
+        (key, record[48..RECORD_LEN].try_into().unwrap())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SavedCache {
+    entries: ByteBuf,
+}
+
+/// SHA-1 digests of file regions hashed before, so rechecking data that did not change since,
+/// e.g. after moving a library around or re-adding a torrent, skips reading it.
+///
+/// Entries are keyed by the file's identity, the region, and the file's modification time,
+/// not by torrent: every torrent with a piece at the same place in the same file shares the
+/// entry. Writing to a file changes its modification time, which retires its entries.
+#[derive(Debug, Clone)]
+pub struct HashCache {
+    max_entries: usize,
+    /// Digest and when the entry was last used, on [`Self::clock`].
+    entries: HashMap<Key, ([u8; 20], u64)>,
+    clock: u64,
+    changed: bool,
+}
+
+impl HashCache {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            entries: HashMap::new(),
+            clock: 0,
+            changed: false,
+        }
+    }
+
+    /// Loads a cache written by [`Self::save`]; a missing file gives an empty cache.
+    pub fn load(path: &Path, max_entries: usize) -> Result<Self> {
+        let mut cache = Self::new(max_entries);
+        if !path.exists() {
+            return Ok(cache);
+        }
+        let bytes = fs::read(path).with_context(|| format!("Failed to read {path:?}"))?;
+        let saved: SavedCache = bendy::serde::from_bytes(&bytes)?;
+        if !saved.entries.len().is_multiple_of(RECORD_LEN) {
+            bail!("Hash cache holds a truncated entry");
+        }
+        // Saved least recently used first, so replaying them restores the order.
+        for record in saved.entries.chunks_exact(RECORD_LEN) {
+            let (key, hash) = Key::decode(record);
+            cache.insert(key, hash);
+        }
+        cache.changed = false;
+        Ok(cache)
+    }
+
+    /// Writes the cache if it changed since it was loaded or last saved.
+    pub fn save(&mut self, path: &Path) -> Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut entries = self.entries.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(_, (_, used))| *used);
+        let mut records = Vec::with_capacity(entries.len() * RECORD_LEN);
+        for (key, (hash, _)) in entries {
+            key.encode(hash, &mut records);
+        }
+        let snapshot = Value::dict().with("entries", records).encode();
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, snapshot).with_context(|| format!("Failed to write {tmp:?}"))?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to replace {path:?}"))?;
+        self.changed = false;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The digest of `length` bytes at `offset` in `file`, read and hashed only if the
+    /// cache does not know it yet.
+    pub fn hash(&mut self, file: &mut File, offset: u64, length: u32) -> Result<[u8; 20]> {
+        let key = FileIdentity::of(file).map(|file| Key {
+            file,
+            offset,
+            length,
+        });
+        let now = self.clock + 1;
+        if let Some(key) = key
+            && let Some((hash, used)) = self.entries.get_mut(&key)
+        {
+            *used = now;
+            self.clock = now;
+            self.changed = true;
+            return Ok(*hash);
+        }
+
+        let mut data = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut data)?;
+        let hash = <[u8; 20]>::from(Sha1::digest(&data));
+        if let Some(key) = key {
+            self.insert(key, hash);
+        }
+        Ok(hash)
+    }
+
+    fn insert(&mut self, key: Key, hash: [u8; 20]) {
+        if self.max_entries == 0 {
+            return;
+        }
+        self.clock += 1;
+        self.entries.insert(key, (hash, self.clock));
+        self.changed = true;
+        if self.entries.len() > self.max_entries {
+            // Evicting in bulk keeps inserts cheap once the cache is full.
+            let excess = self.entries.len() - self.max_entries + self.max_entries / 10;
+            let mut used = self
+                .entries
+                .values()
+                .map(|(_, used)| *used)
+                .collect::<Vec<_>>();
+            used.sort_unstable();
+            let cutoff = used[excess.min(used.len()) - 1];
+            self.entries.retain(|_, (_, used)| *used > cutoff);
+        }
+    }
+}
+
+/// Checks that a file written by [`HashCache::save`] decodes.
+pub fn check_hash_cache(path: &Path) -> Result<()> {
+    HashCache::load(path, usize::MAX).map(drop)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Write;
+    use std::process;
+
+    use super::*;
+
+    #[test]
+    fn reuses_digests_until_the_file_changes() {
+        let dir = env::temp_dir().join(format!("terrent-hash-cache-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data = dir.join("data");
+        fs::write(&data, [7u8; 64]).unwrap();
+
+        let mut cache = HashCache::new(8);
+        let mut file = File::open(&data).unwrap();
+        let hash = cache.hash(&mut file, 0, 32).unwrap();
+        assert_eq!(hash, <[u8; 20]>::from(Sha1::digest([7u8; 32])));
+        cache.hash(&mut file, 32, 32).unwrap();
+
+        let saved = dir.join(HASH_CACHE_FILE);
+        cache.save(&saved).unwrap();
+        check_hash_cache(&saved).unwrap();
+        let mut cache = HashCache::load(&saved, 8).unwrap();
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.hash(&mut file, 0, 32).unwrap(), hash);
+
+        // A cached entry would still claim the old content; the new length retires it.
+        File::options()
+            .append(true)
+            .open(&data)
+            .unwrap()
+            .write_all(&[1])
+            .unwrap();
+        let mut file = File::open(&data).unwrap();
+        cache.hash(&mut file, 0, 32).unwrap();
+        assert_eq!(cache.len(), 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used() {
+        let key = |offset| Key {
+            file: FileIdentity {
+                device: 1,
+                inode: 2,
+                len: 100,
+                modified_secs: 3,
+                modified_nanos: 4,
+            },
+            offset,
+            length: 10,
+        };
+        let mut cache = HashCache::new(3);
+        for offset in 0..3 {
+            cache.insert(key(offset), [0; 20]);
+        }
+        cache.insert(key(0), [0; 20]);
+        cache.insert(key(3), [0; 20]);
+        assert!(cache.entries.contains_key(&key(0)) && !cache.entries.contains_key(&key(1)));
+        assert!(cache.len() <= 3);
+    }
+}
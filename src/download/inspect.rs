@@ -0,0 +1,128 @@
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use sha1::{Digest, Sha1};
+
+use super::partial::{BLOCK_SIZE, PartialPiece};
+use crate::file::TorrentFile;
+
+/// Expected and computed SHA-1 of a piece, for debugging hash failures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PieceReport {
+    pub index: usize,
+    pub expected: [u8; 20],
+    pub computed: [u8; 20],
+}
+
+impl PieceReport {
+    fn new(index: usize, expected: [u8; 20], data: &[u8]) -> Self {
+        Self {
+            index,
+            expected,
+            computed: Sha1::digest(data).into(),
+        }
+    }
+
+    pub fn matches(&self) -> bool {
+        self.expected == self.computed
+    }
+}
+
+/// Reads piece `index` from the torrent's data under `root`, across file boundaries.
+pub fn read_piece(torrent: &TorrentFile, root: &Path, index: usize) -> Result<Vec<u8>> {
+    let length = torrent
+        .piece_size(index)
+        .with_context(|| format!("Torrent has no piece {index}"))?;
+    let mut piece = vec![0; usize::try_from(length)?];
+
+    for span in torrent.piece_spans(index) {
+        let path = root.join(&span.file.path);
+        let mut file = File::open(&path).with_context(|| format!("Failed to open {path:?}"))?;
+        file.seek(SeekFrom::Start(span.file_offset))?;
+        file.read_exact(&mut piece[span.piece_offset..span.piece_offset + span.length])
+            .with_context(|| format!("Failed to read piece {index} from {path:?}"))?;
+    }
+    Ok(piece)
+}
+
+/// Reads `length` bytes at `offset` into piece `index`, e.g. a block a peer asked for.
+pub fn read_block(
+    torrent: &TorrentFile,
+    root: &Path,
+    index: usize,
+    offset: usize,
+    length: usize,
+) -> Result<Vec<u8>> {
+    let piece_length = torrent
+        .piece_size(index)
+        .with_context(|| format!("Torrent has no piece {index}"))?;
+    let end = offset + length;
+    if end as u64 > piece_length {
+        bail!("Block {offset}..{end} is outside piece {index}");
+    }
+
+    let mut block = vec![0; length];
+    for span in torrent.piece_spans(index) {
+        let from = span.piece_offset.max(offset);
+        let to = (span.piece_offset + span.length).min(end);
+        if from >= to {
+            continue;
+        }
+        let path = root.join(&span.file.path);
+        let mut file = File::open(&path).with_context(|| format!("Failed to open {path:?}"))?;
+        file.seek(SeekFrom::Start(
+            span.file_offset + (from - span.piece_offset) as u64,
+        ))?;
+        file.read_exact(&mut block[from - offset..to - offset])
+            .with_context(|| format!("Failed to read piece {index} from {path:?}"))?;
+    }
+    Ok(block)
+}
+
+/// Writes piece `index`, or only its block number `block`, from disk to `output` and reports
+/// the hash of the whole piece.
+pub fn export_piece(
+    torrent: &TorrentFile,
+    root: &Path,
+    index: usize,
+    block: Option<usize>,
+    output: &Path,
+) -> Result<PieceReport> {
+    let piece = read_piece(torrent, root, index)?;
+    let report = PieceReport::new(index, torrent.piece_hashes()[index], &piece);
+    write_dump(&piece, block, output)?;
+    Ok(report)
+}
+
+/// Writes the assembled buffer of a piece that failed its hash check, blocks that never
+/// arrived included as zeros.
+pub fn export_partial(
+    piece: &PartialPiece,
+    expected: [u8; 20],
+    block: Option<usize>,
+    output: &Path,
+) -> Result<PieceReport> {
+    let report = PieceReport {
+        index: piece.index(),
+        expected,
+        computed: piece.hash(),
+    };
+    write_dump(piece.data(), block, output)?;
+    Ok(report)
+}
+
+fn write_dump(piece: &[u8], block: Option<usize>, output: &Path) -> Result<()> {
+    let bytes = match block {
+        Some(block) => {
+            let start = block.saturating_mul(BLOCK_SIZE);
+            if start >= piece.len() {
+                bail!("Piece has no block {block}");
+            }
+            &piece[start..piece.len().min(start + BLOCK_SIZE)]
+        }
+        None => piece,
+    };
+    fs::write(output, bytes).with_context(|| format!("Failed to write {output:?}"))
+}
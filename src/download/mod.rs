@@ -0,0 +1,27 @@
+pub mod assembly;
+pub mod attribution;
+pub mod hash_cache;
+pub mod inspect;
+pub mod partial;
+pub mod picker;
+pub mod relocate;
+pub mod reuse;
+pub mod selection;
+pub mod storage;
+pub mod verify;
+pub mod webseed;
+
+pub use assembly::{BlockOutcome, PieceAssembler, VerifiedPiece, penalize, store_verified};
+pub use attribution::{PieceAttribution, PieceSource};
+pub use hash_cache::{FileIdentity, HashCache, HashCacheConfig};
+pub use inspect::{PieceReport, export_partial, export_piece, read_block, read_piece};
+pub use partial::{BLOCK_SIZE, PartialPiece};
+pub use picker::{Availability, PiecePicker, PieceStrategy, RarestFirst, Sequential};
+pub use relocate::{ConflictPolicy, Relocation, move_completed, relocate_completed};
+pub use reuse::{ReuseReport, ReuseSources, reuse_local_data};
+pub use selection::{FilePriority, Selection, SelectionChange};
+pub use storage::{finish_file, write_piece};
+pub use verify::{
+    AddMode, AssumedCheck, PieceState, PieceStates, check_pieces, verify_in_background,
+};
+pub use webseed::WebSeed;
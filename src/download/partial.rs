@@ -0,0 +1,383 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use sha1::{Digest, Sha1};
+
+use crate::file::encoder::Value;
+
+/// Size of the blocks requested from peers; the last block of a piece may be shorter.
+pub const BLOCK_SIZE: usize = 16 * 1024;
+
+/// Buffer for a piece that is still being downloaded, tracking which blocks arrived.
+///
+/// Blocks are fed into a rolling SHA-1 as soon as every block before them has arrived, so a
+/// piece downloaded in order is already hashed when its last block lands.
+#[derive(Debug, Clone)]
+pub struct PartialPiece {
+    index: usize,
+    data: Vec<u8>,
+    received: Vec<bool>,
+    /// Hash state over `data[..hashed]`; `None` once a hashed block was overwritten with
+    /// different bytes, which leaves only a full hash.
+    hasher: Option<Sha1>,
+    hashed: usize,
+}
+
+impl PartialEq for PartialPiece {
+    /// The hash state is derived from the data and left out.
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.data == other.data && self.received == other.received
+    }
+}
+
+impl Eq for PartialPiece {}
+
+impl PartialPiece {
+    pub fn new(index: usize, length: usize) -> Self {
+        Self {
+            index,
+            data: vec![0; length],
+            received: vec![false; length.div_ceil(BLOCK_SIZE)],
+            hasher: Some(Sha1::new()),
+            hashed: 0,
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.received.len()
+    }
+
+    /// Length of the block starting at `offset`.
+    pub fn block_len(&self, offset: usize) -> usize {
+        BLOCK_SIZE.min(self.data.len().saturating_sub(offset))
+    }
+
+    pub fn has_block(&self, offset: usize) -> bool {
+        self.received
+            .get(offset / BLOCK_SIZE)
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Stores a block; blocks must start on a block boundary and have the expected length.
+    pub fn add_block(&mut self, offset: usize, block: &[u8]) -> Result<()> {
+        if !offset.is_multiple_of(BLOCK_SIZE) || offset >= self.data.len() {
+            bail!("Block offset {offset} is invalid for piece {}", self.index);
+        }
+        if block.len() != self.block_len(offset) {
+            bail!(
+                "Block at {offset} of piece {} has {} bytes, expected {}",
+                self.index,
+                block.len(),
+                self.block_len(offset)
+            );
+        }
+
+        let target = &mut self.data[offset..offset + block.len()];
+        if offset < self.hashed && target != block {
+            self.hasher = None;
+        }
+        target.copy_from_slice(block);
+        self.received[offset / BLOCK_SIZE] = true;
+        self.advance_hash();
+        Ok(())
+    }
+
+    /// Feeds every contiguous received block after the hashed prefix into the hasher.
+    fn advance_hash(&mut self) {
+        let Some(hasher) = &mut self.hasher else {
+            return;
+        };
+        while self.hashed < self.data.len() && self.received[self.hashed / BLOCK_SIZE] {
+            let end = self.data.len().min(self.hashed + BLOCK_SIZE);
+            hasher.update(&self.data[self.hashed..end]);
+            self.hashed = end;
+        }
+    }
+
+    /// Bytes already fed into the rolling hash.
+    pub fn hashed_bytes(&self) -> usize {
+        self.hashed
+    }
+
+    /// SHA-1 of the piece, from the rolling state when it covers everything and otherwise by
+    /// hashing the whole buffer.
+    pub fn hash(&self) -> [u8; 20] {
+        match &self.hasher {
+            Some(hasher) if self.hashed == self.data.len() => hasher.clone().finalize().into(),
+            _ => Sha1::digest(&self.data).into(),
+        }
+    }
+
+    pub fn verify(&self, expected: &[u8; 20]) -> bool {
+        self.is_complete() && self.hash() == *expected
+    }
+
+    /// Offsets of the blocks that still need to be requested.
+    pub fn missing_blocks(&self) -> impl Iterator<Item = usize> + '_ {
+        self.received
+            .iter()
+            .enumerate()
+            .filter_map(|(block, received)| (!received).then_some(block * BLOCK_SIZE))
+    }
+
+    pub fn received_bytes(&self) -> usize {
+        self.received
+            .iter()
+            .enumerate()
+            .filter(|(_, received)| **received)
+            .map(|(block, _)| self.block_len(block * BLOCK_SIZE))
+            .sum()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received.iter().all(|received| *received)
+    }
+
+    /// Drops every received block, e.g. after a failed hash check.
+    pub fn reset(&mut self) {
+        self.received
+            .iter_mut()
+            .for_each(|received| *received = false);
+        self.hasher = Some(Sha1::new());
+        self.hashed = 0;
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    fn to_value(&self) -> Value {
+        let mut blocks = vec![0u8; self.received.len().div_ceil(8)];
+        for (block, _) in self.received.iter().enumerate().filter(|(_, r)| **r) {
+            blocks[block / 8] |= 0x80 >> (block % 8);
+        }
+
+        let data = self
+            .received
+            .iter()
+            .enumerate()
+            .filter(|(_, received)| **received)
+            .flat_map(|(block, _)| {
+                let offset = block * BLOCK_SIZE;
+                &self.data[offset..offset + self.block_len(offset)]
+            })
+            .copied()
+            .collect::<Vec<u8>>();
+
+        Value::dict()
+            .with("blocks", blocks)
+            .with("data", data)
+            .with("index", self.index as u64)
+            .with("length", self.data.len() as u64)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SavedPiece {
+    blocks: ByteBuf,
+    data: ByteBuf,
+    index: usize,
+    length: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct SavedPartials {
+    #[serde(rename = "info hash")]
+    info_hash: ByteBuf,
+    pieces: Vec<SavedPiece>,
+}
+
+/// Persists partially downloaded pieces so a restart resumes at the block level.
+///
+/// Only received blocks are stored; the file is written to a temporary path and renamed so a
+/// crash mid-write never leaves a truncated snapshot behind.
+pub fn save_partials(path: &Path, info_hash: &[u8; 20], pieces: &[PartialPiece]) -> Result<()> {
+    let pieces = pieces
+        .iter()
+        .filter(|piece| piece.received_bytes() > 0)
+        .map(PartialPiece::to_value)
+        .collect::<Vec<_>>();
+    let snapshot = Value::dict()
+        .with("info hash", info_hash.as_slice())
+        .with("pieces", pieces)
+        .encode();
+
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, snapshot).with_context(|| format!("Failed to write {tmp:?}"))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {path:?}"))
+}
+
+/// Checks that a file written by [`save_partials`] decodes and that the data it holds adds up
+/// to its blocks, without knowing which torrent it belongs to.
+pub fn check_partials(path: &Path) -> Result<()> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {path:?}"))?;
+    let saved: SavedPartials = bendy::serde::from_bytes(&bytes)?;
+    if saved.info_hash.len() != 20 {
+        bail!("Info hash has {} bytes", saved.info_hash.len());
+    }
+    for piece in &saved.pieces {
+        let blocks = piece.length.div_ceil(BLOCK_SIZE);
+        if piece.blocks.len() != blocks.div_ceil(8) {
+            bail!("Block map of piece {} has the wrong size", piece.index);
+        }
+        let expected = (0..blocks)
+            .filter(|block| piece.blocks[block / 8] & (0x80 >> (block % 8)) != 0)
+            .map(|block| BLOCK_SIZE.min(piece.length - block * BLOCK_SIZE))
+            .sum::<usize>();
+        if piece.data.len() != expected {
+            bail!(
+                "Piece {} holds {} bytes of data, its blocks add up to {expected}",
+                piece.index,
+                piece.data.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Loads pieces saved by [`save_partials`], ignoring snapshots that belong to another torrent.
+pub fn load_partials(
+    path: &Path,
+    info_hash: &[u8; 20],
+    piece_len: impl Fn(usize) -> Option<usize>,
+) -> Result<Vec<PartialPiece>> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {path:?}"))?;
+    let saved: SavedPartials = bendy::serde::from_bytes(&bytes)?;
+    if saved.info_hash.as_slice() != info_hash {
+        return Ok(Vec::new());
+    }
+
+    let mut pieces = Vec::new();
+    for saved_piece in saved.pieces {
+        if piece_len(saved_piece.index) != Some(saved_piece.length) {
+            continue;
+        }
+
+        let mut piece = PartialPiece::new(saved_piece.index, saved_piece.length);
+        let mut data = saved_piece.data.as_slice();
+        for block in 0..piece.block_count() {
+            let received = saved_piece
+                .blocks
+                .get(block / 8)
+                .is_some_and(|byte| byte & (0x80 >> (block % 8)) != 0);
+            if !received {
+                continue;
+            }
+
+            let offset = block * BLOCK_SIZE;
+            let len = piece.block_len(offset);
+            if data.len() < len {
+                bail!("Truncated block data for piece {}", saved_piece.index);
+            }
+            piece.add_block(offset, &data[..len])?;
+            data = &data[len..];
+        }
+        pieces.push(piece);
+    }
+    Ok(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn block(fill: u8, len: usize) -> Vec<u8> {
+        vec![fill; len]
+    }
+
+    #[test]
+    fn partial_pieces_resume_with_the_same_blocks_and_hash() {
+        let dir = env::temp_dir().join(format!("terrent-partial-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("partials");
+        let info_hash = [7; 20];
+        let length = 2 * BLOCK_SIZE + 100;
+
+        let mut piece = PartialPiece::new(3, length);
+        piece.add_block(0, &block(1, BLOCK_SIZE)).unwrap();
+        piece.add_block(2 * BLOCK_SIZE, &block(3, 100)).unwrap();
+        save_partials(&path, &info_hash, &[piece.clone()]).unwrap();
+        check_partials(&path).unwrap();
+
+        let mut loaded = load_partials(&path, &info_hash, |_| Some(length)).unwrap();
+        assert_eq!(loaded, [piece]);
+        let resumed = &mut loaded[0];
+        assert_eq!(resumed.hashed_bytes(), BLOCK_SIZE);
+        assert_eq!(resumed.missing_blocks().collect::<Vec<_>>(), [BLOCK_SIZE]);
+
+        resumed
+            .add_block(BLOCK_SIZE, &block(2, BLOCK_SIZE))
+            .unwrap();
+        let whole = [block(1, BLOCK_SIZE), block(2, BLOCK_SIZE), block(3, 100)].concat();
+        let expected: [u8; 20] = Sha1::digest(&whole).into();
+        assert!(resumed.verify(&expected));
+
+        assert!(
+            load_partials(&path, &[8; 20], |_| Some(length))
+                .unwrap()
+                .is_empty()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupted_partials_are_rejected() {
+        let dir = env::temp_dir().join(format!("terrent-partial-corrupt-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("partials");
+        let info_hash = [7; 20];
+        let length = 2 * BLOCK_SIZE;
+
+        let mut piece = PartialPiece::new(0, length);
+        piece.add_block(0, &block(1, BLOCK_SIZE)).unwrap();
+        piece.add_block(BLOCK_SIZE, &block(2, BLOCK_SIZE)).unwrap();
+        save_partials(&path, &info_hash, &[piece]).unwrap();
+
+        // Cut into the block data, as a crash of another writer would.
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(check_partials(&path).is_err());
+        assert!(load_partials(&path, &info_hash, |_| Some(length)).is_err());
+
+        // Well-formed, but holding less data than its block map claims.
+        let truncated = Value::dict()
+            .with("info hash", info_hash.as_slice())
+            .with(
+                "pieces",
+                vec![
+                    Value::dict()
+                        .with("blocks", vec![0xc0u8])
+                        .with("data", block(1, BLOCK_SIZE))
+                        .with("index", 0u64)
+                        .with("length", length as u64),
+                ],
+            )
+            .encode();
+        fs::write(&path, truncated).unwrap();
+        assert!(check_partials(&path).is_err());
+        assert!(load_partials(&path, &info_hash, |_| Some(length)).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,317 @@
+use std::fmt;
+
+use crate::metadata::Metadata;
+use crate::peer::Bitfield;
+
+/// How many connected peers have each piece, kept up to date from their bitfields and
+/// `have` messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Availability {
+    counts: Vec<u32>,
+}
+
+impl Availability {
+    pub fn new(piece_count: usize) -> Self {
+        Self {
+            counts: vec![0; piece_count],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// Peers known to have the piece.
+    pub fn get(&self, piece: usize) -> u32 {
+        self.counts.get(piece).copied().unwrap_or(0)
+    }
+
+    /// Counts the pieces of a peer's bitfield, or a have-all as a full one.
+    pub fn add_peer(&mut self, pieces: &Bitfield) {
+        for piece in pieces.pieces() {
+            self.have(piece);
+        }
+    }
+
+    /// Uncounts the pieces of a peer that disconnected, as last known.
+    pub fn remove_peer(&mut self, pieces: &Bitfield) {
+        for piece in pieces.pieces() {
+            if let Some(count) = self.counts.get_mut(piece) {
+                *count = count.saturating_sub(1);
+            }
+        }
+    }
+
+    /// Counts a `have` message; call it only for pieces new to the peer's bitfield, e.g.
+    /// when [`Bitfield::set_piece`] returns true.
+    pub fn have(&mut self, piece: usize) {
+        if let Some(count) = self.counts.get_mut(piece) {
+            *count += 1;
+        }
+    }
+}
+
+/// Picks the next piece to download from pieces a peer can give us. Implement it to swap
+/// in another order, e.g. by file priority.
+pub trait PieceStrategy: fmt::Debug + Send {
+    /// One of `candidates`, which is never empty. `completed` counts the pieces we have.
+    fn pick(
+        &mut self,
+        candidates: &[usize],
+        availability: &Availability,
+        completed: usize,
+    ) -> usize;
+}
+
+/// The rarest pieces first, so pieces only a few peers have are spread before those peers
+/// leave. Until the first few pieces are in, any piece goes instead: a rare piece comes from
+/// few peers and takes longest, while we need something to trade right away.
+#[derive(Debug, Clone)]
+pub struct RarestFirst {
+    /// Pieces picked at random before rarest first starts.
+    random_first: usize,
+    random: Random,
+}
+
+impl RarestFirst {
+    pub const DEFAULT_RANDOM_FIRST: usize = 4;
+
+    pub fn new(random_first: usize) -> Self {
+        Self {
+            random_first,
+            random: Random::seeded(),
+        }
+    }
+
+    /// With a fixed seed, so the picks repeat.
+    pub fn with_seed(random_first: usize, seed: u64) -> Self {
+        Self {
+            random_first,
+            random: Random::new(seed),
+        }
+    }
+}
+
+impl Default for RarestFirst {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_RANDOM_FIRST)
+    }
+}
+
+impl PieceStrategy for RarestFirst {
+    fn pick(
+        &mut self,
+        candidates: &[usize],
+        availability: &Availability,
+        completed: usize,
+    ) -> usize {
+        if completed < self.random_first {
+            return candidates[self.random.below(candidates.len())];
+        }
+        let rarest = candidates
+            .iter()
+            .map(|piece| availability.get(*piece))
+            .min()
+            .unwrap_or(0);
+        let tied = candidates
+            .iter()
+            .copied()
+            .filter(|piece| availability.get(*piece) == rarest)
+            .collect::<Vec<_>>();
+        // Peers picking the same rare piece would all download it from the same few peers.
+        tied[self.random.below(tied.len())]
+    }
+}
+
+/// Pieces in order, so a file can be played while it downloads. Within a few pieces of the
+/// first candidate, the rarest goes first, so a piece only a few peers have is not missed
+/// until playback waits for it.
+#[derive(Debug, Clone)]
+pub struct Sequential {
+    /// Pieces looked ahead of the first candidate, counting it.
+    window: usize,
+}
+
+impl Sequential {
+    pub const DEFAULT_WINDOW: usize = 4;
+
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+        }
+    }
+}
+
+impl Default for Sequential {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WINDOW)
+    }
+}
+
+impl PieceStrategy for Sequential {
+    fn pick(
+        &mut self,
+        candidates: &[usize],
+        availability: &Availability,
+        _completed: usize,
+    ) -> usize {
+        let first = candidates.iter().copied().min().unwrap_or(0);
+        candidates
+            .iter()
+            .copied()
+            .filter(|piece| *piece - first < self.window)
+            // The earlier piece among equally rare ones.
+            .min_by_key(|piece| (availability.get(*piece), *piece))
+            .unwrap_or(first)
+    }
+}
+
+/// Piece availability of the swarm and the strategy choosing from it.
+#[derive(Debug)]
+pub struct PiecePicker {
+    availability: Availability,
+    strategy: Box<dyn PieceStrategy>,
+}
+
+impl PiecePicker {
+    pub fn new(piece_count: usize, strategy: Box<dyn PieceStrategy>) -> Self {
+        Self {
+            availability: Availability::new(piece_count),
+            strategy,
+        }
+    }
+
+    pub fn rarest_first(piece_count: usize) -> Self {
+        Self::new(piece_count, Box::new(RarestFirst::default()))
+    }
+
+    /// The picker a torrent asks for: [`Sequential`] if it downloads in order, otherwise
+    /// [`RarestFirst`].
+    pub fn for_torrent(torrent: &Metadata) -> Self {
+        let mut picker = Self::rarest_first(torrent.piece_count());
+        picker.set_sequential(torrent.sequential);
+        picker
+    }
+
+    /// Switches between [`Sequential`] and [`RarestFirst`], e.g. when the user toggles it.
+    pub fn set_sequential(&mut self, sequential: bool) {
+        if sequential {
+            self.set_strategy(Box::new(Sequential::default()));
+        } else {
+            self.set_strategy(Box::new(RarestFirst::default()));
+        }
+    }
+
+    pub fn availability(&self) -> &Availability {
+        &self.availability
+    }
+
+    pub fn availability_mut(&mut self) -> &mut Availability {
+        &mut self.availability
+    }
+
+    /// Replaces the strategy; availability carries over.
+    pub fn set_strategy(&mut self, strategy: Box<dyn PieceStrategy>) {
+        self.strategy = strategy;
+    }
+
+    /// The next piece to download from a peer with `peer_pieces`, among pieces `needed`
+    /// accepts, e.g. wanted, missing, and not already being downloaded.
+    pub fn pick(
+        &mut self,
+        peer_pieces: &Bitfield,
+        needed: impl Fn(usize) -> bool,
+        completed: usize,
+    ) -> Option<usize> {
+        let candidates = peer_pieces
+            .pieces()
+            .filter(|piece| needed(*piece))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(
+            self.strategy
+                .pick(&candidates, &self.availability, completed),
+        )
+    }
+}
+
+/// Xorshift, plenty to break ties between pieces.
+#[derive(Debug, Clone)]
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        // Xorshift never leaves zero.
+        Self(seed.max(1))
+    }
+
+    /// Seeded by the OS, so sessions started together still pick differently. Without
+    /// randomness the picks only stop varying between sessions, which is harmless.
+    fn seeded() -> Self {
+        Self::new(getrandom::u64().unwrap_or_default())
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bitfield(len: usize, pieces: &[usize]) -> Bitfield {
+        let mut bitfield = Bitfield::new(len);
+        for piece in pieces {
+            bitfield.set_piece(*piece);
+        }
+        bitfield
+    }
+
+    #[test]
+    fn picks_the_rarest_piece_after_the_warm_up() {
+        let mut picker = PiecePicker::new(4, Box::new(RarestFirst::with_seed(1, 7)));
+        let seed = Bitfield::full(4);
+        picker.availability_mut().add_peer(&seed);
+        picker.availability_mut().add_peer(&bitfield(4, &[0, 3]));
+        picker.availability_mut().add_peer(&bitfield(4, &[0, 3]));
+        picker.availability_mut().have(1);
+        assert_eq!(picker.availability().get(2), 1);
+
+        assert!(picker.pick(&seed, |_| true, 0).is_some());
+        assert_eq!(picker.pick(&seed, |_| true, 1), Some(2));
+        assert_eq!(picker.pick(&seed, |piece| piece != 2, 1), Some(1));
+        assert_eq!(picker.pick(&bitfield(4, &[2]), |piece| piece != 2, 1), None);
+
+        picker.availability_mut().remove_peer(&seed);
+        assert_eq!(picker.availability().get(2), 0);
+    }
+
+    #[test]
+    fn sequential_stays_within_its_window() {
+        let mut picker = PiecePicker::new(8, Box::new(Sequential::new(3)));
+        let seed = Bitfield::full(8);
+        picker.availability_mut().add_peer(&seed);
+        picker
+            .availability_mut()
+            .add_peer(&bitfield(8, &[1, 2, 3, 4, 5, 6]));
+        picker
+            .availability_mut()
+            .add_peer(&bitfield(8, &[1, 3, 4, 5, 6]));
+
+        // Piece 2 is rarer than piece 1 and in the window; piece 0 already is downloading.
+        assert_eq!(picker.pick(&seed, |piece| piece != 0, 10), Some(2));
+        assert_eq!(picker.pick(&seed, |piece| piece > 2, 10), Some(3));
+        // Piece 7 is the rarest, but out of the window ahead of piece 4.
+        assert_eq!(picker.pick(&seed, |piece| piece >= 4, 10), Some(4));
+    }
+}
@@ -0,0 +1,245 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::DownloadConfig;
+
+/// What to do when the destination of a completed move already exists.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ConflictPolicy {
+    /// Keep both by moving to `name (1).ext`, `name (2).ext`, and so on.
+    #[default]
+    Rename,
+    Overwrite,
+    /// Leave the data where it is.
+    Skip,
+}
+
+/// What [`relocate_completed`] did with a finished torrent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Relocation {
+    /// No completed directory is configured for the torrent, so it stays put.
+    NoRule,
+    Moved(PathBuf),
+    /// The destination exists and the conflict policy is [`ConflictPolicy::Skip`].
+    Skipped,
+}
+
+/// Moves the torrent's top-level file or directory `name` from `source` into `destination`.
+///
+/// Returns the new location, or `None` when the move was skipped because of a conflict. Moves
+/// across filesystems fall back to copying and deleting the source. An overwritten
+/// destination is only deleted once the new data is in place; until then it is kept under a
+/// hidden name and put back if the move fails.
+pub fn move_completed(
+    source: &Path,
+    name: &str,
+    destination: &Path,
+    policy: ConflictPolicy,
+) -> Result<Option<PathBuf>> {
+    let from = source.join(name);
+    let mut to = destination.join(name);
+    if from == to {
+        return Ok(Some(to));
+    }
+
+    let mut replaced = None;
+    if to.symlink_metadata().is_ok() {
+        match policy {
+            ConflictPolicy::Skip => return Ok(None),
+            ConflictPolicy::Overwrite => {
+                let aside = aside_name(destination, name);
+                fs::rename(&to, &aside)
+                    .with_context(|| format!("Failed to move {to:?} out of the way"))?;
+                replaced = Some(aside);
+            }
+            ConflictPolicy::Rename => to = free_name(destination, name),
+        }
+    }
+
+    let moved = fs::create_dir_all(destination)
+        .with_context(|| format!("Failed to create {destination:?}"))
+        .and_then(|()| {
+            if fs::rename(&from, &to).is_err() {
+                copy(&from, &to).with_context(|| format!("Failed to copy {from:?} to {to:?}"))?;
+            }
+            Ok(())
+        });
+    if let Err(err) = moved {
+        // Whatever is at `to` now is a partial copy of ours; the old data goes back.
+        let _ = remove(&to);
+        if let Some(replaced) = &replaced {
+            let _ = fs::rename(replaced, &to);
+        }
+        return Err(err);
+    }
+
+    if let Some(replaced) = &replaced {
+        remove(replaced).with_context(|| format!("Failed to remove the replaced {replaced:?}"))?;
+    }
+    if from.symlink_metadata().is_ok() {
+        remove(&from).with_context(|| format!("Failed to remove {from:?} after copying"))?;
+    }
+    Ok(Some(to))
+}
+
+/// Hidden name in `dir` that an overwritten `name` is kept under until the move is done.
+fn aside_name(dir: &Path, name: &str) -> PathBuf {
+    (0..)
+        .map(|n| match n {
+            0 => dir.join(format!(".{name}.replaced")),
+            n => dir.join(format!(".{name}.replaced-{n}")),
+        })
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("some suffix is free")
+}
+
+/// First `stem (n).ext` in `dir` that does not exist yet.
+fn free_name(dir: &Path, name: &str) -> PathBuf {
+    let path = Path::new(name);
+    let stem = path
+        .file_stem()
+        .map_or(name.into(), |stem| stem.to_string_lossy());
+    let extension = path.extension().map(|ext| ext.to_string_lossy());
+
+    (1..)
+        .map(|n| match &extension {
+            Some(extension) => dir.join(format!("{stem} ({n}).{extension}")),
+            None => dir.join(format!("{stem} ({n})")),
+        })
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("some suffix is free")
+}
+
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    let metadata = from.symlink_metadata()?;
+    if metadata.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else if metadata.is_symlink() {
+        copy_symlink(from, to)
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+/// Copies the file the link points to, which the system resolves relative to the link.
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+/// Applies the completed-move rules once a torrent finishes: the data under `source` goes to
+/// the directory configured for `label`, or stays put when no rule matches.
+pub fn relocate_completed(
+    config: &DownloadConfig,
+    label: Option<&str>,
+    source: &Path,
+    name: &str,
+) -> Result<Relocation> {
+    let Some(destination) = config.completed_dir_for(label) else {
+        return Ok(Relocation::NoRule);
+    };
+    Ok(
+        match move_completed(source, name, destination, config.on_conflict)? {
+            Some(to) => Relocation::Moved(to),
+            None => Relocation::Skipped,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn setup(test: &str) -> (PathBuf, PathBuf, DownloadConfig) {
+        let root = env::temp_dir().join(format!("terrent-relocate-{test}-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (source, done) = (root.join("source"), root.join("done"));
+        fs::create_dir_all(source.join("album")).unwrap();
+        fs::write(source.join("album").join("track.flac"), b"new").unwrap();
+        fs::create_dir_all(done.join("album")).unwrap();
+        fs::write(done.join("album").join("old.flac"), b"old").unwrap();
+        let config = DownloadConfig {
+            completed_dir: Some(done.clone()),
+            ..DownloadConfig::default()
+        };
+        (root, source, config)
+    }
+
+    #[test]
+    fn overwrite_replaces_the_old_data_once_the_new_is_in_place() {
+        let (root, source, mut config) = setup("overwrite");
+        config.on_conflict = ConflictPolicy::Overwrite;
+        let done = root.join("done");
+
+        let moved = relocate_completed(&config, None, &source, "album").unwrap();
+        assert_eq!(moved, Relocation::Moved(done.join("album")));
+        assert_eq!(
+            fs::read(done.join("album").join("track.flac")).unwrap(),
+            b"new"
+        );
+        assert!(!done.join("album").join("old.flac").exists());
+        assert!(!source.join("album").exists());
+        // Nothing is left under the hidden name.
+        assert_eq!(fs::read_dir(&done).unwrap().count(), 1);
+
+        // A failed move puts the replaced data back.
+        let err = move_completed(&source, "album", &done, ConflictPolicy::Overwrite);
+        assert!(err.is_err());
+        assert_eq!(
+            fs::read(done.join("album").join("track.flac")).unwrap(),
+            b"new"
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn skipping_and_missing_rules_are_told_apart() {
+        let (root, source, mut config) = setup("skip");
+        config.on_conflict = ConflictPolicy::Skip;
+        assert_eq!(
+            relocate_completed(&config, None, &source, "album").unwrap(),
+            Relocation::Skipped
+        );
+        config.completed_dir = None;
+        assert_eq!(
+            relocate_completed(&config, None, &source, "album").unwrap(),
+            Relocation::NoRule
+        );
+        assert!(source.join("album").join("track.flac").exists());
+
+        config.completed_dir = Some(root.join("done"));
+        config.on_conflict = ConflictPolicy::Rename;
+        assert_eq!(
+            relocate_completed(&config, None, &source, "album").unwrap(),
+            Relocation::Moved(root.join("done").join("album (1)"))
+        );
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use sha1::{Digest, Sha1};
+
+use super::verify::{PieceState, PieceStates};
+use crate::file::{FileEntry, FileSpan, TorrentFile};
+
+/// Files on disk that may hold data of a new torrent, indexed by length: a file can only
+/// be reused as a whole, so only same-sized files are worth hashing.
+#[derive(Debug, Default, Clone)]
+pub struct ReuseSources {
+    by_length: HashMap<u64, Vec<PathBuf>>,
+}
+
+/// Pieces filled in from local data.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReuseReport {
+    pub pieces: Vec<usize>,
+    pub bytes: u64,
+}
+
+impl ReuseSources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the files of a loaded torrent whose data lives under `root`.
+    pub fn add_torrent(&mut self, torrent: &TorrentFile, root: &Path) {
+        for file in torrent.files().filter(|file| !file.is_symlink()) {
+            let path = root.join(&file.path);
+            if fs::metadata(&path).is_ok_and(|meta| meta.is_file() && meta.len() == file.length) {
+                self.add(path, file.length);
+            }
+        }
+    }
+
+    /// Adds every file below `dir`; symlinks are not followed, so a library cannot loop.
+    pub fn add_library(&mut self, dir: &Path) -> Result<()> {
+        let entries = fs::read_dir(dir).with_context(|| format!("Failed to read {dir:?}"))?;
+        for entry in entries {
+            let entry = entry?;
+            let kind = entry.file_type()?;
+            if kind.is_dir() {
+                self.add_library(&entry.path())?;
+            } else if kind.is_file() {
+                self.add(entry.path(), entry.metadata()?.len());
+            }
+        }
+        Ok(())
+    }
+
+    fn add(&mut self, path: PathBuf, length: u64) {
+        if length == 0 {
+            return;
+        }
+        let paths = self.by_length.entry(length).or_default();
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+
+    fn candidates(&self, length: u64) -> &[PathBuf] {
+        self.by_length.get(&length).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Copies every missing piece of `torrent` that same-sized files in `sources` hold, with a
+/// matching hash, into its place under `root` and marks it verified in `states`.
+///
+/// Pieces inside a single file are tried against every candidate first; the candidate that
+/// matches is then used for the pieces it shares with neighbouring files.
+pub fn reuse_local_data(
+    torrent: &TorrentFile,
+    root: &Path,
+    sources: &ReuseSources,
+    states: &mut PieceStates,
+) -> Result<ReuseReport> {
+    let mut chosen: HashMap<&Path, &Path> = HashMap::new();
+    let mut report = ReuseReport::default();
+    let missing = (0..torrent.piece_count())
+        .filter(|index| !states.has(*index))
+        .collect::<Vec<_>>();
+    let (inner, shared): (Vec<_>, Vec<_>) = missing
+        .into_iter()
+        .partition(|index| torrent.piece_spans(*index).len() == 1);
+
+    for index in inner {
+        let spans = torrent.piece_spans(index);
+        let file = spans[0].file;
+        let mut candidates = candidates_for(file, root, sources);
+        if let Some(known) = chosen.get(file.path.as_path()) {
+            candidates.retain(|candidate| candidate != known);
+            candidates.insert(0, known);
+        }
+        for candidate in candidates {
+            let Ok(piece) = read_spans(torrent, index, &spans, |_| Some(candidate)) else {
+                continue;
+            };
+            if <[u8; 20]>::from(Sha1::digest(&piece)) == torrent.piece_hashes()[index] {
+                chosen.insert(&file.path, candidate);
+                write_spans(root, &spans, &piece)?;
+                states.set(index, PieceState::Verified);
+                report.pieces.push(index);
+                report.bytes += piece.len() as u64;
+                break;
+            }
+        }
+    }
+
+    for index in shared {
+        let spans = torrent.piece_spans(index);
+        let source = |file: &FileEntry| {
+            chosen
+                .get(file.path.as_path())
+                .copied()
+                .or_else(|| candidates_for(file, root, sources).first().copied())
+        };
+        let Ok(piece) = read_spans(torrent, index, &spans, source) else {
+            continue;
+        };
+        if <[u8; 20]>::from(Sha1::digest(&piece)) == torrent.piece_hashes()[index] {
+            write_spans(root, &spans, &piece)?;
+            states.set(index, PieceState::Verified);
+            report.pieces.push(index);
+            report.bytes += piece.len() as u64;
+        }
+    }
+
+    report.pieces.sort_unstable();
+    Ok(report)
+}
+
+/// Same-sized files other than the file's own destination.
+fn candidates_for<'a>(file: &FileEntry, root: &Path, sources: &'a ReuseSources) -> Vec<&'a Path> {
+    let target = root.join(&file.path);
+    sources
+        .candidates(file.length)
+        .iter()
+        .filter(|candidate| !same_file(candidate, &target))
+        .map(PathBuf::as_path)
+        .collect()
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Reads piece `index` with each span taken from the file `source` picks for it.
+fn read_spans<'a>(
+    torrent: &TorrentFile,
+    index: usize,
+    spans: &[FileSpan],
+    source: impl Fn(&FileEntry) -> Option<&'a Path>,
+) -> Result<Vec<u8>> {
+    let length = torrent.piece_size(index).context("No such piece")?;
+    let mut piece = vec![0; usize::try_from(length)?];
+    for span in spans {
+        let path = source(span.file).context("No local copy of the file")?;
+        let mut file = File::open(path)?;
+        file.seek(SeekFrom::Start(span.file_offset))?;
+        file.read_exact(&mut piece[span.piece_offset..span.piece_offset + span.length])?;
+    }
+    Ok(piece)
+}
+
+fn write_spans(root: &Path, spans: &[FileSpan], piece: &[u8]) -> Result<()> {
+    for span in spans {
+        let path = root.join(&span.file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Failed to open {path:?}"))?;
+        file.seek(SeekFrom::Start(span.file_offset))?;
+        file.write_all(&piece[span.piece_offset..span.piece_offset + span.length])
+            .with_context(|| format!("Failed to write {path:?}"))?;
+    }
+    Ok(())
+}
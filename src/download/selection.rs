@@ -0,0 +1,231 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use super::verify::PieceStates;
+use crate::file::TorrentFile;
+use crate::peer::{BlockRequest, Message, PeerConnection, RequestPipeline};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilePriority {
+    /// Not downloaded; pieces shared with wanted files still are.
+    Skip,
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+/// What a priority change means for the download in progress.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SelectionChange {
+    /// Pieces that became wanted.
+    pub wanted: Vec<usize>,
+    /// Pieces no longer wanted; their outstanding requests and partial data can go.
+    pub unwanted: Vec<usize>,
+    /// Bytes of wanted pieces still missing, to report to trackers as `left`.
+    pub left: u64,
+}
+
+impl SelectionChange {
+    pub fn is_empty(&self) -> bool {
+        self.wanted.is_empty() && self.unwanted.is_empty()
+    }
+
+    /// Whether requests for `piece` should be cancelled.
+    pub fn drops(&self, piece: usize) -> bool {
+        self.unwanted.binary_search(&piece).is_ok()
+    }
+
+    /// Brings one peer up to date with the change: withdraws our requests for pieces no
+    /// longer wanted and declares whether the peer still has anything we want. Returns the
+    /// messages to send it.
+    pub fn apply(
+        &self,
+        selection: &Selection,
+        states: &PieceStates,
+        connection: &mut PeerConnection,
+        pipeline: &mut RequestPipeline,
+    ) -> Vec<Message> {
+        let dropped: Vec<BlockRequest> = connection
+            .requests()
+            .iter()
+            .filter(|block| self.drops(block.piece as usize))
+            .copied()
+            .collect();
+        let mut messages: Vec<Message> = dropped
+            .into_iter()
+            .filter_map(|block| pipeline.cancel(connection, block))
+            .collect();
+        let interested = selection.is_interested(&connection.pieces().to_bools(), states);
+        messages.extend(connection.set_interested(interested));
+        messages
+    }
+}
+
+/// Which pieces to download, derived from per-file priorities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// Byte range of every file in the piece stream, in [`TorrentFile::files`] order.
+    files: Vec<(u64, u64)>,
+    priorities: Vec<FilePriority>,
+    piece_length: u64,
+    total_length: u64,
+    /// Highest priority of the files overlapping each piece.
+    pieces: Vec<FilePriority>,
+}
+
+impl Selection {
+    /// Every file at normal priority.
+    pub fn new(torrent: &TorrentFile) -> Self {
+        let files = torrent
+            .files()
+            .map(|file| (file.offset, file.length))
+            .collect::<Vec<_>>();
+        let mut selection = Self {
+            priorities: vec![FilePriority::default(); files.len()],
+            files,
+            piece_length: torrent.piece_length(),
+            total_length: torrent.total_length(),
+            pieces: vec![FilePriority::Skip; torrent.piece_count()],
+        };
+        selection.pieces = selection.piece_priorities();
+        selection
+    }
+
+    pub fn file_priority(&self, file: usize) -> Option<FilePriority> {
+        self.priorities.get(file).copied()
+    }
+
+    pub fn piece_priority(&self, piece: usize) -> FilePriority {
+        self.pieces
+            .get(piece)
+            .copied()
+            .unwrap_or(FilePriority::Skip)
+    }
+
+    pub fn is_wanted(&self, piece: usize) -> bool {
+        self.piece_priority(piece) != FilePriority::Skip
+    }
+
+    /// Changes the priority of `file` and returns which pieces flipped between wanted and
+    /// unwanted, given the pieces we already have in `states`.
+    pub fn set_priority(
+        &mut self,
+        file: usize,
+        priority: FilePriority,
+        states: &PieceStates,
+    ) -> Result<SelectionChange> {
+        let Some(slot) = self.priorities.get_mut(file) else {
+            bail!("No file {file} in this torrent");
+        };
+        *slot = priority;
+
+        let pieces = self.piece_priorities();
+        let mut change = SelectionChange::default();
+        for (index, (old, new)) in self.pieces.iter().zip(&pieces).enumerate() {
+            let (was, is) = (*old != FilePriority::Skip, *new != FilePriority::Skip);
+            if is && !was {
+                change.wanted.push(index);
+            } else if was && !is {
+                change.unwanted.push(index);
+            }
+        }
+        // Pieces we already have are neither requested nor discarded; they stay on disk.
+        change.wanted.retain(|index| !states.has(*index));
+        change.unwanted.retain(|index| !states.has(*index));
+        self.pieces = pieces;
+        change.left = self.left(states);
+        Ok(change)
+    }
+
+    /// Bytes of wanted pieces we do not have.
+    pub fn left(&self, states: &PieceStates) -> u64 {
+        (0..self.pieces.len())
+            .filter(|index| self.is_wanted(*index) && !states.has(*index))
+            .map(|index| self.piece_size(index))
+            .sum()
+    }
+
+    /// Whether a peer with `peer_pieces` has anything we want and lack, i.e. whether we
+    /// should be interested in it.
+    pub fn is_interested(&self, peer_pieces: &[bool], states: &PieceStates) -> bool {
+        peer_pieces
+            .iter()
+            .enumerate()
+            .any(|(index, has)| *has && self.is_wanted(index) && !states.has(index))
+    }
+
+    fn piece_size(&self, index: usize) -> u64 {
+        let start = index as u64 * self.piece_length;
+        self.piece_length
+            .min(self.total_length.saturating_sub(start))
+    }
+
+    fn piece_priorities(&self) -> Vec<FilePriority> {
+        let mut pieces = vec![FilePriority::Skip; self.pieces.len()];
+        if self.piece_length == 0 {
+            return pieces;
+        }
+        for (&(offset, length), &priority) in self.files.iter().zip(&self.priorities) {
+            if length == 0 {
+                continue;
+            }
+            let first = (offset / self.piece_length) as usize;
+            let last = ((offset + length - 1) / self.piece_length) as usize;
+            for piece in pieces.iter_mut().take(last + 1).skip(first) {
+                *piece = (*piece).max(priority);
+            }
+        }
+        pieces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::download::AddMode;
+    use crate::peer::{Bitfield, PipelineConfig};
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn deselecting_files_cancels_requests_and_drops_interest() {
+        let synthetic = SyntheticTorrent::builder("selected")
+            .piece_length(16 * 1024)
+            .file("a", 16 * 1024)
+            .file("b", 16 * 1024)
+            .build();
+        let states = PieceStates::new(2, AddMode::Check);
+        let mut selection = Selection::new(&synthetic.torrent);
+
+        let mut connection = PeerConnection::new(2);
+        connection.receive(&Bitfield::full(2).to_message()).unwrap();
+        connection.set_interested(true);
+        connection.receive(&Message::Unchoke).unwrap();
+        let mut pipeline = RequestPipeline::new(PipelineConfig::default());
+        let blocks = (0..2).map(|piece| BlockRequest {
+            piece,
+            offset: 0,
+            length: 16 * 1024,
+        });
+        pipeline.fill(&mut connection, blocks, Instant::now());
+
+        let change = selection
+            .set_priority(1, FilePriority::Skip, &states)
+            .unwrap();
+        let messages = change.apply(&selection, &states, &mut connection, &mut pipeline);
+        assert_eq!(messages.len(), 1);
+        assert_eq!(connection.requests().len(), 1);
+        assert!(connection.am_interested());
+
+        let change = selection
+            .set_priority(0, FilePriority::Skip, &states)
+            .unwrap();
+        let messages = change.apply(&selection, &states, &mut connection, &mut pipeline);
+        assert_eq!(messages.last(), Some(&Message::NotInterested));
+        assert!(connection.requests().is_empty());
+        assert_eq!(change.left, 0);
+    }
+}
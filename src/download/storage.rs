@@ -0,0 +1,195 @@
+use std::fs::{self, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::file::{FileEntry, TorrentFile};
+
+/// Writes the data of piece `index` into the torrent's files under `root`, creating files
+/// and directories as needed. Padding is skipped, like [`super::read_piece`] does.
+pub fn write_piece(torrent: &TorrentFile, root: &Path, index: usize, data: &[u8]) -> Result<()> {
+    let expected = torrent
+        .piece_size(index)
+        .with_context(|| format!("Torrent has no piece {index}"))?;
+    if data.len() as u64 != expected {
+        bail!(
+            "Piece {index} has {} bytes, expected {expected}",
+            data.len()
+        );
+    }
+
+    for span in torrent.piece_spans(index) {
+        let path = root.join(&span.file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .with_context(|| format!("Failed to open {path:?}"))?;
+        file.seek(SeekFrom::Start(span.file_offset))?;
+        file.write_all(&data[span.piece_offset..span.piece_offset + span.length])
+            .with_context(|| format!("Failed to write piece {index} to {path:?}"))?;
+    }
+    Ok(())
+}
+
+/// Applies a file's BEP 47 attributes once all of its data is on disk under `root`.
+///
+/// Symlink entries replace whatever placeholder was allocated with the link itself, and
+/// executable entries get their execute bits set on Unix. Hidden files need nothing extra on
+/// Unix, where hiding is part of the name.
+pub fn finish_file(root: &Path, file: &FileEntry) -> Result<()> {
+    let path = root.join(&file.path);
+
+    if file.is_symlink() {
+        let target = file
+            .symlink
+            .as_deref()
+            .expect("symlink entries have a target");
+        let relative = relative_target(&file.path, target)?;
+        if path.symlink_metadata().is_ok() {
+            fs::remove_file(&path).with_context(|| format!("Failed to replace {path:?}"))?;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        return create_symlink(&relative, &path)
+            .with_context(|| format!("Failed to create symlink {path:?}"));
+    }
+
+    if file.is_executable() {
+        set_executable(&path).with_context(|| format!("Failed to mark {path:?} executable"))?;
+    }
+    Ok(())
+}
+
+/// Turns a target relative to the download directory into one relative to the link, refusing
+/// targets that would point outside the torrent: a link inside the torrent's folder may only
+/// point into that folder.
+fn relative_target(link: &Path, target: &Path) -> Result<PathBuf> {
+    let outside_folder = link.parent().is_some_and(|parent| {
+        !parent.as_os_str().is_empty() && target.components().next() != link.components().next()
+    });
+    if outside_folder
+        || target
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        bail!("Symlink target {target:?} escapes the torrent");
+    }
+
+    let depth = link
+        .parent()
+        .map_or(0, |parent| parent.components().count());
+    let mut relative = PathBuf::new();
+    for _ in 0..depth {
+        relative.push("..");
+    }
+    relative.push(target);
+    Ok(relative)
+}
+
+#[cfg(unix)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn create_symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn create_symlink(_target: &Path, _link: &Path) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut permissions = fs::metadata(path)?.permissions();
+    // Grant execute wherever read is granted, like `chmod +x` under a typical umask.
+    let mode = permissions.mode();
+    permissions.set_mode(mode | ((mode & 0o444) >> 2));
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn entry(path: &str, attr: &str, symlink: Option<&str>) -> FileEntry {
+        FileEntry {
+            path: PathBuf::from(path),
+            length: 0,
+            offset: 0,
+            attr: attr.to_string(),
+            symlink: symlink.map(PathBuf::from),
+        }
+    }
+
+    #[test]
+    fn symlink_targets_are_made_relative_to_the_link() {
+        assert_eq!(
+            relative_target(Path::new("t/dir/link"), Path::new("t/file")).unwrap(),
+            Path::new("../../t/file")
+        );
+        assert_eq!(
+            relative_target(Path::new("link"), Path::new("file")).unwrap(),
+            Path::new("file")
+        );
+        for escaping in [
+            "../outside",
+            "/etc/passwd",
+            "t/../../outside",
+            "./file",
+            "other/file",
+        ] {
+            assert!(
+                relative_target(Path::new("t/link"), Path::new(escaping)).is_err(),
+                "{escaping:?} should be refused"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn finishing_files_creates_links_and_marks_executables() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = env::temp_dir().join(format!("terrent-finish-{}", process::id()));
+        fs::create_dir_all(root.join("t/bin")).unwrap();
+        fs::write(root.join("t/file"), b"data").unwrap();
+        fs::write(root.join("t/bin/run"), b"#!/bin/sh").unwrap();
+        fs::set_permissions(root.join("t/bin/run"), fs::Permissions::from_mode(0o644)).unwrap();
+        // A placeholder allocated before the torrent was known to hold a link.
+        fs::write(root.join("t/bin/link"), b"").unwrap();
+
+        finish_file(&root, &entry("t/bin/run", "x", None)).unwrap();
+        finish_file(&root, &entry("t/bin/link", "l", Some("t/file"))).unwrap();
+
+        let mode = fs::metadata(root.join("t/bin/run"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o755);
+        assert_eq!(
+            fs::read_link(root.join("t/bin/link")).unwrap(),
+            Path::new("../../t/file")
+        );
+        assert_eq!(fs::read(root.join("t/bin/link")).unwrap(), b"data");
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
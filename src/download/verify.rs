@@ -0,0 +1,312 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use anyhow::{Result, bail};
+use sha1::{Digest, Sha1};
+
+use super::hash_cache::HashCache;
+use crate::file::TorrentFile;
+use crate::priority;
+
+/// How existing data is treated when a torrent is added.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AddMode {
+    /// Hash every piece before anything is served.
+    #[default]
+    Check,
+    /// Trust the data (e.g. a restored backup): seed right away and verify in the background.
+    AssumeComplete,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PieceState {
+    Missing,
+    /// Taken on trust from [`AddMode::AssumeComplete`] and not hashed yet.
+    Assumed,
+    Verified,
+}
+
+/// Per-piece state of a torrent's local data.
+#[derive(Debug, Clone)]
+pub struct PieceStates {
+    storage: Storage,
+}
+
+/// A torrent where every piece is in the same state, e.g. a complete one being seeded, only
+/// keeps that state and the count.
+#[derive(Debug, Clone)]
+enum Storage {
+    Uniform { state: PieceState, len: usize },
+    Each(Vec<PieceState>),
+}
+
+impl PieceStates {
+    pub fn new(piece_count: usize, mode: AddMode) -> Self {
+        let state = match mode {
+            AddMode::Check => PieceState::Missing,
+            AddMode::AssumeComplete => PieceState::Assumed,
+        };
+        Self {
+            storage: Storage::Each(vec![state; piece_count]),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Uniform { len, .. } => *len,
+            Storage::Each(states) => states.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> Option<PieceState> {
+        match &self.storage {
+            Storage::Uniform { state, len } => (index < *len).then_some(*state),
+            Storage::Each(states) => states.get(index).copied(),
+        }
+    }
+
+    pub fn set(&mut self, index: usize, state: PieceState) {
+        if let Storage::Uniform {
+            state: current,
+            len,
+        } = self.storage
+        {
+            if current == state || index >= len {
+                return;
+            }
+            self.storage = Storage::Each(vec![current; len]);
+        }
+        if let Storage::Each(states) = &mut self.storage
+            && let Some(slot) = states.get_mut(index)
+        {
+            *slot = state;
+        }
+    }
+
+    /// Collapses the states into a single value if every piece shares it; the next [`Self::set`]
+    /// to a different state expands them again.
+    pub fn compact(&mut self) {
+        if let Storage::Each(states) = &self.storage
+            && let Some(first) = states.first().copied()
+            && states.iter().all(|state| *state == first)
+        {
+            self.storage = Storage::Uniform {
+                state: first,
+                len: states.len(),
+            };
+        }
+    }
+
+    /// Bytes held on the heap.
+    pub fn heap_size(&self) -> usize {
+        match &self.storage {
+            Storage::Uniform { .. } => 0,
+            Storage::Each(states) => states.capacity() * size_of::<PieceState>(),
+        }
+    }
+
+    /// Whether the piece can be served to peers; assumed pieces count until proven bad.
+    pub fn has(&self, index: usize) -> bool {
+        matches!(
+            self.get(index),
+            Some(PieceState::Assumed | PieceState::Verified)
+        )
+    }
+
+    pub fn available_count(&self) -> usize {
+        (0..self.len()).filter(|index| self.has(*index)).count()
+    }
+
+    /// Pieces still waiting for the background verification.
+    pub fn assumed(&self) -> Vec<usize> {
+        (0..self.len())
+            .filter(|index| self.get(*index) == Some(PieceState::Assumed))
+            .collect()
+    }
+}
+
+/// Compared piece by piece, whether compacted or not.
+impl PartialEq for PieceStates {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && (0..self.len()).all(|index| self.get(index) == other.get(index))
+    }
+}
+
+impl Eq for PieceStates {}
+
+/// Hashes every piece of `torrent` under `root`: matching pieces are verified, the rest,
+/// unreadable ones included, missing.
+pub fn check_pieces(torrent: &TorrentFile, root: &Path) -> PieceStates {
+    let mut states = PieceStates::new(torrent.piece_count(), AddMode::Check);
+    for (index, expected) in torrent.piece_hashes().iter().enumerate() {
+        if super::inspect::read_piece(torrent, root, index)
+            .is_ok_and(|piece| Sha1::digest(&piece)[..] == expected[..])
+        {
+            states.set(index, PieceState::Verified);
+        }
+    }
+    states
+}
+
+/// Hashes every assumed piece of a single-file torrent on a low-priority thread.
+///
+/// Matching pieces are promoted to [`PieceState::Verified`]; mismatching or unreadable ones are
+/// demoted to [`PieceState::Missing`] so they get downloaded again. The thread returns the
+/// demoted piece indices; a missing data file demotes everything. With a `cache`, pieces
+/// hashed before whose file did not change since are not read again.
+pub fn verify_in_background(
+    data: PathBuf,
+    piece_hashes: Vec<[u8; 20]>,
+    piece_length: u64,
+    total_length: u64,
+    states: Arc<Mutex<PieceStates>>,
+    cache: Option<Arc<Mutex<HashCache>>>,
+) -> JoinHandle<Vec<usize>> {
+    thread::spawn(move || {
+        // Only this thread slows down, so the rest of the client stays responsive; failing
+        // to lower it is no reason to skip the check.
+        let _ = priority::lower_thread_priority();
+
+        let pending = states.lock().unwrap().assumed();
+        let mut file = File::open(&data).ok();
+        let mut demoted = Vec::new();
+
+        for index in pending {
+            let offset = index as u64 * piece_length;
+            let length = piece_length.min(total_length.saturating_sub(offset));
+            let matches = file
+                .as_mut()
+                .zip(u32::try_from(length).ok())
+                .and_then(|(file, length)| match &cache {
+                    Some(cache) => cache.lock().unwrap().hash(file, offset, length).ok(),
+                    None => read_piece(file, offset, length as usize)
+                        .ok()
+                        .map(|piece| Sha1::digest(&piece).into()),
+                })
+                .is_some_and(|digest| piece_hashes.get(index) == Some(&digest));
+
+            let mut states = states.lock().unwrap();
+            // The piece may have been re-downloaded or dropped meanwhile; leave it alone then.
+            if states.get(index) != Some(PieceState::Assumed) {
+                continue;
+            }
+            if matches {
+                states.set(index, PieceState::Verified);
+            } else {
+                states.set(index, PieceState::Missing);
+                demoted.push(index);
+            }
+        }
+
+        demoted
+    })
+}
+
+/// The background check of a torrent added with [`AddMode::AssumeComplete`].
+#[derive(Debug)]
+pub struct AssumedCheck {
+    pub info_hash: [u8; 20],
+    piece_length: u64,
+    total_length: u64,
+    handle: Option<JoinHandle<Vec<usize>>>,
+}
+
+impl AssumedCheck {
+    /// Takes the data of `torrent` under `dir` as complete and starts hashing it with
+    /// [`verify_in_background`], which only handles torrents of a single file.
+    pub fn start(torrent: &TorrentFile, dir: &Path) -> Result<Self> {
+        let [file] = torrent.files().collect::<Vec<_>>()[..] else {
+            bail!("Only single-file torrents can be assumed complete");
+        };
+        let states = PieceStates::new(torrent.piece_count(), AddMode::AssumeComplete);
+        let handle = verify_in_background(
+            dir.join(&file.path),
+            torrent.piece_hashes().to_vec(),
+            torrent.piece_length(),
+            torrent.total_length(),
+            Arc::new(Mutex::new(states)),
+            None,
+        );
+        Ok(Self {
+            info_hash: torrent.info_hash(),
+            piece_length: torrent.piece_length(),
+            total_length: torrent.total_length(),
+            handle: Some(handle),
+        })
+    }
+
+    /// Bytes of the pieces that did not match, once the check is done; each result is
+    /// returned once.
+    pub fn finished(&mut self) -> Option<u64> {
+        let handle = self.handle.take_if(|handle| handle.is_finished())?;
+        Some(self.missing(handle.join()))
+    }
+
+    /// Whether the result was already returned.
+    pub fn is_done(&self) -> bool {
+        self.handle.is_none()
+    }
+
+    /// Waits for the check and returns what [`Self::finished`] would.
+    pub fn wait(mut self) -> u64 {
+        self.handle
+            .take()
+            .map_or(0, |handle| self.missing(handle.join()))
+    }
+
+    /// A check that panicked counts every piece as missing.
+    fn missing(&self, demoted: thread::Result<Vec<usize>>) -> u64 {
+        let Ok(demoted) = demoted else {
+            return self.total_length;
+        };
+        demoted
+            .iter()
+            .map(|&index| {
+                let offset = index as u64 * self.piece_length;
+                self.piece_length
+                    .min(self.total_length.saturating_sub(offset))
+            })
+            .sum()
+    }
+}
+
+fn read_piece(file: &mut File, offset: u64, length: usize) -> Result<Vec<u8>> {
+    let mut piece = vec![0; length];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut piece)?;
+    Ok(piece)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn assumed_checks_count_mismatching_pieces_as_missing() {
+        let root = env::temp_dir().join(format!("terrent-assumed-{}", process::id()));
+        let synthetic = SyntheticTorrent::single("assumed", 40 * 1024, 16 * 1024);
+        synthetic.write_to(&root).unwrap();
+
+        let check = AssumedCheck::start(&synthetic.torrent, &root).unwrap();
+        assert_eq!(check.wait(), 0);
+
+        let path = root.join("assumed");
+        let mut data = fs::read(&path).unwrap();
+        data[40 * 1024 - 1] ^= 0xff;
+        fs::write(&path, data).unwrap();
+        let check = AssumedCheck::start(&synthetic.torrent, &root).unwrap();
+        assert_eq!(check.wait(), 8 * 1024);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use reqwest::{Client, StatusCode, header};
+use url::Url;
+
+use super::assembly::{BlockOutcome, PieceAssembler, store_verified};
+use super::attribution::{PieceAttribution, PieceSource};
+use super::partial::BLOCK_SIZE;
+use super::picker::PiecePicker;
+use super::verify::PieceStates;
+use crate::file::{FileEntry, TorrentFile};
+use crate::metadata::Metadata;
+use crate::peer::Bitfield;
+
+/// HTTP/FTP-style web seed (BEP 19) serving the torrent content as plain files.
+#[derive(Debug, Clone)]
+pub struct WebSeed {
+    client: Client,
+    url: Url,
+}
+
+impl WebSeed {
+    /// Builds the seed from a `url-list` entry.
+    ///
+    /// Per BEP 19 a URL ending in `/` names a directory the torrent's files are found in,
+    /// under the torrent name; otherwise it is the file of a single-file torrent.
+    pub fn new(client: Client, base: &str) -> Result<Self> {
+        let url = Url::parse(base).with_context(|| format!("Invalid web seed URL {base}"))?;
+        if url.cannot_be_a_base() {
+            bail!("Web seed URL {base} cannot be a base");
+        }
+        Ok(Self { client, url })
+    }
+
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// URL of `file` of `torrent` on this seed.
+    pub fn file_url(&self, torrent: &TorrentFile, file: &FileEntry) -> Url {
+        let single_file = file.path == Path::new(torrent.name());
+        if single_file && !self.url.path().ends_with('/') {
+            return self.url.clone();
+        }
+        let mut url = self.url.clone();
+        if let Ok(mut segments) = url.path_segments_mut() {
+            segments.pop_if_empty();
+            for component in file.path.iter() {
+                segments.push(&component.to_string_lossy());
+            }
+        }
+        url
+    }
+
+    /// Downloads `length` bytes of `url` starting at `offset` with an HTTP range request.
+    ///
+    /// A server that ignores the range sends the whole file; only the part up to the end
+    /// of the range is read then.
+    pub async fn fetch_range(&self, url: &Url, offset: u64, length: usize) -> Result<Vec<u8>> {
+        if length == 0 {
+            return Ok(Vec::new());
+        }
+
+        let end = offset + length as u64 - 1;
+        let mut response = self
+            .client
+            .get(url.clone())
+            .header(header::RANGE, format!("bytes={offset}-{end}"))
+            .send()
+            .await
+            .with_context(|| format!("Web seed request to {url} failed"))?;
+
+        let mut skip = match response.status() {
+            StatusCode::PARTIAL_CONTENT => 0,
+            StatusCode::OK => offset,
+            status => bail!("Web seed {url} answered {status}"),
+        };
+        let mut data = Vec::with_capacity(length);
+        while data.len() < length
+            && let Some(chunk) = response.chunk().await?
+        {
+            let start = usize::try_from(skip).unwrap_or(usize::MAX).min(chunk.len());
+            skip -= start as u64;
+            let take = (length - data.len()).min(chunk.len() - start);
+            data.extend_from_slice(&chunk[start..start + take]);
+        }
+
+        if data.len() != length {
+            bail!(
+                "Web seed {url} returned {} bytes, expected {length}",
+                data.len()
+            );
+        }
+        Ok(data)
+    }
+
+    /// Downloads piece `index` of `torrent` from every file it spans, unverified.
+    pub async fn fetch_piece(&self, torrent: &TorrentFile, index: usize) -> Result<Vec<u8>> {
+        let length = torrent
+            .piece_size(index)
+            .with_context(|| format!("Piece {index} is out of range"))?;
+        // Padding is left out of the spans and stays zero.
+        let mut piece = vec![0; usize::try_from(length).context("Piece does not fit in memory")?];
+        for span in torrent.piece_spans(index) {
+            let url = self.file_url(torrent, span.file);
+            let data = self
+                .fetch_range(&url, span.file_offset, span.length)
+                .await?;
+            piece[span.piece_offset..span.piece_offset + span.length].copy_from_slice(&data);
+        }
+        Ok(piece)
+    }
+
+    /// Downloads piece `index` and hands it to `assembler` block by block, like blocks from
+    /// a peer, so the assembler verifies it and records this seed as the source.
+    pub async fn fetch_into(
+        &self,
+        torrent: &TorrentFile,
+        index: usize,
+        assembler: &mut PieceAssembler,
+    ) -> Result<BlockOutcome> {
+        let piece = self.fetch_piece(torrent, index).await?;
+        let source = PieceSource::WebSeed(self.url.to_string());
+        let mut outcome = BlockOutcome::Pending;
+        for (block, data) in piece.chunks(BLOCK_SIZE).enumerate() {
+            outcome = assembler.add_block(source.clone(), index, block * BLOCK_SIZE, data)?;
+        }
+        Ok(outcome)
+    }
+}
+
+/// Downloads every piece `states` lacks from `seeds`, in the order the torrent's
+/// [`PiecePicker`] chooses. Each seed counts as a peer with every piece; pieces go through
+/// the [`PieceAssembler`] and [`store_verified`] like those from peers, so they are hash
+/// checked, written under `root` and attributed to their seed.
+///
+/// A piece is tried on every seed in turn. Returns how many pieces were stored and how
+/// many no seed could provide.
+pub async fn fetch_missing(
+    torrent: &TorrentFile,
+    root: &Path,
+    seeds: &[WebSeed],
+    states: &mut PieceStates,
+    attribution: &mut PieceAttribution,
+) -> Result<(usize, usize)> {
+    let everything = Bitfield::full(torrent.piece_count());
+    let mut picker = PiecePicker::for_torrent(&Metadata::from(torrent));
+    for _ in seeds {
+        picker.availability_mut().add_peer(&everything);
+    }
+    let mut assembler = PieceAssembler::new(torrent);
+    let mut unavailable = BTreeSet::new();
+
+    let (mut fetched, mut failed) = (0, 0);
+    while let Some(index) = picker.pick(
+        &everything,
+        |piece| !states.has(piece) && !unavailable.contains(&piece),
+        states.available_count(),
+    ) {
+        let mut stored = false;
+        for seed in seeds {
+            match seed.fetch_into(torrent, index, &mut assembler).await {
+                Ok(BlockOutcome::Verified(piece)) => {
+                    store_verified(torrent, root, &piece, states, attribution)?;
+                    stored = true;
+                    break;
+                }
+                Ok(_) => eprintln!(
+                    "Piece {index} from web seed {} failed the hash check",
+                    seed.url()
+                ),
+                Err(err) => eprintln!("{err:#}"),
+            }
+        }
+        if stored {
+            fetched += 1;
+        } else {
+            unavailable.insert(index);
+            failed += 1;
+        }
+    }
+    Ok((fetched, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::download::{AddMode, PieceState, read_piece};
+    use crate::testing::SyntheticTorrent;
+
+    /// Serves `files` by path, whole and with `200 OK` whatever range is asked for.
+    async fn serve_ignoring_ranges(files: Vec<(&'static str, Vec<u8>)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 4096];
+                let read = stream.read(&mut request).await.unwrap();
+                let request = String::from_utf8_lossy(&request[..read]).into_owned();
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let body = files
+                    .iter()
+                    .find(|(name, _)| *name == path)
+                    .map(|(_, body)| body.clone())
+                    .unwrap_or_default();
+                let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&body).await;
+            }
+        });
+        format!("http://{addr}/")
+    }
+
+    #[tokio::test]
+    async fn fetches_pieces_across_files_from_servers_ignoring_ranges() {
+        let synthetic = SyntheticTorrent::builder("multi")
+            .piece_length(16 * 1024)
+            .file("a", 10 * 1024)
+            .file("b", 20 * 1024)
+            .build();
+        let data = [synthetic.piece(0), synthetic.piece(1)].concat();
+        let base = serve_ignoring_ranges(vec![
+            ("/multi/a", data[..10 * 1024].to_vec()),
+            ("/multi/b", data[10 * 1024..].to_vec()),
+        ])
+        .await;
+
+        let seed = WebSeed::new(Client::new(), &base).unwrap();
+        for index in 0..2 {
+            let piece = seed.fetch_piece(&synthetic.torrent, index).await.unwrap();
+            assert_eq!(piece, synthetic.piece(index));
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_pieces_are_stored_and_attributed_to_their_seed() {
+        let synthetic = SyntheticTorrent::single("seeded", 40 * 1024, 16 * 1024);
+        let data = (0..3)
+            .map(|index| synthetic.piece(index))
+            .collect::<Vec<_>>();
+        let base = serve_ignoring_ranges(vec![("/seeded", data.concat())]).await;
+        let root = env::temp_dir().join(format!("terrent-webseed-{}", process::id()));
+        fs::create_dir_all(&root).unwrap();
+
+        let seeds = [WebSeed::new(Client::new(), &base).unwrap()];
+        let mut states = PieceStates::new(3, AddMode::Check);
+        states.set(1, PieceState::Verified);
+        let mut attribution = PieceAttribution::new();
+        let outcome = fetch_missing(
+            &synthetic.torrent,
+            &root,
+            &seeds,
+            &mut states,
+            &mut attribution,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome, (2, 0));
+        assert_eq!(states.available_count(), 3);
+        assert!(attribution.get(1).is_none());
+        for index in [0, 2] {
+            let sources = &attribution.get(index).unwrap().sources;
+            assert_eq!(sources, &[PieceSource::WebSeed(base.clone())]);
+            let piece = read_piece(&synthetic.torrent, &root, index).unwrap();
+            assert_eq!(piece, synthetic.piece(index));
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
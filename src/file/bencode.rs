@@ -0,0 +1,401 @@
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use bendy::decoding::{Decoder, Object};
+use serde::{Deserialize, Deserializer};
+use serde_bytes::ByteBuf;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+
+use super::encoder::Value;
+use super::limits::DecodeLimits;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BencodeInfo {
+    /// Concatenated SHA-1 piece hashes; absent from v2-only torrents.
+    #[serde(default)]
+    pub pieces: ByteBuf,
+    #[serde(rename = "piece length")]
+    pub piece_length: u64,
+    /// Set for single-file torrents; multi-file torrents list their files in `files` instead.
+    #[serde(default, deserialize_with = "some")]
+    pub length: Option<u64>,
+    #[serde(default, deserialize_with = "some")]
+    pub files: Option<Vec<BencodeFile>>,
+    pub name: ByteBuf,
+    #[serde(default, rename = "name.utf-8", deserialize_with = "some")]
+    pub name_utf8: Option<ByteBuf>,
+    #[serde(default, deserialize_with = "some")]
+    pub private: Option<usize>,
+    /// Tag private trackers put in the info dictionary so cross-posted content gets a
+    /// distinct info hash per site.
+    #[serde(default, deserialize_with = "some")]
+    pub source: Option<ByteBuf>,
+    /// `2` for v2 and hybrid torrents (BEP 52).
+    #[serde(default, rename = "meta version", deserialize_with = "some")]
+    pub meta_version: Option<u64>,
+    /// The v2 file list, keyed by path component.
+    #[serde(default, rename = "file tree", deserialize_with = "some")]
+    pub file_tree: Option<BTreeMap<ByteBuf, FileTreeNode>>,
+}
+
+/// Entry of a v2 `file tree`: a file keeps its details under the empty key, a directory maps
+/// names to further entries.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum FileTreeNode {
+    File {
+        #[serde(rename = "")]
+        file: V2File,
+    },
+    Directory(BTreeMap<ByteBuf, FileTreeNode>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct V2File {
+    pub length: u64,
+    /// Merkle root of the file's 16 KiB blocks; absent for empty files.
+    #[serde(default, rename = "pieces root", deserialize_with = "some")]
+    pub pieces_root: Option<ByteBuf>,
+}
+
+impl FileTreeNode {
+    pub fn to_value(&self) -> Value {
+        match self {
+            FileTreeNode::File { file } => {
+                let mut entry = Value::dict().with("length", file.length);
+                if let Some(pieces_root) = &file.pieces_root {
+                    entry.insert("pieces root", pieces_root.as_slice());
+                }
+                Value::dict().with("", entry)
+            }
+            FileTreeNode::Directory(children) => file_tree_value(children),
+        }
+    }
+}
+
+fn file_tree_value(tree: &BTreeMap<ByteBuf, FileTreeNode>) -> Value {
+    Value::Dict(
+        tree.iter()
+            .map(|(name, node)| (name.to_vec(), node.to_value()))
+            .collect(),
+    )
+}
+
+/// Appends the files below `tree` in key order, each with its path components.
+fn flatten_tree<'a>(
+    tree: &'a BTreeMap<ByteBuf, FileTreeNode>,
+    prefix: &mut Vec<String>,
+    files: &mut Vec<(Vec<String>, &'a V2File)>,
+) {
+    for (name, node) in tree {
+        prefix.push(String::from_utf8_lossy(name).into_owned());
+        match node {
+            FileTreeNode::File { file } => files.push((prefix.clone(), file)),
+            FileTreeNode::Directory(children) => flatten_tree(children, prefix, files),
+        }
+        prefix.pop();
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BencodeFile {
+    pub length: u64,
+    pub path: Vec<ByteBuf>,
+    #[serde(default, rename = "path.utf-8", deserialize_with = "some")]
+    pub path_utf8: Option<Vec<ByteBuf>>,
+    /// BEP 47 attributes, e.g. `p` for padding files.
+    #[serde(default, deserialize_with = "some")]
+    pub attr: Option<ByteBuf>,
+    /// Target of a symlink (`attr` contains `l`), relative to the torrent root.
+    #[serde(default, rename = "symlink path", deserialize_with = "some")]
+    pub symlink_path: Option<Vec<ByteBuf>>,
+}
+
+impl BencodeFile {
+    pub fn to_value(&self) -> Value {
+        let path = |components: &[ByteBuf]| {
+            components
+                .iter()
+                .map(|component| component.to_vec())
+                .collect::<Vec<_>>()
+        };
+        let mut file = Value::dict()
+            .with("length", self.length)
+            .with("path", path(&self.path));
+        if let Some(path_utf8) = &self.path_utf8 {
+            file.insert("path.utf-8", path(path_utf8));
+        }
+        if let Some(attr) = &self.attr {
+            file.insert("attr", attr.as_slice());
+        }
+        if let Some(symlink_path) = &self.symlink_path {
+            file.insert("symlink path", path(symlink_path));
+        }
+        file
+    }
+
+    /// Path components, preferring `path.utf-8` when it is valid and has the same shape.
+    pub fn path(&self) -> Vec<String> {
+        let utf8 = self
+            .path_utf8
+            .as_ref()
+            .filter(|utf8| utf8.len() == self.path.len());
+        self.path
+            .iter()
+            .enumerate()
+            .map(|(index, component)| {
+                prefer_utf8(component, utf8.map(|utf8| utf8[index].as_slice()))
+            })
+            .collect()
+    }
+
+    pub fn symlink_path(&self) -> Option<Vec<String>> {
+        let components = self.symlink_path.as_ref()?;
+        Some(
+            components
+                .iter()
+                .map(|component| String::from_utf8_lossy(component).into_owned())
+                .collect(),
+        )
+    }
+
+    pub fn attr(&self) -> String {
+        self.attr
+            .as_ref()
+            .map(|attr| String::from_utf8_lossy(attr).into_owned())
+            .unwrap_or_default()
+    }
+}
+
+/// `url-list` may hold a single URL or a list of them (BEP 19).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum UrlList {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl UrlList {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            UrlList::Single(url) if url.is_empty() => Vec::new(),
+            UrlList::Single(url) => vec![url],
+            UrlList::Multiple(urls) => urls,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct BencodeTorrent {
+    #[serde(default, deserialize_with = "some")]
+    pub announce: Option<String>,
+    /// Tracker tiers (BEP 12); takes precedence over `announce` when present.
+    #[serde(default, rename = "announce-list")]
+    pub announce_list: Vec<Vec<String>>,
+    pub info: BencodeInfo,
+    /// DHT bootstrap nodes of trackerless torrents, as `[host, port]` pairs.
+    #[serde(default)]
+    pub nodes: Vec<(String, u16)>,
+    #[serde(default, rename = "created by", deserialize_with = "some")]
+    pub created_by: Option<String>,
+    #[serde(default, rename = "creation date", deserialize_with = "some")]
+    pub creation_date: Option<u64>,
+    #[serde(default, deserialize_with = "some")]
+    pub comment: Option<String>,
+    #[serde(default, deserialize_with = "some")]
+    pub encoding: Option<String>,
+    #[serde(default, rename = "url-list", deserialize_with = "some")]
+    pub url_list: Option<UrlList>,
+    /// Exact bytes of the `info` value as it appeared in the source, used for the info hash.
+    #[serde(skip)]
+    pub info_bytes: Vec<u8>,
+    /// Every top-level entry, byte-exact, so keys not modelled here, e.g. v2 `piece layers`,
+    /// survive re-encoding.
+    #[serde(skip)]
+    pub entries: BTreeMap<Vec<u8>, Value>,
+}
+
+impl BencodeInfo {
+    pub fn to_value(&self) -> Value {
+        let mut info = Value::dict()
+            .with("name", self.name.as_slice())
+            .with("piece length", self.piece_length);
+        if !self.is_v2_only() {
+            info.insert("pieces", self.pieces.as_slice());
+        }
+        if let Some(length) = self.length {
+            info.insert("length", length);
+        }
+        if let Some(files) = &self.files {
+            info.insert(
+                "files",
+                files.iter().map(BencodeFile::to_value).collect::<Vec<_>>(),
+            );
+        }
+        if let Some(name_utf8) = &self.name_utf8 {
+            info.insert("name.utf-8", name_utf8.as_slice());
+        }
+        if let Some(private) = self.private {
+            info.insert("private", private as u64);
+        }
+        if let Some(source) = &self.source {
+            info.insert("source", source.as_slice());
+        }
+        if let Some(meta_version) = self.meta_version {
+            info.insert("meta version", meta_version);
+        }
+        if let Some(file_tree) = &self.file_tree {
+            info.insert("file tree", file_tree_value(file_tree));
+        }
+        info
+    }
+
+    /// Whether the torrent only describes its content through the v2 `file tree`, with no
+    /// v1 `length`, `files` or SHA-1 piece hashes.
+    pub fn is_v2_only(&self) -> bool {
+        self.file_tree.is_some() && self.length.is_none() && self.files.is_none()
+    }
+
+    /// Files of the v2 `file tree` with their path components, in key order.
+    pub fn v2_files(&self) -> Vec<(Vec<String>, &V2File)> {
+        let mut files = Vec::new();
+        if let Some(tree) = &self.file_tree {
+            flatten_tree(tree, &mut Vec::new(), &mut files);
+        }
+        files
+    }
+
+    /// Display name, preferring `name.utf-8` over the legacy `name` key.
+    pub fn name(&self) -> String {
+        prefer_utf8(
+            &self.name,
+            self.name_utf8.as_ref().map(|name| name.as_slice()),
+        )
+    }
+
+    /// Size of the piece stream, including any padding files; `None` when the file lengths
+    /// add up to more than a `u64` holds.
+    pub fn total_length(&self) -> Option<u64> {
+        match &self.files {
+            Some(files) => files
+                .iter()
+                .try_fold(0u64, |total, file| total.checked_add(file.length)),
+            None if self.is_v2_only() => self
+                .v2_files()
+                .iter()
+                .try_fold(0u64, |total, (_, file)| total.checked_add(file.length)),
+            None => Some(self.length.unwrap_or(0)),
+        }
+    }
+
+    pub fn hash(&self) -> [u8; 20] {
+        info_hash(&self.to_value().encode(), self.is_v2_only())
+    }
+
+    pub fn split_piece_hashes(&self) -> Result<Vec<[u8; 20]>> {
+        let chunks = self.pieces.chunks_exact(20);
+        if !chunks.remainder().is_empty() {
+            bail!("Received malformed pieces of length {}", self.pieces.len());
+        }
+        Ok(chunks
+            .map(|chunk| chunk.try_into().expect("chunk is 20 bytes"))
+            .collect())
+    }
+}
+
+impl BencodeTorrent {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_limits(bytes, &DecodeLimits::default())
+    }
+
+    /// Decodes after checking `bytes` against `limits`; violations surface as [`DecodeError`].
+    ///
+    /// [`DecodeError`]: super::limits::DecodeError
+    pub fn from_bytes_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Self> {
+        limits.check(bytes)?;
+        let mut torrent: BencodeTorrent = bendy::serde::from_bytes(bytes)?;
+        torrent.info_bytes = raw_info(bytes)?.to_vec();
+        torrent.entries = raw_entries(bytes)?;
+        Ok(torrent)
+    }
+
+    /// Hash of the raw info bytes, falling back to re-encoding when they are unavailable.
+    pub fn info_hash(&self) -> [u8; 20] {
+        if self.info_bytes.is_empty() {
+            return self.info.hash();
+        }
+        info_hash(&self.info_bytes, self.info.is_v2_only())
+    }
+}
+
+/// SHA-1 of the info dictionary, or for v2-only torrents its SHA-256 truncated to 20 bytes,
+/// which is what trackers and peers see (BEP 52).
+pub fn info_hash(info_bytes: &[u8], v2_only: bool) -> [u8; 20] {
+    if v2_only {
+        let hash = Sha256::digest(info_bytes);
+        return hash[..20]
+            .try_into()
+            .expect("SHA-256 is longer than 20 bytes");
+    }
+    Sha1::digest(info_bytes).into()
+}
+
+/// Picks the `*.utf-8` variant of a string key when it is valid UTF-8, otherwise decodes the
+/// legacy key lossily; old torrents often carry names in a local code page.
+pub fn prefer_utf8(legacy: &[u8], utf8: Option<&[u8]>) -> String {
+    if let Some(utf8) = utf8
+        && let Ok(name) = std::str::from_utf8(utf8)
+    {
+        return name.to_string();
+    }
+    String::from_utf8_lossy(legacy).into_owned()
+}
+
+/// Bendy encodes `Option` as a list, but torrents simply omit absent keys; combined with
+/// `#[serde(default)]` this reads a present key as `Some(value)`.
+pub(crate) fn some<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Splits a bencoded dictionary into its entries, keeping every value byte-exact so the
+/// dictionary can be re-encoded with only the intended keys changed.
+pub fn raw_entries(bytes: &[u8]) -> Result<BTreeMap<Vec<u8>, Value>> {
+    let mut decoder = Decoder::new(bytes);
+    let mut dict = decoder
+        .next_object()?
+        .context("Empty dictionary")?
+        .try_into_dictionary()?;
+
+    let mut entries = BTreeMap::new();
+    while let Some((key, value)) = dict.next_pair()? {
+        let value = match value {
+            Object::Integer(integer) => Value::Raw(format!("i{integer}e").into_bytes()),
+            Object::Bytes(bytes) => Value::Bytes(bytes.to_vec()),
+            Object::List(list) => Value::Raw(list.into_raw()?.to_vec()),
+            Object::Dict(dict) => Value::Raw(dict.into_raw()?.to_vec()),
+        };
+        entries.insert(key.to_vec(), value);
+    }
+    Ok(entries)
+}
+
+/// Returns the exact byte span of the top-level `info` dictionary.
+fn raw_info(bytes: &[u8]) -> Result<&[u8]> {
+    let mut decoder = Decoder::new(bytes);
+    let mut torrent = decoder
+        .next_object()?
+        .context("Empty torrent file")?
+        .try_into_dictionary()?;
+
+    while let Some((key, value)) = torrent.next_pair()? {
+        if key == b"info" {
+            return Ok(value.try_into_dictionary()?.into_raw()?);
+        }
+    }
+    bail!("Torrent has no info dictionary")
+}
@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use sha1::{Digest, Sha1};
+
+use super::encoder::Value;
+use super::merkle::{self, MERKLE_BLOCK_SIZE};
+
+const MIN_PIECE_LENGTH: u64 = 16 * 1024;
+const MAX_PIECE_LENGTH: u64 = 16 * 1024 * 1024;
+/// Auto-selected piece lengths aim for roughly this many pieces.
+const TARGET_PIECE_COUNT: u64 = 1500;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TorrentFormat {
+    /// Classic SHA-1 piece hashes (BEP 3)
+    #[default]
+    V1,
+    /// SHA-256 merkle trees per file only (BEP 52)
+    V2,
+    /// Both v1 and v2 metadata, readable by either kind of client
+    Hybrid,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SourceFile {
+    /// File on disk, or `None` for a BEP 47 padding file made of zeros.
+    path: Option<PathBuf>,
+    /// Path components relative to the torrent root; empty for single-file torrents.
+    components: Vec<String>,
+    length: u64,
+}
+
+/// Creates `.torrent` files from a file or directory on disk.
+#[derive(Debug, Clone)]
+pub struct TorrentBuilder {
+    path: PathBuf,
+    format: TorrentFormat,
+    piece_length: Option<u64>,
+    trackers: Vec<String>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    private: bool,
+    source: Option<String>,
+    threads: Option<usize>,
+}
+
+impl TorrentBuilder {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            format: TorrentFormat::default(),
+            piece_length: None,
+            trackers: Vec::new(),
+            comment: None,
+            created_by: Some(format!("terrent/{}", env!("CARGO_PKG_VERSION"))),
+            private: false,
+            source: None,
+            threads: None,
+        }
+    }
+
+    pub fn format(mut self, format: TorrentFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Sets the piece length; it must be a power of two of at least 16 KiB.
+    pub fn piece_length(mut self, piece_length: u64) -> Self {
+        self.piece_length = Some(piece_length);
+        self
+    }
+
+    /// Adds a tracker; each tracker becomes its own tier in `announce-list`.
+    pub fn tracker(mut self, url: impl Into<String>) -> Self {
+        self.trackers.push(url.into());
+        self
+    }
+
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    pub fn created_by(mut self, created_by: Option<String>) -> Self {
+        self.created_by = created_by;
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// Sets the `source` tag private trackers require, which also changes the info hash.
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Caps the number of hashing threads; defaults to the available parallelism.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Hashes the content and returns the bencoded torrent.
+    pub fn build(&self) -> Result<Vec<u8>> {
+        // Resolves `.`, `..` and a trailing symlink, which have no usable file name.
+        let path = fs::canonicalize(&self.path)
+            .with_context(|| format!("Failed to read {:?}", self.path))?;
+        let name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .with_context(|| format!("Cannot derive a torrent name from {:?}", self.path))?
+            .to_string();
+
+        let single_file = !path.is_dir();
+        let mut files = collect_files(&path)?;
+        let total_length: u64 = files.iter().map(|file| file.length).sum();
+        if total_length == 0 {
+            bail!("Cannot create a torrent from empty content");
+        }
+        if single_file {
+            files[0].components = vec![name.clone()];
+        }
+
+        let piece_length = match self.piece_length {
+            Some(length) if length < MIN_PIECE_LENGTH || !length.is_power_of_two() => {
+                bail!("Piece length must be a power of two of at least {MIN_PIECE_LENGTH} bytes")
+            }
+            Some(length) => length,
+            None => auto_piece_length(total_length),
+        };
+
+        let mut info = Value::dict()
+            .with("name", name)
+            .with("piece length", piece_length);
+        if self.private {
+            info.insert("private", 1i64);
+        }
+        if let Some(source) = &self.source {
+            info.insert("source", source.as_str());
+        }
+
+        let mut piece_layers = None;
+        if self.format != TorrentFormat::V1 {
+            let (file_tree, layers) = self.hash_v2(&files, piece_length)?;
+            info.insert("file tree", file_tree);
+            info.insert("meta version", 2i64);
+            piece_layers = Some(layers);
+        }
+
+        if self.format != TorrentFormat::V2 {
+            // Hybrid torrents pad every file to a piece boundary so v1 and v2 pieces line up.
+            let v1_files = if self.format == TorrentFormat::Hybrid {
+                with_padding(&files, piece_length)
+            } else {
+                files.clone()
+            };
+            let v1_length = v1_files.iter().map(|file| file.length).sum();
+            let pieces = self.hash_units(&v1_files, v1_length, piece_length, |data| {
+                Sha1::digest(data).into()
+            })?;
+            info.insert("pieces", pieces.concat());
+
+            if single_file {
+                info.insert("length", total_length);
+            } else {
+                let entries = v1_files
+                    .iter()
+                    .map(|file| {
+                        let mut entry = Value::dict()
+                            .with("length", file.length)
+                            .with("path", file.components.clone());
+                        if file.path.is_none() {
+                            entry.insert("attr", "p");
+                        }
+                        entry
+                    })
+                    .collect::<Vec<_>>();
+                info.insert("files", entries);
+            }
+        }
+
+        let mut torrent = Value::dict().with("info", info);
+        if let Some(piece_layers) = piece_layers {
+            torrent.insert("piece layers", piece_layers);
+        }
+        if let Some(announce) = self.trackers.first() {
+            torrent.insert("announce", announce.as_str());
+        }
+        if self.trackers.len() > 1 {
+            let tiers = self
+                .trackers
+                .iter()
+                .map(|tracker| vec![tracker.as_str()])
+                .collect::<Vec<_>>();
+            torrent.insert("announce-list", tiers);
+        }
+        if let Some(comment) = &self.comment {
+            torrent.insert("comment", comment.as_str());
+        }
+        if let Some(created_by) = &self.created_by {
+            torrent.insert("created by", created_by.as_str());
+        }
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        torrent.insert("creation date", now);
+
+        Ok(torrent.encode())
+    }
+
+    /// Hashes the content and writes the torrent to `output`.
+    pub fn write(&self, output: impl AsRef<Path>) -> Result<()> {
+        let torrent = self.build()?;
+        fs::write(output.as_ref(), torrent)
+            .with_context(|| format!("Failed to write {:?}", output.as_ref()))
+    }
+
+    /// Builds the v2 `file tree` and the top-level `piece layers` dictionary.
+    fn hash_v2(&self, files: &[SourceFile], piece_length: u64) -> Result<(Value, Value)> {
+        let mut file_tree = Value::dict();
+        let mut piece_layers = Value::dict();
+
+        for file in files {
+            let mut entry = Value::dict().with("length", file.length);
+            if file.length > 0 {
+                let leaves = self.hash_units(
+                    std::slice::from_ref(file),
+                    file.length,
+                    MERKLE_BLOCK_SIZE,
+                    merkle::sha256,
+                )?;
+                let hashes = merkle::file_hashes(leaves, file.length, piece_length);
+                entry.insert("pieces root", hashes.pieces_root.as_slice());
+                if !hashes.piece_layer.is_empty() {
+                    piece_layers.insert(hashes.pieces_root.to_vec(), hashes.piece_layer.concat());
+                }
+            }
+            insert_path(&mut file_tree, &file.components, entry);
+        }
+
+        Ok((file_tree, piece_layers))
+    }
+
+    /// Splits the concatenated content into `unit`-sized chunks and hashes them in parallel.
+    fn hash_units<const N: usize>(
+        &self,
+        files: &[SourceFile],
+        total_length: u64,
+        unit: u64,
+        digest: fn(&[u8]) -> [u8; N],
+    ) -> Result<Vec<[u8; N]>> {
+        let unit_count = total_length.div_ceil(unit) as usize;
+        let threads = self
+            .threads
+            .or_else(|| thread::available_parallelism().ok().map(usize::from))
+            .unwrap_or(1)
+            .clamp(1, unit_count.max(1));
+        let per_thread = unit_count.div_ceil(threads).max(1);
+
+        let mut hashes = vec![[0u8; N]; unit_count];
+        thread::scope(|scope| {
+            let workers = hashes
+                .chunks_mut(per_thread)
+                .enumerate()
+                .map(|(chunk, out)| {
+                    let first_unit = chunk * per_thread;
+                    scope.spawn(move || -> Result<()> {
+                        let mut reader = SpanReader::new(files);
+                        let mut buffer = vec![0u8; unit as usize];
+                        for (offset, hash) in out.iter_mut().enumerate() {
+                            let start = (first_unit + offset) as u64 * unit;
+                            let len = unit.min(total_length - start) as usize;
+                            reader.read_at(start, &mut buffer[..len])?;
+                            *hash = digest(&buffer[..len]);
+                        }
+                        Ok(())
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            workers
+                .into_iter()
+                .try_for_each(|worker| worker.join().expect("hashing thread panicked"))
+        })?;
+
+        Ok(hashes)
+    }
+}
+
+/// Picks a power-of-two piece length giving roughly `TARGET_PIECE_COUNT` pieces.
+pub fn auto_piece_length(total_length: u64) -> u64 {
+    (total_length / TARGET_PIECE_COUNT)
+        .next_power_of_two()
+        .clamp(MIN_PIECE_LENGTH, MAX_PIECE_LENGTH)
+}
+
+/// Inserts a v2 file entry at `components` inside the nested `file tree` dictionaries.
+fn insert_path(tree: &mut Value, components: &[String], entry: Value) {
+    let Value::Dict(dict) = tree else {
+        return;
+    };
+    match components {
+        [] => {}
+        [name] => {
+            dict.insert(name.as_bytes().to_vec(), Value::dict().with("", entry));
+        }
+        [dir, rest @ ..] => {
+            let child = dict
+                .entry(dir.as_bytes().to_vec())
+                .or_insert_with(|| Value::Dict(BTreeMap::new()));
+            insert_path(child, rest, entry);
+        }
+    }
+}
+
+/// Inserts BEP 47 padding files so each file after the first starts on a piece boundary.
+fn with_padding(files: &[SourceFile], piece_length: u64) -> Vec<SourceFile> {
+    let mut padded = Vec::with_capacity(files.len() * 2);
+    let mut offset = 0;
+    for (index, file) in files.iter().enumerate() {
+        padded.push(file.clone());
+        offset += file.length;
+
+        let padding = (piece_length - offset % piece_length) % piece_length;
+        if padding > 0 && index + 1 < files.len() {
+            padded.push(SourceFile {
+                path: None,
+                components: vec![".pad".to_string(), padding.to_string()],
+                length: padding,
+            });
+            offset += padding;
+        }
+    }
+    padded
+}
+
+fn collect_files(root: &Path) -> Result<Vec<SourceFile>> {
+    let metadata = fs::metadata(root).with_context(|| format!("Failed to read {root:?}"))?;
+    if metadata.is_file() {
+        return Ok(vec![SourceFile {
+            path: Some(root.to_path_buf()),
+            components: Vec::new(),
+            length: metadata.len(),
+        }]);
+    }
+
+    let mut files = Vec::new();
+    walk_dir(root, &mut Vec::new(), &mut files)?;
+    if files.is_empty() {
+        bail!("Directory {root:?} contains no files");
+    }
+    Ok(files)
+}
+
+fn walk_dir(dir: &Path, prefix: &mut Vec<String>, files: &mut Vec<SourceFile>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory {dir:?}"))?
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|name| anyhow::anyhow!("File name {name:?} is not valid UTF-8"))?;
+        let path = entry.path();
+        // Symlinked directories are skipped: following them could loop forever or pull in
+        // content from outside the directory.
+        if entry.file_type()?.is_symlink() && fs::metadata(&path).is_ok_and(|meta| meta.is_dir()) {
+            continue;
+        }
+        let metadata = fs::metadata(&path)?;
+
+        prefix.push(name);
+        if metadata.is_dir() {
+            walk_dir(&path, prefix, files)?;
+        } else if metadata.is_file() {
+            files.push(SourceFile {
+                path: Some(path),
+                components: prefix.clone(),
+                length: metadata.len(),
+            });
+        }
+        prefix.pop();
+    }
+    Ok(())
+}
+
+/// Reads byte ranges of the concatenated content, crossing file boundaries as needed.
+struct SpanReader<'a> {
+    files: &'a [SourceFile],
+    open: Option<(usize, File)>,
+}
+
+impl<'a> SpanReader<'a> {
+    fn new(files: &'a [SourceFile]) -> Self {
+        Self { files, open: None }
+    }
+
+    fn read_at(&mut self, mut offset: u64, mut buf: &mut [u8]) -> Result<()> {
+        let mut file_start = 0;
+        for (index, file) in self.files.iter().enumerate() {
+            let file_end = file_start + file.length;
+            if buf.is_empty() {
+                break;
+            }
+            if offset < file_end {
+                let within = offset - file_start;
+                let len = buf.len().min((file_end - offset) as usize);
+
+                if let Some(path) = &file.path {
+                    let handle = match &mut self.open {
+                        Some((open_index, handle)) if *open_index == index => handle,
+                        open => {
+                            let handle = File::open(path)
+                                .with_context(|| format!("Failed to open {path:?}"))?;
+                            &mut open.insert((index, handle)).1
+                        }
+                    };
+                    handle.seek(SeekFrom::Start(within))?;
+                    handle
+                        .read_exact(&mut buf[..len])
+                        .with_context(|| format!("Failed to read {path:?}"))?;
+                } else {
+                    buf[..len].fill(0);
+                }
+
+                buf = &mut buf[len..];
+                offset += len as u64;
+            }
+            file_start = file_end;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use sha2::{Digest, Sha256};
+
+    use super::*;
+    use crate::file::TorrentFile;
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_symlinked_directories_and_names_dot_after_the_directory() {
+        let root = env::temp_dir().join(format!("terrent-builder-{}", process::id()));
+        let dir = root.join("content");
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        fs::write(dir.join("sub/file"), vec![1; 1000]).unwrap();
+        // A cycle back to the top, which must not be followed.
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+
+        let torrent = TorrentBuilder::new(dir.join("sub/.."))
+            .piece_length(MIN_PIECE_LENGTH)
+            .build()
+            .unwrap();
+        let torrent = TorrentFile::from_bytes(&torrent).unwrap();
+        assert_eq!(torrent.name(), "content");
+        let paths = torrent
+            .files()
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [Path::new("content/sub/file")]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn v2_torrents_open_again() {
+        let root = env::temp_dir().join(format!("terrent-builder-v2-{}", process::id()));
+        let dir = root.join("album");
+        fs::create_dir_all(dir.join("disc")).unwrap();
+        fs::write(dir.join("disc/track"), vec![1; 40_000]).unwrap();
+        fs::write(dir.join("cover"), vec![2; 1000]).unwrap();
+
+        let bytes = TorrentBuilder::new(&dir)
+            .format(TorrentFormat::V2)
+            .piece_length(MIN_PIECE_LENGTH)
+            .build()
+            .unwrap();
+        let torrent = TorrentFile::from_bytes(&bytes).unwrap();
+        assert_eq!(torrent.name(), "album");
+        assert_eq!(torrent.total_length(), 41_000);
+        assert_eq!(torrent.piece_count(), 0);
+        let paths = torrent
+            .files()
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [Path::new("album/cover"), Path::new("album/disc/track")]
+        );
+        let info_hash = &<Sha256 as Digest>::digest(torrent.info_bytes())[..20];
+        assert_eq!(torrent.info_hash(), info_hash);
+
+        let single = TorrentBuilder::new(dir.join("cover"))
+            .format(TorrentFormat::V2)
+            .build()
+            .unwrap();
+        let single = TorrentFile::from_bytes(&single).unwrap();
+        let paths = single
+            .files()
+            .map(|file| file.path.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, [Path::new("cover")]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+use std::collections::BTreeMap;
+
+/// A bencode value ready to be serialized.
+///
+/// Dictionaries are kept in a `BTreeMap` so keys are always emitted in the sorted raw-byte
+/// order the spec requires, which keeps hashes of encoded dictionaries stable.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Value {
+    Integer(i64),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Dict(BTreeMap<Vec<u8>, Value>),
+    /// Already-encoded bencode emitted verbatim, used to keep the info dictionary byte-exact.
+    Raw(Vec<u8>),
+}
+
+impl Value {
+    pub fn dict() -> Self {
+        Value::Dict(BTreeMap::new())
+    }
+
+    /// Inserts `value` under `key` when `self` is a dictionary; other variants are left untouched.
+    pub fn insert(&mut self, key: impl Into<Vec<u8>>, value: impl Into<Value>) {
+        if let Value::Dict(dict) = self {
+            dict.insert(key.into(), value.into());
+        }
+    }
+
+    pub fn with(mut self, key: impl Into<Vec<u8>>, value: impl Into<Value>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_to(&mut out);
+        out
+    }
+
+    pub fn encode_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Value::Integer(value) => {
+                out.push(b'i');
+                out.extend_from_slice(value.to_string().as_bytes());
+                out.push(b'e');
+            }
+            Value::Bytes(bytes) => encode_bytes(bytes, out),
+            Value::List(items) => {
+                out.push(b'l');
+                for item in items {
+                    item.encode_to(out);
+                }
+                out.push(b'e');
+            }
+            Value::Dict(dict) => {
+                out.push(b'd');
+                for (key, value) in dict {
+                    encode_bytes(key, out);
+                    value.encode_to(out);
+                }
+                out.push(b'e');
+            }
+            Value::Raw(bytes) => out.extend_from_slice(bytes),
+        }
+    }
+}
+
+fn encode_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(bytes.len().to_string().as_bytes());
+    out.push(b':');
+    out.extend_from_slice(bytes);
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Integer(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Self {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Bytes(value.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Bytes(value.into_bytes())
+    }
+}
+
+impl From<&[u8]> for Value {
+    fn from(value: &[u8]) -> Self {
+        Value::Bytes(value.to_vec())
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(value: Vec<u8>) -> Self {
+        Value::Bytes(value)
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(items: Vec<T>) -> Self {
+        Value::List(items.into_iter().map(Into::into).collect())
+    }
+}
@@ -0,0 +1,69 @@
+use std::path::PathBuf;
+
+/// A file of the torrent and where it sits in the concatenated piece stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    /// Path relative to the download directory; multi-file torrents start with the torrent name.
+    pub path: PathBuf,
+    pub length: u64,
+    /// Offset of the file's first byte in the piece stream.
+    pub offset: u64,
+    /// Raw `attr` flags (BEP 47), e.g. `p` for padding or `x` for executables.
+    pub attr: String,
+    /// Symlink target for `l` entries, relative to the download directory like `path`.
+    pub symlink: Option<PathBuf>,
+}
+
+impl FileEntry {
+    /// Padding files only align the next file to a piece boundary: they hold zeros, are hidden
+    /// from the file list, and are never written to disk.
+    pub fn is_padding(&self) -> bool {
+        self.attr.contains('p')
+    }
+
+    pub fn is_executable(&self) -> bool {
+        self.attr.contains('x')
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.attr.contains('h')
+    }
+
+    /// Symlinks carry no data; only the link itself is created once the torrent is written.
+    pub fn is_symlink(&self) -> bool {
+        self.attr.contains('l') && self.symlink.is_some()
+    }
+}
+
+/// The part of a piece that lives in one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileSpan<'a> {
+    pub file: &'a FileEntry,
+    pub file_offset: u64,
+    pub piece_offset: usize,
+    pub length: usize,
+}
+
+/// Maps `length` bytes starting at `start` in the piece stream onto the files they belong to.
+///
+/// Padding files are skipped; the bytes they cover are zeros and are left out of the result.
+///
+/// `length` is the size of an in-memory piece buffer, so every span offset into it fits a `usize`.
+pub fn spans(files: &[FileEntry], start: u64, length: usize) -> Vec<FileSpan<'_>> {
+    let end = start + length as u64;
+    files
+        .iter()
+        .filter(|file| !file.is_padding() && file.length > 0)
+        .filter(|file| file.offset < end && file.offset + file.length > start)
+        .map(|file| {
+            let from = start.max(file.offset);
+            let to = end.min(file.offset + file.length);
+            FileSpan {
+                file,
+                file_offset: from - file.offset,
+                piece_offset: (from - start) as usize,
+                length: (to - from) as usize,
+            }
+        })
+        .collect()
+}
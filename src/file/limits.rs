@@ -0,0 +1,266 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Bounds enforced while decoding untrusted `.torrent` data.
+///
+/// Real torrents stay far below the defaults; they only exist so a hostile file cannot exhaust
+/// memory or the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecodeLimits {
+    pub max_file_size: usize,
+    pub max_string_length: usize,
+    pub max_depth: usize,
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_file_size: 16 * 1024 * 1024,
+            max_string_length: 16 * 1024 * 1024,
+            max_depth: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    FileTooLarge {
+        limit: usize,
+    },
+    StringTooLong {
+        offset: usize,
+        length: u64,
+        limit: usize,
+    },
+    TooDeep {
+        offset: usize,
+        limit: usize,
+    },
+    Malformed {
+        offset: usize,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::FileTooLarge { limit } => {
+                write!(f, "Torrent exceeds the {limit} byte size limit")
+            }
+            DecodeError::StringTooLong {
+                offset,
+                length,
+                limit,
+            } => write!(
+                f,
+                "String of {length} bytes at offset {offset} exceeds the {limit} byte limit"
+            ),
+            DecodeError::TooDeep { offset, limit } => {
+                write!(
+                    f,
+                    "Nesting at offset {offset} exceeds the depth limit of {limit}"
+                )
+            }
+            DecodeError::Malformed { offset, reason } => {
+                write!(f, "Malformed bencode at offset {offset}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl DecodeLimits {
+    /// Walks the bencode structure once without allocating and checks it against the limits.
+    ///
+    /// Runs before the real decoder so oversized strings or deep nesting are rejected up front.
+    pub fn check(&self, bytes: &[u8]) -> Result<(), DecodeError> {
+        if bytes.len() > self.max_file_size {
+            return Err(DecodeError::FileTooLarge {
+                limit: self.max_file_size,
+            });
+        }
+
+        let mut depth = 0;
+        let mut pos = 0;
+        loop {
+            let Some(&token) = bytes.get(pos) else {
+                return Err(DecodeError::Malformed {
+                    offset: pos,
+                    reason: "unexpected end of input",
+                });
+            };
+
+            match token {
+                b'd' | b'l' => {
+                    depth += 1;
+                    if depth > self.max_depth {
+                        return Err(DecodeError::TooDeep {
+                            offset: pos,
+                            limit: self.max_depth,
+                        });
+                    }
+                    pos += 1;
+                    continue;
+                }
+                b'e' if depth > 0 => {
+                    depth -= 1;
+                    pos += 1;
+                }
+                b'i' => pos = skip_integer(bytes, pos)?,
+                b'0'..=b'9' => pos = self.skip_string(bytes, pos)?,
+                _ => {
+                    return Err(DecodeError::Malformed {
+                        offset: pos,
+                        reason: "unexpected token",
+                    });
+                }
+            }
+
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_string(&self, bytes: &[u8], start: usize) -> Result<usize, DecodeError> {
+        let colon = bytes[start..]
+            .iter()
+            .position(|byte| *byte == b':')
+            .map(|colon| start + colon)
+            .ok_or(DecodeError::Malformed {
+                offset: start,
+                reason: "unterminated string length",
+            })?;
+
+        let length = parse_number(&bytes[start..colon]).ok_or(DecodeError::Malformed {
+            offset: start,
+            reason: "invalid string length",
+        })?;
+        if length > self.max_string_length as u64 {
+            return Err(DecodeError::StringTooLong {
+                offset: start,
+                length,
+                limit: self.max_string_length,
+            });
+        }
+
+        let remaining = bytes.len() - (colon + 1);
+        match usize::try_from(length) {
+            Ok(length) if length <= remaining => Ok(colon + 1 + length),
+            _ => Err(DecodeError::Malformed {
+                offset: start,
+                reason: "string runs past the end of input",
+            }),
+        }
+    }
+}
+
+fn skip_integer(bytes: &[u8], start: usize) -> Result<usize, DecodeError> {
+    let end = bytes[start..]
+        .iter()
+        .position(|byte| *byte == b'e')
+        .map(|end| start + end)
+        .ok_or(DecodeError::Malformed {
+            offset: start,
+            reason: "unterminated integer",
+        })?;
+
+    if !is_valid_integer(&bytes[start + 1..end]) {
+        return Err(DecodeError::Malformed {
+            offset: start,
+            reason: "invalid integer",
+        });
+    }
+    Ok(end + 1)
+}
+
+/// Parses a non-empty run of ASCII digits, rejecting overflow.
+fn parse_number(digits: &[u8]) -> Option<u64> {
+    if digits.is_empty() {
+        return None;
+    }
+    digits.iter().try_fold(0u64, |number, digit| {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        number.checked_mul(10)?.checked_add(u64::from(digit - b'0'))
+    })
+}
+
+/// Whether the body of an `i…e` integer fits what the decoder reads integers into: a `u64`
+/// when positive, e.g. file lengths, and an `i64` when negative.
+fn is_valid_integer(digits: &[u8]) -> bool {
+    match digits.strip_prefix(b"-") {
+        Some(magnitude) => parse_number(magnitude)
+            .is_some_and(|magnitude| 0i64.checked_sub_unsigned(magnitude).is_some()),
+        None => parse_number(digits).is_some(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> DecodeLimits {
+        DecodeLimits {
+            max_file_size: 32,
+            max_string_length: 4,
+            max_depth: 3,
+        }
+    }
+
+    #[test]
+    fn limits_hold_at_the_boundary_and_fail_just_past_it() {
+        let limits = limits();
+        assert_eq!(limits.check(b"llli0eeee"), Ok(()));
+        assert_eq!(
+            limits.check(b"lllli0eeeee"),
+            Err(DecodeError::TooDeep {
+                offset: 3,
+                limit: 3
+            })
+        );
+
+        assert_eq!(limits.check(b"4:abcd"), Ok(()));
+        assert_eq!(
+            limits.check(b"5:abcde"),
+            Err(DecodeError::StringTooLong {
+                offset: 0,
+                length: 5,
+                limit: 4
+            })
+        );
+
+        let exact = format!("l{}e", "i0e".repeat(10));
+        assert_eq!(exact.len(), 32);
+        assert_eq!(limits.check(exact.as_bytes()), Ok(()));
+        let over = format!("l{}e", "i10e".repeat(8));
+        assert_eq!(
+            limits.check(over.as_bytes()),
+            Err(DecodeError::FileTooLarge { limit: 32 })
+        );
+    }
+
+    #[test]
+    fn integers_and_lengths_must_fit_64_bits() {
+        let limits = DecodeLimits::default();
+        assert_eq!(limits.check(b"i18446744073709551615e"), Ok(()));
+        assert_eq!(limits.check(b"i-9223372036854775808e"), Ok(()));
+        assert!(limits.check(b"i18446744073709551616e").is_err());
+        assert!(limits.check(b"i-9223372036854775809e").is_err());
+        assert!(limits.check(b"i-e").is_err());
+
+        assert!(matches!(
+            limits.check(b"18446744073709551615:x"),
+            Err(DecodeError::StringTooLong { .. })
+        ));
+        assert!(matches!(
+            limits.check(b"18446744073709551616:x"),
+            Err(DecodeError::Malformed { .. })
+        ));
+    }
+}
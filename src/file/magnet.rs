@@ -0,0 +1,144 @@
+use anyhow::{Context, Result, bail, ensure};
+use sha1::{Digest, Sha1};
+use url::Url;
+
+use super::encoder::Value;
+use super::torrent_file::TorrentFile;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagnetLink {
+    pub info_hash: [u8; 20],
+    pub display_name: Option<String>,
+    pub trackers: Vec<String>,
+    pub peers: Vec<String>,
+}
+
+impl MagnetLink {
+    pub fn parse(link: &str) -> Result<Self> {
+        let url = Url::parse(link).context("Invalid magnet link")?;
+        if url.scheme() != "magnet" {
+            bail!("Expected a magnet link, got scheme '{}'", url.scheme());
+        }
+
+        let mut info_hash = None;
+        let mut display_name = None;
+        let mut trackers = Vec::new();
+        let mut peers = Vec::new();
+
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "xt" => {
+                    if let Some(hash) = value.strip_prefix("urn:btih:") {
+                        info_hash = Some(decode_info_hash(hash)?);
+                    }
+                }
+                "dn" => display_name = Some(value.into_owned()),
+                "tr" => trackers.push(value.into_owned()),
+                "x.pe" => peers.push(value.into_owned()),
+                _ => {}
+            }
+        }
+
+        let Some(info_hash) = info_hash else {
+            bail!("Magnet link has no 'urn:btih' exact topic");
+        };
+
+        Ok(Self {
+            info_hash,
+            display_name,
+            trackers,
+            peers,
+        })
+    }
+
+    /// Builds the torrent from `info`, the info dictionary fetched from peers (BEP 9),
+    /// with the link's trackers each in a tier of its own.
+    pub fn to_torrent_file(&self, info: &[u8]) -> Result<TorrentFile> {
+        let hash: [u8; 20] = Sha1::digest(info).into();
+        ensure!(
+            hash == self.info_hash,
+            "Metadata does not match the magnet link"
+        );
+
+        let mut torrent = Value::dict().with("info", Value::Raw(info.to_vec()));
+        if let Some(announce) = self.trackers.first() {
+            torrent.insert("announce", announce.as_str());
+            let tiers: Vec<Vec<String>> = self
+                .trackers
+                .iter()
+                .map(|tracker| vec![tracker.clone()])
+                .collect();
+            torrent.insert("announce-list", tiers);
+        }
+        TorrentFile::from_bytes(&torrent.encode()).context("Invalid metadata from peers")
+    }
+}
+
+fn decode_info_hash(encoded: &str) -> Result<[u8; 20]> {
+    let mut hash = [0u8; 20];
+    match encoded.len() {
+        40 => {
+            // `from_str_radix` takes a leading `+`, so the digits are checked first.
+            if !encoded.bytes().all(|c| c.is_ascii_hexdigit()) {
+                bail!("Invalid hex info hash '{encoded}'");
+            }
+            for (byte, pair) in hash.iter_mut().zip(encoded.as_bytes().chunks(2)) {
+                let pair = std::str::from_utf8(pair)?;
+                *byte = u8::from_str_radix(pair, 16)
+                    .with_context(|| format!("Invalid hex info hash '{encoded}'"))?;
+            }
+        }
+        32 => {
+            let mut buffer = 0u16;
+            let mut bits = 0;
+            let mut written = 0;
+            for c in encoded.bytes() {
+                let Some(value) = BASE32_ALPHABET
+                    .iter()
+                    .position(|a| *a == c.to_ascii_uppercase())
+                else {
+                    bail!("Invalid base32 info hash '{encoded}'");
+                };
+                buffer = (buffer << 5) | value as u16;
+                bits += 5;
+                if bits >= 8 {
+                    bits -= 8;
+                    hash[written] = (buffer >> bits) as u8;
+                    written += 1;
+                }
+            }
+        }
+        len => bail!("Info hash must be 40 hex or 32 base32 characters, got {len}"),
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::hex;
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn rejects_signs_in_hex_info_hashes() {
+        let hash = format!("+{}", "a".repeat(39));
+        assert!(MagnetLink::parse(&format!("magnet:?xt=urn:btih:{hash}")).is_err());
+    }
+
+    #[test]
+    fn builds_the_torrent_from_fetched_metadata() {
+        let torrent = SyntheticTorrent::single("fetched", 1024, 16 * 1024).torrent;
+        let link = format!(
+            "magnet:?xt=urn:btih:{}&tr=http://one.example/announce&tr=udp://two.example:80",
+            hex(&torrent.info_hash())
+        );
+        let magnet = MagnetLink::parse(&link).unwrap();
+
+        let fetched = magnet.to_torrent_file(torrent.info_bytes()).unwrap();
+        assert_eq!(fetched.info_hash(), torrent.info_hash());
+        assert_eq!(fetched.trackers().len(), 2);
+        assert!(magnet.to_torrent_file(b"de").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use sha2::{Digest, Sha256};
+
+/// BitTorrent v2 hashes files in 16 KiB leaf blocks (BEP 52).
+pub const MERKLE_BLOCK_SIZE: u64 = 16 * 1024;
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Merkle hashes of a single file as stored in a v2 torrent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHashes {
+    pub pieces_root: [u8; 32],
+    /// Hashes at piece granularity; empty for files no larger than one piece.
+    pub piece_layer: Vec<[u8; 32]>,
+}
+
+/// Builds every layer of the tree, bottom-up, padding the leaves with zero hashes
+/// to a power of two.
+pub fn layers(mut leaves: Vec<[u8; 32]>) -> Vec<Vec<[u8; 32]>> {
+    leaves.resize(leaves.len().max(1).next_power_of_two(), [0; 32]);
+
+    let mut layers = vec![leaves];
+    while let Some(layer) = layers.last().filter(|layer| layer.len() > 1) {
+        let parent = layer
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair[1]);
+                hasher.finalize().into()
+            })
+            .collect();
+        layers.push(parent);
+    }
+    layers
+}
+
+/// Computes the pieces root and piece layer of a file from its 16 KiB leaf hashes.
+pub fn file_hashes(leaves: Vec<[u8; 32]>, file_length: u64, piece_length: u64) -> FileHashes {
+    let layers = layers(leaves);
+    let pieces_root = layers.last().expect("tree has a root")[0];
+
+    let piece_layer = if file_length > piece_length {
+        let height = (piece_length / MERKLE_BLOCK_SIZE).trailing_zeros() as usize;
+        let piece_count = file_length.div_ceil(piece_length) as usize;
+        layers[height][..piece_count].to_vec()
+    } else {
+        Vec::new()
+    };
+
+    FileHashes {
+        pieces_root,
+        piece_layer,
+    }
+}
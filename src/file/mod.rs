@@ -0,0 +1,16 @@
+pub mod bencode;
+pub mod builder;
+pub mod encoder;
+pub mod layout;
+pub mod limits;
+pub mod magnet;
+pub mod merkle;
+mod torrent_file;
+pub mod validation;
+
+pub use builder::{TorrentBuilder, TorrentFormat};
+pub use layout::{FileEntry, FileSpan};
+pub use limits::{DecodeError, DecodeLimits};
+pub use magnet::MagnetLink;
+pub use torrent_file::{DhtNode, InfoHashChange, TorrentFile};
+pub use validation::{TorrentValidationError, ValidationIssue};
@@ -0,0 +1,591 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+use reqwest::{Client, RequestBuilder};
+
+use super::bencode::{self, BencodeTorrent};
+use super::encoder::Value;
+use super::layout::{self, FileEntry, FileSpan};
+use super::limits::{DecodeError, DecodeLimits};
+use super::validation::{self, ValidationIssue};
+use crate::metadata::Metadata;
+use crate::queue::TorrentState;
+use crate::stats::TransferStats;
+
+/// Top-level keys written from the fields of [`TorrentFile`] rather than copied as read.
+const EDITABLE_KEYS: [&str; 9] = [
+    "info",
+    "announce",
+    "announce-list",
+    "nodes",
+    "url-list",
+    "created by",
+    "creation date",
+    "comment",
+    "encoding",
+];
+
+/// DHT node listed in a torrent's `nodes` key, used to bootstrap trackerless torrents.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DhtNode {
+    pub host: String,
+    pub port: u16,
+}
+
+impl DhtNode {
+    pub fn resolve(&self) -> io::Result<Vec<SocketAddr>> {
+        Ok((self.host.as_str(), self.port).to_socket_addrs()?.collect())
+    }
+}
+
+/// Whether an edit may touch the info dictionary, giving the torrent a new info hash and
+/// thereby a different swarm.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InfoHashChange {
+    #[default]
+    Refuse,
+    Allow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentFile {
+    announce: Option<String>,
+    announce_list: Vec<Vec<String>>,
+    nodes: Vec<DhtNode>,
+    info_hash: [u8; 20],
+    info_bytes: Vec<u8>,
+    piece_hashes: Vec<[u8; 20]>,
+    piece_length: u64,
+    length: u64,
+    files: Vec<FileEntry>,
+    name: String,
+    private: Option<usize>,
+    source: Option<String>,
+    url_list: Vec<String>,
+    created_by: Option<String>,
+    creation_date: Option<u64>,
+    comment: Option<String>,
+    encoding: Option<String>,
+    warnings: Vec<ValidationIssue>,
+    /// Whether the content is only described by a v2 `file tree`, which changes how the info
+    /// hash is derived.
+    v2_only: bool,
+    /// Top-level entries as read, including keys this type does not model.
+    entries: BTreeMap<Vec<u8>, Value>,
+}
+
+impl TorrentFile {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_with_limits(path, &DecodeLimits::default())
+    }
+
+    pub fn open_with_limits(path: impl AsRef<Path>, limits: &DecodeLimits) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("Failed to read {path:?}"))?;
+        Self::from_reader_with_limits(file, limits)
+            .with_context(|| format!("Invalid torrent {path:?}"))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        Self::from_bytes_with_limits(bytes, &DecodeLimits::default())
+    }
+
+    pub fn from_bytes_with_limits(bytes: &[u8], limits: &DecodeLimits) -> Result<Self> {
+        BencodeTorrent::from_bytes_with_limits(bytes, limits)?.to_torrent_file()
+    }
+
+    /// Reads a torrent from any reader, e.g. stdin.
+    pub fn from_reader(reader: impl Read) -> Result<Self> {
+        Self::from_reader_with_limits(reader, &DecodeLimits::default())
+    }
+
+    /// Reads at most `limits.max_file_size` bytes, so an endless reader cannot exhaust memory.
+    pub fn from_reader_with_limits(reader: impl Read, limits: &DecodeLimits) -> Result<Self> {
+        let mut bytes = Vec::new();
+        reader
+            .take(limits.max_file_size as u64 + 1)
+            .read_to_end(&mut bytes)
+            .context("Failed to read torrent")?;
+        Self::from_bytes_with_limits(&bytes, limits)
+    }
+
+    /// Downloads a torrent over HTTP(S).
+    pub async fn from_url(url: &str) -> Result<Self> {
+        Self::from_url_with_limits(url, &DecodeLimits::default()).await
+    }
+
+    pub async fn from_url_with_limits(url: &str, limits: &DecodeLimits) -> Result<Self> {
+        Self::from_request(Client::new().get(url), limits).await
+    }
+
+    /// Sends `request`, e.g. one carrying a private tracker's cookies, and decodes the response,
+    /// aborting as soon as it grows past `limits.max_file_size`.
+    pub async fn from_request(request: RequestBuilder, limits: &DecodeLimits) -> Result<Self> {
+        let too_large = DecodeError::FileTooLarge {
+            limit: limits.max_file_size,
+        };
+        let mut response = request
+            .send()
+            .await
+            .context("Failed to fetch torrent")?
+            .error_for_status()?;
+        let url = response.url().clone();
+
+        if response
+            .content_length()
+            .is_some_and(|length| length > limits.max_file_size as u64)
+        {
+            return Err(too_large).with_context(|| format!("Invalid torrent at {url}"));
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if bytes.len() + chunk.len() > limits.max_file_size {
+                return Err(too_large).with_context(|| format!("Invalid torrent at {url}"));
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        Self::from_bytes_with_limits(&bytes, limits)
+            .with_context(|| format!("Invalid torrent at {url}"))
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn info_hash(&self) -> [u8; 20] {
+        self.info_hash
+    }
+
+    /// The info dictionary exactly as it appeared in the source, e.g. to serve it over BEP 9.
+    pub fn info_bytes(&self) -> &[u8] {
+        &self.info_bytes
+    }
+
+    pub fn piece_hashes(&self) -> &[[u8; 20]] {
+        &self.piece_hashes
+    }
+
+    /// Size of the piece stream, including any padding files.
+    pub fn total_length(&self) -> u64 {
+        self.length
+    }
+
+    pub fn piece_length(&self) -> u64 {
+        self.piece_length
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.piece_hashes.len()
+    }
+
+    /// Length of piece `index`; only the last piece may be shorter than `piece_length`.
+    pub fn piece_size(&self, index: usize) -> Option<u64> {
+        if index >= self.piece_count() {
+            return None;
+        }
+        let start = index as u64 * self.piece_length;
+        Some(self.piece_length.min(self.length.saturating_sub(start)))
+    }
+
+    pub fn is_private(&self) -> bool {
+        self.private == Some(1)
+    }
+
+    pub fn source(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn created_by(&self) -> Option<&str> {
+        self.created_by.as_deref()
+    }
+
+    pub fn creation_date(&self) -> Option<u64> {
+        self.creation_date
+    }
+
+    pub fn encoding(&self) -> Option<&str> {
+        self.encoding.as_deref()
+    }
+
+    /// Non-fatal validation issues, e.g. a piece length that is not a power of two.
+    pub fn warnings(&self) -> &[ValidationIssue] {
+        &self.warnings
+    }
+
+    /// Nodes to seed the DHT routing table with, e.g. for torrents without trackers.
+    pub fn nodes(&self) -> &[DhtNode] {
+        &self.nodes
+    }
+
+    /// Tracker tiers, falling back to a single tier holding `announce` for older torrents.
+    pub fn trackers(&self) -> Vec<Vec<String>> {
+        if !self.announce_list.is_empty() {
+            return self.announce_list.clone();
+        }
+        self.announce.iter().map(|url| vec![url.clone()]).collect()
+    }
+
+    /// Appends `url` as a new lowest-priority tier, unless it is already listed.
+    ///
+    /// Like every editing method this leaves the info dictionary alone, so the info hash of the
+    /// saved torrent stays the same.
+    pub fn add_tracker(&mut self, url: impl Into<String>) {
+        let url = url.into();
+        let mut tiers = self.trackers();
+        if tiers.iter().flatten().any(|tracker| *tracker == url) {
+            return;
+        }
+        tiers.push(vec![url]);
+        self.set_trackers(tiers);
+    }
+
+    /// Removes `url` from every tier; returns whether it was listed.
+    pub fn remove_tracker(&mut self, url: &str) -> bool {
+        let mut tiers = self.trackers();
+        let before = tiers.iter().flatten().count();
+        for tier in &mut tiers {
+            tier.retain(|tracker| tracker != url);
+        }
+        let removed = tiers.iter().flatten().count() != before;
+        self.set_trackers(tiers);
+        removed
+    }
+
+    /// Replaces all trackers, dropping empty tiers and keeping `announce` on the first tracker
+    /// for clients that ignore `announce-list`.
+    pub fn set_trackers(&mut self, mut tiers: Vec<Vec<String>>) {
+        tiers.retain(|tier| !tier.is_empty());
+        self.announce = tiers.first().map(|tier| tier[0].clone());
+        self.announce_list = if tiers.len() == 1 && tiers[0].len() == 1 {
+            Vec::new()
+        } else {
+            tiers
+        };
+    }
+
+    /// Files shown to the user and written to disk; BEP 47 padding files are left out.
+    pub fn files(&self) -> impl Iterator<Item = &FileEntry> {
+        self.files.iter().filter(|file| !file.is_padding())
+    }
+
+    /// Where the bytes of piece `index` live on disk, skipping padding.
+    pub fn piece_spans(&self, index: usize) -> Vec<FileSpan<'_>> {
+        // A piece that does not fit in memory cannot be read or written anyway.
+        let Some(length) = self
+            .piece_size(index)
+            .and_then(|length| usize::try_from(length).ok())
+        else {
+            return Vec::new();
+        };
+        layout::spans(&self.files, index as u64 * self.piece_length, length)
+    }
+
+    pub fn web_seeds(&self) -> &[String] {
+        &self.url_list
+    }
+
+    pub fn add_web_seed(&mut self, url: impl Into<String>) {
+        let url = url.into();
+        if !self.url_list.contains(&url) {
+            self.url_list.push(url);
+        }
+    }
+
+    /// Removes a web seed; returns whether it was listed.
+    pub fn remove_web_seed(&mut self, url: &str) -> bool {
+        let before = self.url_list.len();
+        self.url_list.retain(|seed| seed != url);
+        self.url_list.len() != before
+    }
+
+    pub fn clear_web_seeds(&mut self) {
+        self.url_list.clear();
+    }
+
+    pub fn set_comment(&mut self, comment: Option<String>) {
+        self.comment = comment;
+    }
+
+    /// Drops the comment, creator, and creation date, e.g. before re-publishing a torrent.
+    pub fn strip_metadata(&mut self) {
+        self.comment = None;
+        self.created_by = None;
+        self.creation_date = None;
+    }
+
+    /// Sets or clears the private flag (BEP 27).
+    ///
+    /// The flag lives in the info dictionary, so changing it is refused unless `change` is
+    /// [`InfoHashChange::Allow`]; setting it to its current value always succeeds.
+    pub fn set_private(&mut self, private: bool, change: InfoHashChange) -> Result<()> {
+        if self.is_private() == private {
+            return Ok(());
+        }
+        if change == InfoHashChange::Refuse {
+            bail!("Changing the private flag would change the info hash");
+        }
+
+        let mut info = bencode::raw_entries(&self.info_bytes)?;
+        if private {
+            info.insert(b"private".to_vec(), Value::Integer(1));
+        } else {
+            info.remove(b"private".as_slice());
+        }
+        self.info_bytes = Value::Dict(info).encode();
+        self.info_hash = bencode::info_hash(&self.info_bytes, self.v2_only);
+        self.private = private.then_some(1);
+        Ok(())
+    }
+
+    /// Encodes the torrent back into `.torrent` form, keeping the info dictionary and every
+    /// key this type does not model byte-exact.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut torrent = Value::Dict(self.entries.clone());
+        if let Value::Dict(entries) = &mut torrent {
+            for key in EDITABLE_KEYS {
+                entries.remove(key.as_bytes());
+            }
+        }
+        torrent.insert("info", Value::Raw(self.info_bytes.clone()));
+        if let Some(announce) = &self.announce {
+            torrent.insert("announce", announce.as_str());
+        }
+        if !self.announce_list.is_empty() {
+            torrent.insert("announce-list", self.announce_list.clone());
+        }
+        if !self.nodes.is_empty() {
+            let nodes = self
+                .nodes
+                .iter()
+                .map(|node| {
+                    Value::List(vec![node.host.as_str().into(), u64::from(node.port).into()])
+                })
+                .collect::<Vec<_>>();
+            torrent.insert("nodes", nodes);
+        }
+        if !self.url_list.is_empty() {
+            torrent.insert("url-list", self.url_list.clone());
+        }
+        if let Some(created_by) = &self.created_by {
+            torrent.insert("created by", created_by.as_str());
+        }
+        if let Some(creation_date) = self.creation_date {
+            torrent.insert("creation date", creation_date);
+        }
+        if let Some(comment) = &self.comment {
+            torrent.insert("comment", comment.as_str());
+        }
+        if let Some(encoding) = &self.encoding {
+            torrent.insert("encoding", encoding.as_str());
+        }
+        torrent.encode()
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()).with_context(|| format!("Failed to write {path:?}"))
+    }
+}
+
+impl BencodeTorrent {
+    /// Converts the decoded torrent, rejecting it when validation finds fatal issues.
+    pub fn to_torrent_file(&self) -> Result<TorrentFile> {
+        let warnings = validation::validate(&self.info)?;
+        let info_bytes = if self.info_bytes.is_empty() {
+            self.info.to_value().encode()
+        } else {
+            self.info_bytes.clone()
+        };
+
+        Ok(TorrentFile {
+            announce: self.announce.clone(),
+            announce_list: self.announce_list.clone(),
+            nodes: self
+                .nodes
+                .iter()
+                .map(|(host, port)| DhtNode {
+                    host: host.clone(),
+                    port: *port,
+                })
+                .collect(),
+            info_hash: self.info_hash(),
+            info_bytes,
+            piece_hashes: self.info.split_piece_hashes()?,
+            piece_length: self.info.piece_length,
+            length: self
+                .info
+                .total_length()
+                .context("File lengths overflow the total length")?,
+            files: self.file_entries()?,
+            name: self.info.name(),
+            private: self.info.private,
+            source: self
+                .info
+                .source
+                .as_ref()
+                .map(|source| String::from_utf8_lossy(source).into_owned()),
+            url_list: self
+                .url_list
+                .clone()
+                .map(|urls| urls.into_vec())
+                .unwrap_or_default(),
+            created_by: self.created_by.clone(),
+            creation_date: self.creation_date,
+            comment: self.comment.clone(),
+            encoding: self.encoding.clone(),
+            warnings,
+            v2_only: self.info.is_v2_only(),
+            entries: self.entries.clone(),
+        })
+    }
+}
+
+impl BencodeTorrent {
+    fn file_entries(&self) -> Result<Vec<FileEntry>> {
+        let name = self.info.name();
+        if self.info.is_v2_only() {
+            return self.v2_file_entries(&name);
+        }
+        let Some(files) = &self.info.files else {
+            let length = self
+                .info
+                .length
+                .context("Info has neither length nor files")?;
+            return Ok(vec![FileEntry {
+                path: PathBuf::from(&name),
+                length,
+                offset: 0,
+                attr: String::new(),
+                symlink: None,
+            }]);
+        };
+
+        let mut offset = 0;
+        let mut entries = Vec::with_capacity(files.len());
+        for file in files {
+            let mut path = PathBuf::from(&name);
+            path.extend(file.path());
+            let symlink = file.symlink_path().map(|target| {
+                let mut link = PathBuf::from(&name);
+                link.extend(target);
+                link
+            });
+            entries.push(FileEntry {
+                path,
+                length: file.length,
+                offset,
+                attr: file.attr(),
+                symlink,
+            });
+            offset = offset
+                .checked_add(file.length)
+                .context("File lengths overflow the total length")?;
+        }
+        Ok(entries)
+    }
+
+    /// Files of a v2-only torrent laid end to end; a lone file at the top of the tree is the
+    /// torrent itself rather than a file inside a directory.
+    fn v2_file_entries(&self, name: &str) -> Result<Vec<FileEntry>> {
+        let files = self.info.v2_files();
+        let single_file = matches!(files.as_slice(), [(path, _)] if path.len() == 1);
+
+        let mut offset = 0;
+        let mut entries = Vec::with_capacity(files.len());
+        for (components, file) in files {
+            let path = if single_file {
+                PathBuf::from(name)
+            } else {
+                let mut path = PathBuf::from(name);
+                path.extend(components);
+                path
+            };
+            entries.push(FileEntry {
+                path,
+                length: file.length,
+                offset,
+                attr: String::new(),
+                symlink: None,
+            });
+            offset = offset
+                .checked_add(file.length)
+                .context("File lengths overflow the total length")?;
+        }
+        Ok(entries)
+    }
+}
+
+impl From<&TorrentFile> for Metadata {
+    fn from(torrent: &TorrentFile) -> Self {
+        Metadata {
+            name: torrent.name.clone(),
+            info_hash: torrent.info_hash,
+            length: torrent.length,
+            piece_length: torrent.piece_length,
+            pieces: torrent.piece_hashes.clone(),
+            private: torrent.private,
+            source: torrent.source.clone(),
+            announce: torrent.trackers().into_iter().flatten().collect(),
+            web_seeds: torrent.url_list.clone(),
+            created_by: torrent.created_by.clone(),
+            creation_date: torrent.creation_date,
+            comment: torrent.comment.clone(),
+            encoding: torrent.encoding.clone(),
+            label: None,
+            added: None,
+            origin: None,
+            state: TorrentState::default(),
+            sequential: false,
+            stats: TransferStats::default(),
+            peers: Vec::new(),
+            left: None,
+            swarm: None,
+            trackers: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn edits_keep_unknown_keys() {
+        let synthetic = SyntheticTorrent::single("kept", 40_000, 16 * 1024);
+        let mut entries = bencode::raw_entries(&synthetic.torrent.to_bytes()).unwrap();
+        let layers = Value::dict().with([7u8; 32].as_slice(), [8u8; 64].as_slice());
+        entries.insert(b"piece layers".to_vec(), layers.clone());
+        entries.insert(b"x-custom".to_vec(), Value::Raw(b"li1ei2ee".to_vec()));
+        let mut torrent = TorrentFile::from_bytes(&Value::Dict(entries).encode()).unwrap();
+
+        torrent.add_tracker("http://tracker.example/announce");
+        torrent.set_comment(Some("edited".to_string()));
+        let saved = bencode::raw_entries(&torrent.to_bytes()).unwrap();
+        assert_eq!(
+            saved[b"piece layers".as_slice()],
+            Value::Raw(layers.encode())
+        );
+        assert_eq!(
+            saved[b"x-custom".as_slice()],
+            Value::Raw(b"li1ei2ee".to_vec())
+        );
+        assert_eq!(
+            saved[b"comment".as_slice()],
+            Value::Bytes(b"edited".to_vec())
+        );
+
+        let reopened = TorrentFile::from_bytes(&torrent.to_bytes()).unwrap();
+        assert_eq!(reopened.info_hash(), synthetic.torrent.info_hash());
+        assert_eq!(reopened.comment(), Some("edited"));
+    }
+}
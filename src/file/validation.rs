@@ -0,0 +1,238 @@
+use std::fmt;
+use std::path::{Component, Path};
+
+use super::bencode::BencodeInfo;
+
+/// Pieces below this are legal but waste bandwidth on per-piece overhead.
+const MIN_SANE_PIECE_LENGTH: u64 = 16 * 1024;
+
+/// Inconsistency found in a decoded info dictionary.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ValidationIssue {
+    ZeroPieceLength,
+    /// Allowed, but no mainstream client creates such torrents.
+    PieceLengthNotPowerOfTwo(u64),
+    PieceLengthTooSmall(u64),
+    PieceCountMismatch {
+        expected: u64,
+        actual: usize,
+    },
+    /// The file lengths add up to more than a `u64` holds.
+    LengthOverflow,
+    /// The name would escape the download directory or is not a single path component.
+    UnsafeName(String),
+    UnsafeFilePath(Vec<String>),
+    /// A BEP 47 link target that is empty or not made of plain components, so the link
+    /// could point outside the torrent.
+    UnsafeSymlinkPath(Vec<String>),
+}
+
+impl ValidationIssue {
+    /// Warnings are reported but do not prevent opening the torrent.
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            ValidationIssue::PieceLengthNotPowerOfTwo(_) | ValidationIssue::PieceLengthTooSmall(_)
+        )
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::ZeroPieceLength => write!(f, "Piece length is zero"),
+            ValidationIssue::PieceLengthNotPowerOfTwo(length) => {
+                write!(f, "Piece length {length} is not a power of two")
+            }
+            ValidationIssue::PieceLengthTooSmall(length) => write!(
+                f,
+                "Piece length {length} is below {MIN_SANE_PIECE_LENGTH} bytes"
+            ),
+            ValidationIssue::PieceCountMismatch { expected, actual } => write!(
+                f,
+                "Torrent has {actual} piece hashes but its length needs {expected}"
+            ),
+            ValidationIssue::LengthOverflow => write!(f, "File lengths overflow the total length"),
+            ValidationIssue::UnsafeName(name) => write!(f, "Unsafe torrent name {name:?}"),
+            ValidationIssue::UnsafeFilePath(path) => write!(f, "Unsafe file path {path:?}"),
+            ValidationIssue::UnsafeSymlinkPath(path) => {
+                write!(f, "Unsafe symlink target {path:?}")
+            }
+        }
+    }
+}
+
+/// Every fatal issue found in a torrent, so all of them can be reported at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentValidationError {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl fmt::Display for TorrentValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Torrent failed validation:")?;
+        for issue in &self.issues {
+            write!(f, "\n  - {issue}")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for TorrentValidationError {}
+
+/// Checks the info dictionary for consistency; on success returns the warnings found.
+pub fn validate(info: &BencodeInfo) -> Result<Vec<ValidationIssue>, TorrentValidationError> {
+    let mut issues = Vec::new();
+
+    let total_length = info.total_length();
+    if total_length.is_none() {
+        issues.push(ValidationIssue::LengthOverflow);
+    }
+    match info.piece_length {
+        0 => issues.push(ValidationIssue::ZeroPieceLength),
+        length => {
+            if !length.is_power_of_two() {
+                issues.push(ValidationIssue::PieceLengthNotPowerOfTwo(length));
+            }
+            if length < MIN_SANE_PIECE_LENGTH {
+                issues.push(ValidationIssue::PieceLengthTooSmall(length));
+            }
+
+            // v2-only torrents hash each file separately and carry no SHA-1 pieces.
+            if let Some(total_length) = total_length
+                && !info.is_v2_only()
+            {
+                let expected = total_length.div_ceil(length);
+                let actual = info.pieces.len() / 20;
+                if expected != actual as u64 {
+                    issues.push(ValidationIssue::PieceCountMismatch { expected, actual });
+                }
+            }
+        }
+    }
+
+    let name = info.name();
+    if !is_safe_component(&name) {
+        issues.push(ValidationIssue::UnsafeName(name));
+    }
+    for file in info.files.iter().flatten() {
+        let path = file.path();
+        if path.is_empty() || !path.iter().all(|component| is_safe_component(component)) {
+            issues.push(ValidationIssue::UnsafeFilePath(path));
+        }
+        if let Some(target) = file.symlink_path()
+            && (target.is_empty() || !target.iter().all(|component| is_safe_component(component)))
+        {
+            issues.push(ValidationIssue::UnsafeSymlinkPath(target));
+        }
+    }
+    for (path, _) in info.v2_files() {
+        if !path.iter().all(|component| is_safe_component(component)) {
+            issues.push(ValidationIssue::UnsafeFilePath(path));
+        }
+    }
+
+    let (warnings, errors): (Vec<_>, Vec<_>) =
+        issues.into_iter().partition(ValidationIssue::is_warning);
+    if errors.is_empty() {
+        Ok(warnings)
+    } else {
+        Err(TorrentValidationError { issues: errors })
+    }
+}
+
+/// A single plain path component: no `..`, no root or drive prefix, no separators.
+fn is_safe_component(component: &str) -> bool {
+    let mut components = Path::new(component).components();
+    let single = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+    let drive = matches!(component.as_bytes(), [letter, b':', ..] if letter.is_ascii_alphabetic());
+    single && !drive && !component.contains(['/', '\\'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file::bencode::BencodeTorrent;
+    use crate::file::encoder::Value;
+
+    #[test]
+    fn rejects_lengths_that_overflow() {
+        // Written out by hand: the encoder only holds integers up to `i64::MAX`.
+        let half = u64::MAX / 2 + 1;
+        let file = |name| format!("d6:lengthi{half}e4:pathl1:{name}ee");
+        let mut encoded = format!(
+            "d4:infod5:filesl{}{}e4:name4:huge12:piece lengthi{}e6:pieces40:",
+            file("a"),
+            file("b"),
+            1u64 << 62
+        )
+        .into_bytes();
+        encoded.extend([0; 40]);
+        encoded.extend(b"ee");
+
+        let torrent = BencodeTorrent::from_bytes(&encoded).unwrap();
+        assert_eq!(torrent.info.total_length(), None);
+        let error = validate(&torrent.info).unwrap_err();
+        assert!(error.issues.contains(&ValidationIssue::LengthOverflow));
+    }
+
+    #[test]
+    fn rejects_symlink_targets_that_leave_the_torrent() {
+        let torrent = |target: &str| {
+            let info = Value::dict()
+                .with(
+                    "files",
+                    vec![
+                        Value::dict()
+                            .with("attr", "l")
+                            .with("length", 0u64)
+                            .with("path", vec!["link"])
+                            .with("symlink path", target.split('|').collect::<Vec<_>>()),
+                    ],
+                )
+                .with("name", "linked")
+                .with("piece length", 16384u64)
+                .with("pieces", "");
+            BencodeTorrent::from_bytes(&Value::dict().with("info", info).encode()).unwrap()
+        };
+
+        assert!(validate(&torrent("dir|file").info).is_ok());
+        for escaping in ["..|..|etc|passwd", "/etc/passwd", "", "a/../../b"] {
+            let error = validate(&torrent(escaping).info).unwrap_err();
+            assert!(
+                matches!(error.issues[..], [ValidationIssue::UnsafeSymlinkPath(_)]),
+                "{escaping:?} gave {error}"
+            );
+        }
+    }
+
+    #[test]
+    fn only_plain_components_are_safe() {
+        for safe in ["file.txt", "..hidden", "a..b", "C", "name with spaces"] {
+            assert!(is_safe_component(safe), "{safe:?} should be safe");
+        }
+        for unsafe_component in [
+            "",
+            ".",
+            "..",
+            "/",
+            "/etc",
+            "/etc/passwd",
+            "a/b",
+            "a\\b",
+            "..\\x",
+            "C:",
+            "C:evil",
+            "c:\\windows",
+            "\\\\server\\share",
+        ] {
+            assert!(
+                !is_safe_component(unsafe_component),
+                "{unsafe_component:?} should be unsafe"
+            );
+        }
+    }
+}
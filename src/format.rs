@@ -0,0 +1,195 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// Prefixes used to display byte counts.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnitSystem {
+    /// Powers of 1024: KiB, MiB, GiB.
+    #[default]
+    Iec,
+    /// Powers of 1000: kB, MB, GB.
+    Si,
+}
+
+impl UnitSystem {
+    fn base(self) -> f64 {
+        match self {
+            UnitSystem::Iec => 1024.0,
+            UnitSystem::Si => 1000.0,
+        }
+    }
+
+    fn units(self) -> [&'static str; 7] {
+        match self {
+            UnitSystem::Iec => ["B", "KiB", "MiB", "GiB", "TiB", "PiB", "EiB"],
+            UnitSystem::Si => ["B", "kB", "MB", "GB", "TB", "PB", "EB"],
+        }
+    }
+}
+
+/// Lowercase hex digits of `bytes`, e.g. for info hashes and digests.
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Renders `bytes` with three significant digits, e.g. `1.50 KiB`, `97.7 KiB`, `512 MiB`.
+/// Counts below one kilobyte are shown exactly.
+pub fn format_size(bytes: u64, system: UnitSystem) -> String {
+    let base = system.base();
+    let units = system.units();
+    if (bytes as f64) < base {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    // Also move up once rounding would print the base itself, e.g. 1023.7 KiB as 1024 KiB.
+    while size >= base - 0.5 && unit < units.len() - 1 {
+        size /= base;
+        unit += 1;
+    }
+    format!("{size:.*} {}", decimals(size), units[unit])
+}
+
+/// Renders a transfer rate, e.g. `1.50 KiB/s`.
+pub fn format_rate(bytes_per_second: u64, system: UnitSystem) -> String {
+    format!("{}/s", format_size(bytes_per_second, system))
+}
+
+/// Renders a duration in its two largest units, e.g. `45s`, `12m 5s`, `3h 20m`, `2d 4h`.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (days, hours, minutes) = (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60);
+    match (days, hours, minutes) {
+        (0, 0, 0) => format!("{seconds}s"),
+        (0, 0, _) => format!("{minutes}m {}s", seconds % 60),
+        (0, _, _) => format!("{hours}h {minutes}m"),
+        _ => format!("{days}d {hours}h"),
+    }
+}
+
+/// Renders a Unix timestamp as a UTC calendar date, e.g. `2024-02-29`.
+pub fn format_date(unix_seconds: u64) -> String {
+    // Days to civil date, after Howard Hinnant's algorithm, with eras of 400 years
+    // counted from 0000-03-01 so leap days fall at the end of each year.
+    let days = unix_seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Renders a Unix timestamp as a local time of day, e.g. `02:00`.
+pub fn format_time(unix_seconds: u64) -> String {
+    let local = unix_seconds as i64 + local_offset(unix_seconds);
+    let minutes = local.rem_euclid(86_400) / 60;
+    format!("{:02}:{:02}", minutes / 60, minutes % 60)
+}
+
+/// Seconds the local time zone is ahead of UTC at `unix_seconds`; zero where unknown.
+#[cfg(unix)]
+pub fn local_offset(unix_seconds: u64) -> i64 {
+    let time = unix_seconds as libc::time_t;
+    // SAFETY: `tm` is plain data that localtime_r fills in; both pointers are valid for the
+    // duration of the call.
+    let mut tm = unsafe { std::mem::zeroed::<libc::tm>() };
+    if unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+        return 0;
+    }
+    tm.tm_gmtoff as i64
+}
+
+#[cfg(not(unix))]
+pub fn local_offset(_unix_seconds: u64) -> i64 {
+    0
+}
+
+/// Decimals that keep three significant digits, with thresholds placed so rounding never
+/// prints a fourth (9.996 is `10.0`, not `10.00`).
+fn decimals(size: f64) -> usize {
+    if size < 9.995 {
+        2
+    } else if size < 99.95 {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_counts_are_exact() {
+        assert_eq!(format_size(0, UnitSystem::Iec), "0 B");
+        assert_eq!(format_size(1023, UnitSystem::Iec), "1023 B");
+        assert_eq!(format_size(999, UnitSystem::Si), "999 B");
+        assert_eq!(format_size(1000, UnitSystem::Si), "1.00 kB");
+    }
+
+    #[test]
+    fn keeps_three_significant_digits() {
+        assert_eq!(format_size(1536, UnitSystem::Iec), "1.50 KiB");
+        assert_eq!(format_size(100_000, UnitSystem::Iec), "97.7 KiB");
+        assert_eq!(format_size(512 * 1024 * 1024, UnitSystem::Iec), "512 MiB");
+        assert_eq!(format_size(12_345_678, UnitSystem::Si), "12.3 MB");
+        assert_eq!(format_size(123_456_789, UnitSystem::Si), "123 MB");
+    }
+
+    #[test]
+    fn rounding_carries_into_the_next_precision_and_unit() {
+        assert_eq!(format_size(9_996, UnitSystem::Si), "10.0 kB");
+        assert_eq!(format_size(99_960, UnitSystem::Si), "100 kB");
+        assert_eq!(format_size(1_048_064, UnitSystem::Iec), "1.00 MiB");
+        assert_eq!(format_size(999_600, UnitSystem::Si), "1.00 MB");
+    }
+
+    #[test]
+    fn covers_the_whole_u64_range() {
+        assert_eq!(format_size(u64::MAX, UnitSystem::Iec), "16.0 EiB");
+        assert_eq!(format_size(u64::MAX, UnitSystem::Si), "18.4 EB");
+    }
+
+    #[test]
+    fn durations_keep_the_two_largest_units() {
+        assert_eq!(format_duration(Duration::from_secs(45)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(725)), "12m 5s");
+        assert_eq!(
+            format_duration(Duration::from_secs(3 * 3600 + 20 * 60 + 9)),
+            "3h 20m"
+        );
+        assert_eq!(
+            format_duration(Duration::from_secs(2 * 86_400 + 4 * 3600)),
+            "2d 4h"
+        );
+    }
+
+    #[test]
+    fn rates_are_per_second() {
+        assert_eq!(format_rate(0, UnitSystem::Iec), "0 B/s");
+        assert_eq!(format_rate(2048, UnitSystem::Iec), "2.00 KiB/s");
+        assert_eq!(format_rate(2048, UnitSystem::Si), "2.05 kB/s");
+    }
+
+    #[test]
+    fn dates_are_utc_calendar_days() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(1_709_251_199), "2024-02-29");
+        assert_eq!(format_date(1_709_251_200), "2024-03-01");
+        assert_eq!(format_date(4_102_444_800), "2100-01-01");
+    }
+}
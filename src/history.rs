@@ -0,0 +1,143 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{DecodeLimits, TorrentFile};
+use crate::format::hex;
+use crate::metadata::Metadata;
+
+/// A torrent that was removed, kept so it can be found and added again later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub name: String,
+    /// Hex info hash, which also names the kept copy of the `.torrent` file.
+    pub info_hash: String,
+    pub length: u64,
+    pub label: Option<String>,
+    pub downloaded: u64,
+    pub uploaded: u64,
+    /// Unix time the torrent was added, if known.
+    pub added: Option<u64>,
+    /// Unix time the torrent was removed.
+    pub removed: u64,
+    /// File path or URL the torrent was added from.
+    pub origin: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Uploaded over downloaded bytes; `None` if nothing was downloaded.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.downloaded > 0).then(|| self.uploaded as f64 / self.downloaded as f64)
+    }
+}
+
+/// Removed torrents, most recent last, stored in the data directory next to copies of their
+/// `.torrent` files.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+}
+
+impl History {
+    pub fn dir() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("terrent").join("history"))
+    }
+
+    /// Loads the history, which is empty until the first torrent is removed.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::dir().map(|dir| dir.join("history.toml")) else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        toml::from_str(&content).with_context(|| format!("Invalid history {path:?}"))
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = Self::dir().context("No data directory available")?;
+        fs::create_dir_all(&dir)?;
+        let path = dir.join("history.toml");
+        fs::write(&path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {path:?}"))
+    }
+
+    /// Adds a removed torrent, replacing an older entry for the same torrent. The `.torrent`
+    /// file it was added from is copied so re-adding works after the original is gone.
+    pub fn record(&mut self, torrent: &Metadata) -> Result<()> {
+        let entry = HistoryEntry {
+            name: torrent.name.clone(),
+            info_hash: hex(&torrent.info_hash),
+            length: torrent.length,
+            label: torrent.label.clone(),
+            downloaded: torrent.stats.downloaded,
+            uploaded: torrent.stats.uploaded,
+            added: torrent.added,
+            removed: unix_now(),
+            origin: torrent.origin.clone(),
+        };
+        self.entries
+            .retain(|existing| existing.info_hash != entry.info_hash);
+
+        let copied = match (&entry.origin, Self::torrent_path(&entry)) {
+            (Some(origin), Some(copy)) if Path::new(origin).is_file() => {
+                fs::create_dir_all(copy.parent().unwrap_or(Path::new(".")))
+                    .and_then(|()| fs::copy(origin, &copy))
+                    .with_context(|| format!("Failed to keep a copy of {origin}"))
+                    .map(|_| ())
+            }
+            _ => Ok(()),
+        };
+        self.entries.push(entry);
+        copied
+    }
+
+    /// Drops an entry and its copy of the `.torrent` file.
+    pub fn forget(&mut self, info_hash: &str) -> Option<HistoryEntry> {
+        let index = self
+            .entries
+            .iter()
+            .position(|entry| entry.info_hash == info_hash)?;
+        let entry = self.entries.remove(index);
+        if let Some(copy) = Self::torrent_path(&entry) {
+            let _ = fs::remove_file(copy);
+        }
+        Some(entry)
+    }
+
+    /// Opens the torrent of `entry` again, from the kept copy or else from where it was
+    /// originally added.
+    pub fn reopen(entry: &HistoryEntry, limits: &DecodeLimits) -> Result<TorrentFile> {
+        let torrent = match (Self::torrent_path(entry), &entry.origin) {
+            (Some(copy), _) if copy.is_file() => TorrentFile::open_with_limits(copy, limits)?,
+            (_, Some(origin)) if Path::new(origin).is_file() => {
+                TorrentFile::open_with_limits(origin, limits)?
+            }
+            (_, Some(origin)) => bail!("No copy of the torrent kept; add it again from {origin}"),
+            (_, None) => bail!("No copy of the torrent kept"),
+        };
+        // The original file may have been replaced by something else since.
+        if hex(&torrent.info_hash()) != entry.info_hash {
+            bail!("The torrent file no longer matches {}", entry.name);
+        }
+        Ok(torrent)
+    }
+
+    fn torrent_path(entry: &HistoryEntry) -> Option<PathBuf> {
+        Self::dir().map(|dir| dir.join(format!("{}.torrent", entry.info_hash)))
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,84 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Text},
+};
+use tui_widgets::popup::Popup;
+
+use crate::format::{UnitSystem, format_duration, format_size};
+use crate::stats::ChangeSummary;
+
+/// Popup listing what changed while the terminal was unfocused; any key dismisses it.
+#[derive(Debug, Default, Clone)]
+pub struct AwaySummary {
+    summary: Option<ChangeSummary>,
+}
+
+impl AwaySummary {
+    pub fn is_visible(&self) -> bool {
+        self.summary.is_some()
+    }
+
+    /// Shows `summary`, unless nothing happened.
+    pub fn show(&mut self, summary: ChangeSummary) {
+        self.summary = (!summary.is_empty()).then_some(summary);
+    }
+
+    pub fn hide(&mut self) {
+        self.summary = None;
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect, units: UnitSystem) {
+        let Some(summary) = &self.summary else {
+            return;
+        };
+
+        let mut lines = vec![Line::raw(format!(
+            " ↓ {}  ↑ {} ",
+            format_size(summary.downloaded, units),
+            format_size(summary.uploaded, units)
+        ))];
+        if !summary.completed.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                " Completed",
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            lines.extend(
+                summary.completed.iter().map(|name| {
+                    Line::styled(format!("  {name} "), Style::default().fg(Color::Green))
+                }),
+            );
+        }
+        if !summary.errors.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                " Errors",
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            lines.extend(summary.errors.iter().map(|(name, error)| {
+                Line::styled(
+                    format!("  {name}: {error} "),
+                    Style::default().fg(Color::Red),
+                )
+            }));
+        }
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            " Press any key to dismiss ",
+            Style::default().fg(Color::DarkGray),
+        ));
+
+        let popup = Popup::new(Text::from(lines))
+            .title(
+                Line::from(format!(
+                    " While you were away ({}) ",
+                    format_duration(summary.elapsed)
+                ))
+                .centered(),
+            )
+            .style(Style::default().bg(Color::Black));
+        frame.render_widget(&popup, area);
+    }
+}
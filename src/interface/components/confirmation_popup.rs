@@ -9,17 +9,20 @@ use ratatui::{
 };
 use tui_widgets::popup::{Popup, SizedWidgetRef};
 
-#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum ConfirmationChoice {
     Yes,
     #[default]
     No,
+    /// Third button, only offered once a label is set with [`ConfirmationPopup::custom`].
+    Custom,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ConfirmationResult {
     Yes,
     No,
+    Custom,
     Cancelled,
 }
 
@@ -27,7 +30,9 @@ pub enum ConfirmationResult {
 pub enum ConfirmationMessage {
     Confirm,
     Cancel,
-    ToggleChoice,
+    NextChoice,
+    PreviousChoice,
+    ToggleDontAskAgain,
 }
 
 #[derive(Debug, Clone)]
@@ -36,16 +41,20 @@ pub struct ConfirmationPopup {
     content: String,
     yes_label: String,
     no_label: String,
+    custom_label: Option<String>,
+    default_choice: ConfirmationChoice,
     selected: ConfirmationChoice,
+    /// `Some` when the popup offers a "don't ask again" checkbox, holding its state.
+    dont_ask_again: Option<bool>,
     visible: bool,
 }
 
 #[derive(Debug)]
 struct ConfirmationBody<'a> {
     content: &'a str,
-    yes_label: &'a str,
-    no_label: &'a str,
-    selected: &'a ConfirmationChoice,
+    buttons: Vec<(ConfirmationChoice, &'a str, Color)>,
+    selected: ConfirmationChoice,
+    dont_ask_again: Option<bool>,
 }
 
 impl ConfirmationPopup {
@@ -55,14 +64,61 @@ impl ConfirmationPopup {
             content: content.into(),
             yes_label: "Yes".to_string(),
             no_label: "No".to_string(),
+            custom_label: None,
+            default_choice: ConfirmationChoice::default(),
             selected: ConfirmationChoice::default(),
+            dont_ask_again: None,
             visible: false,
         }
     }
 
+    /// Button selected whenever the popup is shown, e.g. `Yes` for harmless prompts.
+    pub fn default_choice(mut self, choice: ConfirmationChoice) -> Self {
+        self.default_choice = choice;
+        self
+    }
+
+    /// Adds a third button between "Yes" and "No".
+    pub fn custom(mut self, label: impl Into<String>) -> Self {
+        self.custom_label = Some(label.into());
+        self
+    }
+
+    /// Offers a "don't ask again" checkbox; read its state with [`Self::dont_ask_again`].
+    pub fn with_dont_ask_again(mut self) -> Self {
+        self.dont_ask_again = Some(false);
+        self
+    }
+
     pub fn show(&mut self) {
         self.visible = true;
-        self.selected = ConfirmationChoice::default();
+        self.selected = self.default_choice;
+        if let Some(checked) = &mut self.dont_ask_again {
+            *checked = false;
+        }
+    }
+
+    /// Whether the user ticked "don't ask again" before answering.
+    pub fn dont_ask_again(&self) -> bool {
+        self.dont_ask_again.unwrap_or(false)
+    }
+
+    fn choices(&self) -> Vec<ConfirmationChoice> {
+        let mut choices = vec![ConfirmationChoice::Yes];
+        if self.custom_label.is_some() {
+            choices.push(ConfirmationChoice::Custom);
+        }
+        choices.push(ConfirmationChoice::No);
+        choices
+    }
+
+    fn cycle_choice(&mut self, step: usize) {
+        let choices = self.choices();
+        let current = choices
+            .iter()
+            .position(|choice| *choice == self.selected)
+            .unwrap_or(0);
+        self.selected = choices[(current + step) % choices.len()];
     }
 
     pub fn hide(&mut self) {
@@ -75,11 +131,18 @@ impl ConfirmationPopup {
 
     pub fn update(&mut self, msg: ConfirmationMessage) -> Option<ConfirmationResult> {
         match msg {
-            ConfirmationMessage::ToggleChoice => {
-                self.selected = match self.selected {
-                    ConfirmationChoice::Yes => ConfirmationChoice::No,
-                    ConfirmationChoice::No => ConfirmationChoice::Yes,
-                };
+            ConfirmationMessage::NextChoice => {
+                self.cycle_choice(1);
+                None
+            }
+            ConfirmationMessage::PreviousChoice => {
+                self.cycle_choice(self.choices().len() - 1);
+                None
+            }
+            ConfirmationMessage::ToggleDontAskAgain => {
+                if let Some(checked) = &mut self.dont_ask_again {
+                    *checked = !*checked;
+                }
                 None
             }
             ConfirmationMessage::Confirm => {
@@ -87,6 +150,7 @@ impl ConfirmationPopup {
                 Some(match self.selected {
                     ConfirmationChoice::Yes => ConfirmationResult::Yes,
                     ConfirmationChoice::No => ConfirmationResult::No,
+                    ConfirmationChoice::Custom => ConfirmationResult::Custom,
                 })
             }
             ConfirmationMessage::Cancel => {
@@ -102,11 +166,15 @@ impl ConfirmationPopup {
         }
 
         match key.code {
-            KeyCode::Left
-            | KeyCode::Right
-            | KeyCode::Char('h')
-            | KeyCode::Char('l')
-            | KeyCode::Tab => Some(ConfirmationMessage::ToggleChoice),
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Tab => {
+                Some(ConfirmationMessage::NextChoice)
+            }
+            KeyCode::Left | KeyCode::Char('h') | KeyCode::BackTab => {
+                Some(ConfirmationMessage::PreviousChoice)
+            }
+            KeyCode::Char('d') | KeyCode::Char(' ') if self.dont_ask_again.is_some() => {
+                Some(ConfirmationMessage::ToggleDontAskAgain)
+            }
             KeyCode::Char('y') | KeyCode::Char('Y') => {
                 self.selected = ConfirmationChoice::Yes;
                 Some(ConfirmationMessage::Confirm)
@@ -129,11 +197,21 @@ impl ConfirmationPopup {
             return;
         }
 
+        let mut buttons = vec![(
+            ConfirmationChoice::Yes,
+            self.yes_label.as_str(),
+            Color::Green,
+        )];
+        if let Some(custom_label) = &self.custom_label {
+            buttons.push((ConfirmationChoice::Custom, custom_label, Color::Yellow));
+        }
+        buttons.push((ConfirmationChoice::No, &self.no_label, Color::Red));
+
         let body = ConfirmationBody {
             content: &self.content,
-            yes_label: &self.yes_label,
-            no_label: &self.no_label,
-            selected: &self.selected,
+            buttons,
+            selected: self.selected,
+            dont_ask_again: self.dont_ask_again,
         };
 
         let popup = Popup::new(body)
@@ -149,9 +227,10 @@ impl WidgetRef for ConfirmationBody<'_> {
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
-                Constraint::Min(3),    // Content area
-                Constraint::Length(1), // Button area
-                Constraint::Length(1), // Hint area
+                Constraint::Min(3),                                       // Content area
+                Constraint::Length(self.dont_ask_again.is_some() as u16), // Checkbox area
+                Constraint::Length(1),                                    // Button area
+                Constraint::Length(1),                                    // Hint area
             ])
             .split(area);
 
@@ -160,76 +239,78 @@ impl WidgetRef for ConfirmationBody<'_> {
             .style(Style::default().fg(Color::White));
         content_paragraph.render_ref(chunks[0], buf);
 
+        if let Some(checked) = self.dont_ask_again {
+            let checkbox = if checked { "[x]" } else { "[ ]" };
+            Paragraph::new(format!("{checkbox} Don't ask again"))
+                .alignment(Alignment::Center)
+                .style(Style::default().fg(Color::Gray))
+                .render_ref(chunks[1], buf);
+        }
+
         let button_chunks = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-            .split(chunks[1]);
-
-        let yes_style = if *self.selected == ConfirmationChoice::Yes {
-            Style::default()
-                .fg(Color::Black)
-                .bg(Color::Green)
-                .add_modifier(Modifier::BOLD)
-        } else {
-            Style::default().fg(Color::Green)
-        };
-
-        let yes_text = if *self.selected == ConfirmationChoice::Yes {
-            format!("[ {} ]", self.yes_label)
-        } else {
-            format!("  {}  ", self.yes_label)
-        };
-
-        let yes_button = Paragraph::new(yes_text)
-            .alignment(Alignment::Center)
-            .style(yes_style);
-        yes_button.render_ref(button_chunks[0], buf);
-
-        let no_style = if *self.selected == ConfirmationChoice::No {
-            Style::default()
-                .fg(Color::Black)
-                .bg(Color::Red)
-                .add_modifier(Modifier::BOLD)
-        } else {
-            Style::default().fg(Color::Red)
-        };
+            .constraints(vec![
+                Constraint::Ratio(1, self.buttons.len() as u32);
+                self.buttons.len()
+            ])
+            .split(chunks[2]);
 
-        let no_text = if *self.selected == ConfirmationChoice::No {
-            format!("[ {} ]", self.no_label)
-        } else {
-            format!("  {}  ", self.no_label)
-        };
+        for ((choice, label, color), area) in self.buttons.iter().zip(button_chunks.iter()) {
+            let (style, text) = if *choice == self.selected {
+                (
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(*color)
+                        .add_modifier(Modifier::BOLD),
+                    format!("[ {label} ]"),
+                )
+            } else {
+                (Style::default().fg(*color), format!("  {label}  "))
+            };
 
-        let no_button = Paragraph::new(no_text)
-            .alignment(Alignment::Center)
-            .style(no_style);
-        no_button.render_ref(button_chunks[1], buf);
+            Paragraph::new(text)
+                .alignment(Alignment::Center)
+                .style(style)
+                .render_ref(*area, buf);
+        }
 
-        let hint = Line::from(vec![
+        let mut hint = vec![
             Span::styled("Arrow/Tab", Style::default().fg(Color::DarkGray)),
             Span::raw(": Navigate | "),
             Span::styled("Enter", Style::default().fg(Color::DarkGray)),
             Span::raw(": Confirm | "),
             Span::styled("Esc", Style::default().fg(Color::DarkGray)),
             Span::raw(": Cancel"),
-        ])
-        .centered();
+        ];
+        if self.dont_ask_again.is_some() {
+            hint.push(Span::raw(" | "));
+            hint.push(Span::styled("d", Style::default().fg(Color::DarkGray)));
+            hint.push(Span::raw(": Don't ask"));
+        }
 
-        Paragraph::new(hint).render_ref(chunks[2], buf);
+        Paragraph::new(Line::from(hint).centered()).render_ref(chunks[3], buf);
     }
 }
 
 impl SizedWidgetRef for ConfirmationBody<'_> {
     fn width(&self) -> usize {
         let content_width = self.content.len();
-        let buttons_width = self.yes_label.len() + self.no_label.len() + 10;
-        let min_width = 50;
+        let buttons_width = self
+            .buttons
+            .iter()
+            .map(|(_, label, _)| label.len() + 5)
+            .sum::<usize>();
+        let min_width = if self.dont_ask_again.is_some() {
+            64
+        } else {
+            50
+        };
 
         content_width.max(buttons_width).max(min_width)
     }
 
     fn height(&self) -> usize {
-        // Content area (3) + Button area (1) + Hint area (1)
-        5
+        // Content area (3) + Checkbox area (0 or 1) + Button area (1) + Hint area (1)
+        5 + self.dont_ask_again.is_some() as usize
     }
 }
@@ -0,0 +1,30 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+/// Bottom line listing the keys that matter right now, htop style. Hints that do not fit
+/// the width are left out rather than cut in half.
+#[derive(Debug, Default, Clone)]
+pub struct HintBar;
+
+impl HintBar {
+    /// Renders `hints` as key and label pairs, in order.
+    pub fn render(&self, frame: &mut Frame, area: Rect, hints: &[(String, &str)]) {
+        let key_style = Style::default().fg(Color::Black).bg(Color::Cyan);
+        let mut spans = Vec::new();
+        let mut width = 0;
+        for (key, label) in hints {
+            let hint_width = key.chars().count() + label.chars().count() + 4;
+            if width + hint_width > usize::from(area.width) {
+                break;
+            }
+            width += hint_width;
+            spans.push(Span::styled(format!(" {key} "), key_style));
+            spans.push(Span::raw(format!(" {label} ")));
+        }
+        frame.render_widget(Line::from(spans), area);
+    }
+}
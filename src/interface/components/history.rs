@@ -0,0 +1,133 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, BorderType, Row, Table, TableState},
+};
+
+use crate::format::{UnitSystem, format_date, format_size};
+use crate::history::{History, HistoryEntry};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HistoryMessage {
+    SelectNext,
+    SelectPrevious,
+    SelectFirst,
+    SelectLast,
+}
+
+/// Full-screen table of removed torrents, most recently removed first.
+#[derive(Debug, Default, Clone)]
+pub struct HistoryList {
+    state: TableState,
+    /// Outcome of the last re-add, shown under the table until the next one.
+    status: Option<String>,
+}
+
+impl HistoryList {
+    pub fn handle_key(&self, key: KeyEvent) -> Option<HistoryMessage> {
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => Some(HistoryMessage::SelectNext),
+            KeyCode::Up | KeyCode::Char('k') => Some(HistoryMessage::SelectPrevious),
+            KeyCode::Home | KeyCode::Char('g') => Some(HistoryMessage::SelectFirst),
+            KeyCode::End | KeyCode::Char('G') => Some(HistoryMessage::SelectLast),
+            _ => None,
+        }
+    }
+
+    pub fn update(&mut self, msg: HistoryMessage, len: usize) {
+        if len == 0 {
+            self.state.select(None);
+            return;
+        }
+
+        let last = len - 1;
+        let selected = self.state.selected().map(|index| index.min(last));
+        self.state.select(Some(match msg {
+            HistoryMessage::SelectNext => selected.map_or(0, |index| (index + 1).min(last)),
+            HistoryMessage::SelectPrevious => selected.map_or(0, |index| index.saturating_sub(1)),
+            HistoryMessage::SelectFirst => 0,
+            HistoryMessage::SelectLast => last,
+        }));
+    }
+
+    pub fn selected<'a>(&self, history: &'a History) -> Option<&'a HistoryEntry> {
+        let index = self.state.selected()?;
+        history.entries.iter().rev().nth(index)
+    }
+
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = Some(status.into());
+    }
+
+    pub fn render(&mut self, frame: &mut Frame, area: Rect, history: &History, units: UnitSystem) {
+        let len = history.entries.len();
+        match self.state.selected() {
+            None if len > 0 => self.state.select(Some(0)),
+            Some(index) if index >= len => self.state.select(len.checked_sub(1)),
+            _ => {}
+        }
+
+        let rows = history.entries.iter().rev().map(|entry| {
+            Row::new([
+                entry.name.clone(),
+                entry.label.clone().unwrap_or_default(),
+                format_size(entry.length, units),
+                format_size(entry.downloaded, units),
+                format_size(entry.uploaded, units),
+                entry
+                    .ratio()
+                    .map_or_else(|| "-".to_string(), |ratio| format!("{ratio:.2}")),
+                entry.added.map(format_date).unwrap_or_default(),
+                format_date(entry.removed),
+            ])
+        });
+
+        let header = Row::new([
+            "Name",
+            "Label",
+            "Size",
+            "Downloaded",
+            "Uploaded",
+            "Ratio",
+            "Added",
+            "Removed",
+        ])
+        .style(Style::default().fg(Color::DarkGray));
+
+        let mut block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(Style::default().fg(Color::Cyan))
+            .title(format!(" History ({len} removed) "))
+            .title_bottom(Line::from(" Enter re-add · x forget ").right_aligned());
+        if let Some(status) = &self.status {
+            block = block.title_bottom(format!(" {status} "));
+        }
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(1),
+                Constraint::Length(12),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(6),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ],
+        )
+        .header(header)
+        .block(block)
+        .row_highlight_style(
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        );
+
+        frame.render_stateful_widget(table, area, &mut self.state);
+    }
+}
@@ -0,0 +1,79 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Text},
+};
+use tui_widgets::popup::Popup;
+
+use crate::session::IntegrityReport;
+
+/// Popup listing files the startup check moved aside; any key dismisses it.
+#[derive(Debug, Default, Clone)]
+pub struct IntegritySummary {
+    report: Option<IntegrityReport>,
+}
+
+impl IntegritySummary {
+    /// Shown only when something was damaged.
+    pub fn new(report: IntegrityReport) -> Self {
+        Self {
+            report: (!report.is_clean()).then_some(report),
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.report.is_some()
+    }
+
+    pub fn hide(&mut self) {
+        self.report = None;
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let Some(report) = &self.report else {
+            return;
+        };
+
+        let mut lines = vec![Line::raw(format!(
+            " {} file(s) were damaged; the rest loaded normally. ",
+            report.quarantined.len() + report.failed.len()
+        ))];
+        if !report.quarantined.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                " Moved to corrupt/",
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            lines.extend(report.quarantined.iter().map(|entry| {
+                Line::styled(
+                    format!("  {}: {} ", entry.path.display(), entry.reason),
+                    Style::default().fg(Color::Yellow),
+                )
+            }));
+        }
+        if !report.failed.is_empty() {
+            lines.push(Line::default());
+            lines.push(Line::styled(
+                " Could not be moved",
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            lines.extend(report.failed.iter().map(|(path, error)| {
+                Line::styled(
+                    format!("  {}: {error} ", path.display()),
+                    Style::default().fg(Color::Red),
+                )
+            }));
+        }
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            " Press any key to dismiss ",
+            Style::default().fg(Color::DarkGray),
+        ));
+
+        let popup = Popup::new(Text::from(lines))
+            .title(Line::from(" Session check ").centered())
+            .style(Style::default().bg(Color::Black));
+        frame.render_widget(&popup, area);
+    }
+}
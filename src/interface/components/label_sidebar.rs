@@ -0,0 +1,57 @@
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, BorderType, Paragraph},
+};
+
+use crate::format::{UnitSystem, format_size};
+use crate::metadata::Metadata;
+use crate::stats::{self, GroupStats};
+
+/// Narrow column summarizing each label's torrents and transfer totals.
+#[derive(Debug, Default, Clone)]
+pub struct LabelSidebar;
+
+impl LabelSidebar {
+    pub fn render(&self, frame: &mut Frame, area: Rect, torrents: &[Metadata], units: UnitSystem) {
+        let mut lines = Vec::new();
+        for (label, group) in stats::by_label(torrents) {
+            let name = label.unwrap_or_else(|| "Unlabeled".to_string());
+            lines.push(Line::styled(
+                name,
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            lines.extend(summary(&group, units));
+            lines.push(Line::default());
+        }
+
+        let sidebar = Paragraph::new(lines).block(
+            Block::bordered()
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(Color::DarkGray))
+                .title(" Labels "),
+        );
+        frame.render_widget(sidebar, area);
+    }
+}
+
+fn summary(group: &GroupStats, units: UnitSystem) -> [Line<'static>; 3] {
+    let ratio = group
+        .transfer
+        .ratio()
+        .map_or_else(|| "-".to_string(), |ratio| format!("{ratio:.2}"));
+    [
+        Line::raw(format!(" {} ({} active)", group.torrents, group.active)),
+        Line::raw(format!(
+            " ↓ {}  ↑ {}",
+            format_size(group.transfer.downloaded, units),
+            format_size(group.transfer.uploaded, units)
+        )),
+        Line::styled(
+            format!(" ratio {ratio}"),
+            Style::default().fg(Color::DarkGray),
+        ),
+    ]
+}
@@ -1,3 +1,29 @@
+pub mod away_summary;
 pub mod confirmation_popup;
+pub mod hint_bar;
+pub mod history;
+pub mod integrity_summary;
+pub mod label_sidebar;
+pub mod peers;
+pub mod retracker_form;
+pub mod statistics;
+pub mod swarm_map;
+pub mod text_input;
+pub mod toast;
+pub mod torrent_details;
+pub mod torrent_list;
 
+pub use away_summary::AwaySummary;
 pub use confirmation_popup::{ConfirmationPopup, ConfirmationResult};
+pub use hint_bar::HintBar;
+pub use history::HistoryList;
+pub use integrity_summary::IntegritySummary;
+pub use label_sidebar::LabelSidebar;
+pub use peers::Peers;
+pub use retracker_form::RetrackerForm;
+pub use statistics::Statistics;
+pub use swarm_map::SwarmMap;
+pub use text_input::TextInputPopup;
+pub use toast::{Toast, ToastKind};
+pub use torrent_details::TorrentDetails;
+pub use torrent_list::TorrentList;
@@ -0,0 +1,127 @@
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, BorderType, Row, Table, TableState},
+};
+
+use crate::format::{UnitSystem, format_rate};
+use crate::metadata::Metadata;
+use crate::stats;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeersMessage {
+    SelectNext,
+    SelectPrevious,
+    SelectFirst,
+    SelectLast,
+}
+
+/// Full-screen table of every connected peer across all torrents.
+#[derive(Debug, Default, Clone)]
+pub struct Peers {
+    state: TableState,
+}
+
+impl Peers {
+    pub fn handle_key(&self, key: KeyEvent) -> Option<PeersMessage> {
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => Some(PeersMessage::SelectNext),
+            KeyCode::Up | KeyCode::Char('k') => Some(PeersMessage::SelectPrevious),
+            KeyCode::Home | KeyCode::Char('g') => Some(PeersMessage::SelectFirst),
+            KeyCode::End | KeyCode::Char('G') => Some(PeersMessage::SelectLast),
+            _ => None,
+        }
+    }
+
+    pub fn update(&mut self, msg: PeersMessage, len: usize) {
+        if len == 0 {
+            self.state.select(None);
+            return;
+        }
+
+        let last = len - 1;
+        let selected = self.state.selected().map(|index| index.min(last));
+        self.state.select(Some(match msg {
+            PeersMessage::SelectNext => selected.map_or(0, |index| (index + 1).min(last)),
+            PeersMessage::SelectPrevious => selected.map_or(0, |index| index.saturating_sub(1)),
+            PeersMessage::SelectFirst => 0,
+            PeersMessage::SelectLast => last,
+        }));
+    }
+
+    pub fn render(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+        torrents: &[Metadata],
+        units: UnitSystem,
+    ) {
+        let peers = stats::all_peers(torrents);
+        let per_ip = stats::torrents_per_ip(torrents);
+
+        let rows = peers
+            .iter()
+            .map(|(torrent, peer)| {
+                let shared = per_ip.get(&peer.addr.ip()).copied().unwrap_or(1);
+                let row = Row::new([
+                    peer.addr.to_string(),
+                    torrent.name.clone(),
+                    peer.client.clone().unwrap_or_default(),
+                    peer.flags.clone(),
+                    format_rate(peer.download_rate, units),
+                    format_rate(peer.upload_rate, units),
+                    peer.upload_queue.to_string(),
+                    peer.hash_failures.to_string(),
+                    shared.to_string(),
+                ]);
+                // One address in several swarms is worth a second look.
+                if shared > 1 {
+                    row.style(Style::default().fg(Color::Yellow))
+                } else {
+                    row
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let header = Row::new([
+            "Address", "Torrent", "Client", "Flags", "↓ Rate", "↑ Rate", "Queue", "Bad", "Torrents",
+        ])
+        .style(Style::default().fg(Color::DarkGray));
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(24),
+                Constraint::Fill(2),
+                Constraint::Fill(1),
+                Constraint::Length(6),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(6),
+                Constraint::Length(4),
+                Constraint::Length(8),
+            ],
+        )
+        .header(header)
+        .block(
+            Block::bordered()
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(Color::Cyan))
+                .title(format!(
+                    " Peers ({} connections, {} addresses) ",
+                    peers.len(),
+                    per_ip.len()
+                )),
+        )
+        .row_highlight_style(
+            Style::default()
+                .fg(Color::Black)
+                .bg(Color::Cyan)
+                .add_modifier(Modifier::BOLD),
+        );
+
+        frame.render_stateful_widget(table, area, &mut self.state);
+    }
+}
@@ -0,0 +1,147 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span, Text},
+};
+use tui_widgets::popup::Popup;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetrackerMessage {
+    Insert(char),
+    Backspace,
+    NextField,
+    Submit,
+    Cancel,
+}
+
+/// Asks for the old and new host of a tracker that moved domains.
+#[derive(Debug, Default, Clone)]
+pub struct RetrackerForm {
+    from: String,
+    to: String,
+    /// Editing `to` rather than `from`.
+    editing_to: bool,
+    /// Why the last submission was refused; cleared on the next edit.
+    error: Option<String>,
+    visible: bool,
+}
+
+impl RetrackerForm {
+    pub fn show(&mut self) {
+        *self = Self {
+            visible: true,
+            ..Self::default()
+        };
+    }
+
+    pub fn hide(&mut self) {
+        self.visible = false;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Keeps the form open with `error` below the fields.
+    pub fn reject(&mut self, error: impl Into<String>) {
+        self.error = Some(error.into());
+    }
+
+    pub fn handle_key(&self, key: KeyEvent) -> Option<RetrackerMessage> {
+        if !self.visible {
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Enter => Some(RetrackerMessage::Submit),
+            KeyCode::Tab | KeyCode::BackTab | KeyCode::Up | KeyCode::Down => {
+                Some(RetrackerMessage::NextField)
+            }
+            KeyCode::Esc => Some(RetrackerMessage::Cancel),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(RetrackerMessage::Cancel)
+            }
+            KeyCode::Backspace => Some(RetrackerMessage::Backspace),
+            KeyCode::Char(c) => Some(RetrackerMessage::Insert(c)),
+            _ => None,
+        }
+    }
+
+    /// Applies an edit; returns the trimmed old and new host once submitted with both filled
+    /// in. The form stays open, so the caller can [`Self::reject`] them or [`Self::hide`] it.
+    pub fn update(&mut self, msg: RetrackerMessage) -> Option<(String, String)> {
+        let field = if self.editing_to {
+            &mut self.to
+        } else {
+            &mut self.from
+        };
+        match msg {
+            RetrackerMessage::Insert(c) => {
+                field.push(c);
+                self.error = None;
+            }
+            RetrackerMessage::Backspace => {
+                field.pop();
+                self.error = None;
+            }
+            RetrackerMessage::NextField => self.editing_to = !self.editing_to,
+            // Enter on the first field moves on, like a form submitted too early.
+            RetrackerMessage::Submit if self.to.trim().is_empty() => self.editing_to = true,
+            RetrackerMessage::Submit if self.from.trim().is_empty() => self.editing_to = false,
+            RetrackerMessage::Submit => {
+                return Some((self.from.trim().to_string(), self.to.trim().to_string()));
+            }
+            RetrackerMessage::Cancel => self.visible = false,
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        if !self.visible {
+            return;
+        }
+
+        let width = usize::from(area.width.saturating_sub(8)).min(56);
+        let mut lines = vec![
+            field("From host", &self.from, !self.editing_to, width),
+            field("To host", &self.to, self.editing_to, width),
+            Line::styled(
+                " Applies to every torrent | Tab: Switch | Enter: Confirm | Esc: Cancel",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ];
+        if let Some(error) = &self.error {
+            lines.insert(
+                2,
+                Line::styled(format!(" {error}"), Style::default().fg(Color::Red)),
+            );
+        }
+
+        let popup = Popup::new(Text::from(lines))
+            .title(Line::from(" Move tracker host ").centered())
+            .style(Style::default().bg(Color::Black));
+        frame.render_widget(&popup, area);
+    }
+}
+
+fn field(label: &str, value: &str, editing: bool, width: usize) -> Line<'static> {
+    let label = format!(" {label:<10} ");
+    let room = width.saturating_sub(label.len() + 1);
+    let shown = value
+        .chars()
+        .skip(value.chars().count().saturating_sub(room))
+        .collect::<String>();
+    let mut spans = vec![
+        Span::styled(label, Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw(shown.clone()),
+    ];
+    if editing {
+        spans.push(Span::styled("█", Style::default().fg(Color::Cyan)));
+    }
+    spans.push(Span::raw(
+        " ".repeat(room.saturating_sub(shown.chars().count())),
+    ));
+    Line::from(spans)
+}
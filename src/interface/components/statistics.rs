@@ -0,0 +1,92 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, BorderType, Row, Table},
+};
+
+use crate::format::{UnitSystem, format_rate, format_size};
+use crate::metadata::Metadata;
+use crate::stats::{self, GroupStats};
+
+/// Full-screen table of per-label transfer totals.
+#[derive(Debug, Default, Clone)]
+pub struct Statistics;
+
+impl Statistics {
+    pub fn render(&self, frame: &mut Frame, area: Rect, torrents: &[Metadata], units: UnitSystem) {
+        let mut rows = stats::by_label(torrents)
+            .into_iter()
+            .map(|(label, group)| {
+                row(
+                    label.unwrap_or_else(|| "Unlabeled".to_string()),
+                    &group,
+                    units,
+                )
+            })
+            .collect::<Vec<_>>();
+        rows.push(
+            row("Total".to_string(), &stats::total(torrents), units)
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        );
+
+        let memory = stats::memory_usage(torrents);
+        let header = Row::new([
+            "Label",
+            "Torrents",
+            "Active",
+            "Downloaded",
+            "Uploaded",
+            "Ratio",
+            "↓ Rate",
+            "↑ Rate",
+        ])
+        .style(Style::default().fg(Color::DarkGray));
+
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(2),
+                Constraint::Length(9),
+                Constraint::Length(7),
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(6),
+                Constraint::Length(12),
+                Constraint::Length(12),
+            ],
+        )
+        .header(header)
+        .block(
+            Block::bordered()
+                .border_type(BorderType::Rounded)
+                .border_style(Style::default().fg(Color::Cyan))
+                .title(" Statistics ")
+                .title_bottom(
+                    Line::from(format!(
+                        " Metadata in memory: {} ",
+                        format_size(memory.bytes, units)
+                    ))
+                    .right_aligned(),
+                ),
+        );
+        frame.render_widget(table, area);
+    }
+}
+
+fn row(label: String, group: &GroupStats, units: UnitSystem) -> Row<'static> {
+    let transfer = &group.transfer;
+    Row::new([
+        label,
+        group.torrents.to_string(),
+        group.active.to_string(),
+        format_size(transfer.downloaded, units),
+        format_size(transfer.uploaded, units),
+        transfer
+            .ratio()
+            .map_or_else(|| "-".to_string(), |ratio| format!("{ratio:.2}")),
+        format_rate(transfer.download_rate, units),
+        format_rate(transfer.upload_rate, units),
+    ])
+}
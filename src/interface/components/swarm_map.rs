@@ -0,0 +1,137 @@
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    symbols::Marker,
+    text::Line,
+    widgets::{Axis, Bar, BarChart, BarGroup, Block, Chart, Dataset, GraphType, Paragraph},
+};
+
+use crate::format::{UnitSystem, format_rate, format_size};
+use crate::metadata::Metadata;
+use crate::stats::PeerStats;
+
+const DOWNLOAD_COLOR: Color = Color::Green;
+const UPLOAD_COLOR: Color = Color::Cyan;
+
+/// The peers of one torrent at a glance: a scatter plot of latency against throughput, where
+/// good peers sit top left, and bars of what each peer gave and took so far.
+#[derive(Debug, Default, Clone)]
+pub struct SwarmMap;
+
+impl SwarmMap {
+    pub fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        torrent: &Metadata,
+        units: UnitSystem,
+        block: Block,
+    ) {
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        if torrent.peers.is_empty() {
+            let empty =
+                Paragraph::new("No peers connected").style(Style::default().fg(Color::DarkGray));
+            frame.render_widget(empty, inner);
+            return;
+        }
+
+        let [plot_area, bars_area] =
+            Layout::vertical([Constraint::Percentage(55), Constraint::Fill(1)]).areas(inner);
+        render_plot(frame, plot_area, &torrent.peers, units);
+        render_contribution(frame, bars_area, &torrent.peers, units);
+    }
+}
+
+/// Peers we mostly download from and peers we mostly upload to, in separate colors. Peers
+/// whose latency is not measured yet are left out and counted in the title.
+fn render_plot(frame: &mut Frame, area: Rect, peers: &[PeerStats], units: UnitSystem) {
+    let point = |peer: &PeerStats| {
+        peer.latency.map(|latency| {
+            (
+                latency.as_secs_f64() * 1000.0,
+                (peer.download_rate + peer.upload_rate) as f64,
+            )
+        })
+    };
+    let (downloading, uploading): (Vec<_>, Vec<_>) = peers
+        .iter()
+        .filter(|peer| peer.latency.is_some())
+        .partition(|peer| peer.download_rate >= peer.upload_rate);
+    let downloading = downloading
+        .into_iter()
+        .filter_map(point)
+        .collect::<Vec<_>>();
+    let uploading = uploading.into_iter().filter_map(point).collect::<Vec<_>>();
+
+    let unmeasured = peers.iter().filter(|peer| peer.latency.is_none()).count();
+    let title = match unmeasured {
+        0 => " Latency / throughput ".to_string(),
+        count => format!(" Latency / throughput ({count} not measured) "),
+    };
+    let points = downloading.iter().chain(&uploading);
+    let max_latency = points.clone().map(|(x, _)| *x).fold(1.0, f64::max);
+    let max_rate = points.map(|(_, y)| *y).fold(1.0, f64::max);
+
+    let dataset = |name: &'static str, data, color| {
+        Dataset::default()
+            .name(name)
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Scatter)
+            .style(Style::default().fg(color))
+            .data(data)
+    };
+    let chart = Chart::new(vec![
+        dataset("mostly ↓", &downloading, DOWNLOAD_COLOR),
+        dataset("mostly ↑", &uploading, UPLOAD_COLOR),
+    ])
+    .block(Block::new().title(title))
+    .x_axis(
+        Axis::default()
+            .style(Style::default().fg(Color::DarkGray))
+            .bounds([0.0, max_latency])
+            .labels(["0 ms".to_string(), format!("{max_latency:.0} ms")]),
+    )
+    .y_axis(
+        Axis::default()
+            .style(Style::default().fg(Color::DarkGray))
+            .bounds([0.0, max_rate])
+            .labels(["0".to_string(), format_rate(max_rate as u64, units)]),
+    );
+    frame.render_widget(chart, area);
+}
+
+/// The peers that moved the most data, as many as fit, with bytes received from and sent to
+/// each.
+fn render_contribution(frame: &mut Frame, area: Rect, peers: &[PeerStats], units: UnitSystem) {
+    let title = Line::from(" Contribution ↓ / ↑ ");
+    let mut top = peers.iter().collect::<Vec<_>>();
+    top.sort_by_key(|peer| std::cmp::Reverse(peer.downloaded + peer.uploaded));
+    // Two bars and a gap per peer, below the title.
+    top.truncate(usize::from(area.height.saturating_sub(1) / 3).max(1));
+
+    let mut chart = BarChart::default()
+        .block(Block::new().title(title))
+        .direction(Direction::Horizontal)
+        .bar_width(1)
+        .bar_gap(0)
+        .group_gap(1);
+    for peer in top {
+        let bar = |bytes: u64, color| {
+            Bar::default()
+                .value(bytes)
+                .text_value(format_size(bytes, units))
+                .style(Style::default().fg(color))
+        };
+        chart = chart.data(
+            BarGroup::default()
+                .label(Line::from(peer.addr.ip().to_string()))
+                .bars(&[
+                    bar(peer.downloaded, DOWNLOAD_COLOR),
+                    bar(peer.uploaded, UPLOAD_COLOR),
+                ]),
+        );
+    }
+    frame.render_widget(chart, area);
+}
@@ -0,0 +1,127 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span, Text},
+};
+use tui_widgets::popup::Popup;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextInputMessage {
+    Insert(char),
+    Backspace,
+    Submit,
+    Cancel,
+}
+
+/// Single-line prompt, e.g. for a tracker URL.
+#[derive(Debug, Clone)]
+pub struct TextInputPopup {
+    title: String,
+    value: String,
+    /// Why the last submitted value was refused; cleared on the next edit.
+    error: Option<String>,
+    visible: bool,
+}
+
+impl TextInputPopup {
+    pub fn new(title: impl Into<String>) -> Self {
+        Self {
+            title: title.into(),
+            value: String::new(),
+            error: None,
+            visible: false,
+        }
+    }
+
+    pub fn show(&mut self) {
+        self.visible = true;
+        self.value.clear();
+        self.error = None;
+    }
+
+    pub fn hide(&mut self) {
+        self.visible = false;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Keeps the popup open with `error` below the value.
+    pub fn reject(&mut self, error: impl Into<String>) {
+        self.error = Some(error.into());
+    }
+
+    pub fn handle_key(&self, key: KeyEvent) -> Option<TextInputMessage> {
+        if !self.visible {
+            return None;
+        }
+
+        match key.code {
+            KeyCode::Enter => Some(TextInputMessage::Submit),
+            KeyCode::Esc => Some(TextInputMessage::Cancel),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(TextInputMessage::Cancel)
+            }
+            KeyCode::Backspace => Some(TextInputMessage::Backspace),
+            KeyCode::Char(c) => Some(TextInputMessage::Insert(c)),
+            _ => None,
+        }
+    }
+
+    /// Applies an edit; returns the trimmed value once submitted. The popup stays open, so
+    /// the caller can [`Self::reject`] the value or [`Self::hide`] it.
+    pub fn update(&mut self, msg: TextInputMessage) -> Option<String> {
+        match msg {
+            TextInputMessage::Insert(c) => {
+                self.value.push(c);
+                self.error = None;
+            }
+            TextInputMessage::Backspace => {
+                self.value.pop();
+                self.error = None;
+            }
+            TextInputMessage::Submit => return Some(self.value.trim().to_string()),
+            TextInputMessage::Cancel => self.visible = false,
+        }
+        None
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        if !self.visible {
+            return;
+        }
+
+        // Wide enough for a typical announce URL with a passkey.
+        let width = usize::from(area.width.saturating_sub(8)).min(72);
+        let shown = self
+            .value
+            .chars()
+            .skip(self.value.chars().count().saturating_sub(width - 2))
+            .collect::<String>();
+        let mut lines = vec![
+            Line::from(vec![
+                Span::raw(format!(" {shown}")),
+                Span::styled("█", Style::default().fg(Color::Cyan)),
+                Span::raw(" ".repeat(width.saturating_sub(shown.chars().count() + 2))),
+            ]),
+            Line::styled(
+                " Enter: Confirm | Esc: Cancel",
+                Style::default().fg(Color::DarkGray),
+            ),
+        ];
+        if let Some(error) = &self.error {
+            lines.insert(
+                1,
+                Line::styled(format!(" {error}"), Style::default().fg(Color::Red)),
+            );
+        }
+
+        let popup = Popup::new(Text::from(lines))
+            .title(Line::from(format!(" {} ", self.title)).centered())
+            .style(Style::default().bg(Color::Black));
+        frame.render_widget(&popup, area);
+    }
+}
@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    Frame,
+    layout::{Constraint, Flex, Layout, Margin, Rect},
+    style::{Color, Style},
+    widgets::{Block, BorderType, Clear, Padding, Paragraph, Wrap},
+};
+
+/// How long a toast stays up.
+const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastKind {
+    Info,
+    Warning,
+}
+
+/// Short notice in the bottom-right corner that goes away on its own and takes no input.
+#[derive(Debug, Default, Clone)]
+pub struct Toast {
+    shown: Option<(String, ToastKind, Instant)>,
+}
+
+impl Toast {
+    pub fn show(&mut self, message: impl Into<String>, kind: ToastKind, now: Instant) {
+        self.shown = Some((message.into(), kind, now));
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.shown.is_some()
+    }
+
+    /// Hides the toast once it has been up long enough; returns whether it was.
+    pub fn expire(&mut self, now: Instant) -> bool {
+        let expired = self
+            .shown
+            .as_ref()
+            .is_some_and(|(_, _, since)| now.duration_since(*since) >= TOAST_DURATION);
+        if expired {
+            self.shown = None;
+        }
+        expired
+    }
+
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let Some((message, kind, _)) = &self.shown else {
+            return;
+        };
+
+        let color = match kind {
+            ToastKind::Info => Color::Cyan,
+            ToastKind::Warning => Color::Yellow,
+        };
+        let width = (message.chars().count() as u16 + 4).min(area.width).min(60);
+        let inner_width = width.saturating_sub(4).max(1);
+        let height = (message.chars().count() as u16).div_ceil(inner_width) + 2;
+        let [_, row] = Layout::vertical([Constraint::Fill(1), Constraint::Length(height)])
+            .areas(area.inner(Margin::new(1, 1)));
+        let [toast_area] = Layout::horizontal([Constraint::Length(width)])
+            .flex(Flex::End)
+            .areas(row);
+
+        let paragraph = Paragraph::new(message.as_str())
+            .wrap(Wrap { trim: true })
+            .style(Style::default().fg(color))
+            .block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(Style::default().fg(color))
+                    .padding(Padding::horizontal(1)),
+            );
+        frame.render_widget(Clear, toast_area);
+        frame.render_widget(paragraph, toast_area);
+    }
+}
@@ -0,0 +1,432 @@
+use std::time::UNIX_EPOCH;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, BorderType, Paragraph, Row, Table, TableState, Wrap},
+};
+
+use super::SwarmMap;
+use crate::download::PieceAttribution;
+use crate::format::{UnitSystem, format_date, format_size, format_time, hex};
+use crate::metadata::Metadata;
+use crate::tracker::{AnnouncePace, TrackerState, TrackerStatus};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TorrentDetailsMessage {
+    ScrollDown,
+    ScrollUp,
+    NextTab,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum DetailsTab {
+    #[default]
+    Overview,
+    /// Per-tracker status with the errors and warnings trackers sent.
+    Trackers,
+    /// Which peers or web seeds supplied each verified piece.
+    Pieces,
+    /// The [`SwarmMap`], when turned on in the config.
+    Swarm,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TorrentDetails {
+    scroll: u16,
+    tab: DetailsTab,
+    swarm_map: Option<SwarmMap>,
+}
+
+impl TorrentDetails {
+    pub fn reset_scroll(&mut self) {
+        self.scroll = 0;
+    }
+
+    /// Shows or hides the swarm map tab, leaving it if it is open.
+    pub fn set_swarm_map(&mut self, enabled: bool) {
+        self.swarm_map = enabled.then_some(SwarmMap);
+        if !enabled && self.tab == DetailsTab::Swarm {
+            self.tab = DetailsTab::Overview;
+        }
+    }
+
+    pub fn is_trackers_tab(&self) -> bool {
+        self.tab == DetailsTab::Trackers
+    }
+
+    pub fn is_pieces_tab(&self) -> bool {
+        self.tab == DetailsTab::Pieces
+    }
+
+    /// Piece highlighted in the pieces tab.
+    pub fn selected_piece(&self, torrent: &Metadata) -> Option<usize> {
+        let last = torrent.piece_count().checked_sub(1)?;
+        Some(usize::from(self.scroll).min(last))
+    }
+
+    /// Tracker highlighted in the trackers tab.
+    pub fn selected_tracker<'a>(&self, torrent: &'a Metadata) -> Option<&'a str> {
+        let last = torrent.announce.len().checked_sub(1)?;
+        Some(&torrent.announce[usize::from(self.scroll).min(last)])
+    }
+
+    pub fn handle_key(&self, key: KeyEvent) -> Option<TorrentDetailsMessage> {
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => Some(TorrentDetailsMessage::ScrollDown),
+            KeyCode::Up | KeyCode::Char('k') => Some(TorrentDetailsMessage::ScrollUp),
+            KeyCode::Char('t') => Some(TorrentDetailsMessage::NextTab),
+            _ => None,
+        }
+    }
+
+    pub fn update(&mut self, msg: TorrentDetailsMessage) {
+        match msg {
+            TorrentDetailsMessage::ScrollDown => self.scroll = self.scroll.saturating_add(1),
+            TorrentDetailsMessage::ScrollUp => self.scroll = self.scroll.saturating_sub(1),
+            TorrentDetailsMessage::NextTab => {
+                self.tab = match self.tab {
+                    DetailsTab::Overview => DetailsTab::Trackers,
+                    DetailsTab::Trackers => DetailsTab::Pieces,
+                    DetailsTab::Pieces if self.swarm_map.is_some() => DetailsTab::Swarm,
+                    DetailsTab::Pieces | DetailsTab::Swarm => DetailsTab::Overview,
+                };
+                self.scroll = 0;
+            }
+        }
+    }
+
+    pub fn render(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        torrent: Option<&Metadata>,
+        attribution: Option<&PieceAttribution>,
+        units: UnitSystem,
+        focused: bool,
+    ) {
+        let border_style = if focused {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+        let block = Block::bordered()
+            .border_type(BorderType::Rounded)
+            .border_style(border_style)
+            .title(self.title());
+
+        let Some(torrent) = torrent else {
+            let empty = Paragraph::new("No torrent selected")
+                .style(Style::default().fg(Color::DarkGray))
+                .block(block);
+            frame.render_widget(empty, area);
+            return;
+        };
+
+        if self.tab == DetailsTab::Trackers {
+            self.render_trackers(frame, area, torrent, block);
+            return;
+        }
+        if self.tab == DetailsTab::Pieces {
+            self.render_pieces(frame, area, torrent, attribution, block);
+            return;
+        }
+        if self.tab == DetailsTab::Swarm
+            && let Some(swarm_map) = &self.swarm_map
+        {
+            swarm_map.render(frame, area, torrent, units, block);
+            return;
+        }
+
+        let info_hash = hex(&torrent.info_hash);
+
+        let mut lines = vec![
+            field("Name", torrent.name.clone()),
+            field("Info hash", info_hash),
+            field("Size", format_size(torrent.length, units)),
+            field(
+                "Pieces",
+                format!(
+                    "{} x {}",
+                    torrent.piece_count(),
+                    format_size(torrent.piece_length, units)
+                ),
+            ),
+            field(
+                "Private",
+                if torrent.is_private() { "yes" } else { "no" }.to_string(),
+            ),
+        ];
+        lines.push(field(
+            "Order",
+            if torrent.sequential {
+                "sequential"
+            } else {
+                "rarest first"
+            }
+            .to_string(),
+        ));
+        if let Some(swarm) = &torrent.swarm {
+            lines.push(field(
+                "Swarm",
+                format!("{} seeders, {} leechers", swarm.complete, swarm.incomplete),
+            ));
+            lines.push(field("Completed", format!("{} times", swarm.downloaded)));
+        }
+        if let Some(source) = &torrent.source {
+            lines.push(field("Source", source.clone()));
+        }
+        if let Some(comment) = &torrent.comment {
+            lines.push(field("Comment", comment.clone()));
+        }
+        if let Some(created_by) = &torrent.created_by {
+            lines.push(field("Created by", created_by.clone()));
+        }
+        if let Some(creation_date) = torrent.creation_date {
+            lines.push(field("Created", creation_date.to_string()));
+        }
+        lines.push(Line::default());
+        lines.push(Line::styled(
+            "Trackers",
+            Style::default().add_modifier(Modifier::BOLD),
+        ));
+        lines.extend(torrent.announce.iter().map(|url| {
+            let Some(state) = torrent.trackers.iter().find(|state| &state.url == url) else {
+                return Line::raw(format!("  {url}"));
+            };
+            let status = match state.status {
+                TrackerStatus::NotContacted => Span::raw(""),
+                TrackerStatus::Working => match &state.warning {
+                    Some(warning) => Span::styled(
+                        format!("working: {warning}"),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    None => Span::styled("working", Style::default().fg(Color::Green)),
+                },
+                TrackerStatus::Failing => Span::styled(
+                    format!(
+                        "failed {}×: {}",
+                        state.failures,
+                        state.last_error.as_deref().unwrap_or_default()
+                    ),
+                    Style::default().fg(Color::Red),
+                ),
+            };
+            Line::from(vec![Span::raw(format!("  {url} ")), status])
+        }));
+        if !torrent.web_seeds.is_empty() {
+            lines.push(Line::styled(
+                "Web seeds",
+                Style::default().add_modifier(Modifier::BOLD),
+            ));
+            lines.extend(
+                torrent
+                    .web_seeds
+                    .iter()
+                    .map(|url| Line::raw(format!("  {url}"))),
+            );
+        }
+
+        let details = Paragraph::new(lines)
+            .block(block)
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(details, area);
+    }
+
+    /// Tab names with the open one highlighted; `t` switches.
+    fn title(&self) -> Line<'static> {
+        let tab = |name: &'static str, tab: DetailsTab| {
+            if self.tab == tab {
+                Span::styled(name, Style::default().add_modifier(Modifier::BOLD))
+            } else {
+                Span::styled(name, Style::default().fg(Color::DarkGray))
+            }
+        };
+        let mut spans = vec![
+            Span::raw(" "),
+            tab("Details", DetailsTab::Overview),
+            Span::raw(" │ "),
+            tab("Trackers", DetailsTab::Trackers),
+            Span::raw(" │ "),
+            tab("Pieces", DetailsTab::Pieces),
+        ];
+        if self.swarm_map.is_some() {
+            spans.push(Span::raw(" │ "));
+            spans.push(tab("Swarm", DetailsTab::Swarm));
+        }
+        spans.push(Span::raw(" "));
+        Line::from(spans)
+    }
+
+    fn render_trackers(&self, frame: &mut Frame, area: Rect, torrent: &Metadata, block: Block) {
+        let rows = torrent.announce.iter().map(|url| {
+            match torrent.trackers.iter().find(|state| &state.url == url) {
+                Some(state) => tracker_row(state),
+                None => Row::new([url.clone(), "not contacted".to_string()])
+                    .style(Style::default().fg(Color::DarkGray)),
+            }
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Fill(2),
+                Constraint::Length(14),
+                Constraint::Length(6),
+                Constraint::Fill(3),
+            ],
+        )
+        .header(
+            Row::new(["URL", "Status", "Peers", "Message"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(block);
+
+        // In this tab the scroll position is the selected tracker.
+        let selected = torrent
+            .announce
+            .len()
+            .checked_sub(1)
+            .map(|last| usize::from(self.scroll).min(last));
+        let mut state = TableState::default().with_selected(selected);
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    /// One row per piece with the sources that supplied it; only the rows on screen are
+    /// built, as torrents can have many thousands of pieces.
+    fn render_pieces(
+        &self,
+        frame: &mut Frame,
+        area: Rect,
+        torrent: &Metadata,
+        attribution: Option<&PieceAttribution>,
+        block: Block,
+    ) {
+        // In this tab the scroll position is the selected piece.
+        let Some(selected) = self.selected_piece(torrent) else {
+            frame.render_widget(Paragraph::new("No pieces").block(block), area);
+            return;
+        };
+        let last = torrent.piece_count() - 1;
+        // Borders and the header row.
+        let visible = usize::from(area.height.saturating_sub(3)).max(1);
+        let first = selected.saturating_sub(visible - 1);
+        let rows = (first..=last.min(first + visible - 1)).map(|piece| {
+            match attribution.and_then(|attribution| attribution.get(piece)) {
+                Some(contribution) => {
+                    let verified_at = contribution
+                        .verified_at
+                        .duration_since(UNIX_EPOCH)
+                        .map(|since| since.as_secs())
+                        .unwrap_or_default();
+                    let sources = contribution
+                        .sources
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    Row::new([
+                        piece.to_string(),
+                        format!("{} {}", format_date(verified_at), format_time(verified_at)),
+                        sources,
+                    ])
+                }
+                None => Row::new([piece.to_string(), String::new(), "unknown".to_string()])
+                    .style(Style::default().fg(Color::DarkGray)),
+            }
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(8),
+                Constraint::Length(17),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(["Piece", "Verified", "Sources"])
+                .style(Style::default().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .block(block);
+
+        let mut state = TableState::default().with_selected(Some(selected - first));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+}
+
+/// A tracker's status; the message column explains a failure or repeats its warning,
+/// which is often why a working tracker returns no peers. Otherwise it warns when we
+/// announce about as often as the tracker's `min interval` allows, or tells where
+/// announces go when a rewrite rule or a redirect sent them elsewhere.
+fn tracker_row(state: &TrackerState) -> Row<'static> {
+    let peers = state
+        .peers
+        .map(|peers| peers.to_string())
+        .unwrap_or_default();
+    let (status, message, color) = match state.status {
+        TrackerStatus::NotContacted => ("not contacted".to_string(), None, Color::DarkGray),
+        TrackerStatus::Working => (
+            "working".to_string(),
+            state.warning.clone(),
+            if state.warning.is_some() {
+                Color::Yellow
+            } else {
+                Color::Green
+            },
+        ),
+        TrackerStatus::Failing => (
+            format!("failed {}×", state.failures),
+            state.last_error.clone(),
+            Color::Red,
+        ),
+    };
+    let message = match (message, tracker_pace(state)) {
+        (Some(message), _) => Line::styled(message, Style::default().fg(color)),
+        (None, Some((pace, color))) => Line::styled(pace, Style::default().fg(color)),
+        (None, None) => Line::styled(tracker_route(state), Style::default().fg(Color::DarkGray)),
+    };
+    Row::new([
+        Line::raw(state.url.clone()),
+        Line::styled(status, Style::default().fg(color)),
+        Line::raw(peers),
+        message,
+    ])
+}
+
+fn tracker_pace(state: &TrackerState) -> Option<(String, Color)> {
+    let min_interval = state.min_interval?.as_secs();
+    match state.pace {
+        AnnouncePace::Relaxed => None,
+        AnnouncePace::Close => Some((
+            format!("announcing close to the {min_interval}s min interval"),
+            Color::Yellow,
+        )),
+        AnnouncePace::TooSoon => Some((
+            format!(
+                "announced before the {min_interval}s min interval ({}×)",
+                state.too_soon
+            ),
+            Color::Red,
+        )),
+    }
+}
+
+fn tracker_route(state: &TrackerState) -> String {
+    match (&state.redirected_to, &state.rewritten_to) {
+        (Some(target), _) => format!("redirected to {target}"),
+        (None, Some(target)) => format!("rewritten to {target}"),
+        (None, None) => String::new(),
+    }
+}
+
+fn field(label: &str, value: String) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(format!("{label:<12}"), Style::default().fg(Color::DarkGray)),
+        Span::raw(value),
+    ])
+}
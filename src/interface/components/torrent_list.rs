@@ -0,0 +1,232 @@
+use std::cmp::Ordering;
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    Frame,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, BorderType, Row, Table, TableState},
+};
+
+use crate::config::{Column, SortKey, View};
+use crate::format::{UnitSystem, format_rate, format_size};
+use crate::metadata::Metadata;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TorrentListMessage {
+    SelectNext,
+    SelectPrevious,
+    SelectFirst,
+    SelectLast,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TorrentList {
+    state: TableState,
+}
+
+impl TorrentList {
+    /// Selected row; an index into [`visible`], not into the torrent list.
+    pub fn selected(&self) -> Option<usize> {
+        self.state.selected()
+    }
+
+    pub fn handle_key(&self, key: KeyEvent) -> Option<TorrentListMessage> {
+        match key.code {
+            KeyCode::Down | KeyCode::Char('j') => Some(TorrentListMessage::SelectNext),
+            KeyCode::Up | KeyCode::Char('k') => Some(TorrentListMessage::SelectPrevious),
+            KeyCode::Home | KeyCode::Char('g') => Some(TorrentListMessage::SelectFirst),
+            KeyCode::End | KeyCode::Char('G') => Some(TorrentListMessage::SelectLast),
+            _ => None,
+        }
+    }
+
+    pub fn update(&mut self, msg: TorrentListMessage, len: usize) {
+        if len == 0 {
+            self.state.select(None);
+            return;
+        }
+
+        let last = len - 1;
+        let selected = self.state.selected().map(|index| index.min(last));
+        self.state.select(Some(match msg {
+            TorrentListMessage::SelectNext => selected.map_or(0, |index| (index + 1).min(last)),
+            TorrentListMessage::SelectPrevious => {
+                selected.map_or(0, |index| index.saturating_sub(1))
+            }
+            TorrentListMessage::SelectFirst => 0,
+            TorrentListMessage::SelectLast => last,
+        }));
+    }
+
+    pub fn render(
+        &mut self,
+        frame: &mut Frame,
+        area: Rect,
+        torrents: &[Metadata],
+        view: &View,
+        units: UnitSystem,
+        focused: bool,
+    ) {
+        let visible = visible(torrents, view);
+        match self.state.selected() {
+            None if !visible.is_empty() => self.state.select(Some(0)),
+            Some(index) if index >= visible.len() => {
+                self.state.select(visible.len().checked_sub(1))
+            }
+            _ => {}
+        }
+
+        let rows = visible.iter().map(|index| {
+            let torrent = &torrents[*index];
+            Row::new(
+                view.columns
+                    .iter()
+                    .map(|column| cell(torrent, *column, units)),
+            )
+        });
+        let header = Row::new(view.columns.iter().map(|column| {
+            let title = title(*column);
+            if sort_column(view.sort) == *column {
+                format!("{title} {}", if view.descending { "▼" } else { "▲" })
+            } else {
+                title.to_string()
+            }
+        }))
+        .style(Style::default().fg(Color::DarkGray));
+        let widths = view.columns.iter().map(|column| width(*column));
+
+        let border_style = if focused {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+
+        let mut title = format!(" {} ({}", view.name, visible.len());
+        if visible.len() != torrents.len() {
+            title.push_str(&format!(" of {}", torrents.len()));
+        }
+        title.push_str(") ");
+
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(
+                Block::bordered()
+                    .border_type(BorderType::Rounded)
+                    .border_style(border_style)
+                    .title(title),
+            )
+            .row_highlight_style(
+                Style::default()
+                    .fg(Color::Black)
+                    .bg(Color::Cyan)
+                    .add_modifier(Modifier::BOLD),
+            );
+
+        frame.render_stateful_widget(table, area, &mut self.state);
+    }
+}
+
+/// Indices of the torrents `view` shows, in display order.
+pub fn visible(torrents: &[Metadata], view: &View) -> Vec<usize> {
+    let filter = view.filter.as_ref().map(|filter| filter.to_lowercase());
+    let mut indices = (0..torrents.len())
+        .filter(|index| {
+            let torrent = &torrents[*index];
+            view.label
+                .as_ref()
+                .is_none_or(|label| torrent.label.as_ref() == Some(label))
+                && filter
+                    .as_ref()
+                    .is_none_or(|filter| torrent.name.to_lowercase().contains(filter))
+        })
+        .collect::<Vec<_>>();
+
+    indices.sort_by(|a, b| {
+        let (a, b) = (&torrents[*a], &torrents[*b]);
+        let group = if view.group_by_label {
+            a.label.cmp(&b.label)
+        } else {
+            Ordering::Equal
+        };
+        let order = compare(a, b, view.sort);
+        group.then(if view.descending {
+            order.reverse()
+        } else {
+            order
+        })
+    });
+    indices
+}
+
+fn compare(a: &Metadata, b: &Metadata, key: SortKey) -> Ordering {
+    match key {
+        SortKey::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        SortKey::Size => a.length.cmp(&b.length),
+        SortKey::Label => a.label.cmp(&b.label),
+        SortKey::Ratio => {
+            let ratio = |torrent: &Metadata| torrent.stats.ratio().unwrap_or(-1.0);
+            ratio(a).total_cmp(&ratio(b))
+        }
+        SortKey::DownloadRate => a.stats.download_rate.cmp(&b.stats.download_rate),
+        SortKey::UploadRate => a.stats.upload_rate.cmp(&b.stats.upload_rate),
+    }
+}
+
+fn sort_column(key: SortKey) -> Column {
+    match key {
+        SortKey::Name => Column::Name,
+        SortKey::Size => Column::Size,
+        SortKey::Label => Column::Label,
+        SortKey::Ratio => Column::Ratio,
+        SortKey::DownloadRate => Column::DownloadRate,
+        SortKey::UploadRate => Column::UploadRate,
+    }
+}
+
+fn title(column: Column) -> &'static str {
+    match column {
+        Column::Name => "Name",
+        Column::Status => "Status",
+        Column::Size => "Size",
+        Column::Label => "Label",
+        Column::Ratio => "Ratio",
+        Column::Downloaded => "Downloaded",
+        Column::Uploaded => "Uploaded",
+        Column::DownloadRate => "↓ Rate",
+        Column::UploadRate => "↑ Rate",
+        Column::Peers => "Peers",
+    }
+}
+
+fn width(column: Column) -> Constraint {
+    match column {
+        Column::Name => Constraint::Fill(1),
+        Column::Label => Constraint::Length(12),
+        Column::Status => Constraint::Length(15),
+        Column::Ratio | Column::Peers => Constraint::Length(7),
+        Column::Size
+        | Column::Downloaded
+        | Column::Uploaded
+        | Column::DownloadRate
+        | Column::UploadRate => Constraint::Length(12),
+    }
+}
+
+fn cell(torrent: &Metadata, column: Column, units: UnitSystem) -> String {
+    let stats = &torrent.stats;
+    match column {
+        Column::Name => torrent.name.clone(),
+        Column::Status => torrent.state.describe(),
+        Column::Size => format_size(torrent.length, units),
+        Column::Label => torrent.label.clone().unwrap_or_default(),
+        Column::Ratio => stats
+            .ratio()
+            .map_or_else(|| "-".to_string(), |ratio| format!("{ratio:.2}")),
+        Column::Downloaded => format_size(stats.downloaded, units),
+        Column::Uploaded => format_size(stats.uploaded, units),
+        Column::DownloadRate => format_rate(stats.download_rate, units),
+        Column::UploadRate => format_rate(stats.upload_rate, units),
+        Column::Peers => torrent.peers.len().to_string(),
+    }
+}
@@ -0,0 +1,56 @@
+use std::backtrace::Backtrace;
+use std::fs;
+use std::io;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+
+use crossterm::event::DisableFocusChange;
+use crossterm::execute;
+
+use crate::history::unix_now;
+
+/// Makes a panic leave a usable terminal and a crash report behind.
+///
+/// The hook restores the terminal before anything is printed, writes the panic message and
+/// a backtrace to the data directory, then lets the previous hook print the message as usual.
+pub fn install_panic_hook() {
+    let previous = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        let report = write_report(info);
+        previous(info);
+        match report {
+            Ok(path) => eprintln!(
+                "terrent crashed; a report was written to {}. Please attach it when reporting \
+                 the bug.",
+                path.display()
+            ),
+            Err(err) => eprintln!("terrent crashed, and the crash report failed too: {err}"),
+        }
+    }));
+}
+
+/// Leaves raw mode and the alternate screen; safe to call more than once.
+pub fn restore_terminal() {
+    let _ = execute!(io::stdout(), DisableFocusChange);
+    ratatui::restore();
+}
+
+fn crash_dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("terrent").join("crashes"))
+}
+
+fn write_report(info: &PanicHookInfo) -> io::Result<PathBuf> {
+    let dir = crash_dir().ok_or_else(|| io::Error::other("No data directory available"))?;
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("crash-{}.txt", unix_now()));
+
+    // The hook info reads "panicked at <location>:" followed by the message.
+    let report = format!(
+        "terrent {}\n{info}\n\n{}\n",
+        env!("CARGO_PKG_VERSION"),
+        Backtrace::force_capture()
+    );
+    fs::write(&path, report)?;
+    Ok(path)
+}
@@ -0,0 +1,313 @@
+//! Keys of the torrents screen and what they do, in one table that both dispatches key
+//! presses and labels the hint bar, so the bar cannot drift from the real bindings.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// Where a binding applies; several hold at once, e.g. `Details` and `Trackers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyContext {
+    Anywhere,
+    /// The split layout, with both panes on screen.
+    Split,
+    List,
+    Details,
+    /// The trackers tab of the details pane.
+    Trackers,
+    /// The pieces tab of the details pane.
+    Pieces,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    ToggleSidebar,
+    Statistics,
+    Peers,
+    History,
+    ToggleLayout,
+    CycleSort,
+    ReverseSort,
+    CycleLabelFilter,
+    ToggleGrouping,
+    SaveView,
+    Retracker,
+    FocusNext,
+    OpenDetails,
+    CloseDetails,
+    AddTracker,
+    RemoveTracker,
+    InspectPiece,
+    RemoveTorrent,
+    Schedule,
+    CancelSchedule,
+    StartNow,
+    ToggleSequential,
+    /// Asks the daemon to announce the selected torrent.
+    AnnounceNow,
+    /// Asks the daemon to announce to the highlighted tracker right away.
+    AnnounceTo,
+}
+
+pub struct Binding {
+    /// The first key is the one the hint bar shows.
+    pub keys: &'static [KeyCode],
+    pub context: KeyContext,
+    pub action: Action,
+    /// Label in the hint bar; `None` keeps the binding out of it.
+    pub hint: Option<&'static str>,
+}
+
+const fn bind(
+    keys: &'static [KeyCode],
+    context: KeyContext,
+    action: Action,
+    hint: Option<&'static str>,
+) -> Binding {
+    Binding {
+        keys,
+        context,
+        action,
+        hint,
+    }
+}
+
+/// Bindings in hint bar order, most relevant first: the bar drops what does not fit.
+pub const BINDINGS: &[Binding] = &[
+    bind(
+        &[KeyCode::Enter],
+        KeyContext::List,
+        Action::OpenDetails,
+        Some("Details"),
+    ),
+    bind(
+        &[KeyCode::Esc],
+        KeyContext::Details,
+        Action::CloseDetails,
+        Some("Back"),
+    ),
+    bind(
+        &[KeyCode::Char('a')],
+        KeyContext::Trackers,
+        Action::AddTracker,
+        Some("Add"),
+    ),
+    bind(
+        &[KeyCode::Char('x'), KeyCode::Delete],
+        KeyContext::Trackers,
+        Action::RemoveTracker,
+        Some("Remove"),
+    ),
+    bind(
+        &[KeyCode::Char('A')],
+        KeyContext::Trackers,
+        Action::AnnounceTo,
+        Some("Announce"),
+    ),
+    bind(
+        &[KeyCode::Char('d')],
+        KeyContext::Pieces,
+        Action::InspectPiece,
+        Some("Dump"),
+    ),
+    bind(
+        &[KeyCode::Char('i')],
+        KeyContext::Details,
+        Action::ToggleSequential,
+        Some("In order"),
+    ),
+    bind(
+        &[KeyCode::Char('x'), KeyCode::Delete],
+        KeyContext::List,
+        Action::RemoveTorrent,
+        Some("Remove"),
+    ),
+    bind(
+        &[KeyCode::Char('S')],
+        KeyContext::List,
+        Action::Schedule,
+        Some("Schedule"),
+    ),
+    bind(
+        &[KeyCode::Char('C')],
+        KeyContext::List,
+        Action::CancelSchedule,
+        Some("Unschedule"),
+    ),
+    bind(
+        &[KeyCode::Char('N')],
+        KeyContext::List,
+        Action::StartNow,
+        Some("Start"),
+    ),
+    bind(
+        &[KeyCode::Char('A')],
+        KeyContext::List,
+        Action::AnnounceNow,
+        Some("Announce"),
+    ),
+    bind(
+        &[KeyCode::Tab],
+        KeyContext::Split,
+        Action::FocusNext,
+        Some("Pane"),
+    ),
+    bind(
+        &[KeyCode::Char('o')],
+        KeyContext::Anywhere,
+        Action::CycleSort,
+        Some("Sort"),
+    ),
+    bind(
+        &[KeyCode::Char('f')],
+        KeyContext::Anywhere,
+        Action::CycleLabelFilter,
+        Some("Filter"),
+    ),
+    bind(
+        &[KeyCode::Char('s')],
+        KeyContext::Anywhere,
+        Action::Statistics,
+        Some("Stats"),
+    ),
+    bind(
+        &[KeyCode::Char('p')],
+        KeyContext::Anywhere,
+        Action::Peers,
+        Some("Peers"),
+    ),
+    bind(
+        &[KeyCode::Char('H')],
+        KeyContext::Anywhere,
+        Action::History,
+        Some("History"),
+    ),
+    bind(
+        &[KeyCode::Char('v')],
+        KeyContext::Anywhere,
+        Action::ToggleLayout,
+        Some("Layout"),
+    ),
+    bind(
+        &[KeyCode::Char('q')],
+        KeyContext::Anywhere,
+        Action::Quit,
+        Some("Quit"),
+    ),
+    bind(
+        &[KeyCode::Char('O')],
+        KeyContext::Anywhere,
+        Action::ReverseSort,
+        None,
+    ),
+    bind(
+        &[KeyCode::Char('b')],
+        KeyContext::Anywhere,
+        Action::ToggleSidebar,
+        None,
+    ),
+    bind(
+        &[KeyCode::Char('L')],
+        KeyContext::Anywhere,
+        Action::ToggleGrouping,
+        None,
+    ),
+    bind(
+        &[KeyCode::Char('W')],
+        KeyContext::Anywhere,
+        Action::SaveView,
+        None,
+    ),
+    bind(
+        &[KeyCode::Char('R')],
+        KeyContext::Anywhere,
+        Action::Retracker,
+        None,
+    ),
+];
+
+/// The action `key` triggers where `contexts` hold. Keys with modifiers other than shift
+/// never match, so e.g. Ctrl-S is not taken for `s`.
+pub fn action(key: KeyEvent, contexts: &[KeyContext]) -> Option<Action> {
+    if !(key.modifiers - KeyModifiers::SHIFT).is_empty() {
+        return None;
+    }
+    BINDINGS
+        .iter()
+        .find(|binding| contexts.contains(&binding.context) && binding.keys.contains(&key.code))
+        .map(|binding| binding.action)
+}
+
+/// Key and label of every hinted binding where `contexts` hold, in bar order.
+pub fn hints(contexts: &[KeyContext]) -> impl Iterator<Item = (String, &'static str, Action)> {
+    BINDINGS.iter().filter_map(|binding| {
+        let hint = binding.hint?;
+        contexts
+            .contains(&binding.context)
+            .then(|| (key_label(binding.keys[0]), hint, binding.action))
+    })
+}
+
+fn key_label(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        key => format!("{key:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_resolve_by_context() {
+        let press = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let list = [KeyContext::Anywhere, KeyContext::List];
+        let trackers = [
+            KeyContext::Anywhere,
+            KeyContext::Details,
+            KeyContext::Trackers,
+        ];
+        assert_eq!(
+            action(press(KeyCode::Char('x')), &list),
+            Some(Action::RemoveTorrent)
+        );
+        assert_eq!(
+            action(press(KeyCode::Delete), &trackers),
+            Some(Action::RemoveTracker)
+        );
+        assert_eq!(action(press(KeyCode::Tab), &list), None);
+        assert_eq!(
+            action(
+                KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL),
+                &list
+            ),
+            None
+        );
+
+        let pieces = [
+            KeyContext::Anywhere,
+            KeyContext::Details,
+            KeyContext::Pieces,
+        ];
+        assert_eq!(
+            action(press(KeyCode::Char('d')), &pieces),
+            Some(Action::InspectPiece)
+        );
+        assert_eq!(action(press(KeyCode::Char('d')), &trackers), None);
+        assert_eq!(
+            action(press(KeyCode::Char('A')), &trackers),
+            Some(Action::AnnounceTo)
+        );
+        assert_eq!(
+            action(press(KeyCode::Char('A')), &list),
+            Some(Action::AnnounceNow)
+        );
+
+        let shown = hints(&trackers).map(|(key, ..)| key).collect::<Vec<_>>();
+        assert_eq!(shown[..3], ["Esc", "a", "x"]);
+    }
+}
@@ -1,28 +1,211 @@
 pub mod components;
+mod crash;
+mod keymap;
+mod redraw;
 
-use std::time::Duration;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::time::{Duration, Instant};
 
+use anyhow::Result;
 use components::confirmation_popup::ConfirmationMessage;
-use components::{ConfirmationPopup, ConfirmationResult};
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
-use ratatui::{Frame, widgets::Paragraph};
+use components::history::HistoryMessage;
+use components::peers::PeersMessage;
+use components::retracker_form::RetrackerMessage;
+use components::text_input::TextInputMessage;
+use components::torrent_details::TorrentDetailsMessage;
+use components::torrent_list::{self, TorrentListMessage};
+use components::{
+    AwaySummary, ConfirmationPopup, ConfirmationResult, HintBar, HistoryList, IntegritySummary,
+    LabelSidebar, Peers, RetrackerForm, Statistics, TextInputPopup, Toast, ToastKind,
+    TorrentDetails, TorrentList,
+};
+use crossterm::event::{self, EnableFocusChange, Event, KeyCode, KeyModifiers};
+use crossterm::execute;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout},
+};
+use url::Url;
 
-#[derive(Debug, Clone)]
+use keymap::{Action, KeyContext};
+use redraw::RedrawPolicy;
+
+use crate::config::{Config, ConfigWatcher, LayoutMode, Prompt};
+use crate::download::{
+    AssumedCheck, PieceAttribution, Relocation, export_piece, relocate_completed,
+};
+use crate::file::TorrentFile;
+use crate::format::hex;
+use crate::history::{self, History};
+use crate::metadata::Metadata;
+use crate::notify::{Notification, Notifier};
+use crate::power::{PowerMonitor, TransferMode};
+use crate::progress::ProgressFile;
+use crate::queue::{self, TorrentState};
+use crate::remote::{RemoteAction, RemoteClient};
+use crate::session;
+use crate::stats::Snapshot;
+use crate::tracker::TrackerEdits;
+use crate::tracker::rewrite::{replace_host, replace_hosts};
+
+/// How often an attached interface asks the daemon for a fresh snapshot.
+const REMOTE_REFRESH: Duration = Duration::from_secs(1);
+/// How often the battery and metered state are checked against the power rules.
+const POWER_POLL: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
 struct Model {
     running_state: RunningState,
+    config: Config,
+    torrents: Vec<Metadata>,
+    focus: Pane,
+    torrent_list: TorrentList,
+    torrent_details: TorrentDetails,
     exit_confirmation: ConfirmationPopup,
+    remove_confirmation: ConfirmationPopup,
+    tracker_confirmation: ConfirmationPopup,
+    /// Tracker the open `tracker_confirmation` asks about.
+    pending_tracker: Option<String>,
+    /// Piece sources of the torrent last shown in the pieces tab, by info hash.
+    attribution: Option<([u8; 20], PieceAttribution)>,
+    label_sidebar: LabelSidebar,
+    hint_bar: HintBar,
+    statistics: Statistics,
+    peers: Peers,
+    /// Removed torrents, loaded once at startup and saved after every change.
+    history: History,
+    history_list: HistoryList,
+    screen: Screen,
+    /// Taken when the terminal lost focus, to summarize what changed once it is back.
+    away_since: Option<Snapshot>,
+    away_summary: AwaySummary,
+    /// Asks for a tracker to add to the selected torrent.
+    tracker_input: TextInputPopup,
+    /// Asks for the local time to start the selected torrent at.
+    schedule_input: TextInputPopup,
+    /// Asks for a tracker host to move every torrent away from.
+    retracker_form: RetrackerForm,
+    notifier: Notifier,
+    /// Taken after the last round of desktop notifications; `None` when they are off.
+    notified: Option<Snapshot>,
+    /// Taken after the last completed-move pass, to find torrents that finished since.
+    relocated: Snapshot,
+    /// Background checks of torrents taken as complete when added.
+    assumed: Vec<AssumedCheck>,
+    power: PowerMonitor,
+    /// When the power rules were last checked; `None` until the first pass.
+    power_polled: Option<Instant>,
+    config_watcher: ConfigWatcher,
+    progress_file: ProgressFile,
+    toast: Toast,
+    /// Damaged files the startup check moved aside, shown until dismissed.
+    integrity_summary: IntegritySummary,
+    /// The daemon whose torrents are shown, when attached to one; `None` for a local session.
+    remote: Option<Remote>,
 }
 
-impl Default for Model {
-    fn default() -> Self {
+#[derive(Debug)]
+struct Remote {
+    client: RemoteClient,
+    refreshed: Instant,
+    /// Whether the last request failed, so a lost connection is reported once.
+    failing: bool,
+}
+
+impl Model {
+    fn new(config: Config, torrents: Vec<Metadata>) -> Self {
+        // Runs before anything is loaded from the data directory, so damaged files are
+        // already out of the way.
+        let integrity = session::check_data_dir();
+        let notifier = Notifier::new(config.notifications.clone());
+        let notified = notifier
+            .is_enabled()
+            .then(|| Snapshot::take(&torrents, Instant::now()));
+        let relocated = Snapshot::take(&torrents, Instant::now());
+        let progress_file = ProgressFile::new(&config.progress_file);
+        let mut torrent_details = TorrentDetails::default();
+        torrent_details.set_swarm_map(config.interface.swarm_map);
         Self {
             running_state: RunningState::default(),
+            config,
+            torrents,
+            focus: Pane::default(),
+            torrent_list: TorrentList::default(),
+            torrent_details,
             exit_confirmation: ConfirmationPopup::new(
                 "Confirm Exit",
                 "Are you sure you want to quit?",
-            ),
+            )
+            .with_dont_ask_again(),
+            remove_confirmation: ConfirmationPopup::new(
+                "Remove Torrent",
+                "Remove the selected torrent? It stays in the history.",
+            )
+            .with_dont_ask_again(),
+            tracker_confirmation: ConfirmationPopup::new(
+                "Remove Tracker",
+                "Remove the selected tracker from this torrent?",
+            )
+            .with_dont_ask_again(),
+            pending_tracker: None,
+            attribution: None,
+            label_sidebar: LabelSidebar,
+            hint_bar: HintBar,
+            statistics: Statistics,
+            peers: Peers::default(),
+            // A damaged history only costs the list of removed torrents, not the session.
+            history: History::load().unwrap_or_default(),
+            history_list: HistoryList::default(),
+            screen: Screen::default(),
+            away_since: None,
+            away_summary: AwaySummary::default(),
+            tracker_input: TextInputPopup::new("Add tracker"),
+            retracker_form: RetrackerForm::default(),
+            schedule_input: TextInputPopup::new("Start at (HH:MM)"),
+            notifier,
+            notified,
+            relocated,
+            assumed: Vec::new(),
+            power: PowerMonitor::new(),
+            power_polled: None,
+            config_watcher: ConfigWatcher::new(),
+            progress_file,
+            toast: Toast::default(),
+            integrity_summary: IntegritySummary::new(integrity),
+            remote: None,
         }
     }
+
+    /// Indices of the torrents shown by the current view, in display order.
+    fn visible(&self) -> Vec<usize> {
+        torrent_list::visible(&self.torrents, &self.config.interface.view)
+    }
+
+    fn selected_torrent(&self) -> Option<&Metadata> {
+        self.selected_index().map(|index| &self.torrents[index])
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        let visible = self.visible();
+        self.torrent_list
+            .selected()
+            .and_then(|index| visible.get(index))
+            .copied()
+    }
+
+    /// Labels in use, for cycling the label filter.
+    fn labels(&self) -> Vec<String> {
+        let mut labels = self
+            .torrents
+            .iter()
+            .filter_map(|torrent| torrent.label.clone())
+            .collect::<Vec<_>>();
+        labels.sort();
+        labels.dedup();
+        labels
+    }
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -32,45 +215,589 @@ enum RunningState {
     Done,
 }
 
+/// Full-screen views that replace the torrent panes while open.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Screen {
+    #[default]
+    Torrents,
+    Statistics,
+    Peers,
+    History,
+}
+
+/// Pane receiving key input; in full-screen layout it is also the only pane shown.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    #[default]
+    List,
+    Details,
+}
+
 #[derive(PartialEq, Eq)]
 enum Message {
     Quit,
     ShowExitConfirmation,
     ExitConfirmation(ConfirmationMessage),
+    ShowRemoveTorrent,
+    RemoveConfirmation(ConfirmationMessage),
+    RemoveTorrent,
+    History(HistoryMessage),
+    /// Adds the torrent of a history entry, by hex info hash, back to the session.
+    Readd(String),
+    /// Drops a history entry, by hex info hash, and its kept `.torrent` file.
+    Forget(String),
+    TorrentList(TorrentListMessage),
+    TorrentDetails(TorrentDetailsMessage),
+    Peers(PeersMessage),
+    Focus(Pane),
+    FocusNext,
+    ToggleLayout,
+    ToggleSidebar,
+    ToggleScreen(Screen),
+    CycleSort,
+    ReverseSort,
+    CycleLabelFilter,
+    ToggleGrouping,
+    SaveView,
+    LoadView(usize),
+    FocusLost,
+    FocusGained,
+    DismissAwaySummary,
+    DismissIntegritySummary,
+    ShowAddTracker,
+    TrackerInput(TextInputMessage),
+    ShowRemoveTracker(String),
+    TrackerConfirmation(ConfirmationMessage),
+    RemoveTracker(String),
+    /// Dumps a piece of the selected torrent from disk and checks its hash.
+    InspectPiece(usize),
+    ShowRetracker,
+    ShowSchedule,
+    ScheduleInput(TextInputMessage),
+    /// Drops the pending start of the selected torrent, leaving it stopped.
+    CancelSchedule,
+    /// Starts the selected torrent now, whether it was stopped or scheduled.
+    StartNow,
+    /// Switches the selected torrent between in-order and rarest-first downloading.
+    ToggleSequential,
+    /// Has the daemon announce the selected torrent as soon as its trackers allow.
+    AnnounceNow,
+    /// Has the daemon announce the selected torrent to this tracker right away.
+    AnnounceTo(String),
+    Retracker(RetrackerMessage),
+    /// The config file was edited; applies what can change without a restart.
+    ReloadConfig,
+    /// Nothing to update, but the screen is out of date, e.g. after a resize.
+    Redraw,
 }
 
-pub fn init() {
+/// Runs the interface; `low_bandwidth` starts in the reduced-redraw mode meant for slow
+/// SSH links, which is also switched on by itself when frames are slow to flush.
+pub fn init(
+    config: Config,
+    torrents: Vec<Metadata>,
+    checks: Vec<AssumedCheck>,
+    low_bandwidth: bool,
+) {
+    let mut model = Model::new(config, torrents);
+    model.assumed = checks;
+    run(model, low_bandwidth);
+}
+
+/// Runs the interface as a client of the daemon at `address`: it shows the daemon's
+/// torrents and sends the user's actions there. Fails before taking over the terminal if
+/// the daemon cannot be reached.
+pub fn attach(config: Config, address: &str, low_bandwidth: bool) -> Result<()> {
+    let mut client = RemoteClient::connect(address, &config.remote)?;
+    let torrents = client.snapshot()?;
+    let mut model = Model::new(config, torrents);
+    model.remote = Some(Remote {
+        client,
+        refreshed: Instant::now(),
+        failing: false,
+    });
+    run(model, low_bandwidth);
+    Ok(())
+}
+
+fn run(mut model: Model, low_bandwidth: bool) {
     let mut terminal = ratatui::init();
-    let mut model = Model::default();
+    crash::install_panic_hook();
+    // Terminals that do not report focus changes simply never show the away summary.
+    let _ = execute!(io::stdout(), EnableFocusChange);
+    let mut redraw = RedrawPolicy::new(low_bandwidth);
+
+    // The hook already restored the terminal and reported the panic; restoring again here
+    // also covers a hook replaced by a dependency.
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        while model.running_state != RunningState::Done {
+            if redraw.should_draw(Instant::now()) {
+                let started = Instant::now();
+                let _ = terminal
+                    .draw(|f| {
+                        view(&mut model, f);
+                        if redraw.is_low_bandwidth() {
+                            redraw::simplify_borders(f.buffer_mut());
+                        }
+                    })
+                    .unwrap();
+                redraw.drawn(started.elapsed(), Instant::now());
+            }
+
+            let mut message = handle_event(&mut model);
+            if message.is_none() && model.config_watcher.changed() {
+                message = Some(Message::ReloadConfig);
+            }
+            if model.toast.expire(Instant::now()) {
+                redraw.invalidate();
+            }
+            if message.is_some() {
+                redraw.invalidate();
+            }
+
+            while message.is_some() {
+                message = update(&mut model, message.unwrap());
+            }
+
+            // An attached daemon starts its scheduled torrents itself.
+            if model.remote.is_none() && start_scheduled(&mut model) {
+                redraw.invalidate();
+            }
+            if refresh_remote(&mut model) {
+                redraw.invalidate();
+            }
+            // An attached daemon moves its own finished torrents.
+            if model.remote.is_none() {
+                relocate_finished(&mut model);
+            }
+            if finish_assumed(&mut model) {
+                redraw.invalidate();
+            }
+            if poll_power(&mut model) {
+                redraw.invalidate();
+            }
+            if load_attribution(&mut model) {
+                redraw.invalidate();
+            }
+            notify_changes(&mut model);
+            write_progress(&mut model);
+        }
+    }));
+
+    crash::restore_terminal();
+    if let Err(payload) = result {
+        panic::resume_unwind(payload);
+    }
+}
+
+/// Starts the torrents whose scheduled time has come; returns whether any did.
+fn start_scheduled(model: &mut Model) -> bool {
+    let started = queue::start_due(&mut model.torrents, history::unix_now());
+    match started.as_slice() {
+        [] => return false,
+        [index] => {
+            let name = &model.torrents[*index].name;
+            model
+                .toast
+                .show(format!("Started {name}"), ToastKind::Info, Instant::now());
+        }
+        _ => model.toast.show(
+            format!("Started {} scheduled torrents", started.len()),
+            ToastKind::Info,
+            Instant::now(),
+        ),
+    }
+    true
+}
+
+/// Replaces the torrents with the daemon's every [`REMOTE_REFRESH`]; returns whether it did.
+fn refresh_remote(model: &mut Model) -> bool {
+    let Some(remote) = &mut model.remote else {
+        return false;
+    };
+    let now = Instant::now();
+    if now.duration_since(remote.refreshed) < REMOTE_REFRESH {
+        return false;
+    }
+    remote.refreshed = now;
+    match remote.client.snapshot() {
+        Ok(torrents) => {
+            if remote.failing {
+                remote.failing = false;
+                let message = format!("Reconnected to {}", remote.client.address());
+                model.toast.show(message, ToastKind::Info, now);
+            }
+            model.torrents = torrents;
+            true
+        }
+        Err(err) => {
+            let reported = std::mem::replace(&mut remote.failing, true);
+            if !reported {
+                model
+                    .toast
+                    .show(format!("{err:#}"), ToastKind::Warning, now);
+            }
+            !reported
+        }
+    }
+}
+
+/// Sends an action on the torrent with `info_hash` to the attached daemon, if any. The
+/// interface already applied it locally; the next snapshot shows what the daemon made of it.
+fn forward(model: &mut Model, info_hash: [u8; 20], action: RemoteAction) {
+    let Some(remote) = &mut model.remote else {
+        return;
+    };
+    if let Err(err) = remote.client.act(info_hash, action) {
+        model
+            .toast
+            .show(format!("{err:#}"), ToastKind::Warning, Instant::now());
+    }
+}
+
+/// Writes piece `index` of the selected torrent next to its data and tells whether it
+/// matches its hash, e.g. to look into a piece that keeps failing.
+fn inspect_piece(model: &mut Model, index: usize) {
+    let Some(torrent) = model.selected_torrent() else {
+        return;
+    };
+    let root = model.config.downloads.download_dir();
+    let output = root.join(format!("{}.piece-{index}.bin", torrent.name));
+    let result = match torrent.origin.as_deref() {
+        Some(origin) if Path::new(origin).is_file() => {
+            TorrentFile::open_with_limits(origin, &model.config.decode)
+                .and_then(|file| export_piece(&file, root, index, None, &output))
+        }
+        _ => Err(anyhow::anyhow!(
+            "The .torrent file of {} is not at hand",
+            torrent.name
+        )),
+    };
+    let (message, kind) = match result {
+        Ok(report) if report.matches() => (
+            format!("Wrote {}; piece {index} is intact", output.display()),
+            ToastKind::Info,
+        ),
+        Ok(report) => (
+            format!(
+                "Wrote {}; piece {index} has SHA-1 {}, expected {}",
+                output.display(),
+                hex(&report.computed),
+                hex(&report.expected)
+            ),
+            ToastKind::Warning,
+        ),
+        Err(err) => (format!("{err:#}"), ToastKind::Warning),
+    };
+    model.toast.show(message, kind, Instant::now());
+}
 
-    while model.running_state != RunningState::Done {
-        let _ = terminal.draw(|f| view(&mut model, f)).unwrap();
+/// Loads the piece sources of the selected torrent once the pieces tab shows it; returns
+/// whether they changed.
+fn load_attribution(model: &mut Model) -> bool {
+    if !model.torrent_details.is_pieces_tab() {
+        return false;
+    }
+    let Some(info_hash) = model.selected_torrent().map(|torrent| torrent.info_hash) else {
+        return false;
+    };
+    if model
+        .attribution
+        .as_ref()
+        .is_some_and(|(loaded, _)| *loaded == info_hash)
+    {
+        return false;
+    }
+    let attribution = PieceAttribution::load(&info_hash).unwrap_or_else(|err| {
+        model
+            .toast
+            .show(format!("{err:#}"), ToastKind::Warning, Instant::now());
+        PieceAttribution::default()
+    });
+    model.attribution = Some((info_hash, attribution));
+    true
+}
 
-        let mut message = handle_event(&mut model);
+fn selected_attribution<'a>(
+    model: &'a Model,
+    torrent: Option<&Metadata>,
+) -> Option<&'a PieceAttribution> {
+    let torrent = torrent?;
+    model
+        .attribution
+        .as_ref()
+        .filter(|(info_hash, _)| *info_hash == torrent.info_hash)
+        .map(|(_, attribution)| attribution)
+}
 
-        while message.is_some() {
-            message = update(&mut model, message.unwrap());
+/// Keeps the edited trackers of a local torrent for the next session; the daemon keeps
+/// those of its own torrents.
+fn save_trackers(model: &mut Model, info_hash: [u8; 20]) {
+    if model.remote.is_some() {
+        return;
+    }
+    let Some(torrent) = model
+        .torrents
+        .iter()
+        .find(|torrent| torrent.info_hash == info_hash)
+    else {
+        return;
+    };
+    if let Err(err) = TrackerEdits::persist(torrent) {
+        model
+            .toast
+            .show(format!("{err:#}"), ToastKind::Warning, Instant::now());
+    }
+}
+
+/// Refreshes the progress file; a failed write is retried on the next pass.
+fn write_progress(model: &mut Model) {
+    let _ = model
+        .progress_file
+        .update(&model.torrents, Instant::now(), history::unix_now());
+}
+
+/// Moves every torrent that finished since the last call to its completed directory.
+fn relocate_finished(model: &mut Model) {
+    let now = Instant::now();
+    let changes = model.relocated.changes(&model.torrents, now);
+    let downloads = &model.config.downloads;
+    for name in &changes.completed {
+        let Some(torrent) = model.torrents.iter().find(|torrent| &torrent.name == name) else {
+            continue;
+        };
+        let relocation = relocate_completed(
+            downloads,
+            torrent.label.as_deref(),
+            downloads.download_dir(),
+            name,
+        );
+        match relocation {
+            Ok(Relocation::NoRule) => {}
+            Ok(Relocation::Moved(to)) => {
+                let message = format!("Moved {name} to {}", to.display());
+                model.toast.show(message, ToastKind::Info, now);
+            }
+            Ok(Relocation::Skipped) => {
+                let message = format!("Left {name} in place: the destination exists");
+                model.toast.show(message, ToastKind::Warning, now);
+            }
+            Err(err) => model
+                .toast
+                .show(format!("{err:#}"), ToastKind::Warning, now),
         }
     }
+    model.relocated = Snapshot::take(&model.torrents, now);
+}
+
+/// Reports the background checks that finished and counts what they found missing as
+/// left again; returns whether any did.
+fn finish_assumed(model: &mut Model) -> bool {
+    let now = Instant::now();
+    let mut finished = false;
+    for check in &mut model.assumed {
+        let Some(missing) = check.finished() else {
+            continue;
+        };
+        finished = true;
+        let Some(torrent) = model
+            .torrents
+            .iter_mut()
+            .find(|torrent| torrent.info_hash == check.info_hash)
+        else {
+            continue;
+        };
+        if missing == 0 {
+            let message = format!("Verified {}", torrent.name);
+            model.toast.show(message, ToastKind::Info, now);
+        } else {
+            torrent.left = Some(torrent.left.unwrap_or_default() + missing);
+            let message = format!(
+                "Part of {} did not match and is missing again",
+                torrent.name
+            );
+            model.toast.show(message, ToastKind::Warning, now);
+        }
+    }
+    model.assumed.retain(|check| !check.is_done());
+    finished
+}
+
+/// Checks the power rules every [`POWER_POLL`] and reports when the transfer mode they ask
+/// for changes; returns whether it did. The interface moves no data itself, so the mode is
+/// only reported, not applied.
+fn poll_power(model: &mut Model) -> bool {
+    let now = Instant::now();
+    if model
+        .power_polled
+        .is_some_and(|polled| now.duration_since(polled) < POWER_POLL)
+    {
+        return false;
+    }
+    model.power_polled = Some(now);
+    let Some(mode) = model.power.poll(&model.config.power) else {
+        return false;
+    };
+    let (message, kind) = match mode {
+        TransferMode::Normal => ("Power rules no longer apply", ToastKind::Info),
+        TransferMode::Throttled(_) => (
+            "On battery or a metered connection; power rules ask to throttle transfers",
+            ToastKind::Warning,
+        ),
+        TransferMode::Paused => (
+            "On battery or a metered connection; power rules ask to pause transfers",
+            ToastKind::Warning,
+        ),
+    };
+    model.toast.show(message, kind, now);
+    true
+}
 
-    ratatui::restore();
+/// Shows a desktop notification for every torrent that finished or failed since the last call.
+fn notify_changes(model: &mut Model) {
+    let Some(snapshot) = &model.notified else {
+        return;
+    };
+    let now = Instant::now();
+    let changes = snapshot.changes(&model.torrents, now);
+    for name in &changes.completed {
+        let folder = model
+            .torrents
+            .iter()
+            .find(|torrent| &torrent.name == name)
+            .and_then(|torrent| {
+                model
+                    .config
+                    .downloads
+                    .completed_dir_for(torrent.label.as_deref())
+            })
+            .map(Path::to_path_buf);
+        let _ = model.notifier.send(&Notification::Completed {
+            name: name.clone(),
+            folder,
+        });
+    }
+    for (name, message) in changes.errors {
+        let _ = model.notifier.send(&Notification::Error { name, message });
+    }
+    model.notified = Some(Snapshot::take(&model.torrents, now));
 }
 
 fn view(model: &mut Model, frame: &mut Frame) {
-    let main_text = "Terrent";
-    frame.render_widget(Paragraph::new(main_text), frame.area());
+    let mut area = frame.area();
+    let units = model.config.interface.units;
+
+    match model.screen {
+        Screen::Torrents => {}
+        Screen::Statistics => {
+            model.statistics.render(frame, area, &model.torrents, units);
+            model.exit_confirmation.render(frame, area);
+            model.toast.render(frame, area);
+            return;
+        }
+        Screen::Peers => {
+            model.peers.render(frame, area, &model.torrents, units);
+            model.exit_confirmation.render(frame, area);
+            model.toast.render(frame, area);
+            return;
+        }
+        Screen::History => {
+            model
+                .history_list
+                .render(frame, area, &model.history, units);
+            model.exit_confirmation.render(frame, area);
+            model.toast.render(frame, area);
+            return;
+        }
+    }
+
+    let [main_area, hint_area] =
+        Layout::vertical([Constraint::Fill(1), Constraint::Length(1)]).areas(area);
+    area = main_area;
+    let hints = keymap::hints(&key_contexts(model))
+        .filter(|(_, _, action)| is_available(model, *action))
+        .map(|(key, label, _)| (key, label))
+        .collect::<Vec<_>>();
+    model.hint_bar.render(frame, hint_area, &hints);
 
+    if model.config.interface.sidebar {
+        let [sidebar_area, main_area] =
+            Layout::horizontal([Constraint::Length(26), Constraint::Fill(1)]).areas(area);
+        model
+            .label_sidebar
+            .render(frame, sidebar_area, &model.torrents, units);
+        area = main_area;
+    }
+
+    match model.config.interface.layout {
+        LayoutMode::Split => {
+            let [list_area, details_area] =
+                Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)])
+                    .areas(area);
+            let selected = model.selected_torrent().cloned();
+            let attribution = selected_attribution(model, selected.as_ref());
+            model.torrent_details.render(
+                frame,
+                details_area,
+                selected.as_ref(),
+                attribution,
+                units,
+                model.focus == Pane::Details,
+            );
+            model.torrent_list.render(
+                frame,
+                list_area,
+                &model.torrents,
+                &model.config.interface.view,
+                units,
+                model.focus == Pane::List,
+            );
+        }
+        LayoutMode::FullScreen => match model.focus {
+            Pane::List => model.torrent_list.render(
+                frame,
+                area,
+                &model.torrents,
+                &model.config.interface.view,
+                units,
+                true,
+            ),
+            Pane::Details => {
+                let selected = model.selected_torrent();
+                model.torrent_details.render(
+                    frame,
+                    area,
+                    selected,
+                    selected_attribution(model, selected),
+                    units,
+                    true,
+                )
+            }
+        },
+    }
+
+    model.tracker_input.render(frame, frame.area());
+    model.retracker_form.render(frame, frame.area());
+    model.schedule_input.render(frame, frame.area());
+    model.remove_confirmation.render(frame, frame.area());
+    model.tracker_confirmation.render(frame, frame.area());
+    model.away_summary.render(frame, frame.area(), units);
+    model.integrity_summary.render(frame, frame.area());
     model.exit_confirmation.render(frame, frame.area());
+    model.toast.render(frame, frame.area());
 }
 
 fn handle_event(model: &mut Model) -> Option<Message> {
-    if event::poll(Duration::from_millis(250)).unwrap()
-        && let Event::Key(key) = event::read().unwrap()
-        && key.kind == event::KeyEventKind::Press
-    {
-        return handle_key(key, model);
+    if !event::poll(Duration::from_millis(250)).unwrap() {
+        return None;
+    }
+    match event::read().unwrap() {
+        Event::Key(key) if key.kind == event::KeyEventKind::Press => handle_key(key, model),
+        Event::FocusLost => Some(Message::FocusLost),
+        Event::FocusGained => Some(Message::FocusGained),
+        Event::Resize(..) => Some(Message::Redraw),
+        _ => None,
     }
-    None
 }
 
 fn handle_key(key: event::KeyEvent, model: &mut Model) -> Option<Message> {
@@ -80,32 +807,623 @@ fn handle_key(key: event::KeyEvent, model: &mut Model) -> Option<Message> {
         }
         return None;
     }
+    if model.remove_confirmation.is_visible() {
+        return model
+            .remove_confirmation
+            .handle_key(key)
+            .map(Message::RemoveConfirmation);
+    }
+    if model.tracker_confirmation.is_visible() {
+        return model
+            .tracker_confirmation
+            .handle_key(key)
+            .map(Message::TrackerConfirmation);
+    }
+    if model.integrity_summary.is_visible() {
+        return Some(Message::DismissIntegritySummary);
+    }
+    if model.away_summary.is_visible() {
+        return Some(Message::DismissAwaySummary);
+    }
+    if model.tracker_input.is_visible() {
+        return model
+            .tracker_input
+            .handle_key(key)
+            .map(Message::TrackerInput);
+    }
+    if model.schedule_input.is_visible() {
+        return model
+            .schedule_input
+            .handle_key(key)
+            .map(Message::ScheduleInput);
+    }
+    if model.retracker_form.is_visible() {
+        return model.retracker_form.handle_key(key).map(Message::Retracker);
+    }
+
+    if model.screen != Screen::Torrents {
+        return match key.code {
+            KeyCode::Char('q') => Some(Message::Quit),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                Some(Message::ShowExitConfirmation)
+            }
+            KeyCode::Esc => Some(Message::ToggleScreen(model.screen)),
+            KeyCode::Char('s') => Some(Message::ToggleScreen(Screen::Statistics)),
+            KeyCode::Char('p') => Some(Message::ToggleScreen(Screen::Peers)),
+            KeyCode::Char('H') => Some(Message::ToggleScreen(Screen::History)),
+            KeyCode::Enter if model.screen == Screen::History => {
+                let entry = model.history_list.selected(&model.history)?;
+                Some(Message::Readd(entry.info_hash.clone()))
+            }
+            KeyCode::Char('x') | KeyCode::Delete if model.screen == Screen::History => {
+                let entry = model.history_list.selected(&model.history)?;
+                Some(Message::Forget(entry.info_hash.clone()))
+            }
+            _ if model.screen == Screen::Peers => model.peers.handle_key(key).map(Message::Peers),
+            _ if model.screen == Screen::History => {
+                model.history_list.handle_key(key).map(Message::History)
+            }
+            _ => None,
+        };
+    }
 
     match key.code {
-        KeyCode::Char('q') => Some(Message::Quit),
         KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-            Some(Message::ShowExitConfirmation)
+            return Some(Message::ShowExitConfirmation);
         }
-        _ => None,
+        KeyCode::Char(digit @ '1'..='9') => {
+            return Some(Message::LoadView(digit as usize - '1' as usize));
+        }
+        _ => {}
+    }
+    if let Some(action) = keymap::action(key, &key_contexts(model)) {
+        return action_message(model, action);
+    }
+
+    match model.focus {
+        Pane::List => model.torrent_list.handle_key(key).map(Message::TorrentList),
+        Pane::Details => model
+            .torrent_details
+            .handle_key(key)
+            .map(Message::TorrentDetails),
+    }
+}
+
+/// Where the keymap's bindings apply right now on the torrents screen.
+fn key_contexts(model: &Model) -> Vec<KeyContext> {
+    let mut contexts = vec![KeyContext::Anywhere];
+    if model.config.interface.layout == LayoutMode::Split {
+        contexts.push(KeyContext::Split);
+    }
+    match model.focus {
+        Pane::List => contexts.push(KeyContext::List),
+        Pane::Details => {
+            contexts.push(KeyContext::Details);
+            if model.torrent_details.is_trackers_tab() {
+                contexts.push(KeyContext::Trackers);
+            }
+            if model.torrent_details.is_pieces_tab() {
+                contexts.push(KeyContext::Pieces);
+            }
+        }
+    }
+    contexts
+}
+
+/// Whether `action` would do anything for the current selection; the hint bar leaves out
+/// the ones that would not.
+fn is_available(model: &Model, action: Action) -> bool {
+    let selected = model.selected_torrent();
+    match action {
+        Action::OpenDetails
+        | Action::RemoveTorrent
+        | Action::Schedule
+        | Action::AddTracker
+        | Action::ToggleSequential => selected.is_some(),
+        Action::CancelSchedule => {
+            selected.is_some_and(|torrent| matches!(torrent.state, TorrentState::Scheduled { .. }))
+        }
+        Action::StartNow => selected.is_some_and(|torrent| torrent.state != TorrentState::Active),
+        Action::RemoveTracker => selected
+            .and_then(|torrent| model.torrent_details.selected_tracker(torrent))
+            .is_some(),
+        // Only the daemon announces.
+        Action::AnnounceNow => model.remote.is_some() && selected.is_some(),
+        Action::AnnounceTo => {
+            model.remote.is_some()
+                && selected
+                    .and_then(|torrent| model.torrent_details.selected_tracker(torrent))
+                    .is_some()
+        }
+        // Only the daemon has the data of its torrents.
+        Action::InspectPiece => {
+            model.remote.is_none()
+                && selected
+                    .and_then(|torrent| model.torrent_details.selected_piece(torrent))
+                    .is_some()
+        }
+        _ => true,
     }
 }
 
+fn action_message(model: &Model, action: Action) -> Option<Message> {
+    Some(match action {
+        Action::Quit => Message::Quit,
+        Action::ToggleSidebar => Message::ToggleSidebar,
+        Action::Statistics => Message::ToggleScreen(Screen::Statistics),
+        Action::Peers => Message::ToggleScreen(Screen::Peers),
+        Action::History => Message::ToggleScreen(Screen::History),
+        Action::ToggleLayout => Message::ToggleLayout,
+        Action::CycleSort => Message::CycleSort,
+        Action::ReverseSort => Message::ReverseSort,
+        Action::CycleLabelFilter => Message::CycleLabelFilter,
+        Action::ToggleGrouping => Message::ToggleGrouping,
+        Action::SaveView => Message::SaveView,
+        Action::Retracker => Message::ShowRetracker,
+        Action::FocusNext => Message::FocusNext,
+        Action::OpenDetails => Message::Focus(Pane::Details),
+        Action::CloseDetails => Message::Focus(Pane::List),
+        Action::AddTracker => Message::ShowAddTracker,
+        Action::RemoveTracker => {
+            let torrent = model.selected_torrent()?;
+            let url = model.torrent_details.selected_tracker(torrent)?;
+            Message::ShowRemoveTracker(url.to_string())
+        }
+        Action::InspectPiece => {
+            let torrent = model.selected_torrent()?;
+            Message::InspectPiece(model.torrent_details.selected_piece(torrent)?)
+        }
+        Action::RemoveTorrent => Message::ShowRemoveTorrent,
+        Action::Schedule => Message::ShowSchedule,
+        Action::CancelSchedule => Message::CancelSchedule,
+        Action::StartNow => Message::StartNow,
+        Action::ToggleSequential => Message::ToggleSequential,
+        Action::AnnounceNow => {
+            model.remote.as_ref()?;
+            Message::AnnounceNow
+        }
+        Action::AnnounceTo => {
+            model.remote.as_ref()?;
+            let torrent = model.selected_torrent()?;
+            let url = model.torrent_details.selected_tracker(torrent)?;
+            Message::AnnounceTo(url.to_string())
+        }
+    })
+}
+
 fn update(model: &mut Model, msg: Message) -> Option<Message> {
     match msg {
         Message::Quit => model.running_state = RunningState::Done,
         Message::ShowExitConfirmation => {
+            if model.config.interface.skip_prompts.contains(&Prompt::Exit) {
+                return Some(Message::Quit);
+            }
             model.exit_confirmation.show();
         }
         Message::ExitConfirmation(confirmation_msg) => {
             if let Some(result) = model.exit_confirmation.update(confirmation_msg) {
                 match result {
-                    ConfirmationResult::Yes => model.running_state = RunningState::Done,
-                    ConfirmationResult::No | ConfirmationResult::Cancelled => {
+                    ConfirmationResult::Yes => {
+                        if model.exit_confirmation.dont_ask_again() {
+                            model.config.interface.skip_prompts.insert(Prompt::Exit);
+                            let _ = model.config.save();
+                        }
+                        model.running_state = RunningState::Done;
+                    }
+                    ConfirmationResult::No
+                    | ConfirmationResult::Custom
+                    | ConfirmationResult::Cancelled => {
                         model.exit_confirmation.hide();
                     }
                 }
             }
         }
+        Message::ShowRemoveTorrent => {
+            model.selected_index()?;
+            if model
+                .config
+                .interface
+                .skip_prompts
+                .contains(&Prompt::Remove)
+            {
+                return Some(Message::RemoveTorrent);
+            }
+            model.remove_confirmation.show();
+        }
+        Message::RemoveConfirmation(confirmation_msg) => {
+            let result = model.remove_confirmation.update(confirmation_msg)?;
+            model.remove_confirmation.hide();
+            if result == ConfirmationResult::Yes {
+                if model.remove_confirmation.dont_ask_again() {
+                    model.config.interface.skip_prompts.insert(Prompt::Remove);
+                    let _ = model.config.save();
+                }
+                return Some(Message::RemoveTorrent);
+            }
+        }
+        Message::RemoveTorrent => {
+            let index = model.selected_index()?;
+            let torrent = model.torrents.remove(index);
+            model.torrent_details.reset_scroll();
+            if model.remote.is_some() {
+                // The daemon keeps the history of its own torrents.
+                forward(model, torrent.info_hash, RemoteAction::Remove);
+                return None;
+            }
+            // Failing to keep the `.torrent` copy still leaves an entry to re-add from.
+            let _ = model.history.record(&torrent);
+            let _ = model.history.save();
+        }
+        Message::History(history_msg) => {
+            model
+                .history_list
+                .update(history_msg, model.history.entries.len());
+        }
+        Message::Readd(info_hash) => {
+            if model.remote.is_some() {
+                model.toast.show(
+                    "Torrents cannot be re-added to an attached daemon",
+                    ToastKind::Warning,
+                    Instant::now(),
+                );
+                return None;
+            }
+            let entry = model
+                .history
+                .entries
+                .iter()
+                .find(|entry| entry.info_hash == info_hash)?
+                .clone();
+            if model
+                .torrents
+                .iter()
+                .any(|torrent| hex(&torrent.info_hash) == info_hash)
+            {
+                model
+                    .history_list
+                    .set_status(format!("{} is already added", entry.name));
+                return None;
+            }
+            match History::reopen(&entry, &model.config.decode) {
+                Ok(torrent) => {
+                    let mut metadata = Metadata {
+                        label: entry.label.clone(),
+                        added: Some(history::unix_now()),
+                        origin: entry.origin.clone(),
+                        sequential: model.config.downloads.sequential,
+                        ..Metadata::from(&torrent)
+                    };
+                    if model.config.low_memory.enabled {
+                        metadata.compact();
+                    }
+                    model.torrents.push(metadata);
+                    model
+                        .history_list
+                        .set_status(format!("Added {} again", entry.name));
+                }
+                Err(err) => model.history_list.set_status(format!("{err:#}")),
+            }
+        }
+        Message::Forget(info_hash) => {
+            model.history.forget(&info_hash)?;
+            let _ = model.history.save();
+        }
+        Message::TorrentList(list_msg) => {
+            model.torrent_list.update(list_msg, model.visible().len());
+            model.torrent_details.reset_scroll();
+        }
+        Message::TorrentDetails(details_msg) => model.torrent_details.update(details_msg),
+        Message::Peers(peers_msg) => {
+            let count = model
+                .torrents
+                .iter()
+                .map(|torrent| torrent.peers.len())
+                .sum();
+            model.peers.update(peers_msg, count);
+        }
+        Message::Focus(pane) => model.focus = pane,
+        Message::FocusNext => {
+            model.focus = match model.focus {
+                Pane::List => Pane::Details,
+                Pane::Details => Pane::List,
+            };
+        }
+        Message::ToggleLayout => {
+            model.config.interface.layout = match model.config.interface.layout {
+                LayoutMode::FullScreen => LayoutMode::Split,
+                LayoutMode::Split => LayoutMode::FullScreen,
+            };
+            // Losing the layout preference is not worth interrupting the session over.
+            let _ = model.config.save();
+        }
+        Message::ToggleSidebar => {
+            model.config.interface.sidebar = !model.config.interface.sidebar;
+            let _ = model.config.save();
+        }
+        Message::CycleSort => {
+            let view = &mut model.config.interface.view;
+            view.sort = view.sort.next();
+            let _ = model.config.save();
+        }
+        Message::ReverseSort => {
+            let view = &mut model.config.interface.view;
+            view.descending = !view.descending;
+            let _ = model.config.save();
+        }
+        Message::CycleLabelFilter => {
+            let labels = model.labels();
+            let view = &mut model.config.interface.view;
+            // No filter, then each label in turn, then back to no filter.
+            view.label = match &view.label {
+                None => labels.first().cloned(),
+                Some(current) => labels
+                    .iter()
+                    .skip_while(|label| *label != current)
+                    .nth(1)
+                    .cloned(),
+            };
+            model
+                .torrent_list
+                .update(TorrentListMessage::SelectFirst, 1);
+            let _ = model.config.save();
+        }
+        Message::ToggleGrouping => {
+            let view = &mut model.config.interface.view;
+            view.group_by_label = !view.group_by_label;
+            let _ = model.config.save();
+        }
+        Message::SaveView => {
+            let interface = &mut model.config.interface;
+            let mut view = interface.view.clone();
+            view.name = format!("View {}", interface.saved_views.len() + 1);
+            interface.saved_views.push(view.clone());
+            interface.view = view;
+            let _ = model.config.save();
+        }
+        Message::LoadView(slot) => {
+            if let Some(view) = model.config.interface.saved_views.get(slot) {
+                model.config.interface.view = view.clone();
+                model
+                    .torrent_list
+                    .update(TorrentListMessage::SelectFirst, 1);
+                let _ = model.config.save();
+            }
+        }
+        Message::ToggleScreen(screen) => {
+            model.screen = if model.screen == screen {
+                Screen::Torrents
+            } else {
+                screen
+            };
+        }
+        Message::FocusLost => {
+            model.away_since = Some(Snapshot::take(&model.torrents, Instant::now()));
+        }
+        Message::FocusGained => {
+            if let Some(snapshot) = model.away_since.take() {
+                model
+                    .away_summary
+                    .show(snapshot.changes(&model.torrents, Instant::now()));
+            }
+        }
+        Message::DismissAwaySummary => model.away_summary.hide(),
+        Message::DismissIntegritySummary => model.integrity_summary.hide(),
+        Message::ShowAddTracker => {
+            if model.selected_torrent().is_some() {
+                model.tracker_input.show();
+            }
+        }
+        Message::TrackerInput(input_msg) => {
+            let url = model.tracker_input.update(input_msg)?;
+            let index = model.selected_index()?;
+            let torrent = &mut model.torrents[index];
+            match check_tracker_url(&url) {
+                Err(error) => model.tracker_input.reject(error),
+                Ok(()) if torrent.announce.contains(&url) => model
+                    .tracker_input
+                    .reject("Already a tracker of this torrent"),
+                Ok(()) => {
+                    torrent.announce.push(url.clone());
+                    let info_hash = torrent.info_hash;
+                    model.tracker_input.hide();
+                    save_trackers(model, info_hash);
+                    forward(model, info_hash, RemoteAction::AddTracker(url));
+                }
+            }
+        }
+        Message::ShowRemoveTracker(url) => {
+            if model
+                .config
+                .interface
+                .skip_prompts
+                .contains(&Prompt::RemoveTracker)
+            {
+                return Some(Message::RemoveTracker(url));
+            }
+            model.pending_tracker = Some(url);
+            model.tracker_confirmation.show();
+        }
+        Message::TrackerConfirmation(confirmation_msg) => {
+            let result = model.tracker_confirmation.update(confirmation_msg)?;
+            model.tracker_confirmation.hide();
+            let url = model.pending_tracker.take()?;
+            if result == ConfirmationResult::Yes {
+                if model.tracker_confirmation.dont_ask_again() {
+                    model
+                        .config
+                        .interface
+                        .skip_prompts
+                        .insert(Prompt::RemoveTracker);
+                    let _ = model.config.save();
+                }
+                return Some(Message::RemoveTracker(url));
+            }
+        }
+        Message::RemoveTracker(url) => {
+            let index = model.selected_index()?;
+            let torrent = &mut model.torrents[index];
+            torrent.announce.retain(|announce| *announce != url);
+            torrent.trackers.retain(|state| state.url != url);
+            let info_hash = torrent.info_hash;
+            save_trackers(model, info_hash);
+            forward(model, info_hash, RemoteAction::RemoveTracker(url));
+        }
+        Message::InspectPiece(index) => inspect_piece(model, index),
+        Message::ShowSchedule => {
+            if model.selected_torrent().is_some() {
+                model.schedule_input.show();
+            }
+        }
+        Message::ScheduleInput(input_msg) => {
+            let time = model.schedule_input.update(input_msg)?;
+            let index = model.selected_index()?;
+            match queue::parse_start_time(&time, history::unix_now()) {
+                Ok(at) => {
+                    queue::schedule(&mut model.torrents[index], at);
+                    model.schedule_input.hide();
+                    let info_hash = model.torrents[index].info_hash;
+                    forward(model, info_hash, RemoteAction::Schedule { at });
+                }
+                Err(err) => model.schedule_input.reject(format!("{err:#}")),
+            }
+        }
+        Message::CancelSchedule => {
+            let index = model.selected_index()?;
+            queue::cancel(&mut model.torrents[index]);
+            let info_hash = model.torrents[index].info_hash;
+            forward(model, info_hash, RemoteAction::CancelSchedule);
+        }
+        Message::StartNow => {
+            let index = model.selected_index()?;
+            queue::start_now(&mut model.torrents[index]);
+            let info_hash = model.torrents[index].info_hash;
+            forward(model, info_hash, RemoteAction::StartNow);
+        }
+        Message::ToggleSequential => {
+            let index = model.selected_index()?;
+            let torrent = &mut model.torrents[index];
+            torrent.sequential = !torrent.sequential;
+            let (info_hash, sequential) = (torrent.info_hash, torrent.sequential);
+            forward(model, info_hash, RemoteAction::SetSequential(sequential));
+        }
+        Message::AnnounceNow => {
+            let info_hash = model.selected_torrent()?.info_hash;
+            forward(model, info_hash, RemoteAction::AnnounceNow);
+        }
+        Message::AnnounceTo(url) => {
+            let info_hash = model.selected_torrent()?.info_hash;
+            forward(model, info_hash, RemoteAction::AnnounceTo(url));
+        }
+        Message::ShowRetracker => model.retracker_form.show(),
+        Message::Retracker(form_msg) => {
+            let (from, to) = model.retracker_form.update(form_msg)?;
+            if url::Host::parse(&to).is_err() {
+                model
+                    .retracker_form
+                    .reject(format!("{to} is not a valid host"));
+                return None;
+            }
+            let (mut torrents, mut urls) = (0, 0);
+            let attached = model.remote.is_some();
+            let mut moved = Vec::new();
+            for torrent in &mut model.torrents {
+                let before = attached.then(|| torrent.announce.clone());
+                let replaced = replace_hosts(&mut torrent.announce, &from, &to);
+                if replaced > 0
+                    && let Some(before) = before
+                {
+                    moved.push((torrent.info_hash, before, torrent.announce.clone()));
+                }
+                for state in &mut torrent.trackers {
+                    if let Some(moved) = replace_host(&state.url, &from, &to) {
+                        state.url = moved;
+                    }
+                }
+                torrents += usize::from(replaced > 0);
+                urls += replaced;
+            }
+            if urls == 0 {
+                model
+                    .retracker_form
+                    .reject(format!("No torrent announces to {from}"));
+                return None;
+            }
+            model.retracker_form.hide();
+            model.toast.show(
+                format!("Moved {urls} tracker(s) in {torrents} torrent(s) to {to}"),
+                ToastKind::Info,
+                Instant::now(),
+            );
+            for (info_hash, before, after) in moved {
+                save_trackers(model, info_hash);
+                for url in after.iter().filter(|url| !before.contains(url)) {
+                    forward(model, info_hash, RemoteAction::AddTracker(url.clone()));
+                }
+                for url in before.into_iter().filter(|url| !after.contains(url)) {
+                    forward(model, info_hash, RemoteAction::RemoveTracker(url));
+                }
+            }
+        }
+        Message::ReloadConfig => {
+            let now = Instant::now();
+            let config = match Config::load() {
+                Ok(config) => config,
+                Err(err) => {
+                    model.toast.show(
+                        format!("{err:#}; keeping the current settings"),
+                        ToastKind::Warning,
+                        now,
+                    );
+                    return None;
+                }
+            };
+            if config == model.config {
+                return None;
+            }
+
+            let notifications_changed = config.notifications != model.config.notifications;
+            if config.progress_file != model.config.progress_file {
+                model.progress_file = ProgressFile::new(&config.progress_file);
+            }
+            let needs_restart = model.config.apply_live(config);
+            model
+                .torrent_details
+                .set_swarm_map(model.config.interface.swarm_map);
+            if notifications_changed {
+                model.notifier = Notifier::new(model.config.notifications.clone());
+                model.notified = model
+                    .notifier
+                    .is_enabled()
+                    .then(|| Snapshot::take(&model.torrents, now));
+            }
+            if needs_restart.is_empty() {
+                model.toast.show("Config reloaded", ToastKind::Info, now);
+            } else {
+                model.toast.show(
+                    format!(
+                        "Config reloaded; restart to apply the {} settings",
+                        needs_restart.join(", ")
+                    ),
+                    ToastKind::Warning,
+                    now,
+                );
+            }
+        }
+        Message::Redraw => {}
     }
     None
 }
+
+/// Refuses URLs no tracker client could announce to.
+fn check_tracker_url(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|err| format!("Invalid URL: {err}"))?;
+    match parsed.scheme() {
+        "http" | "https" | "udp" => {}
+        "ws" | "wss" if cfg!(feature = "webtorrent") => {}
+        scheme => return Err(format!("Unsupported tracker scheme {scheme}")),
+    }
+    if parsed.host_str().is_none() {
+        return Err("Tracker URL has no host".to_string());
+    }
+    Ok(())
+}
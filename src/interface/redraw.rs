@@ -0,0 +1,76 @@
+use std::time::{Duration, Instant};
+
+use ratatui::buffer::Buffer;
+
+/// A frame taking this long to reach the terminal suggests a slow link.
+const SLOW_FRAME: Duration = Duration::from_millis(100);
+/// Consecutive slow frames before switching to low-bandwidth mode on its own.
+const SLOW_FRAMES_TO_SWITCH: u32 = 5;
+/// In low-bandwidth mode, the screen is only refreshed this often without input.
+const LOW_BANDWIDTH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Decides when to redraw; in low-bandwidth mode only after input or every few seconds.
+#[derive(Debug, Clone)]
+pub struct RedrawPolicy {
+    low_bandwidth: bool,
+    dirty: bool,
+    last_draw: Option<Instant>,
+    slow_frames: u32,
+}
+
+impl RedrawPolicy {
+    pub fn new(low_bandwidth: bool) -> Self {
+        Self {
+            low_bandwidth,
+            dirty: true,
+            last_draw: None,
+            slow_frames: 0,
+        }
+    }
+
+    pub fn is_low_bandwidth(&self) -> bool {
+        self.low_bandwidth
+    }
+
+    pub fn should_draw(&self, now: Instant) -> bool {
+        !self.low_bandwidth
+            || self.dirty
+            || self
+                .last_draw
+                .is_none_or(|at| now.duration_since(at) >= LOW_BANDWIDTH_INTERVAL)
+    }
+
+    /// Something changed on screen; draw on the next turn of the loop.
+    pub fn invalidate(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Records a frame that took `took` to draw and flush, switching to low-bandwidth mode
+    /// once the terminal keeps being slow to accept output.
+    pub fn drawn(&mut self, took: Duration, now: Instant) {
+        self.dirty = false;
+        self.last_draw = Some(now);
+        if took < SLOW_FRAME {
+            self.slow_frames = 0;
+            return;
+        }
+        self.slow_frames += 1;
+        if self.slow_frames >= SLOW_FRAMES_TO_SWITCH {
+            self.low_bandwidth = true;
+        }
+    }
+}
+
+/// Replaces box-drawing characters with ASCII, which is cheaper to send and safe on
+/// terminals with odd fonts.
+pub fn simplify_borders(buffer: &mut Buffer) {
+    for cell in buffer.content.iter_mut() {
+        let ascii = match cell.symbol() {
+            "╭" | "╮" | "╰" | "╯" | "┌" | "┐" | "└" | "┘" => "+",
+            "─" => "-",
+            "│" => "|",
+            _ => continue,
+        };
+        cell.set_symbol(ascii);
+    }
+}
@@ -0,0 +1,22 @@
+pub mod config;
+pub mod dht;
+pub mod download;
+pub mod file;
+pub mod format;
+pub mod history;
+pub mod interface;
+pub mod lowmem;
+pub mod metadata;
+pub mod notify;
+pub mod peer;
+pub mod power;
+pub mod priority;
+pub mod progress;
+pub mod queue;
+pub mod remote;
+pub mod selftest;
+pub mod session;
+pub mod stats;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
+pub mod tracker;
@@ -0,0 +1,217 @@
+//! Low-memory seeding mode, for boxes seeding thousands of torrents.
+//!
+//! Complete torrents that go without requests for a while have their parsed `.torrent`
+//! dropped from memory and read back from disk on the next request. Their piece states are
+//! compacted and every torrent's upload queue is capped in bytes.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::file::{DecodeLimits, TorrentFile};
+use crate::format::hex;
+use crate::peer::UploadSchedulerConfig;
+use crate::session;
+use crate::stats::MemoryUsage;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LowMemoryConfig {
+    pub enabled: bool,
+    /// Seconds a complete torrent goes without requests before it is unloaded.
+    pub unload_after_secs: u64,
+    /// Bytes of upload requests queued per torrent; further requests are dropped.
+    pub buffer_per_torrent: u64,
+}
+
+impl Default for LowMemoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            unload_after_secs: 5 * 60,
+            buffer_per_torrent: 1024 * 1024,
+        }
+    }
+}
+
+impl LowMemoryConfig {
+    /// `uploads` with the per-torrent buffer cap applied while the mode is on.
+    pub fn uploads(&self, uploads: &UploadSchedulerConfig) -> UploadSchedulerConfig {
+        let mut uploads = uploads.clone();
+        if self.enabled {
+            uploads.max_queued_bytes = Some(
+                uploads
+                    .max_queued_bytes
+                    .map_or(self.buffer_per_torrent, |max| {
+                        max.min(self.buffer_per_torrent)
+                    }),
+            );
+        }
+        uploads
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CachedTorrent {
+    torrent: TorrentFile,
+    last_used: Instant,
+}
+
+/// Parsed torrents by info hash, unloading idle ones to `.torrent` files below `dir`.
+#[derive(Debug, Clone)]
+pub struct TorrentCache {
+    dir: PathBuf,
+    unload_after: Duration,
+    limits: DecodeLimits,
+    loaded: HashMap<[u8; 20], CachedTorrent>,
+    unloaded: BTreeSet<[u8; 20]>,
+}
+
+impl TorrentCache {
+    pub fn new(dir: PathBuf, config: &LowMemoryConfig, limits: DecodeLimits) -> Self {
+        Self {
+            dir,
+            unload_after: Duration::from_secs(config.unload_after_secs),
+            limits,
+            loaded: HashMap::new(),
+            unloaded: BTreeSet::new(),
+        }
+    }
+
+    /// Where unloaded torrents are kept by default.
+    pub fn default_dir() -> Option<PathBuf> {
+        session::dir().map(|dir| dir.join("unloaded"))
+    }
+
+    pub fn insert(&mut self, torrent: TorrentFile, now: Instant) {
+        let info_hash = torrent.info_hash();
+        self.unloaded.remove(&info_hash);
+        self.loaded.insert(
+            info_hash,
+            CachedTorrent {
+                torrent,
+                last_used: now,
+            },
+        );
+    }
+
+    /// Forgets a torrent, deleting its unloaded copy.
+    pub fn remove(&mut self, info_hash: &[u8; 20]) {
+        self.loaded.remove(info_hash);
+        if self.unloaded.remove(info_hash) {
+            let _ = fs::remove_file(self.path(info_hash));
+        }
+    }
+
+    pub fn is_loaded(&self, info_hash: &[u8; 20]) -> bool {
+        self.loaded.contains_key(info_hash)
+    }
+
+    /// The torrent, read back from disk first if it was unloaded.
+    pub fn get(&mut self, info_hash: &[u8; 20], now: Instant) -> Result<&TorrentFile> {
+        if self.unloaded.contains(info_hash) {
+            let path = self.path(info_hash);
+            let torrent = TorrentFile::open_with_limits(&path, &self.limits)?;
+            if torrent.info_hash() != *info_hash {
+                bail!("{path:?} no longer matches its info hash");
+            }
+            self.insert(torrent, now);
+            let _ = fs::remove_file(path);
+        }
+        let cached = self
+            .loaded
+            .get_mut(info_hash)
+            .context("Torrent is not in the cache")?;
+        cached.last_used = now;
+        Ok(&cached.torrent)
+    }
+
+    /// Unloads every torrent `complete` says is finished that was not used for a while,
+    /// returning how many were unloaded. Torrents that fail to be written stay loaded.
+    pub fn unload_idle(
+        &mut self,
+        now: Instant,
+        complete: impl Fn(&[u8; 20]) -> bool,
+    ) -> Result<usize> {
+        let idle = self
+            .loaded
+            .iter()
+            .filter(|(info_hash, cached)| {
+                now.duration_since(cached.last_used) >= self.unload_after && complete(info_hash)
+            })
+            .map(|(info_hash, _)| *info_hash)
+            .collect::<Vec<_>>();
+        if idle.is_empty() {
+            return Ok(0);
+        }
+
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {:?}", self.dir))?;
+        for info_hash in &idle {
+            self.loaded[info_hash].torrent.save(self.path(info_hash))?;
+            self.loaded.remove(info_hash);
+            self.unloaded.insert(*info_hash);
+        }
+        Ok(idle.len())
+    }
+
+    pub fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            loaded: self.loaded.len(),
+            unloaded: self.unloaded.len(),
+            bytes: self
+                .loaded
+                .values()
+                .map(|cached| torrent_size(&cached.torrent))
+                .sum(),
+        }
+    }
+
+    fn path(&self, info_hash: &[u8; 20]) -> PathBuf {
+        self.dir.join(format!("{}.torrent", hex(info_hash)))
+    }
+}
+
+/// Rough heap size of a parsed torrent; the info dictionary and the piece hashes decoded
+/// from it dwarf everything else.
+fn torrent_size(torrent: &TorrentFile) -> u64 {
+    (torrent.info_bytes().len() + torrent.piece_hashes().len() * 20) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn idle_complete_torrents_are_unloaded_and_reloaded_on_demand() {
+        let dir = env::temp_dir().join(format!("terrent-lowmem-{}", process::id()));
+        let config = LowMemoryConfig::default();
+        let mut cache = TorrentCache::new(dir.clone(), &config, DecodeLimits::default());
+        let seeding = SyntheticTorrent::single("seeding", 64 * 1024, 16 * 1024).torrent;
+        let leeching = SyntheticTorrent::builder("leeching")
+            .seed(2)
+            .build()
+            .torrent;
+        let info_hash = seeding.info_hash();
+
+        let start = Instant::now();
+        cache.insert(seeding.clone(), start);
+        cache.insert(leeching, start);
+        let later = start + Duration::from_secs(config.unload_after_secs);
+        let unloaded = cache.unload_idle(later, |hash| *hash == info_hash).unwrap();
+        assert_eq!(unloaded, 1);
+        assert!(!cache.is_loaded(&info_hash));
+        assert_eq!(cache.usage().loaded, 1);
+
+        assert_eq!(cache.get(&info_hash, later).unwrap(), &seeding);
+        assert!(cache.is_loaded(&info_hash));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,10 +1,613 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
 use clap::Parser;
+use terrent::config::Config;
+use terrent::download::{
+    AddMode, AssumedCheck, PieceAttribution, PieceStates, ReuseSources, WebSeed, check_pieces,
+    finish_file, reuse_local_data, webseed,
+};
+use terrent::file::{InfoHashChange, TorrentBuilder, TorrentFile};
+use terrent::format::hex;
+use terrent::metadata::Metadata;
+use terrent::peer::{
+    AbuseGuard, AbuseGuardConfig, InboundTarget, InboundTorrents, Listener, PeerId, SeedSource,
+    generate_peer_id, seeding_handshake, serve_peer,
+};
+use terrent::queue::{self, TorrentState};
+use terrent::remote::{Daemon, RemoteAction};
+use terrent::tracker::rewrite::{replace_hosts, rewrite};
+use terrent::tracker::{
+    self, AnnounceLifecycle, Announcer, AnnouncerCommand, ScrapeStats, TrackerEdits, TrackerTiers,
+    follow_torrent,
+};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use args::Command;
 
 mod args;
-mod interface;
-mod metadata;
 
-fn main() {
-    let args = args::Arguments::parse();
-    interface::init();
+fn main() -> anyhow::Result<()> {
+    let mut args = args::Arguments::parse();
+
+    if args.low_priority
+        && let Err(err) = terrent::priority::lower_priority()
+    {
+        eprintln!("Warning: {err:#}");
+    }
+
+    match args.command.take() {
+        Some(Command::Create {
+            path,
+            output,
+            trackers,
+            format,
+            piece_length,
+            comment,
+            private,
+            source,
+            threads,
+        }) => {
+            let mut builder = TorrentBuilder::new(&path).format(format).private(private);
+            for tracker in trackers {
+                builder = builder.tracker(tracker);
+            }
+            if let Some(piece_length) = piece_length {
+                builder = builder.piece_length(piece_length);
+            }
+            if let Some(threads) = threads {
+                builder = builder.threads(threads);
+            }
+            if let Some(comment) = comment {
+                builder = builder.comment(comment);
+            }
+            if let Some(source) = source {
+                builder = builder.source(source);
+            }
+
+            let output = output.unwrap_or_else(|| {
+                // `.` and `..` only have a name once resolved.
+                let path = std::fs::canonicalize(&path).unwrap_or(path);
+                let mut name = path.file_name().unwrap_or_default().to_os_string();
+                name.push(".torrent");
+                name.into()
+            });
+            builder.write(&output)?;
+            println!("Created {}", output.display());
+        }
+        Some(Command::Edit {
+            torrent: path,
+            output,
+            add_trackers,
+            remove_trackers,
+            clear_trackers,
+            strip_web_seeds,
+            comment,
+            strip_metadata,
+            private,
+            allow_new_info_hash,
+        }) => {
+            let mut torrent = TorrentFile::open(&path)?;
+            let info_hash = torrent.info_hash();
+
+            if clear_trackers {
+                torrent.set_trackers(Vec::new());
+            }
+            for tracker in &remove_trackers {
+                if !torrent.remove_tracker(tracker) {
+                    eprintln!("Warning: {tracker} is not listed");
+                }
+            }
+            for tracker in add_trackers {
+                torrent.add_tracker(tracker);
+            }
+            if strip_web_seeds {
+                torrent.clear_web_seeds();
+            }
+            if strip_metadata {
+                torrent.strip_metadata();
+            }
+            if let Some(comment) = comment {
+                torrent.set_comment(Some(comment));
+            }
+            if let Some(private) = private {
+                let change = if allow_new_info_hash {
+                    InfoHashChange::Allow
+                } else {
+                    InfoHashChange::Refuse
+                };
+                torrent
+                    .set_private(private, change)
+                    .context("Pass --allow-new-info-hash to confirm")?;
+            }
+
+            let output = output.unwrap_or(path);
+            torrent.save(&output)?;
+            println!("Wrote {}", output.display());
+            if torrent.info_hash() != info_hash {
+                println!("New info hash {}", hex(&torrent.info_hash()));
+            }
+        }
+        Some(Command::Prefill {
+            torrent,
+            data,
+            sources,
+        }) => {
+            let config = Config::load()?;
+            let torrent = TorrentFile::open(&torrent)?;
+            let mut library = ReuseSources::new();
+            for dir in config.downloads.library.iter().chain(&sources) {
+                library.add_library(dir)?;
+            }
+
+            let mut states = PieceStates::new(torrent.piece_count(), AddMode::Check);
+            let report = reuse_local_data(&torrent, &data, &library, &mut states)?;
+            println!(
+                "Reused {} of {} pieces ({} bytes)",
+                report.pieces.len(),
+                torrent.piece_count(),
+                report.bytes
+            );
+        }
+        Some(Command::Fetch { torrent, data }) => {
+            let mut config = Config::load()?;
+            config.proxy_override = args.proxy.clone();
+            let torrent = TorrentFile::open(&torrent)?;
+            let (fetched, failed) = fetch_from_web_seeds(&torrent, &data, &config)?;
+            println!("Fetched {fetched} piece(s) from web seeds");
+            if failed > 0 {
+                anyhow::bail!("{failed} piece(s) could not be fetched");
+            }
+        }
+        Some(Command::Retracker {
+            from,
+            to,
+            torrents,
+            dry_run,
+        }) => {
+            let roots = if torrents.is_empty() {
+                let dir = terrent::session::dir().context("No data directory available")?;
+                ["history", "unloaded"]
+                    .into_iter()
+                    .map(|kept| dir.join(kept))
+                    .filter(|kept| kept.is_dir())
+                    .collect()
+            } else {
+                torrents
+            };
+            let (mut files, mut urls) = (0, 0);
+            for path in torrent_files(&roots)? {
+                let mut torrent = TorrentFile::open(&path)?;
+                let mut tiers = torrent.trackers();
+                let replaced = tiers
+                    .iter_mut()
+                    .map(|tier| replace_hosts(tier, &from, &to))
+                    .sum::<usize>();
+                if replaced == 0 {
+                    continue;
+                }
+                if !dry_run {
+                    torrent.set_trackers(tiers);
+                    torrent.save(&path)?;
+                }
+                println!("{}: {replaced} tracker(s)", path.display());
+                files += 1;
+                urls += replaced;
+            }
+            let verb = if dry_run { "Would move" } else { "Moved" };
+            println!("{verb} {urls} tracker(s) in {files} torrent(s) from {from} to {to}");
+        }
+        Some(Command::Selftest) => {
+            let mut failed = 0;
+            for (name, result) in terrent::selftest::run() {
+                match result {
+                    Ok(()) => println!("PASS {name}"),
+                    Err(err) => {
+                        failed += 1;
+                        println!("FAIL {name}: {err:#}");
+                    }
+                }
+            }
+            if failed > 0 {
+                anyhow::bail!("{failed} self-test check(s) failed");
+            }
+            println!("All checks passed");
+        }
+        Some(Command::Daemon { torrents }) => {
+            let mut config = Config::load()?;
+            config.proxy_override = args.proxy.clone();
+            let (opened, checks) = open_torrents(&torrents, &args, &config)?;
+            report_checks(checks);
+            let (torrents, files): (Vec<_>, Vec<_>) = opened.into_iter().unzip();
+            let runtime = tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()?;
+            let peer_id = generate_peer_id()?;
+            let (engine, actions) = tokio::sync::mpsc::unbounded_channel();
+            let daemon = Daemon::bind(&config.remote, torrents)?.with_engine(engine);
+            start_announcers(
+                &runtime,
+                &config,
+                peer_id,
+                &files,
+                daemon.torrents(),
+                actions,
+            )?;
+            start_peer_listener(&runtime, &config, peer_id, files, daemon.torrents())?;
+            println!("Listening on {}", daemon.local_addr()?);
+            daemon.run()?;
+        }
+        Some(Command::Attach { address }) => {
+            let config = Config::load()?;
+            terrent::interface::attach(config, &address, args.low_bandwidth)?;
+        }
+        None => {
+            let mut config = Config::load()?;
+            config.proxy_override = args.proxy.clone();
+            let (opened, checks) = open_torrents(&args.torrents, &args, &config)?;
+            let torrents = opened.into_iter().map(|(metadata, _)| metadata).collect();
+            terrent::interface::init(config, torrents, checks, args.low_bandwidth);
+        }
+    }
+
+    Ok(())
+}
+
+/// Announces the torrents of `session` to their trackers on `runtime` while they are
+/// active, with the peer port of `config.listen`, and passes on the announce and tracker
+/// actions clients send to the daemon.
+fn start_announcers(
+    runtime: &Runtime,
+    config: &Config,
+    peer_id: PeerId,
+    torrents: &[TorrentFile],
+    session: Arc<Mutex<Vec<Metadata>>>,
+    mut actions: UnboundedReceiver<([u8; 20], RemoteAction)>,
+) -> anyhow::Result<()> {
+    let client = config.http_client()?;
+    let udp = tracker::udp::ConnectionIds::default();
+    let key = tracker::generate_key()?;
+    let mut commands = HashMap::new();
+    for torrent in torrents {
+        let info_hash = torrent.info_hash();
+        let announce = session
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|metadata| metadata.info_hash == info_hash)
+            .map(|metadata| metadata.announce.clone());
+        let Some(announce) = announce else {
+            continue;
+        };
+        let mut tiers = TrackerTiers::new(torrent.trackers())
+            .with_all_tiers(config.announce.all_tiers)
+            .with_proxy(config.proxy().is_some())
+            .with_rewrites(config.tracker_rewrites.clone());
+        // Trackers edited since, as the session lists them, replace the torrent's own.
+        for url in tiers.urls().concat() {
+            if !announce.contains(&url) {
+                tiers.remove_tracker(&url);
+            }
+        }
+        for url in announce {
+            tiers.add_tracker(url);
+        }
+        let lifecycle = AnnounceLifecycle::new(peer_id, config.listen.port)
+            .with_key(key)
+            .with_config(config.announce);
+        let announcer = Announcer::new(client.clone(), tiers, lifecycle).with_udp(udp.clone());
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+        runtime.spawn(follow_torrent(
+            info_hash,
+            Arc::clone(&session),
+            announcer,
+            receiver,
+        ));
+        commands.insert(info_hash, sender);
+    }
+
+    runtime.spawn(async move {
+        while let Some((info_hash, action)) = actions.recv().await {
+            let command = match action {
+                RemoteAction::AnnounceNow => AnnouncerCommand::AnnounceNow,
+                RemoteAction::AnnounceTo(url) => AnnouncerCommand::AnnounceTo(url),
+                RemoteAction::AddTracker(url) => AnnouncerCommand::AddTracker(url),
+                RemoteAction::RemoveTracker(url) => AnnouncerCommand::RemoveTracker(url),
+                // Pausing, resuming and removing show in the session itself.
+                _ => continue,
+            };
+            if let Some(commands) = commands.get(&info_hash) {
+                let _ = commands.send(command).await;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Opens the peer port of `config.listen` and seeds `torrents` on `runtime` to the peers
+/// that connect, for as long as the daemon keeps them active in `session`. Each torrent is
+/// taken once its data was checked, or right away if it is assumed complete. Inbound peers
+/// are screened by the abuse guard and handshaken before a session serves them.
+fn start_peer_listener(
+    runtime: &Runtime,
+    config: &Config,
+    peer_id: PeerId,
+    torrents: Vec<TorrentFile>,
+    session: Arc<Mutex<Vec<Metadata>>>,
+) -> anyhow::Result<()> {
+    if !config.listen.enabled {
+        return Ok(());
+    }
+    let inbound = InboundTorrents::default();
+    let (peers, mut arrivals) = tokio::sync::mpsc::channel(config.listen.max_handshakes.max(1));
+    let listener = runtime.block_on(Listener::bind(
+        &config.listen,
+        &config.network.timings(),
+        inbound.clone(),
+        AbuseGuard::new(AbuseGuardConfig::default()),
+    ))?;
+    println!("Accepting peers on {}", listener.local_addr()?);
+    let guard = listener.guard();
+
+    let root = config.downloads.download_dir().to_path_buf();
+    let sources = Arc::new(Mutex::new(HashMap::new()));
+    runtime.spawn(async move {
+        tokio::spawn(listener.run());
+        for torrent in torrents {
+            let info_hash = torrent.info_hash();
+            let assumed = session
+                .lock()
+                .unwrap()
+                .iter()
+                .any(|metadata| metadata.info_hash == info_hash && metadata.left == Some(0));
+            let (root, inbound, peers, sources) = (
+                root.clone(),
+                inbound.clone(),
+                peers.clone(),
+                sources.clone(),
+            );
+            tokio::task::spawn_blocking(move || {
+                let states = if assumed {
+                    PieceStates::new(torrent.piece_count(), AddMode::AssumeComplete)
+                } else {
+                    check_pieces(&torrent, &root)
+                };
+                let source = SeedSource {
+                    torrent,
+                    root,
+                    states,
+                };
+                sources.lock().unwrap().insert(info_hash, Arc::new(source));
+                inbound.register(InboundTarget {
+                    handshake: seeding_handshake(info_hash, peer_id),
+                    peers,
+                });
+            });
+        }
+        while let Some(peer) = arrivals.recv().await {
+            let source = sources
+                .lock()
+                .unwrap()
+                .get(&peer.handshake.info_hash)
+                .cloned();
+            if let Some(source) = source {
+                // Peers come and go; a session that breaks only ends that peer.
+                tokio::spawn(serve_peer(
+                    peer,
+                    source,
+                    Arc::clone(&session),
+                    Arc::clone(&guard),
+                ));
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Downloads every piece missing or damaged under `data` from the torrent's web seeds;
+/// returns how many were fetched and how many no seed could provide. The seed of each
+/// piece is kept for the pieces tab. Once every piece is there, symlinks are created and
+/// executables marked.
+fn fetch_from_web_seeds(
+    torrent: &TorrentFile,
+    data: &Path,
+    config: &Config,
+) -> anyhow::Result<(usize, usize)> {
+    if torrent.web_seeds().is_empty() {
+        anyhow::bail!("{} has no web seeds", torrent.name());
+    }
+    let client = config.http_client()?;
+    let seeds = torrent
+        .web_seeds()
+        .iter()
+        .map(|url| WebSeed::new(client.clone(), url))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let mut states = check_pieces(torrent, data);
+
+    let info_hash = torrent.info_hash();
+    let mut attribution = PieceAttribution::load(&info_hash).unwrap_or_else(|err| {
+        eprintln!("Warning: {err:#}");
+        PieceAttribution::new()
+    });
+    let (fetched, failed) = runtime.block_on(webseed::fetch_missing(
+        torrent,
+        data,
+        &seeds,
+        &mut states,
+        &mut attribution,
+    ))?;
+    if fetched > 0
+        && let Err(err) = attribution.save(&info_hash)
+    {
+        eprintln!("Warning: {err:#}");
+    }
+    if failed == 0 {
+        for file in torrent.files() {
+            finish_file(data, file)?;
+        }
+    }
+    Ok((fetched, failed))
+}
+
+/// A torrent's metadata along with the file it was loaded from.
+type OpenedTorrent = (Metadata, TorrentFile);
+
+/// Loads the torrents given on the command line as the options ask, each with its file,
+/// along with the background checks of those `--assume-complete` takes as complete.
+fn open_torrents(
+    sources: &[String],
+    args: &args::Arguments,
+    config: &Config,
+) -> anyhow::Result<(Vec<OpenedTorrent>, Vec<AssumedCheck>)> {
+    let now = terrent::history::unix_now();
+    let state = match &args.start_at {
+        Some(time) => TorrentState::Scheduled {
+            at: queue::parse_start_time(time, now)?,
+        },
+        None => TorrentState::Active,
+    };
+    let edits = TrackerEdits::load().unwrap_or_else(|err| {
+        eprintln!("Warning: {err:#}");
+        TrackerEdits::default()
+    });
+    let mut torrents = Vec::new();
+    let mut checks = Vec::new();
+    for source in sources {
+        let torrent = load_torrent(source, config)?;
+        let mut metadata = Metadata {
+            label: args.label.clone(),
+            added: Some(now),
+            origin: origin(source),
+            state,
+            sequential: args.sequential || config.downloads.sequential,
+            swarm: if args.scrape {
+                scrape_swarm(&torrent, config)
+            } else {
+                None
+            },
+            ..Metadata::from(&torrent)
+        };
+        edits.apply(&mut metadata);
+        if args.assume_complete {
+            match AssumedCheck::start(&torrent, config.downloads.download_dir()) {
+                Ok(check) => {
+                    metadata.left = Some(0);
+                    checks.push(check);
+                }
+                Err(err) => eprintln!("Warning: {}: {err:#}", metadata.name),
+            }
+        }
+        if config.low_memory.enabled {
+            metadata.compact();
+        }
+        torrents.push((metadata, torrent));
+    }
+    Ok((torrents, checks))
+}
+
+/// Reports the outcome of every check once it is done, on a thread of its own.
+fn report_checks(checks: Vec<AssumedCheck>) {
+    if checks.is_empty() {
+        return;
+    }
+    std::thread::spawn(move || {
+        for check in checks {
+            let info_hash = hex(&check.info_hash);
+            let missing = check.wait();
+            if missing == 0 {
+                println!("Verified {info_hash}");
+            } else {
+                println!("{info_hash}: {missing} bytes did not match and are missing again");
+            }
+        }
+    });
+}
+
+fn load_torrent(source: &str, config: &Config) -> anyhow::Result<TorrentFile> {
+    let limits = &config.decode;
+    if source == "-" {
+        return TorrentFile::from_reader_with_limits(std::io::stdin().lock(), limits)
+            .context("Invalid torrent from stdin");
+    }
+    if source.starts_with("http://") || source.starts_with("https://") {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let request = config
+            .http_client()?
+            .get(source)
+            .headers(config.headers_for(source)?);
+        return runtime.block_on(TorrentFile::from_request(request, limits));
+    }
+    TorrentFile::open_with_limits(source, limits)
+}
+
+/// Where a torrent was added from, as an absolute path for files so the history can find
+/// it again from any working directory.
+fn origin(source: &str) -> Option<String> {
+    match source {
+        "-" => None,
+        url if url.starts_with("http://") || url.starts_with("https://") => Some(url.to_string()),
+        path => Some(
+            std::fs::canonicalize(path)
+                .map_or_else(|_| path.to_string(), |path| path.display().to_string()),
+        ),
+    }
+}
+
+/// The `.torrent` files among `roots`, searching directories one level deep.
+fn torrent_files(roots: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for root in roots {
+        if !root.is_dir() {
+            files.push(root.clone());
+            continue;
+        }
+        for entry in std::fs::read_dir(root).with_context(|| format!("Failed to read {root:?}"))? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|ext| ext == "torrent") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Swarm counts from the first tracker that answers a scrape. UDP trackers are skipped
+/// behind a proxy, since they would reveal our address.
+fn scrape_swarm(torrent: &TorrentFile, config: &Config) -> Option<ScrapeStats> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .ok()?;
+    let client = match config.http_client() {
+        Ok(client) => client,
+        Err(err) => {
+            eprintln!("{err:#}");
+            return None;
+        }
+    };
+    let udp = tracker::udp::ConnectionIds::default();
+    runtime.block_on(async {
+        for tracker in torrent.trackers().iter().flatten() {
+            let tracker =
+                rewrite(tracker, &config.tracker_rewrites).unwrap_or_else(|| tracker.clone());
+            if config.proxy().is_some() && tracker.starts_with("udp://") {
+                continue;
+            }
+            match tracker::scrape(&client, &udp, &tracker, torrent.info_hash()).await {
+                Ok(stats) => return Some(stats),
+                Err(err) => eprintln!("{err:#}"),
+            }
+        }
+        None
+    })
 }
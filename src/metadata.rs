@@ -1,14 +1,98 @@
+use std::net::SocketAddr;
+
+use crate::queue::TorrentState;
+use crate::stats::{PeerStats, TransferStats};
+use crate::tracker::{ScrapeStats, TrackerState};
+
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Metadata {
     pub name: String,
+    pub info_hash: [u8; 20],
+    pub length: u64,
     pub piece_length: u64,
     pub pieces: Vec<[u8; 20]>,
     pub private: Option<usize>,
+    pub source: Option<String>,
 
     pub announce: Vec<String>,
+    pub web_seeds: Vec<String>,
+
+    pub created_by: Option<String>,
+    pub creation_date: Option<u64>,
+    pub comment: Option<String>,
+    pub encoding: Option<String>,
+
+    /// User-assigned group, e.g. a category like "tv" or "linux-isos".
+    pub label: Option<String>,
+    /// Unix time the torrent was added, if known.
+    pub added: Option<u64>,
+    /// File path or URL the torrent was added from; `None` for stdin.
+    pub origin: Option<String>,
+    pub state: TorrentState,
+    /// Download pieces in order, e.g. to play a video before it is complete.
+    pub sequential: bool,
+    pub stats: TransferStats,
+    /// Bytes still missing; `None` until the data on disk was checked.
+    pub left: Option<u64>,
+    pub peers: Vec<PeerStats>,
+    /// Swarm counts from the last tracker scrape.
+    pub swarm: Option<ScrapeStats>,
+    /// Announce status per tracker; empty until the first announce.
+    pub trackers: Vec<TrackerState>,
+}
+
+impl Metadata {
+    /// Whether the torrent is private (BEP 27) and may only use its trackers' peers.
+    pub fn is_private(&self) -> bool {
+        self.private == Some(1)
+    }
+
+    pub fn piece_count(&self) -> usize {
+        if self.piece_length == 0 {
+            return 0;
+        }
+        self.length.div_ceil(self.piece_length) as usize
+    }
+
+    /// Drops what only the download itself needs, for the low-memory seeding mode; the
+    /// piece hashes can be read back from the `.torrent` file.
+    pub fn compact(&mut self) {
+        self.pieces = Vec::new();
+        self.announce.shrink_to_fit();
+        self.web_seeds.shrink_to_fit();
+        self.peers.shrink_to_fit();
+        self.trackers.shrink_to_fit();
+    }
+
+    /// Rough number of bytes held on the heap.
+    pub fn heap_size(&self) -> usize {
+        let strings = [
+            Some(&self.name),
+            self.source.as_ref(),
+            self.created_by.as_ref(),
+            self.comment.as_ref(),
+            self.encoding.as_ref(),
+            self.label.as_ref(),
+            self.origin.as_ref(),
+        ]
+        .into_iter()
+        .flatten()
+        .chain(&self.announce)
+        .chain(&self.web_seeds)
+        .map(String::capacity)
+        .sum::<usize>();
+        strings
+            + self.pieces.capacity() * size_of::<[u8; 20]>()
+            + self.peers.capacity() * size_of::<PeerStats>()
+            + self.trackers.capacity() * size_of::<TrackerState>()
+    }
 
-    created_by: Option<String>,
-    creation_date: Option<u64>,
-    comment: Option<String>,
-    encoding: Option<String>,
+    /// Counts piece data sent to `addr` towards the torrent and the peer, so announces
+    /// report what was really uploaded.
+    pub fn record_upload(&mut self, addr: SocketAddr, bytes: u64) {
+        self.stats.uploaded += bytes;
+        if let Some(peer) = self.peers.iter_mut().find(|peer| peer.addr == addr) {
+            peer.uploaded += bytes;
+        }
+    }
 }
@@ -0,0 +1,228 @@
+//! Desktop notifications for finished and failing torrents. On Linux with the
+//! `notifications` feature they go to the desktop's notification server over D-Bus
+//! (`org.freedesktop.Notifications`); elsewhere [`Notifier`] does nothing.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Off unless asked for: the terminal is usually where the user looks.
+    pub enabled: bool,
+    pub on_complete: bool,
+    /// Tracker errors that were not there before.
+    pub on_error: bool,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            on_complete: true,
+            on_error: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Notification {
+    /// `folder` is offered as an "Open folder" action.
+    Completed {
+        name: String,
+        folder: Option<PathBuf>,
+    },
+    Error {
+        name: String,
+        message: String,
+    },
+}
+
+impl Notification {
+    pub fn summary(&self) -> &'static str {
+        match self {
+            Notification::Completed { .. } => "Download complete",
+            Notification::Error { .. } => "Torrent error",
+        }
+    }
+
+    pub fn body(&self) -> String {
+        match self {
+            Notification::Completed { name, .. } => name.clone(),
+            Notification::Error { name, message } => format!("{name}\n{message}"),
+        }
+    }
+
+    fn wanted(&self, config: &NotificationConfig) -> bool {
+        config.enabled
+            && match self {
+                Notification::Completed { .. } => config.on_complete,
+                Notification::Error { .. } => config.on_error,
+            }
+    }
+}
+
+/// Sends [`Notification`]s the configuration asks for.
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    config: NotificationConfig,
+    /// Session bus; `None` without one, e.g. over SSH, which silences notifications.
+    #[cfg(all(target_os = "linux", feature = "notifications"))]
+    connection: Option<zbus::blocking::Connection>,
+}
+
+impl Notifier {
+    pub fn new(config: NotificationConfig) -> Self {
+        Self {
+            #[cfg(all(target_os = "linux", feature = "notifications"))]
+            connection: config
+                .enabled
+                .then(|| zbus::blocking::Connection::session().ok())
+                .flatten(),
+            config,
+        }
+    }
+
+    /// Whether any notification can be shown at all.
+    pub fn is_enabled(&self) -> bool {
+        #[cfg(all(target_os = "linux", feature = "notifications"))]
+        return self.config.enabled && self.connection.is_some();
+        #[cfg(not(all(target_os = "linux", feature = "notifications")))]
+        false
+    }
+
+    pub fn send(&self, notification: &Notification) -> Result<()> {
+        if !notification.wanted(&self.config) {
+            return Ok(());
+        }
+        #[cfg(all(target_os = "linux", feature = "notifications"))]
+        if let Some(connection) = &self.connection {
+            dbus::show(connection, notification)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "notifications"))]
+mod dbus {
+    use std::collections::HashMap;
+    use std::path::Path;
+    use std::process::Command;
+    use std::thread;
+
+    use anyhow::{Context, Result};
+    use url::Url;
+    use zbus::blocking::{Connection, Proxy};
+    use zbus::zvariant::Value;
+
+    use super::Notification;
+
+    const APP_NAME: &str = "terrent";
+    const OPEN_FOLDER: &str = "open-folder";
+    /// Let the server pick how long the notification stays.
+    const DEFAULT_EXPIRY: i32 = -1;
+    const URGENCY_NORMAL: u8 = 1;
+    const URGENCY_CRITICAL: u8 = 2;
+
+    pub fn show(connection: &Connection, notification: &Notification) -> Result<()> {
+        let proxy = Proxy::new(
+            connection,
+            "org.freedesktop.Notifications",
+            "/org/freedesktop/Notifications",
+            "org.freedesktop.Notifications",
+        )?;
+
+        let (category, urgency) = match notification {
+            Notification::Completed { .. } => ("transfer.complete", URGENCY_NORMAL),
+            Notification::Error { .. } => ("transfer.error", URGENCY_CRITICAL),
+        };
+        let hints = HashMap::from([
+            ("category", Value::from(category)),
+            ("urgency", Value::from(urgency)),
+        ]);
+        let folder = match notification {
+            Notification::Completed {
+                folder: Some(folder),
+                ..
+            } => Some(folder.clone()),
+            _ => None,
+        };
+        let actions = match folder {
+            Some(_) => vec![OPEN_FOLDER, "Open folder"],
+            None => Vec::new(),
+        };
+
+        // Subscribed before sending, so a click right away is not missed.
+        let signals = folder
+            .is_some()
+            .then(|| proxy.receive_all_signals())
+            .transpose()?;
+        let id: u32 = proxy
+            .call(
+                "Notify",
+                &(
+                    APP_NAME,
+                    0u32,
+                    "",
+                    notification.summary(),
+                    notification.body(),
+                    actions,
+                    hints,
+                    DEFAULT_EXPIRY,
+                ),
+            )
+            .context("Failed to show a desktop notification")?;
+
+        if let (Some(signals), Some(folder)) = (signals, folder) {
+            let connection = connection.clone();
+            // Waits for the click until the notification goes away.
+            thread::spawn(move || {
+                for signal in signals {
+                    let header = signal.header();
+                    let Some(member) = header.member() else {
+                        continue;
+                    };
+                    let body = signal.body();
+                    match member.as_str() {
+                        "ActionInvoked"
+                            if body.deserialize::<(u32, String)>().is_ok_and(
+                                |(signal_id, action)| signal_id == id && action == OPEN_FOLDER,
+                            ) =>
+                        {
+                            open_folder(&connection, &folder)
+                        }
+                        "NotificationClosed"
+                            if body
+                                .deserialize::<(u32, u32)>()
+                                .is_ok_and(|(signal_id, _)| signal_id == id) =>
+                        {
+                            break;
+                        }
+                        _ => {}
+                    }
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Asks the desktop's file manager to show `folder`, falling back to `xdg-open`.
+    fn open_folder(connection: &Connection, folder: &Path) {
+        let shown = Url::from_directory_path(folder).is_ok_and(|url| {
+            connection
+                .call_method(
+                    Some("org.freedesktop.FileManager1"),
+                    "/org/freedesktop/FileManager1",
+                    Some("org.freedesktop.FileManager1"),
+                    "ShowFolders",
+                    &(vec![url.as_str()], ""),
+                )
+                .is_ok()
+        });
+        if !shown {
+            let _ = Command::new("xdg-open").arg(folder).spawn();
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbuseGuardConfig {
+    /// Sliding window used to count connection attempts per address.
+    pub connection_window: Duration,
+    /// Connection attempts allowed inside `connection_window` before rate limiting.
+    pub max_connections_per_window: usize,
+    /// Connections shorter than this count as connect-and-drop behaviour.
+    pub short_lived_threshold: Duration,
+    /// Strikes needed before an address gets banned.
+    pub strikes_before_ban: u32,
+    /// Strikes are forgotten once an address behaves for this long.
+    pub strike_decay: Duration,
+    pub ban_duration: Duration,
+}
+
+impl Default for AbuseGuardConfig {
+    fn default() -> Self {
+        Self {
+            connection_window: Duration::from_secs(60),
+            max_connections_per_window: 10,
+            short_lived_threshold: Duration::from_secs(5),
+            strikes_before_ban: 6,
+            strike_decay: Duration::from_secs(60 * 60),
+            ban_duration: Duration::from_secs(30 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Offense {
+    ConnectionFlood,
+    ShortLivedConnection,
+    InvalidHandshake,
+    OversizedRequest,
+    /// Sent blocks of a piece that failed its hash check.
+    CorruptData,
+    /// Sent peer exchange messages faster than BEP 11 allows.
+    PexFlood,
+}
+
+impl Offense {
+    fn strikes(&self) -> u32 {
+        match self {
+            Offense::ConnectionFlood | Offense::ShortLivedConnection | Offense::PexFlood => 1,
+            Offense::InvalidHandshake | Offense::CorruptData => 2,
+            Offense::OversizedRequest => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Admission {
+    Allowed,
+    RateLimited,
+    Banned { until: Instant },
+}
+
+#[derive(Debug, Default, Clone)]
+struct AbuseRecord {
+    connections: VecDeque<Instant>,
+    strikes: u32,
+    last_offense: Option<Instant>,
+    banned_until: Option<Instant>,
+}
+
+impl AbuseRecord {
+    fn active_ban(&self, now: Instant) -> Option<Instant> {
+        self.banned_until.filter(|until| *until > now)
+    }
+
+    fn is_idle(&self, now: Instant, config: &AbuseGuardConfig) -> bool {
+        let strikes_expired = self
+            .last_offense
+            .is_none_or(|at| now.duration_since(at) >= config.strike_decay);
+
+        self.connections.is_empty() && strikes_expired && self.active_ban(now).is_none()
+    }
+}
+
+/// Tracks inbound connection behaviour per address and temporarily bans abusers.
+///
+/// Time is always passed in by the caller so the listener can drive it from its own clock.
+#[derive(Debug, Default, Clone)]
+pub struct AbuseGuard {
+    config: AbuseGuardConfig,
+    records: HashMap<IpAddr, AbuseRecord>,
+}
+
+impl AbuseGuard {
+    pub fn new(config: AbuseGuardConfig) -> Self {
+        Self {
+            config,
+            records: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &AbuseGuardConfig {
+        &self.config
+    }
+
+    /// Registers an inbound connection attempt and decides whether to accept it.
+    pub fn on_connect(&mut self, ip: IpAddr, now: Instant) -> Admission {
+        if let Some(until) = self.ban_expiry(ip, now) {
+            return Admission::Banned { until };
+        }
+
+        let window = self.config.connection_window;
+        let record = self.records.entry(ip).or_default();
+        while record
+            .connections
+            .front()
+            .is_some_and(|at| now.duration_since(*at) >= window)
+        {
+            record.connections.pop_front();
+        }
+        record.connections.push_back(now);
+
+        if record.connections.len() > self.config.max_connections_per_window {
+            return match self.record_offense(ip, Offense::ConnectionFlood, now) {
+                Admission::Allowed => Admission::RateLimited,
+                admission => admission,
+            };
+        }
+
+        Admission::Allowed
+    }
+
+    /// Registers a closed connection, striking addresses that drop right after connecting.
+    pub fn on_disconnect(&mut self, ip: IpAddr, connected_for: Duration, now: Instant) {
+        if connected_for < self.config.short_lived_threshold {
+            self.record_offense(ip, Offense::ShortLivedConnection, now);
+        }
+    }
+
+    /// Adds strikes for `offense` and bans the address once it crosses the threshold.
+    pub fn record_offense(&mut self, ip: IpAddr, offense: Offense, now: Instant) -> Admission {
+        let config = &self.config;
+        let record = self.records.entry(ip).or_default();
+
+        if let Some(until) = record.active_ban(now) {
+            return Admission::Banned { until };
+        }
+
+        if record
+            .last_offense
+            .is_some_and(|at| now.duration_since(at) >= config.strike_decay)
+        {
+            record.strikes = 0;
+        }
+
+        record.strikes += offense.strikes();
+        record.last_offense = Some(now);
+
+        if record.strikes >= config.strikes_before_ban {
+            let until = now + config.ban_duration;
+            record.strikes = 0;
+            record.connections.clear();
+            record.banned_until = Some(until);
+            return Admission::Banned { until };
+        }
+
+        Admission::Allowed
+    }
+
+    pub fn is_banned(&self, ip: IpAddr, now: Instant) -> bool {
+        self.ban_expiry(ip, now).is_some()
+    }
+
+    pub fn ban_expiry(&self, ip: IpAddr, now: Instant) -> Option<Instant> {
+        self.records
+            .get(&ip)
+            .and_then(|record| record.active_ban(now))
+    }
+
+    pub fn banned(&self, now: Instant) -> impl Iterator<Item = (IpAddr, Instant)> + '_ {
+        self.records
+            .iter()
+            .filter_map(move |(ip, record)| record.active_ban(now).map(|until| (*ip, until)))
+    }
+
+    pub fn unban(&mut self, ip: IpAddr) {
+        self.records.remove(&ip);
+    }
+
+    /// Drops bookkeeping for addresses with no recent activity, strikes, or active ban.
+    pub fn prune(&mut self, now: Instant) {
+        let config = &self.config;
+        for record in self.records.values_mut() {
+            while record
+                .connections
+                .front()
+                .is_some_and(|at| now.duration_since(*at) >= config.connection_window)
+            {
+                record.connections.pop_front();
+            }
+        }
+        self.records
+            .retain(|_, record| !record.is_idle(now, config));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+    #[test]
+    fn strikes_add_up_to_a_ban_and_decay_when_spaced_out() {
+        let mut guard = AbuseGuard::default();
+        let start = Instant::now();
+        let config = AbuseGuardConfig::default();
+
+        // Two invalid handshakes score four strikes, short of the six a ban takes.
+        guard.record_offense(PEER, Offense::InvalidHandshake, start);
+        guard.record_offense(PEER, Offense::InvalidHandshake, start);
+        assert!(!guard.is_banned(PEER, start));
+
+        // After a quiet spell they are forgotten, so an oversized request alone is no ban.
+        let later = start + config.strike_decay;
+        guard.record_offense(PEER, Offense::OversizedRequest, later);
+        assert!(!guard.is_banned(PEER, later));
+
+        // Within the decay a connection dropped right away tops it up to six.
+        guard.on_disconnect(PEER, config.short_lived_threshold, later);
+        assert!(!guard.is_banned(PEER, later));
+        guard.on_disconnect(PEER, Duration::ZERO, later);
+        guard.record_offense(PEER, Offense::InvalidHandshake, later);
+        assert_eq!(
+            guard.on_connect(PEER, later),
+            Admission::Banned {
+                until: later + config.ban_duration
+            }
+        );
+    }
+
+    #[test]
+    fn bans_expire_and_idle_addresses_are_pruned() {
+        let mut guard = AbuseGuard::default();
+        let start = Instant::now();
+        let config = AbuseGuardConfig::default();
+        for _ in 0..2 {
+            guard.record_offense(PEER, Offense::OversizedRequest, start);
+        }
+        assert_eq!(guard.banned(start).count(), 1);
+
+        let expired = start + config.ban_duration;
+        assert!(!guard.is_banned(PEER, expired));
+        assert_eq!(guard.on_connect(PEER, expired), Admission::Allowed);
+
+        // The record stays while its connection and strikes are recent, then goes.
+        guard.prune(expired);
+        assert_eq!(guard.records.len(), 1);
+        guard.prune(start + config.strike_decay);
+        assert!(guard.records.is_empty());
+    }
+}
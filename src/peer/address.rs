@@ -0,0 +1,52 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use super::id::PeerId;
+
+/// Length of a compact IPv4 peer entry (BEP 23): address, port.
+pub const COMPACT_V4_LEN: usize = 6;
+/// Length of a compact IPv6 peer entry (BEP 7): address, port.
+pub const COMPACT_V6_LEN: usize = 18;
+
+/// A peer to connect to, as handed out by a tracker or the DHT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Peer {
+    pub addr: SocketAddr,
+    /// Only known from trackers answering with the dictionary model.
+    pub id: Option<PeerId>,
+}
+
+impl Peer {
+    /// Decodes one compact entry, IPv4 or IPv6 depending on its length.
+    pub fn from_compact(bytes: &[u8]) -> Option<Self> {
+        let (ip, port) = match bytes.len() {
+            COMPACT_V4_LEN => {
+                let ip: [u8; 4] = bytes[..4].try_into().expect("slice is 4 bytes");
+                (IpAddr::V4(Ipv4Addr::from(ip)), &bytes[4..])
+            }
+            COMPACT_V6_LEN => {
+                let ip: [u8; 16] = bytes[..16].try_into().expect("slice is 16 bytes");
+                (IpAddr::V6(Ipv6Addr::from(ip)), &bytes[16..])
+            }
+            _ => return None,
+        };
+        let port = u16::from_be_bytes([port[0], port[1]]);
+        Some(Self::from(SocketAddr::new(ip, port)))
+    }
+
+    /// Decodes a string of compact entries of `entry_len` bytes each.
+    pub fn compact_list(bytes: &[u8], entry_len: usize) -> Option<Vec<Self>> {
+        if !bytes.len().is_multiple_of(entry_len) {
+            return None;
+        }
+        bytes
+            .chunks_exact(entry_len)
+            .map(Self::from_compact)
+            .collect()
+    }
+}
+
+impl From<SocketAddr> for Peer {
+    fn from(addr: SocketAddr) -> Self {
+        Self { addr, id: None }
+    }
+}
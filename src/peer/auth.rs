@@ -0,0 +1,209 @@
+//! Authenticated swarms: a closed group shares a secret, and peers prove they know it with a
+//! challenge and response over a `tt_auth` extension message (BEP 10) before we serve them.
+//! Peers without the secret, or without the extension, never get data from us.
+
+use std::fmt;
+
+use anyhow::{Context, Result, bail};
+use bendy::decoding::{Decoder, Object};
+use bendy::encoding::Encoder;
+use hmac::{Hmac, KeyInit, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::extension::ExtendedHandshake;
+use super::id::PeerId;
+
+pub const TT_AUTH: &str = "tt_auth";
+const NONCE_LEN: usize = 32;
+
+/// Pre-shared secret of an authenticated swarm; never printed.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SwarmSecret(String);
+
+impl SwarmSecret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Self(secret.into())
+    }
+}
+
+impl fmt::Debug for SwarmSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SwarmSecret(<redacted>)")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthMessage {
+    /// Asks the peer to prove it knows the secret by signing `nonce`.
+    Challenge {
+        nonce: Vec<u8>,
+    },
+    Response {
+        proof: Vec<u8>,
+    },
+}
+
+impl AuthMessage {
+    /// Encodes the message payload that follows the extended message id.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new();
+        encoder.emit_dict(|mut dict| {
+            match self {
+                AuthMessage::Challenge { nonce } => {
+                    dict.emit_pair(b"msg_type", 0)?;
+                    dict.emit_pair_with(b"nonce", |e| e.emit_bytes(nonce))?;
+                }
+                AuthMessage::Response { proof } => {
+                    dict.emit_pair(b"msg_type", 1)?;
+                    dict.emit_pair_with(b"proof", |e| e.emit_bytes(proof))?;
+                }
+            }
+            Ok(())
+        })?;
+        Ok(encoder.get_output()?)
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(payload);
+        let mut dict = decoder
+            .next_object()?
+            .context("Empty tt_auth message")?
+            .try_into_dictionary()?;
+
+        let mut msg_type = None;
+        let mut value = None;
+        while let Some((key, object)) = dict.next_pair()? {
+            match (key, object) {
+                (b"msg_type", Object::Integer(integer)) => msg_type = Some(integer.parse::<u8>()?),
+                (b"nonce" | b"proof", Object::Bytes(bytes)) => value = Some(bytes.to_vec()),
+                _ => {}
+            }
+        }
+
+        match msg_type {
+            Some(0) => Ok(AuthMessage::Challenge {
+                nonce: value.context("tt_auth challenge without nonce")?,
+            }),
+            Some(1) => Ok(AuthMessage::Response {
+                proof: value.context("tt_auth response without proof")?,
+            }),
+            other => bail!("Unknown tt_auth message type {other:?}"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum AuthState {
+    /// Our challenge is out, or not sent yet.
+    #[default]
+    Pending,
+    Verified,
+    /// Wrong proof, or the peer does not speak `tt_auth`; the connection should be dropped.
+    Failed,
+}
+
+/// Authentication of one connection in an authenticated swarm. Both sides challenge each
+/// other; a proof binds the secret to the info hash, the challenger's nonce and the peer id
+/// of the prover, so it is useless on any other connection.
+#[derive(Debug, Clone)]
+pub struct SwarmAuth {
+    key: [u8; 32],
+    info_hash: [u8; 20],
+    our_id: PeerId,
+    their_id: PeerId,
+    nonce: Vec<u8>,
+    state: AuthState,
+}
+
+impl SwarmAuth {
+    /// Fails for a peer using our own id: it could answer our challenge by echoing it back
+    /// to us, so the proof would not show it knows the secret.
+    pub fn new(
+        secret: &SwarmSecret,
+        info_hash: [u8; 20],
+        our_id: PeerId,
+        their_id: PeerId,
+    ) -> Result<Self> {
+        if their_id == our_id {
+            bail!("Peer uses our own peer id");
+        }
+        let mut nonce = vec![0; NONCE_LEN];
+        getrandom::fill(&mut nonce).context("Failed to generate a tt_auth nonce")?;
+        Ok(Self {
+            key: Sha256::digest(secret.0.as_bytes()).into(),
+            info_hash,
+            our_id,
+            their_id,
+            nonce,
+            state: AuthState::default(),
+        })
+    }
+
+    pub fn state(&self) -> AuthState {
+        self.state
+    }
+
+    /// Whether the peer may be unchoked and have its requests answered.
+    pub fn may_serve(&self) -> bool {
+        self.state == AuthState::Verified
+    }
+
+    /// Adds `tt_auth` to our extension handshake under message id `id`.
+    pub fn advertise(handshake: &mut ExtendedHandshake, id: u8) {
+        handshake.extensions.insert(TT_AUTH.to_string(), id);
+    }
+
+    /// Checks the peer's extension handshake; our challenge is returned when it speaks
+    /// `tt_auth`.
+    pub fn start(&mut self, theirs: &ExtendedHandshake) -> Option<AuthMessage> {
+        if theirs.extension_id(TT_AUTH).is_none() {
+            self.state = AuthState::Failed;
+            return None;
+        }
+        Some(AuthMessage::Challenge {
+            nonce: self.nonce.clone(),
+        })
+    }
+
+    /// Answers a challenge or checks the proof for ours; returns the reply to send, if any.
+    pub fn handle(&mut self, message: &AuthMessage) -> Option<AuthMessage> {
+        match message {
+            AuthMessage::Challenge { nonce } => {
+                // Our nonces are this long; anything else does not come from a terrent peer.
+                if nonce.len() != NONCE_LEN {
+                    self.state = AuthState::Failed;
+                    return None;
+                }
+                Some(AuthMessage::Response {
+                    proof: self.proof(nonce, &self.our_id).to_vec(),
+                })
+            }
+            AuthMessage::Response { proof } => {
+                if self.state == AuthState::Pending {
+                    let expected = self.proof(&self.nonce, &self.their_id);
+                    self.state = if constant_time_eq(proof, &expected) {
+                        AuthState::Verified
+                    } else {
+                        AuthState::Failed
+                    };
+                }
+                None
+            }
+        }
+    }
+
+    /// HMAC-SHA-256 (RFC 2104) over the nonce, the info hash and the prover's peer id.
+    fn proof(&self, nonce: &[u8], prover: &PeerId) -> [u8; 32] {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC takes any key");
+        mac.update(nonce);
+        mac.update(&self.info_hash);
+        mac.update(prover);
+        mac.finalize().into_bytes().into()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
@@ -0,0 +1,174 @@
+use anyhow::{Result, bail};
+
+use super::message::Message;
+
+/// Which pieces a peer has, in the wire layout of the bitfield message: the high bit of the
+/// first byte is piece 0, and spare bits at the end are zero.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Bitfield {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bitfield {
+    /// No pieces out of `len`.
+    pub fn new(len: usize) -> Self {
+        Self {
+            bytes: vec![0; len.div_ceil(8)],
+            len,
+        }
+    }
+
+    /// Every piece out of `len`, as a seed has.
+    pub fn full(len: usize) -> Self {
+        let mut bitfield = Self {
+            bytes: vec![0xff; len.div_ceil(8)],
+            len,
+        };
+        bitfield.clear_spare_bits();
+        bitfield
+    }
+
+    /// Checks a received bitfield against the torrent's `len` pieces: the byte count has to
+    /// match and spare bits have to be clear.
+    pub fn from_bytes(bytes: &[u8], len: usize) -> Result<Self> {
+        if bytes.len() != len.div_ceil(8) {
+            bail!(
+                "Bitfield has {} bytes, {len} pieces need {}",
+                bytes.len(),
+                len.div_ceil(8)
+            );
+        }
+        let bitfield = Self {
+            bytes: bytes.to_vec(),
+            len,
+        };
+        if bitfield.spare_bits() != 0 {
+            bail!("Bitfield has spare bits set");
+        }
+        Ok(bitfield)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn to_message(&self) -> Message {
+        Message::Bitfield(self.bytes.clone())
+    }
+
+    /// The shortest announcement for a peer that speaks the fast extension: `HaveAll` or
+    /// `HaveNone` when they say the same as the bitfield.
+    pub fn to_fast_message(&self) -> Message {
+        if self.is_complete() {
+            Message::HaveAll
+        } else if self.count() == 0 {
+            Message::HaveNone
+        } else {
+            self.to_message()
+        }
+    }
+
+    /// Number of pieces, set or not.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// False for pieces past the end.
+    pub fn has_piece(&self, index: usize) -> bool {
+        index < self.len && self.bytes[index / 8] & mask(index) != 0
+    }
+
+    /// Marks `index` as present; returns false when it is past the end, e.g. from a `have`
+    /// message for a piece the torrent does not have.
+    pub fn set_piece(&mut self, index: usize) -> bool {
+        if index >= self.len {
+            return false;
+        }
+        self.bytes[index / 8] |= mask(index);
+        true
+    }
+
+    pub fn clear_piece(&mut self, index: usize) {
+        if index < self.len {
+            self.bytes[index / 8] &= !mask(index);
+        }
+    }
+
+    /// Indices of the pieces present, in order.
+    pub fn pieces(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.len).filter(|index| self.has_piece(*index))
+    }
+
+    pub fn count(&self) -> usize {
+        self.bytes
+            .iter()
+            .map(|byte| byte.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.count() == self.len
+    }
+
+    /// One flag per piece, as [`crate::download::Selection::is_interested`] takes them.
+    pub fn to_bools(&self) -> Vec<bool> {
+        (0..self.len).map(|index| self.has_piece(index)).collect()
+    }
+
+    fn spare_bits(&self) -> u8 {
+        match (self.len % 8, self.bytes.last()) {
+            (0, _) | (_, None) => 0,
+            (used, Some(last)) => last & (0xff >> used),
+        }
+    }
+
+    fn clear_spare_bits(&mut self) {
+        let spare = self.spare_bits();
+        if let Some(last) = self.bytes.last_mut() {
+            *last &= !spare;
+        }
+    }
+}
+
+fn mask(index: usize) -> u8 {
+    0x80 >> (index % 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_the_bitfield_message() {
+        let mut bitfield = Bitfield::new(10);
+        bitfield.set_piece(0);
+        bitfield.set_piece(9);
+        assert!(!bitfield.set_piece(10));
+        assert_eq!(bitfield.as_bytes(), [0x80, 0x40]);
+
+        let Message::Bitfield(bytes) =
+            Message::decode(&bitfield.to_message().encode()[4..]).unwrap()
+        else {
+            panic!("Not a bitfield message");
+        };
+        let decoded = Bitfield::from_bytes(&bytes, 10).unwrap();
+        assert_eq!(decoded, bitfield);
+        assert_eq!(decoded.pieces().collect::<Vec<_>>(), [0, 9]);
+        assert_eq!(Bitfield::full(10).as_bytes(), [0xff, 0xc0]);
+    }
+
+    #[test]
+    fn rejects_spare_bits_and_wrong_lengths() {
+        assert!(Bitfield::from_bytes(&[0xff, 0xc0], 10).is_ok());
+        assert!(Bitfield::from_bytes(&[0xff, 0xe0], 10).is_err());
+        assert!(Bitfield::from_bytes(&[0xff, 0x01], 10).is_err());
+        assert!(Bitfield::from_bytes(&[0xff], 10).is_err());
+        assert!(Bitfield::from_bytes(&[0xff, 0xc0, 0x00], 10).is_err());
+        assert!(Bitfield::from_bytes(&[0xff], 8).is_ok());
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+
+use super::auth::SwarmAuth;
+use super::connection::PeerConnection;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChokerConfig {
+    /// Peers unchoked at once, the optimistic unchoke included.
+    pub upload_slots: usize,
+    /// How often the regular slots are reassigned.
+    pub interval: Duration,
+    /// How often the optimistic slot moves to another peer.
+    pub optimistic_interval: Duration,
+}
+
+impl Default for ChokerConfig {
+    fn default() -> Self {
+        Self {
+            upload_slots: 4,
+            interval: Duration::from_secs(10),
+            optimistic_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+/// What the choker needs to know about one connected peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChokeCandidate<P> {
+    pub peer: P,
+    pub interested: bool,
+    /// Bytes per second the peer sends us.
+    pub download_rate: u64,
+    /// Bytes per second we send the peer.
+    pub upload_rate: u64,
+}
+
+impl<P> ChokeCandidate<P> {
+    /// A connected peer with the rates of its [`PeerConnection::transfer`] at `now`.
+    pub fn from_connection(peer: P, connection: &PeerConnection, now: Instant) -> Self {
+        Self {
+            peer,
+            interested: connection.peer_interested(),
+            download_rate: connection.transfer().download_rate(now),
+            upload_rate: connection.transfer().upload_rate(now),
+        }
+    }
+
+    /// In an authenticated swarm, keeps the peer out of the upload slots until it has
+    /// proven it knows the secret.
+    pub fn with_auth(mut self, auth: &SwarmAuth) -> Self {
+        self.interested &= auth.may_serve();
+        self
+    }
+}
+
+/// Changes to apply after a round; each peer gets a choke or unchoke message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChokeDecision<P> {
+    pub unchoke: Vec<P>,
+    pub choke: Vec<P>,
+}
+
+/// Tit-for-tat choking (BEP 3): the peers that give us the most are unchoked, plus one
+/// optimistic unchoke so newcomers get a chance to prove themselves.
+///
+/// While seeding nobody gives us anything, so the peers we upload to fastest keep their
+/// slots instead, which spreads the data quickly.
+#[derive(Debug, Clone)]
+pub struct Choker<P> {
+    config: ChokerConfig,
+    unchoked: BTreeSet<P>,
+    optimistic: Option<P>,
+    last_round: Option<Instant>,
+    last_optimistic: Option<Instant>,
+}
+
+impl<P: Clone + Ord> Choker<P> {
+    pub fn new(config: ChokerConfig) -> Self {
+        Self {
+            config,
+            unchoked: BTreeSet::new(),
+            optimistic: None,
+            last_round: None,
+            last_optimistic: None,
+        }
+    }
+
+    pub fn is_unchoked(&self, peer: &P) -> bool {
+        self.unchoked.contains(peer)
+    }
+
+    pub fn optimistic(&self) -> Option<&P> {
+        self.optimistic.as_ref()
+    }
+
+    /// Forgets a disconnected peer; its slot is refilled in the next round.
+    pub fn remove(&mut self, peer: &P) {
+        self.unchoked.remove(peer);
+        if self.optimistic.as_ref() == Some(peer) {
+            self.optimistic = None;
+        }
+    }
+
+    /// Runs a round if one is due, returning the peers whose state changed.
+    pub fn tick(
+        &mut self,
+        candidates: &[ChokeCandidate<P>],
+        seeding: bool,
+        now: Instant,
+    ) -> Option<ChokeDecision<P>> {
+        let due = |last: Option<Instant>, interval| {
+            last.is_none_or(|at: Instant| now.duration_since(at) >= interval)
+        };
+        if !due(self.last_round, self.config.interval) {
+            return None;
+        }
+        self.last_round = Some(now);
+        let rotate = due(self.last_optimistic, self.config.optimistic_interval)
+            || self
+                .optimistic
+                .as_ref()
+                .is_none_or(|peer| !candidates.iter().any(|c| c.peer == *peer && c.interested));
+        if rotate {
+            self.last_optimistic = Some(now);
+        }
+        Some(self.round(candidates, seeding, rotate))
+    }
+
+    fn round(
+        &mut self,
+        candidates: &[ChokeCandidate<P>],
+        seeding: bool,
+        rotate: bool,
+    ) -> ChokeDecision<P> {
+        let mut interested = candidates
+            .iter()
+            .filter(|candidate| candidate.interested)
+            .collect::<Vec<_>>();
+        interested.sort_by(|a, b| {
+            let rate = |candidate: &ChokeCandidate<P>| {
+                if seeding {
+                    candidate.upload_rate
+                } else {
+                    candidate.download_rate
+                }
+            };
+            rate(b).cmp(&rate(a)).then_with(|| a.peer.cmp(&b.peer))
+        });
+
+        let regular_slots = self.config.upload_slots.saturating_sub(1);
+        let mut unchoked = interested
+            .iter()
+            .take(regular_slots)
+            .map(|candidate| candidate.peer.clone())
+            .collect::<BTreeSet<_>>();
+
+        if rotate {
+            // Peers take turns in address order, starting after the previous one.
+            let choked = interested
+                .iter()
+                .map(|candidate| &candidate.peer)
+                .filter(|peer| !unchoked.contains(*peer))
+                .collect::<BTreeSet<_>>();
+            self.optimistic = self
+                .optimistic
+                .as_ref()
+                .and_then(|previous| choked.iter().find(|peer| **peer > previous))
+                .or_else(|| choked.first())
+                .map(|peer| (*peer).clone());
+        }
+        if let Some(optimistic) = &self.optimistic
+            && self.config.upload_slots > 0
+        {
+            unchoked.insert(optimistic.clone());
+        }
+
+        let decision = ChokeDecision {
+            unchoke: unchoked.difference(&self.unchoked).cloned().collect(),
+            choke: self.unchoked.difference(&unchoked).cloned().collect(),
+        };
+        self.unchoked = unchoked;
+        decision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(peer: u8, download_rate: u64, upload_rate: u64) -> ChokeCandidate<u8> {
+        ChokeCandidate {
+            peer,
+            interested: true,
+            download_rate,
+            upload_rate,
+        }
+    }
+
+    #[test]
+    fn unchokes_the_best_reciprocators_and_one_optimistic_peer() {
+        let mut choker = Choker::new(ChokerConfig::default());
+        let candidates = [
+            candidate(1, 100, 0),
+            candidate(2, 500, 0),
+            candidate(3, 300, 0),
+            candidate(4, 0, 0),
+            candidate(5, 200, 0),
+        ];
+        let decision = choker.tick(&candidates, false, Instant::now()).unwrap();
+        assert_eq!(decision.unchoke, vec![1, 2, 3, 5]);
+        assert_eq!(choker.optimistic(), Some(&1));
+        assert!(decision.choke.is_empty());
+    }
+
+    #[test]
+    fn rotates_the_optimistic_slot() {
+        let mut choker = Choker::new(ChokerConfig {
+            upload_slots: 2,
+            ..ChokerConfig::default()
+        });
+        let candidates = [candidate(1, 9, 0), candidate(2, 0, 0), candidate(3, 0, 0)];
+        let start = Instant::now();
+        choker.tick(&candidates, false, start);
+        assert_eq!(choker.optimistic(), Some(&2));
+
+        // The regular round keeps the optimistic peer until its time is up.
+        assert!(choker.tick(&candidates, false, start).is_none());
+        let decision = choker
+            .tick(&candidates, false, start + Duration::from_secs(10))
+            .unwrap();
+        assert!(decision.unchoke.is_empty() && decision.choke.is_empty());
+
+        let decision = choker
+            .tick(&candidates, false, start + Duration::from_secs(30))
+            .unwrap();
+        assert_eq!(decision.unchoke, vec![3]);
+        assert_eq!(decision.choke, vec![2]);
+    }
+
+    #[test]
+    fn seeding_prefers_fast_downloaders() {
+        let mut choker = Choker::new(ChokerConfig {
+            upload_slots: 2,
+            ..ChokerConfig::default()
+        });
+        let candidates = [
+            candidate(1, 0, 10),
+            candidate(2, 0, 900),
+            candidate(3, 0, 50),
+        ];
+        choker.tick(&candidates, true, Instant::now());
+        assert!(choker.is_unchoked(&2));
+        // Peer 1 only holds the optimistic slot.
+        assert_eq!(choker.optimistic(), Some(&1));
+        assert!(!choker.is_unchoked(&3));
+    }
+}
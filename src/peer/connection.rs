@@ -0,0 +1,629 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Result, bail, ensure};
+
+use super::bitfield::Bitfield;
+use super::message::Message;
+use super::transfer::PeerTransfer;
+
+/// Longest block a peer may ask for; the usual size is [`super::message::BLOCK_SIZE`], and
+/// some old clients request up to this much.
+pub const MAX_REQUEST_LEN: u32 = 128 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct BlockRequest {
+    pub piece: u32,
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl BlockRequest {
+    pub fn to_request(self) -> Message {
+        Message::Request {
+            piece: self.piece,
+            offset: self.offset,
+            length: self.length,
+        }
+    }
+
+    pub fn to_cancel(self) -> Message {
+        Message::Cancel {
+            piece: self.piece,
+            offset: self.offset,
+            length: self.length,
+        }
+    }
+
+    pub fn to_reject(self) -> Message {
+        Message::RejectRequest {
+            piece: self.piece,
+            offset: self.offset,
+            length: self.length,
+        }
+    }
+}
+
+/// The two choke and two interest flags of one connection (BEP 3), with what the peer has
+/// and the requests in flight either way.
+///
+/// Both sides start out choking and not interested. Incoming messages drive the peer's
+/// flags through [`Self::receive`]; the scheduler drives ours, and every method that
+/// changes one returns the message that tells the peer.
+///
+/// With the fast extension (BEP 6) negotiated, every request gets an answer: a choke no
+/// longer discards requests silently, refused ones are rejected, and allowed-fast pieces
+/// may be requested while choked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerConnection {
+    am_choking: bool,
+    am_interested: bool,
+    peer_choking: bool,
+    peer_interested: bool,
+    pieces: Bitfield,
+    /// Our requests the peer has not answered yet.
+    requests: Vec<BlockRequest>,
+    /// Our requests a choke or reject cancelled, for the scheduler to hand to other peers.
+    dropped: Vec<BlockRequest>,
+    /// The peer's requests we have not served yet.
+    peer_requests: Vec<BlockRequest>,
+    /// The peer's requests we refused and still have to reject.
+    rejected: Vec<BlockRequest>,
+    /// A bitfield is only allowed as the first message.
+    received_any: bool,
+    /// Both sides set the fast extension bit in their handshakes.
+    fast: bool,
+    /// Pieces the peer lets us request while it chokes us.
+    allowed_fast: BTreeSet<u32>,
+    /// Pieces we serve the peer while choking it.
+    granted_fast: BTreeSet<u32>,
+    transfer: PeerTransfer,
+}
+
+impl PeerConnection {
+    /// A connection to a peer of a torrent with `piece_count` pieces, right after the
+    /// handshake.
+    pub fn new(piece_count: usize) -> Self {
+        Self {
+            am_choking: true,
+            am_interested: false,
+            peer_choking: true,
+            peer_interested: false,
+            pieces: Bitfield::new(piece_count),
+            requests: Vec::new(),
+            dropped: Vec::new(),
+            peer_requests: Vec::new(),
+            rejected: Vec::new(),
+            received_any: false,
+            fast: false,
+            allowed_fast: BTreeSet::new(),
+            granted_fast: BTreeSet::new(),
+            transfer: PeerTransfer::new(),
+        }
+    }
+
+    /// Turns on the fast extension, for when both handshakes advertised it
+    /// ([`super::fast::supports_fast`]).
+    pub fn with_fast(mut self, fast: bool) -> Self {
+        self.fast = fast;
+        self
+    }
+
+    pub fn fast(&self) -> bool {
+        self.fast
+    }
+
+    pub fn am_choking(&self) -> bool {
+        self.am_choking
+    }
+
+    pub fn am_interested(&self) -> bool {
+        self.am_interested
+    }
+
+    pub fn peer_choking(&self) -> bool {
+        self.peer_choking
+    }
+
+    pub fn peer_interested(&self) -> bool {
+        self.peer_interested
+    }
+
+    /// Pieces the peer announced.
+    pub fn pieces(&self) -> &Bitfield {
+        &self.pieces
+    }
+
+    pub fn requests(&self) -> &[BlockRequest] {
+        &self.requests
+    }
+
+    /// Pieces the peer lets us request while it chokes us.
+    pub fn allowed_fast(&self) -> &BTreeSet<u32> {
+        &self.allowed_fast
+    }
+
+    /// Pieces we serve the peer while choking it.
+    pub fn granted_fast(&self) -> &BTreeSet<u32> {
+        &self.granted_fast
+    }
+
+    /// Data moved over the connection, for the peers tab and the choker.
+    pub fn transfer(&self) -> &PeerTransfer {
+        &self.transfer
+    }
+
+    /// For recording data as it is written to or read from the peer.
+    pub fn transfer_mut(&mut self) -> &mut PeerTransfer {
+        &mut self.transfer
+    }
+
+    /// Whether we may send requests: we want something and the peer lets us.
+    pub fn can_request(&self) -> bool {
+        self.am_interested && !self.peer_choking
+    }
+
+    /// Applies a message from the peer. Errors are protocol violations the connection
+    /// should be closed for.
+    pub fn receive(&mut self, message: &Message) -> Result<()> {
+        let first = !self.received_any;
+        if !matches!(message, Message::KeepAlive) {
+            self.received_any = true;
+        }
+
+        if matches!(
+            message,
+            Message::SuggestPiece { .. }
+                | Message::HaveAll
+                | Message::HaveNone
+                | Message::RejectRequest { .. }
+                | Message::AllowedFast { .. }
+        ) {
+            ensure!(
+                self.fast,
+                "Peer sent a fast extension message without the extension"
+            );
+        }
+
+        match message {
+            Message::KeepAlive | Message::Port(_) | Message::Extended { .. } => {}
+            Message::Unknown { .. } => {}
+            Message::Choke => {
+                self.peer_choking = true;
+                // A choking peer discards our requests without answering them, unless it
+                // speaks the fast extension and rejects them one by one.
+                if !self.fast {
+                    self.dropped.append(&mut self.requests);
+                }
+            }
+            Message::Unchoke => self.peer_choking = false,
+            Message::Interested => self.peer_interested = true,
+            Message::NotInterested => self.peer_interested = false,
+            Message::Have { piece } => {
+                if !self.pieces.set_piece(*piece as usize) {
+                    bail!("Peer has piece {piece}, which does not exist");
+                }
+            }
+            Message::Bitfield(bytes) => {
+                if !first {
+                    bail!("Peer sent a bitfield after other messages");
+                }
+                self.pieces = Bitfield::from_bytes(bytes, self.pieces.len())?;
+            }
+            Message::HaveAll | Message::HaveNone => {
+                if !first {
+                    bail!("Peer sent {message:?} after other messages");
+                }
+                self.pieces = if *message == Message::HaveAll {
+                    Bitfield::full(self.pieces.len())
+                } else {
+                    Bitfield::new(self.pieces.len())
+                };
+            }
+            Message::SuggestPiece { piece } | Message::AllowedFast { piece } => {
+                if *piece as usize >= self.pieces.len() {
+                    bail!("Peer named piece {piece}, which does not exist");
+                }
+                if matches!(message, Message::AllowedFast { .. }) {
+                    self.allowed_fast.insert(*piece);
+                }
+            }
+            Message::RejectRequest {
+                piece,
+                offset,
+                length,
+            } => {
+                let request = BlockRequest {
+                    piece: *piece,
+                    offset: *offset,
+                    length: *length,
+                };
+                let Some(index) = self.requests.iter().position(|queued| *queued == request) else {
+                    bail!("Peer rejected a block we did not request");
+                };
+                self.dropped.push(self.requests.remove(index));
+            }
+            Message::Request {
+                piece,
+                offset,
+                length,
+            } => {
+                let request = BlockRequest {
+                    piece: *piece,
+                    offset: *offset,
+                    length: *length,
+                };
+                if *length == 0 || *length > MAX_REQUEST_LEN {
+                    bail!("Peer requested a {length}-byte block");
+                }
+                // Requests that crossed our choke on the wire are dropped, not an offense.
+                if self.am_choking && !self.granted_fast.contains(piece) {
+                    if self.fast {
+                        self.rejected.push(request);
+                    }
+                } else if !self.peer_requests.contains(&request) {
+                    self.peer_requests.push(request);
+                }
+            }
+            Message::Cancel {
+                piece,
+                offset,
+                length,
+            } => {
+                let request = BlockRequest {
+                    piece: *piece,
+                    offset: *offset,
+                    length: *length,
+                };
+                self.peer_requests.retain(|queued| *queued != request);
+            }
+            Message::Piece {
+                piece,
+                offset,
+                data,
+            } => {
+                // A block we cancelled may still arrive; it is simply not ours to track.
+                let length = u32::try_from(data.len()).unwrap_or(u32::MAX);
+                self.requests.retain(|request| {
+                    *request
+                        != BlockRequest {
+                            piece: *piece,
+                            offset: *offset,
+                            length,
+                        }
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Declares whether we want anything from the peer.
+    pub fn set_interested(&mut self, interested: bool) -> Option<Message> {
+        if self.am_interested == interested {
+            return None;
+        }
+        self.am_interested = interested;
+        Some(if interested {
+            Message::Interested
+        } else {
+            Message::NotInterested
+        })
+    }
+
+    /// Stops serving the peer, dropping the requests it has queued. With the fast extension
+    /// they are rejected instead, except for allowed-fast pieces, which are still served.
+    pub fn choke(&mut self) -> Option<Message> {
+        if self.am_choking {
+            return None;
+        }
+        self.am_choking = true;
+        let granted = &self.granted_fast;
+        let (kept, refused) = self
+            .peer_requests
+            .drain(..)
+            .partition(|request| granted.contains(&request.piece));
+        self.peer_requests = kept;
+        if self.fast {
+            self.rejected.extend(refused);
+        }
+        Some(Message::Choke)
+    }
+
+    pub fn unchoke(&mut self) -> Option<Message> {
+        if !self.am_choking {
+            return None;
+        }
+        self.am_choking = false;
+        Some(Message::Unchoke)
+    }
+
+    /// Sends a request, which the peer must allow and be able to answer.
+    pub fn request(&mut self, block: BlockRequest) -> Result<Message> {
+        if !self.am_interested {
+            bail!("Requests need interest to be declared first");
+        }
+        if self.peer_choking && !self.allowed_fast.contains(&block.piece) {
+            bail!("The peer is choking us");
+        }
+        if !self.pieces.has_piece(block.piece as usize) {
+            bail!("The peer does not have piece {}", block.piece);
+        }
+        if self.requests.contains(&block) {
+            bail!("Block is already requested");
+        }
+        self.requests.push(block);
+        Ok(block.to_request())
+    }
+
+    /// Withdraws one of our requests, e.g. in endgame once another peer delivered it.
+    pub fn cancel(&mut self, block: BlockRequest) -> Option<Message> {
+        let index = self.requests.iter().position(|request| *request == block)?;
+        self.requests.remove(index);
+        Some(block.to_cancel())
+    }
+
+    /// Requests a choke or reject cancelled since the last call.
+    pub fn take_dropped(&mut self) -> Vec<BlockRequest> {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Lets the peer download `pieces` while we choke it, returning the messages that tell
+    /// it about the ones not granted before. Does nothing without the fast extension.
+    pub fn grant_allowed_fast(&mut self, pieces: impl IntoIterator<Item = u32>) -> Vec<Message> {
+        if !self.fast {
+            return Vec::new();
+        }
+        pieces
+            .into_iter()
+            .filter(|piece| (*piece as usize) < self.pieces.len())
+            .filter(|piece| self.granted_fast.insert(*piece))
+            .map(|piece| Message::AllowedFast { piece })
+            .collect()
+    }
+
+    /// Refuses one of the peer's requests, e.g. when the upload queue is full. Returns the
+    /// reject to send with the fast extension; without it the request is just forgotten.
+    pub fn reject(&mut self, block: BlockRequest) -> Option<Message> {
+        self.peer_requests.retain(|queued| *queued != block);
+        self.fast.then(|| block.to_reject())
+    }
+
+    /// Rejects for the peer's requests refused since the last call.
+    pub fn take_rejects(&mut self) -> Vec<Message> {
+        self.rejected
+            .drain(..)
+            .map(BlockRequest::to_reject)
+            .collect()
+    }
+
+    /// The oldest request of the peer's to serve.
+    pub fn next_peer_request(&mut self) -> Option<BlockRequest> {
+        (!self.peer_requests.is_empty()).then(|| self.peer_requests.remove(0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK: BlockRequest = BlockRequest {
+        piece: 1,
+        offset: 0,
+        length: 16 * 1024,
+    };
+
+    /// A peer with every one of 4 pieces that has unchoked us while we are interested.
+    fn downloading() -> PeerConnection {
+        let mut connection = PeerConnection::new(4);
+        connection.receive(&Message::Bitfield(vec![0xf0])).unwrap();
+        connection.set_interested(true);
+        connection.receive(&Message::Unchoke).unwrap();
+        connection
+    }
+
+    #[test]
+    fn starts_choked_and_not_interested() {
+        let connection = PeerConnection::new(4);
+        assert!(connection.am_choking() && connection.peer_choking());
+        assert!(!connection.am_interested() && !connection.peer_interested());
+        assert!(!connection.can_request());
+    }
+
+    #[test]
+    fn peer_messages_drive_peer_flags() {
+        let mut connection = PeerConnection::new(4);
+        connection.receive(&Message::Interested).unwrap();
+        assert!(connection.peer_interested());
+        connection.receive(&Message::Unchoke).unwrap();
+        assert!(!connection.peer_choking());
+        connection.receive(&Message::NotInterested).unwrap();
+        connection.receive(&Message::Choke).unwrap();
+        assert!(!connection.peer_interested() && connection.peer_choking());
+    }
+
+    #[test]
+    fn our_changes_produce_messages_once() {
+        let mut connection = PeerConnection::new(4);
+        assert_eq!(connection.set_interested(true), Some(Message::Interested));
+        assert_eq!(connection.set_interested(true), None);
+        assert_eq!(connection.unchoke(), Some(Message::Unchoke));
+        assert_eq!(connection.unchoke(), None);
+        assert_eq!(connection.choke(), Some(Message::Choke));
+        assert_eq!(
+            connection.set_interested(false),
+            Some(Message::NotInterested)
+        );
+    }
+
+    #[test]
+    fn requests_need_interest_and_an_unchoke() {
+        let mut connection = PeerConnection::new(4);
+        connection.receive(&Message::Have { piece: 1 }).unwrap();
+        assert!(connection.request(BLOCK).is_err());
+
+        connection.set_interested(true);
+        assert!(connection.request(BLOCK).is_err());
+
+        connection.receive(&Message::Unchoke).unwrap();
+        assert_eq!(connection.request(BLOCK).unwrap(), BLOCK.to_request());
+        assert!(connection.request(BLOCK).is_err());
+    }
+
+    #[test]
+    fn refuses_requests_for_pieces_the_peer_lacks() {
+        let mut connection = PeerConnection::new(4);
+        connection.set_interested(true);
+        connection.receive(&Message::Unchoke).unwrap();
+        assert!(connection.request(BLOCK).is_err());
+    }
+
+    #[test]
+    fn choke_drops_outstanding_requests() {
+        let mut connection = downloading();
+        connection.request(BLOCK).unwrap();
+        connection.receive(&Message::Choke).unwrap();
+        assert!(connection.requests().is_empty());
+        assert_eq!(connection.take_dropped(), vec![BLOCK]);
+        assert!(connection.take_dropped().is_empty());
+        assert!(connection.request(BLOCK).is_err());
+    }
+
+    #[test]
+    fn piece_answers_the_matching_request() {
+        let mut connection = downloading();
+        connection.request(BLOCK).unwrap();
+        connection
+            .receive(&Message::Piece {
+                piece: 1,
+                offset: 0,
+                data: vec![0; 16 * 1024],
+            })
+            .unwrap();
+        assert!(connection.requests().is_empty());
+
+        // A block that was cancelled meanwhile is tolerated.
+        connection
+            .receive(&Message::Piece {
+                piece: 2,
+                offset: 0,
+                data: vec![0; 16],
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn cancel_withdraws_our_request() {
+        let mut connection = downloading();
+        connection.request(BLOCK).unwrap();
+        assert_eq!(connection.cancel(BLOCK), Some(BLOCK.to_cancel()));
+        assert_eq!(connection.cancel(BLOCK), None);
+    }
+
+    #[test]
+    fn queues_peer_requests_only_while_unchoked() {
+        let mut connection = PeerConnection::new(4);
+        connection.receive(&BLOCK.to_request()).unwrap();
+        assert_eq!(connection.next_peer_request(), None);
+
+        connection.unchoke();
+        connection.receive(&BLOCK.to_request()).unwrap();
+        connection.receive(&BLOCK.to_request()).unwrap();
+        assert_eq!(connection.next_peer_request(), Some(BLOCK));
+        assert_eq!(connection.next_peer_request(), None);
+
+        connection.receive(&BLOCK.to_request()).unwrap();
+        connection.receive(&BLOCK.to_cancel()).unwrap();
+        assert_eq!(connection.next_peer_request(), None);
+
+        connection.receive(&BLOCK.to_request()).unwrap();
+        connection.choke();
+        assert_eq!(connection.next_peer_request(), None);
+    }
+
+    #[test]
+    fn rejects_oversized_requests() {
+        let mut connection = PeerConnection::new(4);
+        connection.unchoke();
+        let huge = BlockRequest {
+            length: MAX_REQUEST_LEN + 1,
+            ..BLOCK
+        };
+        assert!(connection.receive(&huge.to_request()).is_err());
+        let empty = BlockRequest { length: 0, ..BLOCK };
+        assert!(connection.receive(&empty.to_request()).is_err());
+    }
+
+    #[test]
+    fn bitfield_only_as_first_message() {
+        let mut connection = PeerConnection::new(4);
+        connection.receive(&Message::KeepAlive).unwrap();
+        connection.receive(&Message::Bitfield(vec![0x80])).unwrap();
+        assert!(connection.pieces().has_piece(0));
+
+        let mut connection = PeerConnection::new(4);
+        connection.receive(&Message::Unchoke).unwrap();
+        assert!(connection.receive(&Message::Bitfield(vec![0x80])).is_err());
+    }
+
+    #[test]
+    fn fast_messages_need_the_extension() {
+        let mut connection = PeerConnection::new(4);
+        assert!(connection.receive(&Message::HaveAll).is_err());
+
+        let mut connection = PeerConnection::new(4).with_fast(true);
+        connection.receive(&Message::HaveAll).unwrap();
+        assert!(connection.pieces().is_complete());
+        assert!(connection.receive(&Message::HaveNone).is_err());
+    }
+
+    #[test]
+    fn fast_peers_reject_instead_of_dropping() {
+        let mut connection = downloading().with_fast(true);
+        connection.request(BLOCK).unwrap();
+        connection.receive(&Message::Choke).unwrap();
+        assert_eq!(connection.requests(), [BLOCK]);
+        assert!(connection.take_dropped().is_empty());
+
+        connection.receive(&BLOCK.to_reject()).unwrap();
+        assert_eq!(connection.take_dropped(), vec![BLOCK]);
+        assert!(connection.receive(&BLOCK.to_reject()).is_err());
+
+        // Our side rejects what a choke refuses.
+        connection.unchoke();
+        connection.receive(&BLOCK.to_request()).unwrap();
+        connection.choke();
+        connection.receive(&BLOCK.to_request()).unwrap();
+        assert_eq!(
+            connection.take_rejects(),
+            [BLOCK.to_reject(), BLOCK.to_reject()]
+        );
+        assert_eq!(connection.reject(BLOCK), Some(BLOCK.to_reject()));
+    }
+
+    #[test]
+    fn allowed_fast_pieces_work_while_choked() {
+        let mut connection = PeerConnection::new(4).with_fast(true);
+        connection.receive(&Message::HaveAll).unwrap();
+        connection.set_interested(true);
+        assert!(connection.request(BLOCK).is_err());
+        connection
+            .receive(&Message::AllowedFast { piece: 1 })
+            .unwrap();
+        assert_eq!(connection.request(BLOCK).unwrap(), BLOCK.to_request());
+
+        assert_eq!(
+            connection.grant_allowed_fast([1, 1, 9]),
+            [Message::AllowedFast { piece: 1 }]
+        );
+        connection.receive(&BLOCK.to_request()).unwrap();
+        assert_eq!(connection.next_peer_request(), Some(BLOCK));
+        assert!(connection.take_rejects().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_piece_indices() {
+        let mut connection = PeerConnection::new(4);
+        assert!(connection.receive(&Message::Have { piece: 4 }).is_err());
+        let mut connection = PeerConnection::new(4);
+        assert!(connection.receive(&Message::Bitfield(vec![0xf8])).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DialTrackerConfig {
+    /// Failed dials in a row, with no success in between, before a subnet is suppressed.
+    pub failures_before_suppression: u32,
+    /// Suppression length the first time; it doubles on every repeat up to `max_suppression`.
+    pub initial_suppression: Duration,
+    pub max_suppression: Duration,
+    /// Subnets remembered at most; past it, those not suppressed are forgotten, least
+    /// recently dialed first.
+    pub max_subnets: usize,
+}
+
+impl Default for DialTrackerConfig {
+    fn default() -> Self {
+        Self {
+            failures_before_suppression: 8,
+            initial_suppression: Duration::from_secs(5 * 60),
+            max_suppression: Duration::from_secs(4 * 60 * 60),
+            max_subnets: 4096,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DialOutcome {
+    Connected,
+    Refused,
+    TimedOut,
+    Unreachable,
+}
+
+/// Address block dial outcomes are grouped by: a /24 for IPv4, a /64 for IPv6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Subnet {
+    V4([u8; 3]),
+    V6([u16; 4]),
+}
+
+impl Subnet {
+    pub fn of(ip: IpAddr) -> Self {
+        match ip.to_canonical() {
+            IpAddr::V4(ip) => {
+                let [a, b, c, _] = ip.octets();
+                Subnet::V4([a, b, c])
+            }
+            IpAddr::V6(ip) => {
+                let [a, b, c, d, ..] = ip.segments();
+                Subnet::V6([a, b, c, d])
+            }
+        }
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Subnet::V4([a, b, c]) => write!(f, "{}/24", Ipv4Addr::new(*a, *b, *c, 0)),
+            Subnet::V6([a, b, c, d]) => {
+                write!(f, "{}/64", Ipv6Addr::new(*a, *b, *c, *d, 0, 0, 0, 0))
+            }
+        }
+    }
+}
+
+/// Dial counters of one subnet.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SubnetStats {
+    pub connected: u64,
+    pub refused: u64,
+    pub timed_out: u64,
+    pub unreachable: u64,
+    /// Failures since the last successful dial.
+    pub consecutive_failures: u32,
+    /// How often the subnet has been suppressed; drives the backoff.
+    pub suppressions: u32,
+    pub suppressed_until: Option<Instant>,
+    pub last_dial: Option<Instant>,
+}
+
+impl SubnetStats {
+    fn active_suppression(&self, now: Instant) -> Option<Instant> {
+        self.suppressed_until.filter(|until| *until > now)
+    }
+
+    fn reset_backoff(&mut self) {
+        self.consecutive_failures = 0;
+        self.suppressions = 0;
+        self.suppressed_until = None;
+    }
+}
+
+/// Records outgoing dial results per subnet and backs off subnets that keep failing, e.g.
+/// dead ranges injected into tracker responses, so dial slots go to reachable peers.
+#[derive(Debug, Default, Clone)]
+pub struct DialTracker {
+    config: DialTrackerConfig,
+    subnets: HashMap<Subnet, SubnetStats>,
+}
+
+impl DialTracker {
+    pub fn new(config: DialTrackerConfig) -> Self {
+        Self {
+            config,
+            subnets: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DialTrackerConfig {
+        &self.config
+    }
+
+    /// Whether dialing `addr` is worth a slot right now.
+    pub fn should_dial(&self, addr: SocketAddr, now: Instant) -> bool {
+        self.suppression_expiry(Subnet::of(addr.ip()), now)
+            .is_none()
+    }
+
+    /// Records a dial result; returns the suppression end when this result triggered one.
+    pub fn record(
+        &mut self,
+        addr: SocketAddr,
+        outcome: DialOutcome,
+        now: Instant,
+    ) -> Option<Instant> {
+        let subnet = Subnet::of(addr.ip());
+        if !self.subnets.contains_key(&subnet) && self.subnets.len() >= self.config.max_subnets {
+            self.prune(now);
+        }
+        let config = &self.config;
+        let stats = self.subnets.entry(subnet).or_default();
+        stats.last_dial = Some(now);
+
+        match outcome {
+            DialOutcome::Connected => {
+                stats.connected += 1;
+                stats.reset_backoff();
+                return None;
+            }
+            DialOutcome::Refused => stats.refused += 1,
+            DialOutcome::TimedOut => stats.timed_out += 1,
+            DialOutcome::Unreachable => stats.unreachable += 1,
+        }
+
+        stats.consecutive_failures += 1;
+        if stats.consecutive_failures < config.failures_before_suppression
+            || stats.active_suppression(now).is_some()
+        {
+            return None;
+        }
+
+        let backoff = config
+            .initial_suppression
+            .saturating_mul(1 << stats.suppressions.min(16))
+            .min(config.max_suppression);
+        let until = now + backoff;
+        stats.suppressions += 1;
+        stats.consecutive_failures = 0;
+        stats.suppressed_until = Some(until);
+        Some(until)
+    }
+
+    pub fn suppression_expiry(&self, subnet: Subnet, now: Instant) -> Option<Instant> {
+        self.subnets
+            .get(&subnet)
+            .and_then(|stats| stats.active_suppression(now))
+    }
+
+    pub fn stats(&self, subnet: Subnet) -> Option<&SubnetStats> {
+        self.subnets.get(&subnet)
+    }
+
+    /// Currently suppressed subnets with their statistics, for inspection.
+    pub fn suppressed(&self, now: Instant) -> impl Iterator<Item = (Subnet, &SubnetStats)> + '_ {
+        self.subnets
+            .iter()
+            .filter(move |(_, stats)| stats.active_suppression(now).is_some())
+            .map(|(subnet, stats)| (*subnet, stats))
+    }
+
+    /// Lifts a suppression by hand and resets the subnet's backoff.
+    pub fn clear(&mut self, subnet: Subnet) {
+        if let Some(stats) = self.subnets.get_mut(&subnet) {
+            stats.reset_backoff();
+        }
+    }
+
+    /// Forgets subnets that are not suppressed, least recently dialed first, until fewer
+    /// than `max_subnets` remain, so peers spread over endless ranges cannot grow the map
+    /// without bound. Suppressed subnets stay until their suppression ends.
+    pub fn prune(&mut self, now: Instant) {
+        let mut idle = self
+            .subnets
+            .iter()
+            .filter(|(_, stats)| stats.active_suppression(now).is_none())
+            .map(|(subnet, stats)| (stats.last_dial, *subnet))
+            .collect::<Vec<_>>();
+        idle.sort_unstable();
+        let excess = (self.subnets.len() + 1).saturating_sub(self.config.max_subnets);
+        for (_, subnet) in idle.into_iter().take(excess) {
+            self.subnets.remove(&subnet);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.subnets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.subnets.is_empty()
+    }
+
+    pub fn clear_all(&mut self) {
+        self.subnets
+            .values_mut()
+            .for_each(SubnetStats::reset_backoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(a: u8, b: u8) -> SocketAddr {
+        ([10, a, b, 1], 6881).into()
+    }
+
+    #[test]
+    fn suppresses_failing_subnets_with_growing_backoff() {
+        let mut tracker = DialTracker::new(DialTrackerConfig {
+            failures_before_suppression: 2,
+            initial_suppression: Duration::from_secs(60),
+            ..DialTrackerConfig::default()
+        });
+        let start = Instant::now();
+        let peer = addr(0, 0);
+        let neighbour: SocketAddr = ([10, 0, 0, 200], 51413).into();
+
+        assert_eq!(tracker.record(peer, DialOutcome::TimedOut, start), None);
+        let until = tracker.record(peer, DialOutcome::Refused, start);
+        assert_eq!(until, Some(start + Duration::from_secs(60)));
+        assert!(!tracker.should_dial(neighbour, start));
+        assert!(tracker.should_dial(addr(0, 1), start));
+
+        let later = start + Duration::from_secs(60);
+        assert!(tracker.should_dial(peer, later));
+        tracker.record(peer, DialOutcome::Unreachable, later);
+        let until = tracker.record(peer, DialOutcome::Unreachable, later);
+        assert_eq!(until, Some(later + Duration::from_secs(120)));
+
+        tracker.clear(Subnet::of(peer.ip()));
+        assert!(tracker.should_dial(peer, later));
+        assert_eq!(tracker.stats(Subnet::of(peer.ip())).unwrap().unreachable, 2);
+    }
+
+    #[test]
+    fn a_connection_resets_the_failures() {
+        let mut tracker = DialTracker::new(DialTrackerConfig {
+            failures_before_suppression: 2,
+            ..DialTrackerConfig::default()
+        });
+        let now = Instant::now();
+        tracker.record(addr(0, 0), DialOutcome::TimedOut, now);
+        tracker.record(addr(0, 0), DialOutcome::Connected, now);
+        assert_eq!(tracker.record(addr(0, 0), DialOutcome::TimedOut, now), None);
+        assert!(tracker.should_dial(addr(0, 0), now));
+    }
+
+    #[test]
+    fn forgets_idle_subnets_past_the_limit() {
+        let mut tracker = DialTracker::new(DialTrackerConfig {
+            failures_before_suppression: 1,
+            max_subnets: 3,
+            ..DialTrackerConfig::default()
+        });
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+        tracker.record(addr(0, 0), DialOutcome::TimedOut, start);
+        tracker.record(addr(0, 1), DialOutcome::Connected, start + second);
+        tracker.record(addr(0, 2), DialOutcome::Connected, start + 2 * second);
+        tracker.record(addr(0, 3), DialOutcome::Connected, start + 3 * second);
+
+        // The suppressed subnet stays; the least recently dialed idle one goes.
+        assert_eq!(tracker.len(), 3);
+        assert!(!tracker.should_dial(addr(0, 0), start + 3 * second));
+        assert!(tracker.stats(Subnet::of(addr(0, 1).ip())).is_none());
+
+        for b in 4..100 {
+            tracker.record(addr(0, b), DialOutcome::Connected, start + 3 * second);
+        }
+        assert_eq!(tracker.len(), 3);
+    }
+}
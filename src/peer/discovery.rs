@@ -0,0 +1,82 @@
+use crate::metadata::Metadata;
+
+use super::extension::{ExtendedHandshake, UT_PEX};
+use super::pool::PeerSource;
+
+/// Peer sources a torrent may use besides its trackers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Discovery {
+    pub dht: bool,
+    /// Peer exchange (`ut_pex`) with connected peers.
+    pub pex: bool,
+    /// Local service discovery multicast (BEP 14).
+    pub local: bool,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self {
+            dht: true,
+            pex: true,
+            local: true,
+        }
+    }
+}
+
+impl Discovery {
+    /// Private torrents (BEP 27) may only get peers from their trackers, so the tracker can
+    /// account for every peer.
+    pub fn for_torrent(torrent: &Metadata) -> Self {
+        if torrent.is_private() {
+            Self {
+                dht: false,
+                pex: false,
+                local: false,
+            }
+        } else {
+            Self::default()
+        }
+    }
+
+    /// Whether peers learned from `source` may be used; trackers always may.
+    pub fn allows(&self, source: PeerSource) -> bool {
+        match source {
+            PeerSource::Tracker => true,
+            PeerSource::Dht => self.dht,
+            PeerSource::Pex => self.pex,
+            PeerSource::Local => self.local,
+        }
+    }
+
+    /// Stops advertising `ut_pex` when peer exchange is off, so peers do not send peer lists.
+    pub fn restrict(&self, handshake: &mut ExtendedHandshake) {
+        if !self.pex {
+            handshake.extensions.remove(UT_PEX);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::CandidatePool;
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn private_torrents_only_take_tracker_peers() {
+        let synthetic = SyntheticTorrent::builder("private").private(true).build();
+        let torrent = Metadata::from(&synthetic.torrent);
+        let discovery = Discovery::for_torrent(&torrent);
+        assert!(!discovery.dht && !discovery.pex && !discovery.local);
+
+        let mut pool = CandidatePool::for_torrent(&torrent, 8);
+        let peer = |port| std::net::SocketAddr::from(([10, 0, 0, 1], port));
+        assert!(!pool.add(peer(1), PeerSource::Dht));
+        assert!(!pool.add(peer(2), PeerSource::Pex));
+        assert!(!pool.add(peer(3), PeerSource::Local));
+        assert!(pool.add(peer(4), PeerSource::Tracker));
+
+        let public = Metadata::from(&SyntheticTorrent::builder("public").build().torrent);
+        assert_eq!(Discovery::for_torrent(&public), Discovery::default());
+    }
+}
@@ -0,0 +1,142 @@
+use std::hash::Hash;
+
+use super::connection::BlockRequest;
+use super::pipeline::InFlight;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndgameConfig {
+    /// Endgame starts once no more than this many blocks are missing.
+    pub threshold_blocks: usize,
+    /// Peers one block is requested from at most, so endgame does not flood the swarm.
+    pub max_requests_per_block: usize,
+}
+
+impl Default for EndgameConfig {
+    fn default() -> Self {
+        Self {
+            threshold_blocks: 32,
+            max_requests_per_block: 3,
+        }
+    }
+}
+
+/// Decides which blocks to request from whom, requesting the last few blocks of a download
+/// from several peers at once so one slow peer cannot hold up the finish.
+///
+/// Outside endgame every block is requested from one peer only. Once few enough blocks are
+/// missing, blocks already in flight are handed to further peers, least requested first,
+/// and whichever peer delivers a block first wins; the others are cancelled.
+#[derive(Debug, Clone)]
+pub struct Endgame<P> {
+    config: EndgameConfig,
+    in_flight: InFlight<P>,
+    active: bool,
+}
+
+impl<P: Clone + Eq + Hash> Endgame<P> {
+    pub fn new(config: EndgameConfig) -> Self {
+        Self {
+            config,
+            in_flight: InFlight::new(),
+            active: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Takes the number of blocks still missing, requested or not, and returns whether
+    /// endgame is on. It ends again if more blocks go missing, e.g. after a failed hash.
+    pub fn update(&mut self, missing_blocks: usize) -> bool {
+        self.active = missing_blocks > 0 && missing_blocks <= self.config.threshold_blocks;
+        self.active
+    }
+
+    /// The blocks of `missing` to request from `peer`, in the order to request them: blocks
+    /// nobody was asked for, then in endgame blocks others were asked for, least requested
+    /// first. Blocks the peer already has a request for are left out.
+    pub fn pick(
+        &self,
+        peer: &P,
+        missing: impl IntoIterator<Item = BlockRequest>,
+    ) -> Vec<BlockRequest> {
+        let mut blocks = missing
+            .into_iter()
+            .filter(|block| {
+                let peers = self.in_flight.peers(block);
+                if peers.contains(peer) {
+                    return false;
+                }
+                if self.active {
+                    peers.len() < self.config.max_requests_per_block
+                } else {
+                    peers.is_empty()
+                }
+            })
+            .collect::<Vec<_>>();
+        // Stable, so blocks asked from equally many peers keep their order.
+        blocks.sort_by_key(|block| self.in_flight.peers(block).len());
+        blocks
+    }
+
+    /// Records that `block` was requested from `peer`.
+    pub fn requested(&mut self, block: BlockRequest, peer: P) {
+        self.in_flight.add(block, peer);
+    }
+
+    /// Records that `from` delivered the block and returns the peers to send a cancel to.
+    pub fn delivered(&mut self, block: &BlockRequest, from: &P) -> Vec<P> {
+        self.in_flight.delivered(block, from)
+    }
+
+    /// Forgets a request the peer will not answer, e.g. one that timed out or was rejected.
+    pub fn dropped(&mut self, block: &BlockRequest, peer: &P) {
+        self.in_flight.remove(block, peer);
+    }
+
+    /// Forgets every request of a peer that disconnected.
+    pub fn remove_peer(&mut self, peer: &P) {
+        self.in_flight.remove_peer(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::message::BLOCK_SIZE;
+
+    fn block(index: u32) -> BlockRequest {
+        BlockRequest {
+            piece: 0,
+            offset: index * BLOCK_SIZE,
+            length: BLOCK_SIZE,
+        }
+    }
+
+    #[test]
+    fn duplicates_requests_only_in_endgame() {
+        let mut endgame = Endgame::new(EndgameConfig {
+            threshold_blocks: 2,
+            max_requests_per_block: 2,
+        });
+        let missing = [block(0), block(1), block(2)];
+        endgame.update(missing.len());
+        endgame.requested(block(0), "a");
+        assert_eq!(endgame.pick(&"b", missing), [block(1), block(2)]);
+
+        endgame.requested(block(1), "a");
+        endgame.requested(block(1), "b");
+        endgame.requested(block(2), "a");
+        assert!(endgame.update(2));
+        let missing = [block(1), block(2)];
+        assert_eq!(endgame.pick(&"b", missing), [block(2)]);
+        assert_eq!(endgame.pick(&"c", missing), [block(2)]);
+
+        endgame.requested(block(2), "c");
+        assert_eq!(endgame.delivered(&block(2), &"c"), ["a"]);
+        assert_eq!(endgame.pick(&"c", [block(1)]), []);
+        endgame.remove_peer(&"b");
+        assert_eq!(endgame.pick(&"c", [block(1)]), [block(1)]);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+use anyhow::{Context, Result};
+use bendy::decoding::{Decoder, Object};
+use bendy::encoding::Encoder;
+
+use super::external::{ip_from_bytes, ip_to_bytes};
+
+/// Message id used by the extension protocol (BEP 10) on the peer wire.
+pub const EXTENDED_MESSAGE_ID: u8 = 20;
+/// Extended message id reserved for the extension handshake itself.
+pub const EXTENDED_HANDSHAKE_ID: u8 = 0;
+
+pub const UT_METADATA: &str = "ut_metadata";
+pub const UT_PEX: &str = "ut_pex";
+
+/// Returns whether the reserved handshake bytes advertise extension protocol support.
+pub fn supports_extensions(reserved: &[u8; 8]) -> bool {
+    reserved[5] & 0x10 != 0
+}
+
+pub fn set_extensions_bit(reserved: &mut [u8; 8]) {
+    reserved[5] |= 0x10;
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExtendedHandshake {
+    /// Extension names mapped to the message ids the sender wants to receive them on.
+    pub extensions: BTreeMap<String, u8>,
+    pub metadata_size: Option<usize>,
+    pub listen_port: Option<u16>,
+    pub client: Option<String>,
+    /// The receiver's address as the sender sees it.
+    pub your_ip: Option<IpAddr>,
+}
+
+impl ExtendedHandshake {
+    pub fn extension_id(&self, name: &str) -> Option<u8> {
+        self.extensions.get(name).copied().filter(|id| *id != 0)
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new();
+        encoder.emit_dict(|mut dict| {
+            dict.emit_pair_with(b"m", |e| {
+                e.emit_dict(|mut m| {
+                    for (name, id) in &self.extensions {
+                        m.emit_pair(name.as_bytes(), id)?;
+                    }
+                    Ok(())
+                })
+            })?;
+            if let Some(size) = self.metadata_size {
+                dict.emit_pair(b"metadata_size", size)?;
+            }
+            if let Some(port) = self.listen_port {
+                dict.emit_pair(b"p", port)?;
+            }
+            if let Some(client) = &self.client {
+                dict.emit_pair(b"v", client)?;
+            }
+            if let Some(ip) = self.your_ip {
+                dict.emit_pair_with(b"yourip", |e| e.emit_bytes(&ip_to_bytes(ip)))?;
+            }
+            Ok(())
+        })?;
+        Ok(encoder.get_output()?)
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(payload);
+        let mut dict = decoder
+            .next_object()?
+            .context("Empty extension handshake")?
+            .try_into_dictionary()?;
+
+        let mut handshake = Self::default();
+        while let Some((key, value)) = dict.next_pair()? {
+            match (key, value) {
+                (b"m", Object::Dict(mut m)) => {
+                    while let Some((name, id)) = m.next_pair()? {
+                        if let Object::Integer(id) = id
+                            && let Ok(id) = id.parse()
+                        {
+                            let name = String::from_utf8_lossy(name).into_owned();
+                            handshake.extensions.insert(name, id);
+                        }
+                    }
+                }
+                (b"metadata_size", Object::Integer(size)) => {
+                    handshake.metadata_size = size.parse().ok();
+                }
+                (b"p", Object::Integer(port)) => handshake.listen_port = port.parse().ok(),
+                (b"v", Object::Bytes(client)) => {
+                    handshake.client = Some(String::from_utf8_lossy(client).into_owned());
+                }
+                (b"yourip", Object::Bytes(ip)) => handshake.your_ip = ip_from_bytes(ip),
+                _ => {}
+            }
+        }
+
+        Ok(handshake)
+    }
+}
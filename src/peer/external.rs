@@ -0,0 +1,125 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use super::address::Peer;
+
+/// A tracker's report counts as much as this many peers'; peers are easier to fake.
+const TRACKER_WEIGHT: u32 = 2;
+/// Weight of agreeing reports before a detected address is believed.
+const MIN_WEIGHT: u32 = 2;
+
+/// Who told us our address.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ExternalSource {
+    /// A tracker's `external ip` (BEP 24), keyed by its announce URL.
+    Tracker(String),
+    /// A peer's `yourip` in the extension handshake (BEP 10).
+    Peer(IpAddr),
+}
+
+impl ExternalSource {
+    fn weight(&self) -> u32 {
+        match self {
+            ExternalSource::Tracker(_) => TRACKER_WEIGHT,
+            ExternalSource::Peer(_) => 1,
+        }
+    }
+}
+
+/// Our address as others see it: set by the user, or agreed on by trackers and peers.
+#[derive(Debug, Default, Clone)]
+pub struct ExternalAddress {
+    configured: Option<IpAddr>,
+    /// Reported addresses with the sources that reported them; a source only keeps its
+    /// latest report.
+    reports: HashMap<ExternalSource, IpAddr>,
+}
+
+impl ExternalAddress {
+    pub fn new(configured: Option<IpAddr>) -> Self {
+        Self {
+            configured,
+            reports: HashMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, ip: IpAddr, source: ExternalSource) {
+        if is_public(ip) {
+            self.reports.insert(source, ip);
+        }
+    }
+
+    /// The configured address, otherwise the detected one with the most weight behind it.
+    pub fn get(&self) -> Option<IpAddr> {
+        self.configured.or_else(|| self.detected())
+    }
+
+    pub fn detected(&self) -> Option<IpAddr> {
+        let mut weights: HashMap<IpAddr, u32> = HashMap::new();
+        for (source, ip) in &self.reports {
+            *weights.entry(*ip).or_default() += source.weight();
+        }
+        weights
+            .into_iter()
+            .filter(|(_, weight)| *weight >= MIN_WEIGHT)
+            .max_by_key(|(ip, weight)| (*weight, *ip))
+            .map(|(ip, _)| ip)
+    }
+
+    /// Whether `addr` is this client listening on `port`, as trackers and the DHT
+    /// sometimes hand our own address back.
+    pub fn is_self(&self, addr: SocketAddr, port: u16) -> bool {
+        addr.port() == port
+            && (addr.ip().is_loopback()
+                || self.configured == Some(addr.ip())
+                || self.reported().contains(&addr.ip()))
+    }
+
+    /// Drops our own address from `peers`.
+    pub fn retain_others(&self, peers: &mut Vec<Peer>, port: u16) {
+        peers.retain(|peer| !self.is_self(peer.addr, port));
+    }
+
+    /// Every address anyone reported; a lone report is not enough to be announced, but
+    /// is enough to skip dialing it.
+    fn reported(&self) -> HashSet<IpAddr> {
+        self.reports.values().copied().collect()
+    }
+}
+
+/// Decodes a compact address as used by `external ip` and `yourip`: 4 or 16 bytes.
+pub fn ip_from_bytes(bytes: &[u8]) -> Option<IpAddr> {
+    match bytes.len() {
+        4 => Some(IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?))),
+        16 => Some(IpAddr::V6(Ipv6Addr::from(
+            <[u8; 16]>::try_from(bytes).ok()?,
+        ))),
+        _ => None,
+    }
+}
+
+pub fn ip_to_bytes(ip: IpAddr) -> Vec<u8> {
+    match ip {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    }
+}
+
+/// Private and link-local addresses only mean the reporter is on our network.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast())
+        }
+        IpAddr::V6(ip) => {
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_unique_local()
+                || ip.is_unicast_link_local())
+        }
+    }
+}
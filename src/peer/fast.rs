@@ -0,0 +1,63 @@
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+use sha1::{Digest, Sha1};
+
+/// Allowed-fast pieces granted to each peer; BEP 6 suggests 10.
+pub const ALLOWED_FAST_COUNT: usize = 10;
+
+/// Returns whether the reserved handshake bytes advertise the fast extension (BEP 6).
+pub fn supports_fast(reserved: &[u8; 8]) -> bool {
+    reserved[7] & 0x04 != 0
+}
+
+pub fn set_fast_bit(reserved: &mut [u8; 8]) {
+    reserved[7] |= 0x04;
+}
+
+/// The `count` pieces a peer at `ip` may download while choked, derived from its /24 and
+/// the info hash as BEP 6 describes, so every peer behind one NAT gets the same set.
+/// IPv6 peers get none, as the BEP only defines the set for IPv4.
+pub fn allowed_fast_set(
+    ip: IpAddr,
+    info_hash: &[u8; 20],
+    piece_count: usize,
+    count: usize,
+) -> BTreeSet<u32> {
+    let mut pieces = BTreeSet::new();
+    let IpAddr::V4(ip) = ip.to_canonical() else {
+        return pieces;
+    };
+    let count = count.min(piece_count);
+
+    let mut seed = (u32::from(ip) & 0xffff_ff00).to_be_bytes().to_vec();
+    seed.extend_from_slice(info_hash);
+    while pieces.len() < count {
+        let digest: [u8; 20] = Sha1::digest(&seed).into();
+        for chunk in digest.chunks_exact(4) {
+            if pieces.len() == count {
+                break;
+            }
+            let value = u32::from_be_bytes(chunk.try_into().expect("chunk is 4 bytes"));
+            pieces.insert((u64::from(value) % piece_count as u64) as u32);
+        }
+        seed = digest.to_vec();
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_bep_6_example() {
+        let ip = [80, 4, 4, 200].into();
+        let info_hash = [0xaa; 20];
+        assert_eq!(
+            allowed_fast_set(ip, &info_hash, 1313, 9),
+            BTreeSet::from([1059, 431, 808, 1217, 287, 376, 1188, 353, 508])
+        );
+        assert!(allowed_fast_set("::1".parse().unwrap(), &info_hash, 1313, 9).is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+use std::net::SocketAddr;
+
+use anyhow::{Context, Result, bail};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+
+use super::extension::supports_extensions;
+use super::fast::supports_fast;
+use super::id::PeerId;
+use super::profile::NetworkTimings;
+
+/// Protocol string that opens every handshake (BEP 3).
+pub const PROTOCOL: &[u8; 19] = b"BitTorrent protocol";
+pub const HANDSHAKE_LEN: usize = 1 + PROTOCOL.len() + 8 + 20 + 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Handshake {
+    /// Extension bits, e.g. [`super::extension::set_extensions_bit`].
+    pub reserved: [u8; 8],
+    pub info_hash: [u8; 20],
+    pub peer_id: PeerId,
+}
+
+impl Handshake {
+    pub fn new(info_hash: [u8; 20], peer_id: PeerId) -> Self {
+        Self {
+            reserved: [0; 8],
+            info_hash,
+            peer_id,
+        }
+    }
+
+    pub fn supports_extensions(&self) -> bool {
+        supports_extensions(&self.reserved)
+    }
+
+    pub fn supports_fast(&self) -> bool {
+        supports_fast(&self.reserved)
+    }
+
+    pub fn encode(&self) -> [u8; HANDSHAKE_LEN] {
+        let mut bytes = [0; HANDSHAKE_LEN];
+        bytes[0] = PROTOCOL.len() as u8;
+        bytes[1..20].copy_from_slice(PROTOCOL);
+        bytes[20..28].copy_from_slice(&self.reserved);
+        bytes[28..48].copy_from_slice(&self.info_hash);
+        bytes[48..68].copy_from_slice(&self.peer_id);
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8; HANDSHAKE_LEN]) -> Result<Self> {
+        if usize::from(bytes[0]) != PROTOCOL.len() || &bytes[1..20] != PROTOCOL {
+            bail!("Not a BitTorrent handshake");
+        }
+        Ok(Self {
+            reserved: bytes[20..28].try_into().expect("slice is 8 bytes"),
+            info_hash: bytes[28..48].try_into().expect("slice is 20 bytes"),
+            peer_id: bytes[48..68].try_into().expect("slice is 20 bytes"),
+        })
+    }
+}
+
+/// Connects to `addr` and exchanges handshakes, each step within its timeout of `timings`.
+/// Returns the stream, ready for peer messages, and the remote handshake.
+pub async fn connect(
+    addr: SocketAddr,
+    ours: &Handshake,
+    timings: &NetworkTimings,
+) -> Result<(TcpStream, Handshake)> {
+    let mut stream = timeout(timings.dial_timeout, TcpStream::connect(addr))
+        .await
+        .with_context(|| format!("Connecting to {addr} timed out"))?
+        .with_context(|| format!("Failed to connect to {addr}"))?;
+    let theirs = timeout(timings.handshake_timeout, exchange(&mut stream, ours))
+        .await
+        .with_context(|| format!("Handshake with {addr} timed out"))?
+        .with_context(|| format!("Handshake with {addr} failed"))?;
+    Ok((stream, theirs))
+}
+
+/// Reads the handshake of a peer that connected to us; it names the torrent the peer wants,
+/// so ours can only be sent once that is known.
+pub async fn receive<S>(stream: &mut S) -> Result<Handshake>
+where
+    S: AsyncRead + Unpin,
+{
+    let mut bytes = [0; HANDSHAKE_LEN];
+    stream
+        .read_exact(&mut bytes)
+        .await
+        .context("Peer closed the connection during the handshake")?;
+    Handshake::decode(&bytes)
+}
+
+/// Sends our handshake and reads the peer's, which must be for the same torrent and not
+/// from ourselves.
+pub async fn exchange<S>(stream: &mut S, ours: &Handshake) -> Result<Handshake>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(&ours.encode()).await?;
+    stream.flush().await?;
+
+    let theirs = receive(stream).await?;
+    if theirs.info_hash != ours.info_hash {
+        bail!("Peer answered for a different torrent");
+    }
+    if theirs.peer_id == ours.peer_id {
+        bail!("Connected to ourselves");
+    }
+    Ok(theirs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::extension::set_extensions_bit;
+    use crate::peer::fast::set_fast_bit;
+
+    fn handshake(peer_id: u8) -> Handshake {
+        let mut handshake = Handshake::new([1; 20], [peer_id; 20]);
+        set_extensions_bit(&mut handshake.reserved);
+        set_fast_bit(&mut handshake.reserved);
+        handshake
+    }
+
+    #[test]
+    fn round_trips_with_its_reserved_bits() {
+        let ours = handshake(2);
+        let encoded = ours.encode();
+        assert_eq!(encoded[0], 19);
+        assert_eq!(&encoded[1..20], PROTOCOL);
+
+        let decoded = Handshake::decode(&encoded).unwrap();
+        assert_eq!(decoded, ours);
+        assert!(decoded.supports_extensions() && decoded.supports_fast());
+    }
+
+    #[test]
+    fn rejects_a_bad_pstrlen_or_protocol() {
+        let mut encoded = handshake(2).encode();
+        encoded[0] = 18;
+        assert!(Handshake::decode(&encoded).is_err());
+
+        let mut encoded = handshake(2).encode();
+        encoded[1] = b'b';
+        assert!(Handshake::decode(&encoded).is_err());
+    }
+
+    #[tokio::test]
+    async fn exchange_refuses_other_torrents_and_ourselves() {
+        let exchange_with = |theirs: Handshake| async move {
+            let (mut local, mut remote) = tokio::io::duplex(HANDSHAKE_LEN * 2);
+            remote.write_all(&theirs.encode()).await.unwrap();
+            exchange(&mut local, &handshake(2)).await
+        };
+        assert_eq!(exchange_with(handshake(3)).await.unwrap(), handshake(3));
+        assert!(exchange_with(handshake(2)).await.is_err());
+        let mut other = handshake(3);
+        other.info_hash = [9; 20];
+        assert!(exchange_with(other).await.is_err());
+
+        let (mut local, remote) = tokio::io::duplex(HANDSHAKE_LEN);
+        drop(remote);
+        assert!(receive(&mut local).await.is_err());
+    }
+}
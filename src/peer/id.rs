@@ -0,0 +1,20 @@
+use anyhow::{Context, Result};
+
+pub type PeerId = [u8; 20];
+
+/// Azureus-style client prefix: `-`, two letters, four version digits, `-`.
+const PREFIX: &[u8; 8] = b"-TT0100-";
+const ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// Peer id for this session; the suffix only needs to be unlikely to collide, not secret.
+pub fn generate_peer_id() -> Result<PeerId> {
+    let mut seed = [0; 12];
+    getrandom::fill(&mut seed).context("No randomness for a peer id")?;
+
+    let mut id = [0; 20];
+    id[..8].copy_from_slice(PREFIX);
+    for (byte, random) in id[8..].iter_mut().zip(seed.iter()) {
+        *byte = ALPHABET[usize::from(*random) % ALPHABET.len()];
+    }
+    Ok(id)
+}
@@ -0,0 +1,133 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+
+use super::message::{MAX_MESSAGE_LEN, Message, read_message, write_message};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeepAliveConfig {
+    /// A keep-alive goes out after this long without sending anything else.
+    pub interval: Duration,
+    /// Peers that send nothing, not even keep-alives, for this long are dropped. Should be
+    /// well over the two minutes other clients wait between keep-alives.
+    pub idle_timeout: Duration,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(2 * 60),
+            idle_timeout: Duration::from_secs(4 * 60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleAction {
+    SendKeepAlive,
+    /// The peer went silent past the idle timeout.
+    Disconnect,
+}
+
+/// When a connection last sent and received anything, and so when it next needs a
+/// keep-alive or is given up on.
+#[derive(Debug, Clone)]
+pub struct IdleTimers {
+    config: KeepAliveConfig,
+    last_sent: Instant,
+    last_received: Instant,
+}
+
+impl IdleTimers {
+    /// Timers for a connection that was just established.
+    pub fn new(config: KeepAliveConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_sent: now,
+            last_received: now,
+        }
+    }
+
+    pub fn sent(&mut self, now: Instant) {
+        self.last_sent = now;
+    }
+
+    pub fn received(&mut self, now: Instant) {
+        self.last_received = now;
+    }
+
+    /// The next time [`Self::poll`] has something to do.
+    pub fn deadline(&self) -> Instant {
+        (self.last_sent + self.config.interval).min(self.last_received + self.config.idle_timeout)
+    }
+
+    /// What is due at `now`; a silent peer wins over our own keep-alive.
+    pub fn poll(&self, now: Instant) -> Option<IdleAction> {
+        if now.duration_since(self.last_received) >= self.config.idle_timeout {
+            Some(IdleAction::Disconnect)
+        } else if now.duration_since(self.last_sent) >= self.config.interval {
+            Some(IdleAction::SendKeepAlive)
+        } else {
+            None
+        }
+    }
+}
+
+/// Moves messages between a connected peer and the rest of the client until either side
+/// closes, keeping the connection alive and dropping it once the peer goes silent.
+///
+/// Messages from `outgoing` are written to the peer and everything the peer sends goes to
+/// `incoming`. Closing `outgoing`, or dropping the receiver of `incoming`, ends the task.
+pub async fn run_connection<S>(
+    stream: S,
+    config: KeepAliveConfig,
+    mut outgoing: mpsc::Receiver<Message>,
+    incoming: mpsc::Sender<Message>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite,
+{
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    // Reading lives in its own future so a timer firing never drops a half-read message.
+    let (received_tx, mut received) = mpsc::channel(16);
+    let read_loop = async move {
+        loop {
+            let message = read_message(&mut reader, MAX_MESSAGE_LEN).await?;
+            if received_tx.send(message).await.is_err() {
+                return Ok::<(), anyhow::Error>(());
+            }
+        }
+    };
+    tokio::pin!(read_loop);
+
+    let mut timers = IdleTimers::new(config, Instant::now());
+    loop {
+        let deadline = tokio::time::Instant::from_std(timers.deadline());
+        tokio::select! {
+            result = &mut read_loop => return result,
+            Some(message) = received.recv() => {
+                timers.received(Instant::now());
+                if incoming.send(message).await.is_err() {
+                    return Ok(());
+                }
+            }
+            message = outgoing.recv() => {
+                let Some(message) = message else {
+                    return Ok(());
+                };
+                write_message(&mut writer, &message).await?;
+                timers.sent(Instant::now());
+            }
+            () = tokio::time::sleep_until(deadline) => match timers.poll(Instant::now()) {
+                Some(IdleAction::SendKeepAlive) => {
+                    write_message(&mut writer, &Message::KeepAlive).await?;
+                    timers.sent(Instant::now());
+                }
+                Some(IdleAction::Disconnect) => bail!("Peer was silent for too long"),
+                None => {}
+            },
+        }
+    }
+}
@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::time::timeout;
+
+use super::abuse::{AbuseGuard, Admission, Offense};
+use super::handshake::{Handshake, receive};
+use super::profile::NetworkTimings;
+
+/// Wait after a failed accept, e.g. while out of file descriptors, before the next one.
+const ACCEPT_BACKOFF: Duration = Duration::from_secs(1);
+/// How often the abuse guard forgets addresses that behaved.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ListenConfig {
+    pub enabled: bool,
+    /// TCP port peers connect to; the one announced to trackers and the DHT.
+    pub port: u16,
+    /// Inbound connections still handshaking at once; further ones are closed right away.
+    pub max_handshakes: usize,
+    /// Seconds an inbound peer gets to send its handshake; unset follows the network
+    /// profile.
+    pub handshake_timeout_secs: Option<u64>,
+}
+
+impl Default for ListenConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            port: 6881,
+            max_handshakes: 32,
+            handshake_timeout_secs: None,
+        }
+    }
+}
+
+/// A peer that connected to us and finished the handshake, ready for peer messages.
+#[derive(Debug)]
+pub struct InboundPeer {
+    pub stream: TcpStream,
+    pub addr: SocketAddr,
+    pub handshake: Handshake,
+}
+
+/// Where inbound peers of one torrent go.
+#[derive(Debug, Clone)]
+pub struct InboundTarget {
+    /// Our handshake for the torrent, sent back to peers asking for it.
+    pub handshake: Handshake,
+    /// The torrent's peer manager; a full channel means it takes no more peers.
+    pub peers: mpsc::Sender<InboundPeer>,
+}
+
+/// The torrents of the session that accept inbound peers, by info hash. Clones share the
+/// same set, so the session registers torrents while the listener runs.
+#[derive(Debug, Clone, Default)]
+pub struct InboundTorrents {
+    targets: Arc<Mutex<HashMap<[u8; 20], InboundTarget>>>,
+}
+
+impl InboundTorrents {
+    pub fn register(&self, target: InboundTarget) {
+        self.lock().insert(target.handshake.info_hash, target);
+    }
+
+    /// Stops accepting peers for a torrent, e.g. once it is paused or removed.
+    pub fn unregister(&self, info_hash: &[u8; 20]) {
+        self.lock().remove(info_hash);
+    }
+
+    pub fn get(&self, info_hash: &[u8; 20]) -> Option<InboundTarget> {
+        self.lock().get(info_hash).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<[u8; 20], InboundTarget>> {
+        // The map stays consistent even if a holder panicked.
+        self.targets
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Accepts inbound peers and hands each to the peer manager of the torrent it asks for.
+///
+/// Peers for unknown torrents, peers the [`AbuseGuard`] turns away, and peers arriving while
+/// too many handshakes are under way or their torrent takes no more peers are dropped.
+pub struct Listener {
+    listener: TcpListener,
+    torrents: InboundTorrents,
+    guard: Arc<Mutex<AbuseGuard>>,
+    max_handshakes: usize,
+    handshake_timeout: Duration,
+    handshaking: Arc<AtomicUsize>,
+}
+
+impl Listener {
+    /// Listens on `config.port` over IPv6 and IPv4, or IPv4 only on hosts without IPv6.
+    pub async fn bind(
+        config: &ListenConfig,
+        network: &NetworkTimings,
+        torrents: InboundTorrents,
+        guard: AbuseGuard,
+    ) -> Result<Self> {
+        let listener = match bind_dual_stack(config.port) {
+            Ok(listener) => listener,
+            Err(_) => TcpListener::bind((Ipv4Addr::UNSPECIFIED, config.port))
+                .await
+                .with_context(|| format!("Failed to listen on port {}", config.port))?,
+        };
+        Ok(Self {
+            listener,
+            torrents,
+            guard: Arc::new(Mutex::new(guard)),
+            max_handshakes: config.max_handshakes,
+            handshake_timeout: config
+                .handshake_timeout_secs
+                .map_or(network.handshake_timeout, Duration::from_secs),
+            handshaking: Arc::new(AtomicUsize::new(0)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// The abuse guard screening arrivals, shared with the sessions of accepted peers so
+    /// they report what those peers do later.
+    pub fn guard(&self) -> Arc<Mutex<AbuseGuard>> {
+        Arc::clone(&self.guard)
+    }
+
+    /// Accepts peers, handshaking with each in its own task; runs until dropped.
+    pub async fn run(self) {
+        let mut prune = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            let accepted = tokio::select! {
+                accepted = self.listener.accept() => accepted,
+                _ = prune.tick() => {
+                    lock(&self.guard).prune(Instant::now());
+                    continue;
+                }
+            };
+            let (stream, addr) = match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    // Running out of file descriptors passes once connections close.
+                    eprintln!("Accepting a peer failed: {err}");
+                    tokio::time::sleep(ACCEPT_BACKOFF).await;
+                    continue;
+                }
+            };
+            let addr = SocketAddr::new(addr.ip().to_canonical(), addr.port());
+            let admission = lock(&self.guard).on_connect(addr.ip(), Instant::now());
+            if admission != Admission::Allowed
+                || self.handshaking.load(Ordering::Relaxed) >= self.max_handshakes
+            {
+                continue;
+            }
+
+            self.handshaking.fetch_add(1, Ordering::Relaxed);
+            let torrents = self.torrents.clone();
+            let guard = Arc::clone(&self.guard);
+            let handshaking = Arc::clone(&self.handshaking);
+            let limit = self.handshake_timeout;
+            tokio::spawn(async move {
+                let result = timeout(limit, accept(stream, addr, &torrents)).await;
+                handshaking.fetch_sub(1, Ordering::Relaxed);
+                if !matches!(result, Ok(Ok(()))) {
+                    lock(&guard).record_offense(
+                        addr.ip(),
+                        Offense::InvalidHandshake,
+                        Instant::now(),
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// Listens on every IPv6 and IPv4 address; IPv4 peers show up as mapped addresses.
+fn bind_dual_stack(port: u16) -> Result<TcpListener> {
+    let socket = Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_only_v6(false)?;
+    socket.set_nonblocking(true)?;
+    socket
+        .bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())
+        .with_context(|| format!("Failed to listen on port {port}"))?;
+    socket.listen(1024)?;
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// Reads the peer's handshake, answers it for a registered torrent, and passes the peer on.
+/// A torrent that takes no more peers is not an error of the peer's, and neither is a
+/// connection to ourselves, e.g. after a tracker handed us our own address.
+async fn accept(mut stream: TcpStream, addr: SocketAddr, torrents: &InboundTorrents) -> Result<()> {
+    let theirs = receive(&mut stream).await?;
+    let Some(target) = torrents.get(&theirs.info_hash) else {
+        bail!("Peer asked for a torrent we do not have");
+    };
+    if theirs.peer_id == target.handshake.peer_id {
+        return Ok(());
+    }
+    if target.peers.capacity() == 0 {
+        return Ok(());
+    }
+
+    stream.write_all(&target.handshake.encode()).await?;
+    stream.flush().await?;
+    let _ = target.peers.try_send(InboundPeer {
+        stream,
+        addr,
+        handshake: theirs,
+    });
+    Ok(())
+}
+
+fn lock(guard: &Mutex<AbuseGuard>) -> std::sync::MutexGuard<'_, AbuseGuard> {
+    guard
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::AbuseGuardConfig;
+    use crate::peer::handshake::exchange;
+
+    #[tokio::test]
+    async fn hands_peers_to_the_torrent_they_ask_for() {
+        let torrents = InboundTorrents::default();
+        let (peers, mut inbound) = mpsc::channel(1);
+        let ours = Handshake::new([1; 20], *b"-TT0100-listener0000");
+        torrents.register(InboundTarget {
+            handshake: ours,
+            peers,
+        });
+        let config = ListenConfig {
+            port: 0,
+            ..ListenConfig::default()
+        };
+        let listener = Listener::bind(
+            &config,
+            &NetworkTimings::default(),
+            torrents,
+            AbuseGuard::new(AbuseGuardConfig::default()),
+        )
+        .await
+        .unwrap();
+        let addr = SocketAddr::from((Ipv4Addr::LOCALHOST, listener.local_addr().unwrap().port()));
+        tokio::spawn(listener.run());
+
+        // Reaching ourselves is no offense, however often it happens.
+        for _ in 0..4 {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            assert!(exchange(&mut stream, &ours).await.is_err());
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let theirs = Handshake::new([1; 20], *b"-TT0100-remotepeer00");
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert_eq!(exchange(&mut stream, &theirs).await.unwrap(), ours);
+        assert_eq!(inbound.recv().await.unwrap().handshake, theirs);
+
+        let unknown = Handshake::new([2; 20], *b"-TT0100-remotepeer00");
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        assert!(exchange(&mut stream, &unknown).await.is_err());
+    }
+}
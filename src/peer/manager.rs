@@ -0,0 +1,469 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use super::dial::{DialOutcome, DialTracker};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConnectionManagerConfig {
+    /// Connections across all torrents, half-open ones included.
+    pub max_connections: usize,
+    pub max_peers_per_torrent: usize,
+    /// Outgoing connections still connecting or handshaking; many OSes and home routers
+    /// choke on large numbers of these.
+    pub max_half_open: usize,
+    /// How long a peer gets to show what it is worth before it may be replaced.
+    pub replace_after: Duration,
+    /// Peers slower than this, in bytes per second, make way for faster ones when no slot
+    /// is free; the download rate counts, or the upload rate for seeding torrents.
+    pub replace_below_rate: u64,
+}
+
+impl Default for ConnectionManagerConfig {
+    fn default() -> Self {
+        Self {
+            max_connections: 200,
+            max_peers_per_torrent: 50,
+            max_half_open: 20,
+            replace_after: Duration::from_secs(60),
+            replace_below_rate: 1024,
+        }
+    }
+}
+
+/// Why a connection was not allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Refusal {
+    /// A connection to the same address already exists or is being set up.
+    Duplicate,
+    HalfOpenLimit,
+    /// The peer's subnet failed too many dials in a row and is backed off.
+    Suppressed,
+    TorrentFull,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectDecision {
+    Connect,
+    /// Connect after closing this slower connection, which the manager already forgot.
+    Replace(SocketAddr),
+    Refuse(Refusal),
+}
+
+#[derive(Debug, Clone)]
+struct Connection {
+    torrent: [u8; 20],
+    /// `None` while the connection is half-open.
+    connected_at: Option<Instant>,
+    download_rate: u64,
+    upload_rate: u64,
+}
+
+/// Every peer connection of the session, keeping them within the global, per-torrent, and
+/// half-open limits. Peers are told apart by the address they listen on: dialed peers by
+/// the address we dialed, inbound ones by their source address until their extension
+/// handshake names their listen port ([`Self::listening_on`]). Once it has, a peer is never
+/// connected twice, whichever side dialed.
+///
+/// Dial candidates usually come from a [`super::CandidatePool`]; the manager decides which
+/// of them may connect.
+#[derive(Debug, Clone)]
+pub struct ConnectionManager {
+    config: ConnectionManagerConfig,
+    connections: HashMap<SocketAddr, Connection>,
+    /// Torrents we only upload for, whose peers are judged by upload rate.
+    seeding: HashSet<[u8; 20]>,
+    /// Outcomes of our dials, backing off subnets that keep failing.
+    dials: DialTracker,
+}
+
+impl ConnectionManager {
+    pub fn new(config: ConnectionManagerConfig) -> Self {
+        Self {
+            config,
+            connections: HashMap::new(),
+            seeding: HashSet::new(),
+            dials: DialTracker::default(),
+        }
+    }
+
+    /// Judges dials by `dials`, e.g. one set up with the network profile's retry timing.
+    pub fn with_dial_tracker(mut self, dials: DialTracker) -> Self {
+        self.dials = dials;
+        self
+    }
+
+    pub fn dial_tracker(&self) -> &DialTracker {
+        &self.dials
+    }
+
+    /// For lifting suppressions by hand.
+    pub fn dial_tracker_mut(&mut self) -> &mut DialTracker {
+        &mut self.dials
+    }
+
+    /// Asks to dial `addr` for `torrent`; on success the connection counts as half-open
+    /// until [`Self::connected`].
+    ///
+    /// `known_rate` is what the peer reached on an earlier connection, if it had one. When
+    /// no slot is free, only a peer known to be faster than the slowest connection
+    /// replaces it; an untried peer is refused.
+    pub fn dial(
+        &mut self,
+        torrent: [u8; 20],
+        addr: SocketAddr,
+        known_rate: Option<u64>,
+        now: Instant,
+    ) -> ConnectDecision {
+        if self.half_open() >= self.config.max_half_open {
+            return ConnectDecision::Refuse(Refusal::HalfOpenLimit);
+        }
+        if !self.dials.should_dial(addr, now) {
+            return ConnectDecision::Refuse(Refusal::Suppressed);
+        }
+        self.admit(torrent, addr, None, known_rate, now)
+    }
+
+    /// Asks to keep a peer that connected to us and finished the handshake; `known_rate` as
+    /// for [`Self::dial`].
+    pub fn accept(
+        &mut self,
+        torrent: [u8; 20],
+        addr: SocketAddr,
+        known_rate: Option<u64>,
+        now: Instant,
+    ) -> ConnectDecision {
+        self.admit(torrent, addr, Some(now), known_rate, now)
+    }
+
+    /// Re-keys an inbound connection from its source address `addr` to the listen `port`
+    /// from the peer's extension handshake, so dialing that address later is caught as a
+    /// duplicate. Returns `false`, forgetting this connection, when the peer is already
+    /// connected under its listen address; the caller closes this one.
+    pub fn listening_on(&mut self, addr: SocketAddr, port: u16) -> bool {
+        let listen = key(SocketAddr::new(addr.ip(), port));
+        let addr = key(addr);
+        if listen == addr {
+            return true;
+        }
+        let Some(connection) = self.connections.remove(&addr) else {
+            return true;
+        };
+        if self.connections.contains_key(&listen) {
+            return false;
+        }
+        self.connections.insert(listen, connection);
+        true
+    }
+
+    /// Switches `torrent` between downloading and seeding, which decides whether its peers
+    /// are judged by download or upload rate.
+    pub fn set_seeding(&mut self, torrent: [u8; 20], seeding: bool) {
+        if seeding {
+            self.seeding.insert(torrent);
+        } else {
+            self.seeding.remove(&torrent);
+        }
+    }
+
+    /// Marks a dialed connection as established once the handshake succeeded.
+    pub fn connected(&mut self, addr: SocketAddr, now: Instant) {
+        if let Some(connection) = self.connections.get_mut(&key(addr))
+            && connection.connected_at.is_none()
+        {
+            connection.connected_at = Some(now);
+            self.dials.record(addr, DialOutcome::Connected, now);
+        }
+    }
+
+    /// Forgets a dial that failed and counts it against the peer's subnet; returns the end
+    /// of the suppression this failure started, if any.
+    pub fn dial_failed(
+        &mut self,
+        addr: SocketAddr,
+        outcome: DialOutcome,
+        now: Instant,
+    ) -> Option<Instant> {
+        self.connections.remove(&key(addr));
+        self.dials.record(addr, outcome, now)
+    }
+
+    /// Records how fast a peer currently sends us data.
+    pub fn set_download_rate(&mut self, addr: SocketAddr, rate: u64) {
+        if let Some(connection) = self.connections.get_mut(&key(addr)) {
+            connection.download_rate = rate;
+        }
+    }
+
+    /// Records how fast we currently send a peer data.
+    pub fn set_upload_rate(&mut self, addr: SocketAddr, rate: u64) {
+        if let Some(connection) = self.connections.get_mut(&key(addr)) {
+            connection.upload_rate = rate;
+        }
+    }
+
+    /// Forgets a connection that closed; a failed dial goes to [`Self::dial_failed`].
+    pub fn remove(&mut self, addr: SocketAddr) {
+        self.connections.remove(&key(addr));
+    }
+
+    pub fn contains(&self, addr: SocketAddr) -> bool {
+        self.connections.contains_key(&key(addr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    pub fn half_open(&self) -> usize {
+        self.connections
+            .values()
+            .filter(|connection| connection.connected_at.is_none())
+            .count()
+    }
+
+    pub fn peers_of(&self, torrent: &[u8; 20]) -> usize {
+        self.connections
+            .values()
+            .filter(|connection| connection.torrent == *torrent)
+            .count()
+    }
+
+    fn admit(
+        &mut self,
+        torrent: [u8; 20],
+        addr: SocketAddr,
+        connected_at: Option<Instant>,
+        known_rate: Option<u64>,
+        now: Instant,
+    ) -> ConnectDecision {
+        let addr = key(addr);
+        if self.connections.contains_key(&addr) {
+            return ConnectDecision::Refuse(Refusal::Duplicate);
+        }
+
+        // A full torrent can only trade one of its own peers; a full session any peer.
+        let decision = if self.peers_of(&torrent) >= self.config.max_peers_per_torrent {
+            match self.slowest(Some(&torrent), known_rate, now) {
+                Some(slowest) => ConnectDecision::Replace(slowest),
+                None => return ConnectDecision::Refuse(Refusal::TorrentFull),
+            }
+        } else if self.connections.len() >= self.config.max_connections {
+            match self.slowest(None, known_rate, now) {
+                Some(slowest) => ConnectDecision::Replace(slowest),
+                None => return ConnectDecision::Refuse(Refusal::Full),
+            }
+        } else {
+            ConnectDecision::Connect
+        };
+
+        if let ConnectDecision::Replace(slowest) = decision {
+            self.connections.remove(&slowest);
+        }
+        self.connections.insert(
+            addr,
+            Connection {
+                torrent,
+                connected_at,
+                download_rate: 0,
+                upload_rate: 0,
+            },
+        );
+        decision
+    }
+
+    /// The slowest connection, of `torrent` if given, that had its chance, is still slow
+    /// enough to be worth swapping, and is slower than `known_rate`. Without a known rate
+    /// the newcomer might be slower still, so nothing is swapped.
+    fn slowest(
+        &self,
+        torrent: Option<&[u8; 20]>,
+        known_rate: Option<u64>,
+        now: Instant,
+    ) -> Option<SocketAddr> {
+        let known_rate = known_rate?;
+        self.connections
+            .iter()
+            .filter(|(_, connection)| torrent.is_none_or(|torrent| connection.torrent == *torrent))
+            .map(|(addr, connection)| (addr, connection, self.rate(connection)))
+            .filter(|(_, connection, rate)| {
+                connection.connected_at.is_some_and(|at| {
+                    now.duration_since(at) >= self.config.replace_after
+                        && *rate < self.config.replace_below_rate
+                        && *rate < known_rate
+                })
+            })
+            .min_by_key(|(addr, _, rate)| (*rate, **addr))
+            .map(|(addr, _, _)| *addr)
+    }
+
+    /// What a connection is judged by: the download rate, or the upload rate while seeding.
+    fn rate(&self, connection: &Connection) -> u64 {
+        if self.seeding.contains(&connection.torrent) {
+            connection.upload_rate
+        } else {
+            connection.download_rate
+        }
+    }
+}
+
+/// IPv4-mapped IPv6 addresses name the same peer as the plain IPv4 one.
+fn key(addr: SocketAddr) -> SocketAddr {
+    SocketAddr::new(addr.ip().to_canonical(), addr.port())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peer::DialTrackerConfig;
+
+    const TORRENT: [u8; 20] = [1; 20];
+
+    fn addr(last: u8) -> SocketAddr {
+        ([10, 0, 0, last], 6881).into()
+    }
+
+    #[test]
+    fn enforces_limits_and_deduplicates() {
+        let mut manager = ConnectionManager::new(ConnectionManagerConfig {
+            max_connections: 3,
+            max_peers_per_torrent: 2,
+            max_half_open: 1,
+            ..ConnectionManagerConfig::default()
+        });
+        let now = Instant::now();
+        assert_eq!(
+            manager.dial(TORRENT, addr(1), None, now),
+            ConnectDecision::Connect
+        );
+        assert_eq!(
+            manager.dial(TORRENT, addr(2), None, now),
+            ConnectDecision::Refuse(Refusal::HalfOpenLimit)
+        );
+        manager.connected(addr(1), now);
+        let mapped = "[::ffff:10.0.0.1]:6881".parse().unwrap();
+        assert_eq!(
+            manager.accept(TORRENT, mapped, None, now),
+            ConnectDecision::Refuse(Refusal::Duplicate)
+        );
+
+        assert_eq!(
+            manager.accept(TORRENT, addr(2), None, now),
+            ConnectDecision::Connect
+        );
+        assert_eq!(
+            manager.accept(TORRENT, addr(3), None, now),
+            ConnectDecision::Refuse(Refusal::TorrentFull)
+        );
+        assert_eq!(
+            manager.accept([2; 20], addr(3), None, now),
+            ConnectDecision::Connect
+        );
+        assert_eq!(
+            manager.accept([3; 20], addr(4), None, now),
+            ConnectDecision::Refuse(Refusal::Full)
+        );
+    }
+
+    #[test]
+    fn replaces_the_slowest_peer_once_it_had_its_chance() {
+        let mut manager = ConnectionManager::new(ConnectionManagerConfig {
+            max_peers_per_torrent: 2,
+            ..ConnectionManagerConfig::default()
+        });
+        let start = Instant::now();
+        manager.accept(TORRENT, addr(1), None, start);
+        manager.accept(TORRENT, addr(2), None, start);
+        manager.set_download_rate(addr(1), 100);
+        manager.set_download_rate(addr(2), 50_000);
+        assert_eq!(
+            manager.accept(TORRENT, addr(3), Some(500), start),
+            ConnectDecision::Refuse(Refusal::TorrentFull)
+        );
+
+        // An untried peer, or one no faster, might be worse than what we have.
+        let later = start + Duration::from_secs(60);
+        assert_eq!(
+            manager.accept(TORRENT, addr(3), None, later),
+            ConnectDecision::Refuse(Refusal::TorrentFull)
+        );
+        assert_eq!(
+            manager.accept(TORRENT, addr(3), Some(100), later),
+            ConnectDecision::Refuse(Refusal::TorrentFull)
+        );
+        assert_eq!(
+            manager.accept(TORRENT, addr(3), Some(500), later),
+            ConnectDecision::Replace(addr(1))
+        );
+        assert!(!manager.contains(addr(1)) && manager.contains(addr(3)));
+        assert_eq!(manager.peers_of(&TORRENT), 2);
+    }
+
+    #[test]
+    fn seeding_torrents_judge_peers_by_upload_rate() {
+        let mut manager = ConnectionManager::new(ConnectionManagerConfig {
+            max_peers_per_torrent: 2,
+            ..ConnectionManagerConfig::default()
+        });
+        let start = Instant::now();
+        manager.accept(TORRENT, addr(1), None, start);
+        manager.accept(TORRENT, addr(2), None, start);
+        manager.set_seeding(TORRENT, true);
+        manager.set_upload_rate(addr(1), 50_000);
+        manager.set_upload_rate(addr(2), 100);
+
+        let later = start + Duration::from_secs(60);
+        assert_eq!(
+            manager.accept(TORRENT, addr(3), Some(500), later),
+            ConnectDecision::Replace(addr(2))
+        );
+    }
+
+    #[test]
+    fn inbound_peers_are_known_by_their_listen_port() {
+        let mut manager = ConnectionManager::new(ConnectionManagerConfig::default());
+        let now = Instant::now();
+        let inbound = SocketAddr::from(([10, 0, 0, 1], 50_000));
+        manager.accept(TORRENT, inbound, None, now);
+        assert!(manager.listening_on(inbound, 6881));
+        assert!(manager.contains(addr(1)) && !manager.contains(inbound));
+        assert_eq!(
+            manager.dial(TORRENT, addr(1), None, now),
+            ConnectDecision::Refuse(Refusal::Duplicate)
+        );
+
+        // The same peer connecting again from another port is told to go.
+        let again = SocketAddr::from(([10, 0, 0, 1], 50_001));
+        manager.accept(TORRENT, again, None, now);
+        assert!(!manager.listening_on(again, 6881));
+        assert_eq!(manager.len(), 1);
+    }
+
+    #[test]
+    fn failing_subnets_are_not_dialed() {
+        let mut manager = ConnectionManager::new(ConnectionManagerConfig::default())
+            .with_dial_tracker(DialTracker::new(DialTrackerConfig {
+                failures_before_suppression: 2,
+                ..Default::default()
+            }));
+        let now = Instant::now();
+        for last in 1..=2 {
+            manager.dial(TORRENT, addr(last), None, now);
+            manager.dial_failed(addr(last), DialOutcome::TimedOut, now);
+        }
+        assert!(manager.is_empty());
+        assert_eq!(
+            manager.dial(TORRENT, addr(3), None, now),
+            ConnectDecision::Refuse(Refusal::Suppressed)
+        );
+        assert_eq!(
+            manager.accept(TORRENT, addr(3), None, now),
+            ConnectDecision::Connect
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use anyhow::{Context, Result, bail, ensure};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use super::extension::EXTENDED_MESSAGE_ID;
+
+/// Size of the blocks pieces are requested in; peers may refuse larger requests.
+pub const BLOCK_SIZE: u32 = 16 * 1024;
+/// Longest message accepted by default: room for a bitfield of 8 million pieces, far above
+/// a block. Longer length prefixes are garbage or an attempt to make us buffer them.
+pub const MAX_MESSAGE_LEN: usize = 1 << 20;
+
+const CHOKE: u8 = 0;
+const UNCHOKE: u8 = 1;
+const INTERESTED: u8 = 2;
+const NOT_INTERESTED: u8 = 3;
+const HAVE: u8 = 4;
+const BITFIELD: u8 = 5;
+const REQUEST: u8 = 6;
+const PIECE: u8 = 7;
+const CANCEL: u8 = 8;
+const PORT: u8 = 9;
+const SUGGEST_PIECE: u8 = 0x0d;
+const HAVE_ALL: u8 = 0x0e;
+const HAVE_NONE: u8 = 0x0f;
+const REJECT_REQUEST: u8 = 0x10;
+const ALLOWED_FAST: u8 = 0x11;
+
+/// A peer wire message (BEP 3), plus the fast extension (BEP 6) and the extended messages
+/// of BEP 10.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// Zero-length message that only keeps the connection open.
+    KeepAlive,
+    Choke,
+    Unchoke,
+    Interested,
+    NotInterested,
+    Have {
+        piece: u32,
+    },
+    /// Raw bitfield bytes; checking them against the piece count is up to the receiver.
+    Bitfield(Vec<u8>),
+    Request {
+        piece: u32,
+        offset: u32,
+        length: u32,
+    },
+    Piece {
+        piece: u32,
+        offset: u32,
+        data: Vec<u8>,
+    },
+    Cancel {
+        piece: u32,
+        offset: u32,
+        length: u32,
+    },
+    /// DHT port of the peer (BEP 5).
+    Port(u16),
+    /// A piece the peer would like us to download, e.g. one it has cached.
+    SuggestPiece {
+        piece: u32,
+    },
+    /// Stands in for a bitfield with every piece set.
+    HaveAll,
+    /// Stands in for an empty bitfield.
+    HaveNone,
+    /// Answers a request that will not be served.
+    RejectRequest {
+        piece: u32,
+        offset: u32,
+        length: u32,
+    },
+    /// A piece the peer serves us even while choking us.
+    AllowedFast {
+        piece: u32,
+    },
+    Extended {
+        id: u8,
+        payload: Vec<u8>,
+    },
+    /// A message id we do not speak, e.g. from an unsupported BEP; it can be skipped.
+    Unknown {
+        id: u8,
+        payload: Vec<u8>,
+    },
+}
+
+impl Message {
+    /// The message with its 4-byte length prefix.
+    pub fn encode(&self) -> Vec<u8> {
+        let (id, payload) = match self {
+            Message::KeepAlive => return 0u32.to_be_bytes().to_vec(),
+            Message::Choke => (CHOKE, Vec::new()),
+            Message::Unchoke => (UNCHOKE, Vec::new()),
+            Message::Interested => (INTERESTED, Vec::new()),
+            Message::NotInterested => (NOT_INTERESTED, Vec::new()),
+            Message::Have { piece } => (HAVE, piece.to_be_bytes().to_vec()),
+            Message::Bitfield(bits) => (BITFIELD, bits.clone()),
+            Message::Request {
+                piece,
+                offset,
+                length,
+            } => (REQUEST, triple(*piece, *offset, *length)),
+            Message::Piece {
+                piece,
+                offset,
+                data,
+            } => {
+                let mut payload = Vec::with_capacity(8 + data.len());
+                payload.extend_from_slice(&piece.to_be_bytes());
+                payload.extend_from_slice(&offset.to_be_bytes());
+                payload.extend_from_slice(data);
+                (PIECE, payload)
+            }
+            Message::Cancel {
+                piece,
+                offset,
+                length,
+            } => (CANCEL, triple(*piece, *offset, *length)),
+            Message::Port(port) => (PORT, port.to_be_bytes().to_vec()),
+            Message::SuggestPiece { piece } => (SUGGEST_PIECE, piece.to_be_bytes().to_vec()),
+            Message::HaveAll => (HAVE_ALL, Vec::new()),
+            Message::HaveNone => (HAVE_NONE, Vec::new()),
+            Message::RejectRequest {
+                piece,
+                offset,
+                length,
+            } => (REJECT_REQUEST, triple(*piece, *offset, *length)),
+            Message::AllowedFast { piece } => (ALLOWED_FAST, piece.to_be_bytes().to_vec()),
+            Message::Extended { id, payload } => {
+                let mut extended = Vec::with_capacity(1 + payload.len());
+                extended.push(*id);
+                extended.extend_from_slice(payload);
+                (EXTENDED_MESSAGE_ID, extended)
+            }
+            Message::Unknown { id, payload } => (*id, payload.clone()),
+        };
+
+        let length = u32::try_from(1 + payload.len()).expect("message fits a length prefix");
+        let mut bytes = Vec::with_capacity(4 + 1 + payload.len());
+        bytes.extend_from_slice(&length.to_be_bytes());
+        bytes.push(id);
+        bytes.extend_from_slice(&payload);
+        bytes
+    }
+
+    /// Decodes the body of one message, i.e. what follows the length prefix.
+    pub fn decode(body: &[u8]) -> Result<Self> {
+        let Some((&id, payload)) = body.split_first() else {
+            return Ok(Message::KeepAlive);
+        };
+        let expect = |len: usize| {
+            ensure!(
+                payload.len() == len,
+                "Message {id} has {} payload bytes instead of {len}",
+                payload.len()
+            );
+            Ok(())
+        };
+
+        Ok(match id {
+            CHOKE => {
+                expect(0)?;
+                Message::Choke
+            }
+            UNCHOKE => {
+                expect(0)?;
+                Message::Unchoke
+            }
+            INTERESTED => {
+                expect(0)?;
+                Message::Interested
+            }
+            NOT_INTERESTED => {
+                expect(0)?;
+                Message::NotInterested
+            }
+            HAVE | SUGGEST_PIECE | ALLOWED_FAST => {
+                expect(4)?;
+                let piece = u32_at(payload, 0);
+                match id {
+                    HAVE => Message::Have { piece },
+                    SUGGEST_PIECE => Message::SuggestPiece { piece },
+                    _ => Message::AllowedFast { piece },
+                }
+            }
+            BITFIELD => Message::Bitfield(payload.to_vec()),
+            REQUEST | CANCEL | REJECT_REQUEST => {
+                expect(12)?;
+                let (piece, offset, length) =
+                    (u32_at(payload, 0), u32_at(payload, 4), u32_at(payload, 8));
+                match id {
+                    REQUEST => Message::Request {
+                        piece,
+                        offset,
+                        length,
+                    },
+                    CANCEL => Message::Cancel {
+                        piece,
+                        offset,
+                        length,
+                    },
+                    _ => Message::RejectRequest {
+                        piece,
+                        offset,
+                        length,
+                    },
+                }
+            }
+            HAVE_ALL => {
+                expect(0)?;
+                Message::HaveAll
+            }
+            HAVE_NONE => {
+                expect(0)?;
+                Message::HaveNone
+            }
+            PIECE => {
+                ensure!(payload.len() >= 8, "Piece message without a block header");
+                Message::Piece {
+                    piece: u32_at(payload, 0),
+                    offset: u32_at(payload, 4),
+                    data: payload[8..].to_vec(),
+                }
+            }
+            PORT => {
+                expect(2)?;
+                Message::Port(u16::from_be_bytes([payload[0], payload[1]]))
+            }
+            EXTENDED_MESSAGE_ID => {
+                let (&id, payload) = payload.split_first().context("Empty extended message")?;
+                Message::Extended {
+                    id,
+                    payload: payload.to_vec(),
+                }
+            }
+            id => Message::Unknown {
+                id,
+                payload: payload.to_vec(),
+            },
+        })
+    }
+
+    /// Decodes the first message in `buffer`, returning it with the bytes it took, or `None`
+    /// while it is incomplete. Fails on a length prefix over `max_len`.
+    pub fn parse(buffer: &[u8], max_len: usize) -> Result<Option<(Self, usize)>> {
+        let Some(prefix) = buffer.get(..4) else {
+            return Ok(None);
+        };
+        let length = check_length(u32_at(prefix, 0), max_len)?;
+        let Some(body) = buffer.get(4..4 + length) else {
+            return Ok(None);
+        };
+        Ok(Some((Self::decode(body)?, 4 + length)))
+    }
+}
+
+/// Reads the next message, refusing length prefixes over `max_len` before reading the body.
+pub async fn read_message<R>(reader: &mut R, max_len: usize) -> Result<Message>
+where
+    R: AsyncRead + Unpin,
+{
+    let length = check_length(reader.read_u32().await?, max_len)?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await?;
+    Message::decode(&body)
+}
+
+pub async fn write_message<W>(writer: &mut W, message: &Message) -> Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    writer.write_all(&message.encode()).await?;
+    Ok(writer.flush().await?)
+}
+
+fn check_length(length: u32, max_len: usize) -> Result<usize> {
+    let length = usize::try_from(length)?;
+    if length > max_len {
+        bail!("Peer sent a {length}-byte message; at most {max_len} are accepted");
+    }
+    Ok(length)
+}
+
+fn triple(piece: u32, offset: u32, length: u32) -> Vec<u8> {
+    [piece, offset, length]
+        .iter()
+        .flat_map(|value| value.to_be_bytes())
+        .collect()
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(
+        bytes[offset..offset + 4]
+            .try_into()
+            .expect("slice is 4 bytes"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_message() -> Vec<Message> {
+        vec![
+            Message::KeepAlive,
+            Message::Choke,
+            Message::Unchoke,
+            Message::Interested,
+            Message::NotInterested,
+            Message::Have { piece: 7 },
+            Message::Bitfield(vec![0xa0, 0x80]),
+            Message::Request {
+                piece: 1,
+                offset: BLOCK_SIZE,
+                length: BLOCK_SIZE,
+            },
+            Message::Piece {
+                piece: 1,
+                offset: 0,
+                data: vec![1, 2, 3],
+            },
+            Message::Cancel {
+                piece: 1,
+                offset: 0,
+                length: BLOCK_SIZE,
+            },
+            Message::Port(6881),
+            Message::SuggestPiece { piece: 3 },
+            Message::HaveAll,
+            Message::HaveNone,
+            Message::RejectRequest {
+                piece: 2,
+                offset: 0,
+                length: BLOCK_SIZE,
+            },
+            Message::AllowedFast { piece: 4 },
+            Message::Extended {
+                id: 0,
+                payload: b"d1:md6:ut_pexi1eee".to_vec(),
+            },
+            Message::Unknown {
+                id: 0x20,
+                payload: vec![5],
+            },
+        ]
+    }
+
+    #[test]
+    fn every_message_round_trips() {
+        for message in every_message() {
+            let encoded = message.encode();
+            assert_eq!(
+                Message::parse(&encoded, MAX_MESSAGE_LEN).unwrap(),
+                Some((message.clone(), encoded.len()))
+            );
+        }
+    }
+
+    #[test]
+    fn waits_for_truncated_messages() {
+        let encoded = Message::Request {
+            piece: 1,
+            offset: 0,
+            length: BLOCK_SIZE,
+        }
+        .encode();
+        for end in 0..encoded.len() {
+            assert_eq!(
+                Message::parse(&encoded[..end], MAX_MESSAGE_LEN).unwrap(),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_payloads_and_oversized_prefixes() {
+        assert!(Message::decode(&[CHOKE, 0]).is_err());
+        assert!(Message::decode(&[HAVE, 0, 0, 1]).is_err());
+        assert!(Message::decode(&[REQUEST, 0, 0, 0, 1, 0, 0, 0, 0]).is_err());
+        assert!(Message::decode(&[PIECE, 0, 0, 0, 1]).is_err());
+        assert!(Message::decode(&[PORT, 0x1a]).is_err());
+        assert!(Message::decode(&[EXTENDED_MESSAGE_ID]).is_err());
+
+        let oversized = Message::Bitfield(vec![0; 16]).encode();
+        assert!(Message::parse(&oversized, 16).is_err());
+        assert!(Message::parse(&oversized, 17).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn refuses_long_prefixes_before_reading_the_body() {
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        writer.write_all(&u32::MAX.to_be_bytes()).await.unwrap();
+        assert!(read_message(&mut reader, MAX_MESSAGE_LEN).await.is_err());
+
+        let (mut writer, mut reader) = tokio::io::duplex(64);
+        write_message(&mut writer, &Message::Have { piece: 7 })
+            .await
+            .unwrap();
+        assert_eq!(
+            read_message(&mut reader, MAX_MESSAGE_LEN).await.unwrap(),
+            Message::Have { piece: 7 }
+        );
+    }
+}
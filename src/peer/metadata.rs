@@ -0,0 +1,229 @@
+use anyhow::{Context, Result, bail};
+use bendy::decoding::{Decoder, Object};
+use bendy::encoding::Encoder;
+use sha1::{Digest, Sha1};
+
+use super::extension::{ExtendedHandshake, UT_METADATA};
+
+/// Metadata is exchanged in 16 KiB pieces (BEP 9).
+pub const METADATA_PIECE_SIZE: usize = 16 * 1024;
+/// Refuse advertised metadata sizes above this to avoid allocating for hostile peers.
+pub const MAX_METADATA_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataMessage {
+    Request {
+        piece: usize,
+    },
+    Data {
+        piece: usize,
+        total_size: usize,
+        data: Vec<u8>,
+    },
+    Reject {
+        piece: usize,
+    },
+}
+
+impl MetadataMessage {
+    fn msg_type(&self) -> u8 {
+        match self {
+            MetadataMessage::Request { .. } => 0,
+            MetadataMessage::Data { .. } => 1,
+            MetadataMessage::Reject { .. } => 2,
+        }
+    }
+
+    pub fn piece(&self) -> usize {
+        match self {
+            MetadataMessage::Request { piece }
+            | MetadataMessage::Data { piece, .. }
+            | MetadataMessage::Reject { piece } => *piece,
+        }
+    }
+
+    /// Encodes the message payload that follows the extended message id.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let mut encoder = Encoder::new();
+        encoder.emit_dict(|mut dict| {
+            dict.emit_pair(b"msg_type", self.msg_type())?;
+            dict.emit_pair(b"piece", self.piece())?;
+            if let MetadataMessage::Data { total_size, .. } = self {
+                dict.emit_pair(b"total_size", total_size)?;
+            }
+            Ok(())
+        })?;
+
+        let mut payload = encoder.get_output()?;
+        if let MetadataMessage::Data { data, .. } = self {
+            payload.extend_from_slice(data);
+        }
+        Ok(payload)
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(payload);
+        let mut dict = decoder
+            .next_object()?
+            .context("Empty ut_metadata message")?
+            .try_into_dictionary()?;
+
+        let mut msg_type = None;
+        let mut piece = None;
+        let mut total_size = None;
+        while let Some((key, value)) = dict.next_pair()? {
+            let target = match key {
+                b"msg_type" => &mut msg_type,
+                b"piece" => &mut piece,
+                b"total_size" => &mut total_size,
+                _ => continue,
+            };
+            if let Object::Integer(value) = value {
+                *target = Some(value.parse::<usize>()?);
+            }
+        }
+        let header_len = dict.into_raw()?.len();
+
+        let piece = piece.context("ut_metadata message without piece index")?;
+        match msg_type {
+            Some(0) => Ok(MetadataMessage::Request { piece }),
+            Some(1) => Ok(MetadataMessage::Data {
+                piece,
+                total_size: total_size.context("ut_metadata data without total_size")?,
+                data: payload[header_len..].to_vec(),
+            }),
+            Some(2) => Ok(MetadataMessage::Reject { piece }),
+            other => bail!("Unknown ut_metadata message type {other:?}"),
+        }
+    }
+}
+
+/// Collects metadata pieces from peers and verifies the result against the info hash.
+#[derive(Debug, Clone)]
+pub struct MetadataAssembler {
+    info_hash: [u8; 20],
+    total_size: usize,
+    pieces: Vec<Option<Vec<u8>>>,
+}
+
+impl MetadataAssembler {
+    pub fn new(info_hash: [u8; 20], total_size: usize) -> Result<Self> {
+        if total_size == 0 || total_size > MAX_METADATA_SIZE {
+            bail!("Refusing metadata of {total_size} bytes");
+        }
+
+        Ok(Self {
+            info_hash,
+            total_size,
+            pieces: vec![None; total_size.div_ceil(METADATA_PIECE_SIZE)],
+        })
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.total_size
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.pieces.len()
+    }
+
+    pub fn piece_len(&self, piece: usize) -> usize {
+        let start = piece * METADATA_PIECE_SIZE;
+        METADATA_PIECE_SIZE.min(self.total_size.saturating_sub(start))
+    }
+
+    pub fn missing(&self) -> impl Iterator<Item = usize> + '_ {
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter_map(|(index, piece)| piece.is_none().then_some(index))
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pieces.iter().all(Option::is_some)
+    }
+
+    pub fn receive(&mut self, piece: usize, data: Vec<u8>) -> Result<()> {
+        if piece >= self.pieces.len() {
+            bail!("Metadata piece {piece} out of range");
+        }
+        if data.len() != self.piece_len(piece) {
+            bail!(
+                "Metadata piece {piece} has {} bytes, expected {}",
+                data.len(),
+                self.piece_len(piece)
+            );
+        }
+        self.pieces[piece] = Some(data);
+        Ok(())
+    }
+
+    /// Returns the raw bencoded info dictionary once every piece arrived and the hash matches.
+    ///
+    /// On a hash mismatch all pieces are dropped so the exchange can restart with other peers.
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        if !self.is_complete() {
+            bail!("Metadata is incomplete");
+        }
+
+        let info: Vec<u8> = self.pieces.iter().flatten().flatten().copied().collect();
+        let hash: [u8; 20] = Sha1::digest(&info).into();
+        if hash != self.info_hash {
+            self.pieces.iter_mut().for_each(|piece| *piece = None);
+            bail!("Metadata does not match the info hash");
+        }
+
+        Ok(info)
+    }
+}
+
+/// Answers ut_metadata requests from peers with our info dictionary, once we have it.
+#[derive(Debug, Default, Clone)]
+pub struct MetadataServer {
+    info: Option<Vec<u8>>,
+}
+
+impl MetadataServer {
+    /// `info` is the raw bencoded info dictionary; `None` while it is still being fetched.
+    pub fn new(info: Option<Vec<u8>>) -> Self {
+        Self { info }
+    }
+
+    /// Starts serving metadata that just finished downloading.
+    pub fn set_info(&mut self, info: Vec<u8>) {
+        self.info = Some(info);
+    }
+
+    pub fn metadata_size(&self) -> Option<usize> {
+        self.info.as_ref().map(Vec::len)
+    }
+
+    /// Announces ut_metadata on `id` in our extension handshake, with the size peers need
+    /// to start requesting. Without metadata the extension is still advertised, but
+    /// requests are rejected until it arrives.
+    pub fn advertise(&self, handshake: &mut ExtendedHandshake, id: u8) {
+        handshake.extensions.insert(UT_METADATA.to_string(), id);
+        handshake.metadata_size = self.metadata_size();
+    }
+
+    /// Reply to a message from a peer; only requests get one.
+    pub fn respond(&self, message: &MetadataMessage) -> Option<MetadataMessage> {
+        let MetadataMessage::Request { piece } = *message else {
+            return None;
+        };
+        let Some(info) = &self.info else {
+            return Some(MetadataMessage::Reject { piece });
+        };
+
+        let start = piece.saturating_mul(METADATA_PIECE_SIZE);
+        if start >= info.len() {
+            return Some(MetadataMessage::Reject { piece });
+        }
+        let end = (start + METADATA_PIECE_SIZE).min(info.len());
+        Some(MetadataMessage::Data {
+            piece,
+            total_size: info.len(),
+            data: info[start..end].to_vec(),
+        })
+    }
+}
@@ -0,0 +1,57 @@
+pub mod abuse;
+pub mod address;
+pub mod auth;
+pub mod bitfield;
+pub mod choker;
+pub mod connection;
+pub mod dial;
+pub mod discovery;
+pub mod endgame;
+pub mod extension;
+pub mod external;
+pub mod fast;
+pub mod handshake;
+pub mod id;
+pub mod keepalive;
+pub mod listener;
+pub mod manager;
+pub mod message;
+pub mod metadata;
+pub mod mse;
+pub mod pex;
+pub mod pipeline;
+pub mod pool;
+pub mod profile;
+pub mod seeding;
+pub mod serve;
+pub mod session;
+pub mod transfer;
+pub mod upload;
+
+pub use abuse::{AbuseGuard, AbuseGuardConfig, Admission, Offense};
+pub use address::Peer;
+pub use auth::{AuthMessage, AuthState, SwarmAuth, SwarmSecret};
+pub use bitfield::Bitfield;
+pub use choker::{ChokeCandidate, ChokeDecision, Choker, ChokerConfig};
+pub use connection::{BlockRequest, PeerConnection};
+pub use dial::{DialOutcome, DialTracker, DialTrackerConfig, Subnet, SubnetStats};
+pub use discovery::Discovery;
+pub use endgame::{Endgame, EndgameConfig};
+pub use extension::ExtendedHandshake;
+pub use external::{ExternalAddress, ExternalSource};
+pub use handshake::Handshake;
+pub use id::{PeerId, generate_peer_id};
+pub use keepalive::{IdleAction, IdleTimers, KeepAliveConfig, run_connection};
+pub use listener::{InboundPeer, InboundTarget, InboundTorrents, ListenConfig, Listener};
+pub use manager::{ConnectDecision, ConnectionManager, ConnectionManagerConfig, Refusal};
+pub use message::Message;
+pub use metadata::{MetadataAssembler, MetadataMessage, MetadataServer};
+pub use pex::{PexConfig, PexMessage, PexState};
+pub use pipeline::{InFlight, PipelineConfig, RequestPipeline};
+pub use pool::{CandidatePool, PeerSource};
+pub use profile::{NetworkConfig, NetworkProfile, NetworkTimings};
+pub use seeding::{DisconnectReason, FreeRiderPolicy, SeedingConfig, SeedingPeer};
+pub use serve::serve_request;
+pub use session::{SeedSource, seeding_handshake, serve_peer};
+pub use transfer::{PeerTransfer, RATE_WINDOW, RollingRate};
+pub use upload::{UploadOrder, UploadScheduler, UploadSchedulerConfig};
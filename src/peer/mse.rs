@@ -0,0 +1,186 @@
+//! Primitives of message stream encryption (MSE, also called protocol encryption): the
+//! Diffie-Hellman exchange over the 768-bit prime of the spec and the RC4 streams keyed from
+//! its shared secret. Peers cannot negotiate it yet; the self-test checks them meanwhile.
+
+use anyhow::{Context, Result, ensure};
+use sha1::{Digest, Sha1};
+
+pub const KEY_LEN: usize = 96;
+/// Private keys are 160 bits, as the spec recommends.
+pub const PRIVATE_KEY_LEN: usize = 20;
+/// Keystream bytes thrown away before use, hiding RC4's biased start.
+const DISCARD: usize = 1024;
+
+/// The prime P of the spec, big-endian; the generator is 2.
+const PRIME: [u8; KEY_LEN] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xc9, 0x0f, 0xda, 0xa2, 0x21, 0x68, 0xc2, 0x34,
+    0xc4, 0xc6, 0x62, 0x8b, 0x80, 0xdc, 0x1c, 0xd1, 0x29, 0x02, 0x4e, 0x08, 0x8a, 0x67, 0xcc, 0x74,
+    0x02, 0x0b, 0xbe, 0xa6, 0x3b, 0x13, 0x9b, 0x22, 0x51, 0x4a, 0x08, 0x79, 0x8e, 0x34, 0x04, 0xdd,
+    0xef, 0x95, 0x19, 0xb3, 0xcd, 0x3a, 0x43, 0x1b, 0x30, 0x2b, 0x0a, 0x6d, 0xf2, 0x5f, 0x14, 0x37,
+    0x4f, 0xe1, 0x35, 0x6d, 0x6d, 0x51, 0xc2, 0x45, 0xe4, 0x85, 0xb5, 0x76, 0x62, 0x5e, 0x7e, 0xc6,
+    0xf4, 0x4c, 0x42, 0xe9, 0xa6, 0x3a, 0x36, 0x21, 0x00, 0x00, 0x00, 0x00, 0x00, 0x09, 0x05, 0x63,
+];
+
+pub type PrivateKey = [u8; PRIVATE_KEY_LEN];
+pub type PublicKey = [u8; KEY_LEN];
+
+pub fn generate_private_key() -> Result<PrivateKey> {
+    let mut key = [0; PRIVATE_KEY_LEN];
+    getrandom::fill(&mut key).context("No randomness for an MSE key")?;
+    Ok(key)
+}
+
+/// 2 to the power of `private`, modulo P: the key sent to the peer.
+pub fn public_key(private: &PrivateKey) -> PublicKey {
+    let mut two = [0; KEY_LEN];
+    two[KEY_LEN - 1] = 2;
+    to_bytes(&pow_mod(&from_bytes(&two), private))
+}
+
+/// The secret both sides derive from their private key and the other's public one. Keys
+/// outside 2..P-1 would make the secret guessable and are refused.
+pub fn shared_secret(private: &PrivateKey, theirs: &PublicKey) -> Result<[u8; KEY_LEN]> {
+    let mut one = [0; KEY_LEN];
+    one[KEY_LEN - 1] = 1;
+    let prime = from_bytes(&PRIME);
+    let theirs = from_bytes(theirs);
+    let last = sub(&prime, &from_bytes(&one));
+    ensure!(
+        theirs > from_bytes(&one) && theirs < last,
+        "Peer sent an invalid MSE public key"
+    );
+    Ok(to_bytes(&pow_mod(&theirs, private)))
+}
+
+/// The RC4 streams of one side, `(outgoing, incoming)`: the side that connected encrypts
+/// with `keyA` and the other with `keyB`, both salted with the info hash (`SKEY`).
+pub fn stream_ciphers(secret: &[u8; KEY_LEN], info_hash: &[u8; 20], initiator: bool) -> (Rc4, Rc4) {
+    let key = |name: &[u8]| {
+        let mut hasher = Sha1::new();
+        hasher.update(name);
+        hasher.update(secret);
+        hasher.update(info_hash);
+        let mut cipher = Rc4::new(&hasher.finalize());
+        cipher.apply(&mut [0; DISCARD]);
+        cipher
+    };
+    let (a, b) = (key(b"keyA"), key(b"keyB"));
+    if initiator { (a, b) } else { (b, a) }
+}
+
+/// The RC4 stream cipher; encrypting and decrypting are the same operation.
+#[derive(Clone)]
+pub struct Rc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    pub fn new(key: &[u8]) -> Self {
+        let mut state = [0; 256];
+        for (index, byte) in state.iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        let mut j = 0u8;
+        for index in 0..256 {
+            j = j
+                .wrapping_add(state[index])
+                .wrapping_add(key[index % key.len()]);
+            state.swap(index, usize::from(j));
+        }
+        Self { state, i: 0, j: 0 }
+    }
+
+    /// XORs `data` with the next bytes of the keystream.
+    pub fn apply(&mut self, data: &mut [u8]) {
+        for byte in data {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.state[usize::from(self.i)]);
+            self.state.swap(usize::from(self.i), usize::from(self.j));
+            let index =
+                self.state[usize::from(self.i)].wrapping_add(self.state[usize::from(self.j)]);
+            *byte ^= self.state[usize::from(index)];
+        }
+    }
+}
+
+/// A 768-bit number, most significant limb first so the derived ordering is numeric.
+type Number = [u64; KEY_LEN / 8];
+
+fn from_bytes(bytes: &[u8; KEY_LEN]) -> Number {
+    let mut number = [0; KEY_LEN / 8];
+    for (limb, chunk) in number.iter_mut().zip(bytes.chunks_exact(8)) {
+        *limb = u64::from_be_bytes(chunk.try_into().expect("chunk is 8 bytes"));
+    }
+    number
+}
+
+fn to_bytes(number: &Number) -> [u8; KEY_LEN] {
+    let mut bytes = [0; KEY_LEN];
+    for (chunk, limb) in bytes.chunks_exact_mut(8).zip(number) {
+        chunk.copy_from_slice(&limb.to_be_bytes());
+    }
+    bytes
+}
+
+/// `a - b`, wrapping around 2^768.
+fn sub(a: &Number, b: &Number) -> Number {
+    let mut result = [0; KEY_LEN / 8];
+    let mut borrow = false;
+    for index in (0..result.len()).rev() {
+        let (value, first) = a[index].overflowing_sub(b[index]);
+        let (value, second) = value.overflowing_sub(u64::from(borrow));
+        result[index] = value;
+        borrow = first || second;
+    }
+    result
+}
+
+/// `a + b` modulo P, for `a` and `b` below P.
+fn add_mod(a: &Number, b: &Number, prime: &Number) -> Number {
+    let mut sum = [0; KEY_LEN / 8];
+    let mut carry = false;
+    for index in (0..sum.len()).rev() {
+        let (value, first) = a[index].overflowing_add(b[index]);
+        let (value, second) = value.overflowing_add(u64::from(carry));
+        sum[index] = value;
+        carry = first || second;
+    }
+    // The true sum is below 2P, so one subtraction reduces it; wrapping covers the carry.
+    if carry || sum >= *prime {
+        sub(&sum, prime)
+    } else {
+        sum
+    }
+}
+
+/// `a * b` modulo P by doubling and adding, for `a` and `b` below P.
+fn mul_mod(a: &Number, b: &Number, prime: &Number) -> Number {
+    let mut result = [0; KEY_LEN / 8];
+    for limb in a {
+        for bit in (0..64).rev() {
+            result = add_mod(&result, &result, prime);
+            if limb >> bit & 1 == 1 {
+                result = add_mod(&result, b, prime);
+            }
+        }
+    }
+    result
+}
+
+/// `base` to the power of the big-endian `exponent`, modulo P, for `base` below P.
+fn pow_mod(base: &Number, exponent: &[u8]) -> Number {
+    let prime = from_bytes(&PRIME);
+    let mut result = [0; KEY_LEN / 8];
+    result[result.len() - 1] = 1;
+    for byte in exponent {
+        for bit in (0..8).rev() {
+            result = mul_mod(&result, &result, &prime);
+            if byte >> bit & 1 == 1 {
+                result = mul_mod(&result, base, &prime);
+            }
+        }
+    }
+    result
+}
@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use bendy::decoding::{Decoder, Object};
+use bendy::encoding::Encoder;
+
+use super::address::{COMPACT_V4_LEN, COMPACT_V6_LEN, Peer};
+use super::extension::{ExtendedHandshake, UT_PEX};
+
+/// Per-peer flags sent alongside `added` (BEP 11).
+pub const PEX_ENCRYPTION: u8 = 0x01;
+pub const PEX_SEED: u8 = 0x02;
+pub const PEX_UTP: u8 = 0x04;
+pub const PEX_HOLEPUNCH: u8 = 0x08;
+pub const PEX_REACHABLE: u8 = 0x10;
+
+/// Peers that connected or disconnected since the previous message to the same peer.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PexMessage {
+    /// New peers with their `PEX_*` flags.
+    pub added: Vec<(SocketAddr, u8)>,
+    pub dropped: Vec<SocketAddr>,
+}
+
+impl PexMessage {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.dropped.is_empty()
+    }
+
+    /// Encodes the payload that follows the extended message id; IPv4 and IPv6 peers go in
+    /// separate lists.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let (added_v4, added_v6): (Vec<&(SocketAddr, u8)>, Vec<_>) = self
+            .added
+            .iter()
+            .partition(|(addr, _)| addr.ip().to_canonical().is_ipv4());
+        let (dropped_v4, dropped_v6): (Vec<&SocketAddr>, Vec<_>) = self
+            .dropped
+            .iter()
+            .partition(|addr| addr.ip().to_canonical().is_ipv4());
+        let compact = |addrs: Vec<&SocketAddr>| {
+            addrs
+                .into_iter()
+                .flat_map(|addr| to_compact(*addr))
+                .collect::<Vec<_>>()
+        };
+        let flags =
+            |added: &[&(SocketAddr, u8)]| added.iter().map(|(_, flags)| *flags).collect::<Vec<_>>();
+        let lists = [
+            (
+                &b"added"[..],
+                compact(added_v4.iter().map(|(addr, _)| addr).collect()),
+            ),
+            (b"added.f", flags(&added_v4)),
+            (
+                b"added6",
+                compact(added_v6.iter().map(|(addr, _)| addr).collect()),
+            ),
+            (b"added6.f", flags(&added_v6)),
+            (b"dropped", compact(dropped_v4)),
+            (b"dropped6", compact(dropped_v6)),
+        ];
+
+        let mut encoder = Encoder::new();
+        encoder.emit_dict(|mut dict| {
+            for (key, bytes) in &lists {
+                dict.emit_pair_with(key, |e| e.emit_bytes(bytes))?;
+            }
+            Ok(())
+        })?;
+        Ok(encoder.get_output()?)
+    }
+
+    /// Decodes a message, skipping lists whose length is not a whole number of entries.
+    /// Missing flags count as zero.
+    pub fn decode(payload: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::new(payload);
+        let mut dict = decoder
+            .next_object()?
+            .context("Empty ut_pex message")?
+            .try_into_dictionary()?;
+
+        let mut lists = BTreeMap::<&[u8], Vec<u8>>::new();
+        while let Some((key, value)) = dict.next_pair()? {
+            if let Object::Bytes(bytes) = value {
+                lists.insert(key, bytes.to_vec());
+            }
+        }
+        let list = |key: &[u8], entry_len| {
+            lists
+                .get(key)
+                .and_then(|bytes| Peer::compact_list(bytes, entry_len))
+                .unwrap_or_default()
+                .into_iter()
+                .map(|peer| peer.addr)
+        };
+        let flagged = |key: &[u8], flags_key: &[u8], entry_len| {
+            let flags = lists.get(flags_key).cloned().unwrap_or_default();
+            list(key, entry_len)
+                .enumerate()
+                .map(|(index, addr)| (addr, flags.get(index).copied().unwrap_or(0)))
+                .collect::<Vec<_>>()
+        };
+
+        let mut added = flagged(b"added", b"added.f", COMPACT_V4_LEN);
+        added.extend(flagged(b"added6", b"added6.f", COMPACT_V6_LEN));
+        let dropped = list(b"dropped", COMPACT_V4_LEN)
+            .chain(list(b"dropped6", COMPACT_V6_LEN))
+            .collect();
+        Ok(Self { added, dropped })
+    }
+}
+
+fn to_compact(addr: SocketAddr) -> Vec<u8> {
+    let mut bytes = match addr.ip().to_canonical() {
+        IpAddr::V4(ip) => ip.octets().to_vec(),
+        IpAddr::V6(ip) => ip.octets().to_vec(),
+    };
+    bytes.extend_from_slice(&addr.port().to_be_bytes());
+    bytes
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PexConfig {
+    /// How often a message goes out to each peer; BEP 11 asks for at most once a minute.
+    pub interval: Duration,
+    /// Entries per list in one message, sent or accepted; BEP 11 allows 50.
+    pub max_peers: usize,
+    /// Messages from a peer closer together than this are dropped as a flood. Kept below
+    /// `interval` to allow for timer jitter on the other side.
+    pub min_receive_interval: Duration,
+}
+
+impl Default for PexConfig {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            max_peers: 50,
+            min_receive_interval: Duration::from_secs(45),
+        }
+    }
+}
+
+/// Peer exchange with one connected peer: what we told it so far and when it last wrote.
+///
+/// Only create one for torrents whose [`super::Discovery`] allows PEX; private torrents
+/// must neither send nor use peer lists.
+#[derive(Debug, Clone)]
+pub struct PexState {
+    config: PexConfig,
+    /// Peers the remote side knows about from our previous messages.
+    advertised: BTreeMap<SocketAddr, u8>,
+    last_sent: Option<Instant>,
+    last_received: Option<Instant>,
+}
+
+impl PexState {
+    /// Starts counting from `now`, so the first message goes out one interval after the
+    /// connection is set up, as other clients expect.
+    pub fn new(config: PexConfig, now: Instant) -> Self {
+        Self {
+            config,
+            advertised: BTreeMap::new(),
+            last_sent: Some(now),
+            last_received: None,
+        }
+    }
+
+    /// Offers `ut_pex` on message id `id`; [`super::Discovery::restrict`] takes it out again
+    /// for private torrents.
+    pub fn advertise(handshake: &mut ExtendedHandshake, id: u8) {
+        handshake.extensions.insert(UT_PEX.to_string(), id);
+    }
+
+    /// The id the peer takes PEX messages on, from its extension handshake; `None` when it
+    /// does not support them.
+    pub fn remote_id(handshake: &ExtendedHandshake) -> Option<u8> {
+        handshake.extension_id(UT_PEX)
+    }
+
+    /// The message to send if one is due, listing how `connected` (peer to `PEX_*` flags)
+    /// changed since the previous message. The receiving peer itself should not be part of
+    /// `connected`. Changes beyond the per-message cap are sent next time.
+    pub fn outgoing(
+        &mut self,
+        connected: &BTreeMap<SocketAddr, u8>,
+        now: Instant,
+    ) -> Option<PexMessage> {
+        if self
+            .last_sent
+            .is_some_and(|at| now.duration_since(at) < self.config.interval)
+        {
+            return None;
+        }
+        self.last_sent = Some(now);
+
+        let added = connected
+            .iter()
+            .filter(|(addr, _)| !self.advertised.contains_key(addr))
+            .take(self.config.max_peers)
+            .map(|(addr, flags)| (*addr, *flags))
+            .collect::<Vec<_>>();
+        let dropped = self
+            .advertised
+            .keys()
+            .filter(|addr| !connected.contains_key(addr))
+            .take(self.config.max_peers)
+            .copied()
+            .collect::<Vec<_>>();
+        for addr in &dropped {
+            self.advertised.remove(addr);
+        }
+        self.advertised.extend(added.iter().copied());
+
+        let message = PexMessage { added, dropped };
+        (!message.is_empty()).then_some(message)
+    }
+
+    /// Takes a received message and returns the peers worth adding to the candidate pool.
+    /// `None` means the peer sends too often and the message was dropped; the caller may
+    /// count that as an offense. Entries past the per-message cap are ignored.
+    pub fn incoming(&mut self, message: PexMessage, now: Instant) -> Option<Vec<(SocketAddr, u8)>> {
+        if self
+            .last_received
+            .is_some_and(|at| now.duration_since(at) < self.config.min_receive_interval)
+        {
+            return None;
+        }
+        self.last_received = Some(now);
+        Some(
+            message
+                .added
+                .into_iter()
+                .take(self.config.max_peers)
+                .filter(|(addr, _)| addr.port() != 0 && !addr.ip().is_unspecified())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(last: u8) -> SocketAddr {
+        ([10, 0, 0, last], 6881).into()
+    }
+
+    #[test]
+    fn round_trips_both_address_families() {
+        let message = PexMessage {
+            added: vec![
+                (addr(1), PEX_SEED | PEX_ENCRYPTION),
+                ("[2001:db8::1]:51413".parse().unwrap(), PEX_UTP),
+            ],
+            dropped: vec![addr(2)],
+        };
+        assert_eq!(
+            PexMessage::decode(&message.encode().unwrap()).unwrap(),
+            message
+        );
+    }
+
+    #[test]
+    fn sends_changes_since_the_last_message() {
+        let start = Instant::now();
+        let mut pex = PexState::new(PexConfig::default(), start);
+        let mut connected = BTreeMap::from([(addr(1), 0), (addr(2), PEX_SEED)]);
+        assert_eq!(pex.outgoing(&connected, start), None);
+
+        let first = start + Duration::from_secs(60);
+        let message = pex.outgoing(&connected, first).unwrap();
+        assert_eq!(message.added, [(addr(1), 0), (addr(2), PEX_SEED)]);
+
+        connected.remove(&addr(1));
+        connected.insert(addr(3), 0);
+        let message = pex
+            .outgoing(&connected, first + Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(message.added, [(addr(3), 0)]);
+        assert_eq!(message.dropped, [addr(1)]);
+    }
+
+    #[test]
+    fn drops_messages_that_come_too_often() {
+        let start = Instant::now();
+        let mut pex = PexState::new(PexConfig::default(), start);
+        let message = PexMessage {
+            added: (1..=60).map(|last| (addr(last), 0)).collect(),
+            dropped: Vec::new(),
+        };
+        assert_eq!(pex.incoming(message.clone(), start).unwrap().len(), 50);
+        assert_eq!(
+            pex.incoming(message.clone(), start + Duration::from_secs(10)),
+            None
+        );
+        assert!(
+            pex.incoming(message, start + Duration::from_secs(60))
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use super::connection::{BlockRequest, PeerConnection};
+use super::message::{BLOCK_SIZE, Message};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// Requests kept outstanding on every unchoked connection, however slow the peer.
+    pub queue_depth: usize,
+    /// Upper bound for the depth a fast peer is raised to.
+    pub max_queue_depth: usize,
+    /// How much of the peer's measured rate to keep queued; the depth grows until this many
+    /// seconds of blocks are in flight, which hides the round trip on fast peers.
+    pub queue_time: Duration,
+    /// Requests unanswered for this long are withdrawn and handed to other peers.
+    pub request_timeout: Duration,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            queue_depth: 5,
+            max_queue_depth: 250,
+            queue_time: Duration::from_secs(3),
+            request_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Keeps a connection's request queue full so throughput is not bound by latency.
+///
+/// Tracks when each of our requests went out, estimates the peer's download rate from the
+/// blocks it answers, and sizes the queue to that rate.
+#[derive(Debug, Clone)]
+pub struct RequestPipeline {
+    config: PipelineConfig,
+    sent: HashMap<BlockRequest, Instant>,
+    /// Smoothed bytes per second the peer delivered; zero until the first full sample.
+    rate: f64,
+    window_start: Option<Instant>,
+    window_bytes: u64,
+}
+
+impl RequestPipeline {
+    pub fn new(config: PipelineConfig) -> Self {
+        Self {
+            config,
+            sent: HashMap::new(),
+            rate: 0.0,
+            window_start: None,
+            window_bytes: 0,
+        }
+    }
+
+    /// Outstanding requests to aim for, from the configured depth up to the maximum.
+    pub fn depth(&self) -> usize {
+        let wanted = self.rate * self.config.queue_time.as_secs_f64() / f64::from(BLOCK_SIZE);
+        (wanted as usize).clamp(self.config.queue_depth, self.config.max_queue_depth)
+    }
+
+    /// Bytes per second the peer delivered recently.
+    pub fn rate(&self) -> u64 {
+        self.rate as u64
+    }
+
+    /// Requests blocks from `wanted`, in order, until the queue is at its depth. Blocks the
+    /// connection refuses, e.g. for pieces the peer lacks, are skipped.
+    pub fn fill(
+        &mut self,
+        connection: &mut PeerConnection,
+        wanted: impl IntoIterator<Item = BlockRequest>,
+        now: Instant,
+    ) -> Vec<Message> {
+        // A choke drops requests on the connection without us hearing about each one.
+        self.sent
+            .retain(|block, _| connection.requests().contains(block));
+        if !connection.can_request() {
+            return Vec::new();
+        }
+
+        let depth = self.depth();
+        let mut messages = Vec::new();
+        for block in wanted {
+            if connection.requests().len() >= depth {
+                break;
+            }
+            if let Ok(message) = connection.request(block) {
+                self.sent.insert(block, now);
+                messages.push(message);
+            }
+        }
+        messages
+    }
+
+    /// Notes a block the peer delivered and returns the round trip of its request; blocks we
+    /// did not ask it for are ignored.
+    pub fn received(&mut self, block: BlockRequest, now: Instant) -> Option<Duration> {
+        let sent = self.sent.remove(&block)?;
+        let start = *self.window_start.get_or_insert(now);
+        self.window_bytes += u64::from(block.length);
+
+        let elapsed = now.duration_since(start);
+        if elapsed >= Duration::from_secs(1) {
+            let sample = self.window_bytes as f64 / elapsed.as_secs_f64();
+            self.rate = if self.rate == 0.0 {
+                sample
+            } else {
+                (self.rate * 3.0 + sample) / 4.0
+            };
+            self.window_start = Some(now);
+            self.window_bytes = 0;
+        }
+        Some(now.saturating_duration_since(sent))
+    }
+
+    /// Withdraws a request, e.g. because another peer delivered the block first.
+    pub fn cancel(
+        &mut self,
+        connection: &mut PeerConnection,
+        block: BlockRequest,
+    ) -> Option<Message> {
+        self.sent.remove(&block);
+        connection.cancel(block)
+    }
+
+    /// Withdraws requests unanswered past the timeout. Each returned block should be sent
+    /// to the peer as a cancel and requested from someone else.
+    ///
+    /// A peer that lets requests time out is slower than measured, so its rate estimate is
+    /// halved and the queue shrinks with it.
+    pub fn expire(&mut self, connection: &mut PeerConnection, now: Instant) -> Vec<BlockRequest> {
+        let timeout = self.config.request_timeout;
+        let mut expired = self
+            .sent
+            .iter()
+            .filter(|(_, sent)| now.duration_since(**sent) >= timeout)
+            .map(|(block, _)| *block)
+            .collect::<Vec<_>>();
+        expired.sort();
+
+        for block in &expired {
+            self.sent.remove(block);
+            connection.cancel(*block);
+        }
+        if !expired.is_empty() {
+            self.rate /= 2.0;
+        }
+        expired
+    }
+}
+
+/// Which peers each block is requested from, so the other requests can be cancelled once
+/// one peer delivers it.
+#[derive(Debug, Clone)]
+pub struct InFlight<P> {
+    requested: HashMap<BlockRequest, Vec<P>>,
+}
+
+impl<P> Default for InFlight<P> {
+    fn default() -> Self {
+        Self {
+            requested: HashMap::new(),
+        }
+    }
+}
+
+impl<P: Clone + Eq + Hash> InFlight<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, block: BlockRequest, peer: P) {
+        let peers = self.requested.entry(block).or_default();
+        if !peers.contains(&peer) {
+            peers.push(peer);
+        }
+    }
+
+    pub fn is_requested(&self, block: &BlockRequest) -> bool {
+        self.requested.contains_key(block)
+    }
+
+    /// Peers the block is requested from.
+    pub fn peers(&self, block: &BlockRequest) -> &[P] {
+        self.requested.get(block).map_or(&[], Vec::as_slice)
+    }
+
+    /// Forgets one peer's request, e.g. after it timed out or the peer choked us.
+    pub fn remove(&mut self, block: &BlockRequest, peer: &P) {
+        if let Some(peers) = self.requested.get_mut(block) {
+            peers.retain(|requested| requested != peer);
+            if peers.is_empty() {
+                self.requested.remove(block);
+            }
+        }
+    }
+
+    /// Forgets every request of a peer that disconnected.
+    pub fn remove_peer(&mut self, peer: &P) {
+        self.requested.retain(|_, peers| {
+            peers.retain(|requested| requested != peer);
+            !peers.is_empty()
+        });
+    }
+
+    /// Records that `from` delivered the block and returns the other peers it is still
+    /// requested from, which should each get a cancel.
+    pub fn delivered(&mut self, block: &BlockRequest, from: &P) -> Vec<P> {
+        let mut peers = self.requested.remove(block).unwrap_or_default();
+        peers.retain(|peer| peer != from);
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(offset: u32) -> BlockRequest {
+        BlockRequest {
+            piece: 0,
+            offset,
+            length: BLOCK_SIZE,
+        }
+    }
+
+    fn downloading() -> PeerConnection {
+        let mut connection = PeerConnection::new(1);
+        connection.receive(&Message::Have { piece: 0 }).unwrap();
+        connection.set_interested(true);
+        connection.receive(&Message::Unchoke).unwrap();
+        connection
+    }
+
+    #[test]
+    fn fills_up_to_the_configured_depth() {
+        let mut connection = downloading();
+        let mut pipeline = RequestPipeline::new(PipelineConfig::default());
+        let now = Instant::now();
+        let wanted = (0..10).map(|index| block(index * BLOCK_SIZE));
+
+        assert_eq!(pipeline.fill(&mut connection, wanted.clone(), now).len(), 5);
+        assert_eq!(pipeline.fill(&mut connection, wanted, now).len(), 0);
+    }
+
+    #[test]
+    fn fast_peers_get_deeper_queues() {
+        let mut connection = downloading();
+        let mut pipeline = RequestPipeline::new(PipelineConfig::default());
+        let start = Instant::now();
+        let wanted = (0..20)
+            .map(|index| block(index * BLOCK_SIZE))
+            .collect::<Vec<_>>();
+        pipeline.fill(&mut connection, wanted.clone(), start);
+
+        // The first block a second in closes the sample: six blocks are 96 KiB/s, worth 18
+        // blocks over three seconds.
+        for now in [start, start + Duration::from_secs(1)] {
+            for block in connection.requests().to_vec() {
+                pipeline.received(block, now);
+                connection.cancel(block);
+            }
+            pipeline.fill(&mut connection, wanted.clone(), now);
+        }
+        assert_eq!(pipeline.depth(), 18);
+    }
+
+    #[test]
+    fn expires_unanswered_requests() {
+        let mut connection = downloading();
+        let mut pipeline = RequestPipeline::new(PipelineConfig::default());
+        let now = Instant::now();
+        pipeline.fill(&mut connection, [block(0)], now);
+
+        assert!(pipeline.expire(&mut connection, now).is_empty());
+        let later = now + Duration::from_secs(60);
+        assert_eq!(pipeline.expire(&mut connection, later), vec![block(0)]);
+        assert!(connection.requests().is_empty());
+    }
+
+    #[test]
+    fn delivery_cancels_duplicate_requests() {
+        let mut in_flight = InFlight::new();
+        in_flight.add(block(0), "a");
+        in_flight.add(block(0), "b");
+        in_flight.add(block(0), "c");
+        in_flight.remove(&block(0), &"c");
+
+        assert_eq!(in_flight.delivered(&block(0), &"a"), vec!["b"]);
+        assert!(!in_flight.is_requested(&block(0)));
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::net::SocketAddr;
+
+use super::discovery::Discovery;
+use crate::metadata::Metadata;
+
+/// Where a candidate peer was heard of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PeerSource {
+    Tracker,
+    Dht,
+    /// Peer exchange with a connected peer (BEP 11).
+    Pex,
+    /// Local service discovery (BEP 14).
+    Local,
+}
+
+/// Addresses of a torrent's peers that are known but not connected, dialed in the order
+/// they were learned.
+#[derive(Debug, Clone)]
+pub struct CandidatePool {
+    discovery: Discovery,
+    capacity: usize,
+    sources: BTreeMap<SocketAddr, PeerSource>,
+    order: VecDeque<SocketAddr>,
+}
+
+impl CandidatePool {
+    /// A pool holding at most `capacity` peers; sources `discovery` turns off are ignored,
+    /// which keeps DHT and PEX peers out of private torrents.
+    pub fn new(discovery: Discovery, capacity: usize) -> Self {
+        Self {
+            discovery,
+            capacity,
+            sources: BTreeMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// A pool for `torrent` whose sources follow [`Discovery::for_torrent`].
+    pub fn for_torrent(torrent: &Metadata, capacity: usize) -> Self {
+        Self::new(Discovery::for_torrent(torrent), capacity)
+    }
+
+    /// Adds a peer unless it is already known, its source is off, or the pool is full;
+    /// returns whether it was added.
+    pub fn add(&mut self, addr: SocketAddr, source: PeerSource) -> bool {
+        if !self.discovery.allows(source)
+            || self.sources.len() >= self.capacity
+            || self.sources.contains_key(&addr)
+        {
+            return false;
+        }
+        self.sources.insert(addr, source);
+        self.order.push_back(addr);
+        true
+    }
+
+    /// Forgets a peer, e.g. once it connected to us by itself.
+    pub fn remove(&mut self, addr: &SocketAddr) {
+        if self.sources.remove(addr).is_some() {
+            self.order.retain(|queued| queued != addr);
+        }
+    }
+
+    /// The next peer to dial.
+    pub fn pop(&mut self) -> Option<(SocketAddr, PeerSource)> {
+        let addr = self.order.pop_front()?;
+        let source = self.sources.remove(&addr)?;
+        Some((addr, source))
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
@@ -0,0 +1,141 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::dial::DialTrackerConfig;
+use super::pipeline::PipelineConfig;
+
+/// The kind of network the client runs on, setting every peer timer to suit it at once.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetworkProfile {
+    /// Peers on the same local network: fail fast and retry soon.
+    Lan,
+    #[default]
+    Broadband,
+    /// Satellite and other links with round trips near a second: wait longer before giving
+    /// up on a peer, and keep more requests in flight.
+    HighLatency,
+}
+
+/// Timers derived from a [`NetworkProfile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkTimings {
+    /// Time to open the TCP connection to a peer.
+    pub dial_timeout: Duration,
+    /// Time a peer gets to answer our handshake, or to send its own when it dialed us.
+    pub handshake_timeout: Duration,
+    /// Requests unanswered for this long are handed to other peers.
+    pub request_timeout: Duration,
+    /// Seconds of a peer's rate kept requested, see [`PipelineConfig::queue_time`].
+    pub queue_time: Duration,
+    /// How long a subnet that keeps failing to connect is left alone at first.
+    pub retry_after: Duration,
+}
+
+impl NetworkProfile {
+    pub fn timings(self) -> NetworkTimings {
+        let secs = Duration::from_secs;
+        match self {
+            NetworkProfile::Lan => NetworkTimings {
+                dial_timeout: secs(3),
+                handshake_timeout: secs(5),
+                request_timeout: secs(15),
+                queue_time: secs(1),
+                retry_after: secs(60),
+            },
+            NetworkProfile::Broadband => NetworkTimings {
+                dial_timeout: secs(10),
+                handshake_timeout: secs(10),
+                request_timeout: secs(60),
+                queue_time: secs(3),
+                retry_after: secs(5 * 60),
+            },
+            NetworkProfile::HighLatency => NetworkTimings {
+                dial_timeout: secs(30),
+                handshake_timeout: secs(30),
+                request_timeout: secs(120),
+                queue_time: secs(6),
+                retry_after: secs(10 * 60),
+            },
+        }
+    }
+}
+
+impl Default for NetworkTimings {
+    fn default() -> Self {
+        NetworkProfile::default().timings()
+    }
+}
+
+impl NetworkTimings {
+    /// The default pipeline with this network's request timing.
+    pub fn pipeline(&self) -> PipelineConfig {
+        PipelineConfig {
+            request_timeout: self.request_timeout,
+            queue_time: self.queue_time,
+            ..PipelineConfig::default()
+        }
+    }
+
+    /// The default dial tracker with this network's retry timing.
+    pub fn dial_tracker(&self) -> DialTrackerConfig {
+        DialTrackerConfig {
+            initial_suppression: self.retry_after,
+            ..DialTrackerConfig::default()
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    pub profile: NetworkProfile,
+    /// Seconds to connect to a peer, overriding the profile.
+    pub dial_timeout_secs: Option<u64>,
+    /// Seconds for the handshake, overriding the profile.
+    pub handshake_timeout_secs: Option<u64>,
+    /// Seconds before an unanswered request goes to another peer, overriding the profile.
+    pub request_timeout_secs: Option<u64>,
+}
+
+impl NetworkConfig {
+    /// The profile's timers with the overrides applied.
+    pub fn timings(&self) -> NetworkTimings {
+        let mut timings = self.profile.timings();
+        let overrides = [
+            (self.dial_timeout_secs, &mut timings.dial_timeout),
+            (self.handshake_timeout_secs, &mut timings.handshake_timeout),
+            (self.request_timeout_secs, &mut timings.request_timeout),
+        ];
+        for (secs, timer) in overrides {
+            if let Some(secs) = secs {
+                *timer = Duration::from_secs(secs);
+            }
+        }
+        timings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overrides_replace_single_timers() {
+        let config: NetworkConfig = toml::from_str(
+            r#"
+            profile = "high-latency"
+            handshake_timeout_secs = 45
+            "#,
+        )
+        .unwrap();
+        let timings = config.timings();
+        assert_eq!(timings.handshake_timeout, Duration::from_secs(45));
+        assert_eq!(
+            timings.dial_timeout,
+            NetworkProfile::HighLatency.timings().dial_timeout
+        );
+        assert_eq!(timings.pipeline().request_timeout, Duration::from_secs(120));
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+/// Which peers to drop while seeding once every upload slot is taken.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SeedingConfig {
+    /// Disconnect peers that already have every piece; two seeds have nothing to trade.
+    pub disconnect_seeds: bool,
+    /// Disconnect peers that never sent us data across `free_rider_sessions` sessions.
+    pub disconnect_free_riders: bool,
+    pub free_rider_sessions: u32,
+}
+
+impl Default for SeedingConfig {
+    fn default() -> Self {
+        Self {
+            disconnect_seeds: false,
+            disconnect_free_riders: false,
+            free_rider_sessions: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    Seed,
+    FreeRider,
+}
+
+/// What the connection knows about one connected peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeedingPeer {
+    pub ip: IpAddr,
+    /// The peer's bitfield and haves cover every piece.
+    pub has_all: bool,
+    /// Bytes of piece data the peer sent us on this connection.
+    pub downloaded: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct History {
+    sessions: u32,
+    downloaded: u64,
+}
+
+/// Picks peers to disconnect while seeding so upload slots go to leechers that trade back.
+///
+/// Remembers per address how much each past session gave us; the caller reports a session
+/// when its connection closes.
+#[derive(Debug, Default, Clone)]
+pub struct FreeRiderPolicy {
+    config: SeedingConfig,
+    history: HashMap<IpAddr, History>,
+}
+
+impl FreeRiderPolicy {
+    pub fn new(config: SeedingConfig) -> Self {
+        Self {
+            config,
+            history: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &SeedingConfig {
+        &self.config
+    }
+
+    /// Records a closed connection that sent us `downloaded` bytes.
+    pub fn on_session_end(&mut self, ip: IpAddr, downloaded: u64) {
+        let history = self.history.entry(ip).or_default();
+        history.sessions += 1;
+        history.downloaded += downloaded;
+    }
+
+    /// Whether `peer` should be dropped; only ever while seeding with no free upload slot.
+    pub fn evaluate(
+        &self,
+        peer: &SeedingPeer,
+        seeding: bool,
+        slots_full: bool,
+    ) -> Option<DisconnectReason> {
+        if !seeding || !slots_full {
+            return None;
+        }
+        if self.config.disconnect_seeds && peer.has_all {
+            return Some(DisconnectReason::Seed);
+        }
+        if self.config.disconnect_free_riders && self.is_free_rider(peer) {
+            return Some(DisconnectReason::FreeRider);
+        }
+        None
+    }
+
+    fn is_free_rider(&self, peer: &SeedingPeer) -> bool {
+        let Some(history) = self.history.get(&peer.ip) else {
+            return false;
+        };
+        history.sessions >= self.config.free_rider_sessions
+            && history.downloaded + peer.downloaded == 0
+    }
+}
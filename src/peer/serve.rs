@@ -0,0 +1,175 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use super::auth::SwarmAuth;
+use super::connection::{BlockRequest, MAX_REQUEST_LEN, PeerConnection};
+use super::message::Message;
+use crate::download::{PieceStates, read_block};
+use crate::file::TorrentFile;
+
+/// Answers a peer's request with the block read from the torrent's data under `root`.
+///
+/// Requests are refused while we choke the peer, unless its piece is one of the peer's
+/// allowed-fast pieces, in an authenticated swarm until `auth` has verified the peer, for
+/// blocks over [`MAX_REQUEST_LEN`], and for pieces we do not have or ranges outside the
+/// piece. With the fast extension a refusal is answered with a reject; without it the
+/// request is an error the caller drops. The caller counts the block's length with
+/// [`crate::metadata::Metadata::record_upload`] once it is sent.
+pub fn serve_request(
+    torrent: &TorrentFile,
+    root: &Path,
+    states: &PieceStates,
+    connection: &PeerConnection,
+    auth: Option<&SwarmAuth>,
+    request: BlockRequest,
+) -> Result<Message> {
+    if let Err(refusal) = check_request(torrent, states, connection, auth, request) {
+        if connection.fast() {
+            return Ok(request.to_reject());
+        }
+        return Err(refusal);
+    }
+
+    let data = read_block(
+        torrent,
+        root,
+        request.piece as usize,
+        request.offset as usize,
+        request.length as usize,
+    )?;
+    Ok(Message::Piece {
+        piece: request.piece,
+        offset: request.offset,
+        data,
+    })
+}
+
+fn check_request(
+    torrent: &TorrentFile,
+    states: &PieceStates,
+    connection: &PeerConnection,
+    auth: Option<&SwarmAuth>,
+    request: BlockRequest,
+) -> Result<()> {
+    if auth.is_some_and(|auth| !auth.may_serve()) {
+        bail!("Request from a peer that has not authenticated");
+    }
+    if connection.am_choking() && !connection.granted_fast().contains(&request.piece) {
+        bail!(
+            "Request for piece {} while the peer is choked",
+            request.piece
+        );
+    }
+    if request.length == 0 || request.length > MAX_REQUEST_LEN {
+        bail!("Peer requested a {}-byte block", request.length);
+    }
+    let index = request.piece as usize;
+    if !states.has(index) {
+        bail!("Request for piece {index}, which we do not have");
+    }
+    let end = u64::from(request.offset) + u64::from(request.length);
+    if torrent.piece_size(index).is_none_or(|size| end > size) {
+        bail!("Request for a block outside piece {index}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+    use crate::download::AddMode;
+    use crate::peer::{ExtendedHandshake, SwarmSecret};
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn unauthenticated_peers_are_refused() {
+        let synthetic = SyntheticTorrent::single("served", 32 * 1024, 16 * 1024);
+        let root = env::temp_dir().join(format!("terrent-serve-{}", process::id()));
+        synthetic.write_to(&root).unwrap();
+        let states = PieceStates::new(2, AddMode::AssumeComplete);
+        let mut connection = PeerConnection::new(2);
+        connection.unchoke();
+        let request = BlockRequest {
+            piece: 1,
+            offset: 0,
+            length: 1024,
+        };
+
+        let info_hash = synthetic.torrent.info_hash();
+        let (ours, theirs) = (*b"-TT0100-aaaaaaaaaaaa", *b"-TT0100-bbbbbbbbbbbb");
+        let secret = SwarmSecret::new("correct horse");
+        let mut auth = SwarmAuth::new(&secret, info_hash, ours, theirs).unwrap();
+        let serve = |auth: Option<&SwarmAuth>| {
+            serve_request(
+                &synthetic.torrent,
+                &root,
+                &states,
+                &connection,
+                auth,
+                request,
+            )
+        };
+        assert!(serve(Some(&auth)).is_err());
+
+        let mut handshake = ExtendedHandshake::default();
+        SwarmAuth::advertise(&mut handshake, 5);
+        let challenge = auth.start(&handshake).unwrap();
+        let mut peer = SwarmAuth::new(&secret, info_hash, theirs, ours).unwrap();
+        let response = peer.handle(&challenge).unwrap();
+        auth.handle(&response);
+        let Message::Piece { data, .. } = serve(Some(&auth)).unwrap() else {
+            panic!("Expected a piece");
+        };
+        assert_eq!(data, synthetic.piece(1)[..1024]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn fast_peers_get_allowed_pieces_while_choked_and_rejects_otherwise() {
+        let synthetic = SyntheticTorrent::single("fast", 32 * 1024, 16 * 1024);
+        let root = env::temp_dir().join(format!("terrent-serve-fast-{}", process::id()));
+        synthetic.write_to(&root).unwrap();
+        let states = PieceStates::new(2, AddMode::AssumeComplete);
+        let mut connection = PeerConnection::new(2).with_fast(true);
+        connection.grant_allowed_fast([1]);
+        let request = |piece| BlockRequest {
+            piece,
+            offset: 0,
+            length: 1024,
+        };
+        let serve = |connection: &PeerConnection, request| {
+            serve_request(
+                &synthetic.torrent,
+                &root,
+                &states,
+                connection,
+                None,
+                request,
+            )
+        };
+
+        assert!(matches!(
+            serve(&connection, request(1)).unwrap(),
+            Message::Piece { piece: 1, .. }
+        ));
+        assert_eq!(
+            serve(&connection, request(0)).unwrap(),
+            request(0).to_reject()
+        );
+        let past_end = BlockRequest {
+            offset: 16 * 1024,
+            ..request(1)
+        };
+        assert_eq!(serve(&connection, past_end).unwrap(), past_end.to_reject());
+
+        // Without the extension there is nothing to answer a refusal with.
+        let slow = PeerConnection::new(2);
+        assert!(serve(&slow, request(1)).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+//! Sessions with inbound peers: once the listener has handshaken a peer, the session sends
+//! it what we have and serves its requests from the data on disk until either side leaves.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use tokio::sync::mpsc;
+
+use super::abuse::{AbuseGuard, Offense};
+use super::bitfield::Bitfield;
+use super::connection::{MAX_REQUEST_LEN, PeerConnection};
+use super::fast::{ALLOWED_FAST_COUNT, allowed_fast_set, set_fast_bit};
+use super::handshake::Handshake;
+use super::id::PeerId;
+use super::keepalive::{KeepAliveConfig, run_connection};
+use super::listener::InboundPeer;
+use super::message::Message;
+use super::serve::serve_request;
+use crate::download::PieceStates;
+use crate::file::TorrentFile;
+use crate::metadata::Metadata;
+use crate::queue::TorrentState;
+use crate::stats::PeerStats;
+
+/// How often a session checks that its torrent is still active.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Messages buffered each way between a session and its connection.
+const QUEUE_LEN: usize = 64;
+
+/// A torrent served to inbound peers: its files, where its data lives and which pieces of
+/// it are there.
+#[derive(Debug)]
+pub struct SeedSource {
+    pub torrent: TorrentFile,
+    pub root: PathBuf,
+    pub states: PieceStates,
+}
+
+impl SeedSource {
+    fn bitfield(&self) -> Bitfield {
+        let mut bitfield = Bitfield::new(self.states.len());
+        for index in (0..self.states.len()).filter(|index| self.states.has(*index)) {
+            bitfield.set_piece(index);
+        }
+        bitfield
+    }
+}
+
+/// Our handshake for torrents served by [`serve_peer`], which speaks the fast extension.
+pub fn seeding_handshake(info_hash: [u8; 20], peer_id: PeerId) -> Handshake {
+    let mut handshake = Handshake::new(info_hash, peer_id);
+    set_fast_bit(&mut handshake.reserved);
+    handshake
+}
+
+/// Serves an inbound peer of `source` until it disconnects, breaks the protocol, or its
+/// torrent is no longer active in `torrents`, where the peer is listed while connected and
+/// the bytes sent are counted.
+///
+/// Every interested peer is unchoked; with the fast extension it also gets its
+/// allowed-fast pieces, so it has something to request before that. Oversized requests and
+/// peers that leave right after connecting are reported to `guard`.
+pub async fn serve_peer(
+    peer: InboundPeer,
+    source: Arc<SeedSource>,
+    torrents: Arc<Mutex<Vec<Metadata>>>,
+    guard: Arc<Mutex<AbuseGuard>>,
+) -> Result<()> {
+    let InboundPeer {
+        stream,
+        addr,
+        handshake,
+    } = peer;
+    let info_hash = handshake.info_hash;
+    let piece_count = source.torrent.piece_count();
+    let fast = handshake.supports_fast();
+    let mut connection = PeerConnection::new(piece_count).with_fast(fast);
+
+    let (outgoing, outgoing_rx) = mpsc::channel(QUEUE_LEN);
+    let (incoming_tx, mut incoming) = mpsc::channel(QUEUE_LEN);
+    let link = tokio::spawn(run_connection(
+        stream,
+        KeepAliveConfig::default(),
+        outgoing_rx,
+        incoming_tx,
+    ));
+
+    let bitfield = source.bitfield();
+    let mut greeting = vec![if fast {
+        bitfield.to_fast_message()
+    } else {
+        bitfield.to_message()
+    }];
+    greeting.extend(connection.grant_allowed_fast(allowed_fast_set(
+        addr.ip(),
+        &info_hash,
+        piece_count,
+        ALLOWED_FAST_COUNT,
+    )));
+
+    let connected = Instant::now();
+    if let Some(torrent) = find(&mut lock(&torrents), &info_hash) {
+        torrent.peers.push(PeerStats::new(addr, "I"));
+    }
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+    let mut paused = false;
+    let result = async {
+        for message in greeting {
+            send(&outgoing, message).await?;
+        }
+        loop {
+            tokio::select! {
+                message = incoming.recv() => {
+                    let Some(message) = message else {
+                        return Ok(());
+                    };
+                    if let Message::Request { length, .. } = message
+                        && length > MAX_REQUEST_LEN
+                    {
+                        lock(&guard).record_offense(
+                            addr.ip(),
+                            Offense::OversizedRequest,
+                            Instant::now(),
+                        );
+                    }
+                    connection.receive(&message)?;
+                    for reply in respond(&mut connection, &message, &source)? {
+                        if let Message::Piece { data, .. } = &reply {
+                            let bytes = data.len() as u64;
+                            connection.transfer_mut().record_upload(bytes, Instant::now());
+                            if let Some(torrent) = find(&mut lock(&torrents), &info_hash) {
+                                torrent.record_upload(addr, bytes);
+                            }
+                        }
+                        send(&outgoing, reply).await?;
+                    }
+                }
+                _ = refresh.tick() => {
+                    let mut torrents = lock(&torrents);
+                    let Some(torrent) = find(&mut torrents, &info_hash)
+                        .filter(|torrent| torrent.state == TorrentState::Active)
+                    else {
+                        paused = true;
+                        return Ok(());
+                    };
+                    if let Some(peer) = torrent.peers.iter_mut().find(|peer| peer.addr == addr) {
+                        peer.update(connection.transfer(), Instant::now());
+                    }
+                }
+            }
+        }
+    }
+    .await;
+
+    drop(outgoing);
+    link.abort();
+    if let Some(torrent) = find(&mut lock(&torrents), &info_hash) {
+        torrent.peers.retain(|peer| peer.addr != addr);
+    }
+    // Closing the session ourselves says nothing about the peer.
+    if !paused {
+        let now = Instant::now();
+        lock(&guard).on_disconnect(addr.ip(), now.duration_since(connected), now);
+    }
+    result
+}
+
+/// Our answers to `message`: unchoking a peer that became interested, choking one that lost
+/// interest, and serving or rejecting what it requested.
+fn respond(
+    connection: &mut PeerConnection,
+    message: &Message,
+    source: &SeedSource,
+) -> Result<Vec<Message>> {
+    let mut replies = Vec::new();
+    match message {
+        Message::Interested => replies.extend(connection.unchoke()),
+        Message::NotInterested => replies.extend(connection.choke()),
+        _ => {}
+    }
+    replies.extend(connection.take_rejects());
+    while let Some(request) = connection.next_peer_request() {
+        replies.push(serve_request(
+            &source.torrent,
+            &source.root,
+            &source.states,
+            connection,
+            None,
+            request,
+        )?);
+    }
+    Ok(replies)
+}
+
+async fn send(outgoing: &mpsc::Sender<Message>, message: Message) -> Result<()> {
+    if outgoing.send(message).await.is_err() {
+        bail!("Connection closed");
+    }
+    Ok(())
+}
+
+fn find<'a>(torrents: &'a mut [Metadata], info_hash: &[u8; 20]) -> Option<&'a mut Metadata> {
+    torrents
+        .iter_mut()
+        .find(|torrent| torrent.info_hash == *info_hash)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::{env, process};
+
+    use tokio::net::{TcpListener, TcpStream};
+
+    use super::*;
+    use crate::download::AddMode;
+    use crate::peer::AbuseGuardConfig;
+    use crate::peer::message::{MAX_MESSAGE_LEN, read_message, write_message};
+    use crate::testing::SyntheticTorrent;
+
+    /// A connected pair: the session's end as an inbound peer asking for `info_hash`, and
+    /// the remote peer's end.
+    async fn connected(info_hash: [u8; 20], fast: bool) -> (InboundPeer, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let remote = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (stream, addr): (TcpStream, SocketAddr) = listener.accept().await.unwrap();
+        let mut handshake = Handshake::new(info_hash, *b"-TT0100-remotepeer00");
+        if fast {
+            set_fast_bit(&mut handshake.reserved);
+        }
+        let peer = InboundPeer {
+            stream,
+            addr,
+            handshake,
+        };
+        (peer, remote)
+    }
+
+    /// A guard that bans after `strikes`, to tell whether a session reported anything.
+    fn guard(strikes: u32) -> Arc<Mutex<AbuseGuard>> {
+        Arc::new(Mutex::new(AbuseGuard::new(AbuseGuardConfig {
+            strikes_before_ban: strikes,
+            ..AbuseGuardConfig::default()
+        })))
+    }
+
+    async fn next(remote: &mut TcpStream) -> Message {
+        read_message(remote, MAX_MESSAGE_LEN).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_interested_peers_and_counts_the_upload() {
+        let synthetic = SyntheticTorrent::single("seeded", 40 * 1024, 16 * 1024);
+        let root = env::temp_dir().join(format!("terrent-session-{}", process::id()));
+        synthetic.write_to(&root).unwrap();
+        let info_hash = synthetic.torrent.info_hash();
+        let torrents = Arc::new(Mutex::new(vec![Metadata::from(&synthetic.torrent)]));
+        let source = Arc::new(SeedSource {
+            torrent: synthetic.torrent.clone(),
+            root: root.clone(),
+            states: PieceStates::new(3, AddMode::AssumeComplete),
+        });
+
+        let (peer, mut remote) = connected(info_hash, false).await;
+        let ip = peer.addr.ip();
+        let guard = guard(4);
+        let session = tokio::spawn(serve_peer(
+            peer,
+            source,
+            Arc::clone(&torrents),
+            Arc::clone(&guard),
+        ));
+        assert_eq!(next(&mut remote).await, Message::Bitfield(vec![0xe0]));
+
+        write_message(&mut remote, &Message::Interested)
+            .await
+            .unwrap();
+        assert_eq!(next(&mut remote).await, Message::Unchoke);
+        let request = Message::Request {
+            piece: 2,
+            offset: 1024,
+            length: 4096,
+        };
+        write_message(&mut remote, &request).await.unwrap();
+        assert_eq!(
+            next(&mut remote).await,
+            Message::Piece {
+                piece: 2,
+                offset: 1024,
+                data: synthetic.piece(2)[1024..5120].to_vec(),
+            }
+        );
+        assert_eq!(torrents.lock().unwrap()[0].stats.uploaded, 4096);
+        let listed = torrents.lock().unwrap()[0].peers.clone();
+        assert_eq!(listed.len(), 1);
+        assert_eq!((listed[0].flags.as_str(), listed[0].uploaded), ("I", 4096));
+
+        // An oversized request breaks the protocol and ends the session; with the short
+        // connection that is enough strikes for a ban.
+        let request = Message::Request {
+            piece: 0,
+            offset: 0,
+            length: MAX_REQUEST_LEN + 1,
+        };
+        write_message(&mut remote, &request).await.unwrap();
+        assert!(session.await.unwrap().is_err());
+        assert!(guard.lock().unwrap().is_banned(ip, Instant::now()));
+        assert!(torrents.lock().unwrap()[0].peers.is_empty());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn ends_once_the_torrent_is_paused() {
+        let synthetic = SyntheticTorrent::single("paused", 16 * 1024, 16 * 1024);
+        let info_hash = synthetic.torrent.info_hash();
+        let torrents = Arc::new(Mutex::new(vec![Metadata::from(&synthetic.torrent)]));
+        let source = Arc::new(SeedSource {
+            torrent: synthetic.torrent.clone(),
+            root: env::temp_dir(),
+            states: PieceStates::new(1, AddMode::Check),
+        });
+
+        let (peer, mut remote) = connected(info_hash, true).await;
+        let ip = peer.addr.ip();
+        let guard = guard(1);
+        let session = tokio::spawn(serve_peer(
+            peer,
+            source,
+            Arc::clone(&torrents),
+            Arc::clone(&guard),
+        ));
+        assert_eq!(next(&mut remote).await, Message::HaveNone);
+        torrents.lock().unwrap()[0].state = TorrentState::Stopped;
+        assert!(session.await.unwrap().is_ok());
+        // Pausing is our doing, so the short connection is not held against the peer.
+        assert!(!guard.lock().unwrap().is_banned(ip, Instant::now()));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::stats::TransferStats;
+
+/// Span rates of a peer are averaged over.
+pub const RATE_WINDOW: Duration = Duration::from_secs(20);
+
+/// Bytes per second over the last [`RATE_WINDOW`], counted in one-second slots so a burst
+/// fades out of the rate instead of dropping off at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollingRate {
+    /// Bytes of each second, oldest first; the last slot started at `newest`.
+    slots: VecDeque<u64>,
+    newest: Option<Instant>,
+    /// When the first bytes were counted, so a young connection is not averaged over time
+    /// it did not exist.
+    started: Option<Instant>,
+}
+
+impl RollingRate {
+    pub fn new() -> Self {
+        Self {
+            slots: VecDeque::new(),
+            newest: None,
+            started: None,
+        }
+    }
+
+    pub fn add(&mut self, bytes: u64, now: Instant) {
+        let slot_count = RATE_WINDOW.as_secs() as usize;
+        match self.newest {
+            None => {
+                self.started = Some(now);
+                self.newest = Some(now);
+                self.slots.push_back(0);
+            }
+            Some(newest) => {
+                let elapsed = now.saturating_duration_since(newest).as_secs();
+                for _ in 0..elapsed.min(slot_count as u64) {
+                    self.slots.push_back(0);
+                }
+                self.newest = Some(newest + Duration::from_secs(elapsed));
+            }
+        }
+        while self.slots.len() > slot_count {
+            self.slots.pop_front();
+        }
+        if let Some(slot) = self.slots.back_mut() {
+            *slot += bytes;
+        }
+    }
+
+    /// Average bytes per second at `now`.
+    pub fn rate(&self, now: Instant) -> u64 {
+        let (Some(newest), Some(started)) = (self.newest, self.started) else {
+            return 0;
+        };
+        let slot_count = RATE_WINDOW.as_secs() as usize;
+        let stale = now.saturating_duration_since(newest).as_secs() as usize;
+        let bytes = self
+            .slots
+            .iter()
+            .rev()
+            .take(slot_count.saturating_sub(stale))
+            .sum::<u64>();
+        let span = now
+            .saturating_duration_since(started)
+            .clamp(Duration::from_secs(1), RATE_WINDOW);
+        (bytes as f64 / span.as_secs_f64()) as u64
+    }
+}
+
+impl Default for RollingRate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What went over one peer connection: piece data both ways with their recent rates, how
+/// quickly the peer answers our requests, and how often its data failed the hash check.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PeerTransfer {
+    downloaded: u64,
+    uploaded: u64,
+    download: RollingRate,
+    upload: RollingRate,
+    /// Smoothed round trip of our requests.
+    latency: Option<Duration>,
+    hash_failures: u32,
+}
+
+impl PeerTransfer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts piece data the peer sent us.
+    pub fn record_download(&mut self, bytes: u64, now: Instant) {
+        self.downloaded += bytes;
+        self.download.add(bytes, now);
+    }
+
+    /// Counts piece data we sent the peer.
+    pub fn record_upload(&mut self, bytes: u64, now: Instant) {
+        self.uploaded += bytes;
+        self.upload.add(bytes, now);
+    }
+
+    /// Counts the round trip of an answered request, e.g. from
+    /// [`super::RequestPipeline::received`].
+    pub fn record_latency(&mut self, round_trip: Duration) {
+        self.latency = Some(match self.latency {
+            // Smoothed like TCP's round trip estimate, so one slow block does not swing it.
+            Some(latency) => (latency * 7 + round_trip) / 8,
+            None => round_trip,
+        });
+    }
+
+    /// Counts a piece that failed its hash check with data from the peer in it.
+    pub fn record_hash_failure(&mut self) {
+        self.hash_failures += 1;
+    }
+
+    pub fn downloaded(&self) -> u64 {
+        self.downloaded
+    }
+
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+
+    pub fn download_rate(&self, now: Instant) -> u64 {
+        self.download.rate(now)
+    }
+
+    pub fn upload_rate(&self, now: Instant) -> u64 {
+        self.upload.rate(now)
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency
+    }
+
+    pub fn hash_failures(&self) -> u32 {
+        self.hash_failures
+    }
+
+    /// Counters and rates at `now`, in the form torrents report them.
+    pub fn stats(&self, now: Instant) -> TransferStats {
+        TransferStats {
+            downloaded: self.downloaded,
+            uploaded: self.uploaded,
+            download_rate: self.download_rate(now),
+            upload_rate: self.upload_rate(now),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_average_over_the_window_and_fade() {
+        let start = Instant::now();
+        let mut transfer = PeerTransfer::new();
+        for second in 0..10 {
+            transfer.record_download(1000, start + Duration::from_secs(second));
+        }
+        transfer.record_upload(500, start);
+        assert_eq!(transfer.downloaded(), 10_000);
+        assert_eq!(
+            transfer.download_rate(start + Duration::from_secs(10)),
+            1000
+        );
+        // The first second left the window, and the rest is spread over all of it.
+        assert_eq!(transfer.download_rate(start + Duration::from_secs(20)), 450);
+        assert_eq!(transfer.download_rate(start + Duration::from_secs(40)), 0);
+        assert_eq!(transfer.upload_rate(start + Duration::from_secs(25)), 0);
+
+        transfer.record_latency(Duration::from_millis(80));
+        transfer.record_latency(Duration::from_millis(160));
+        assert_eq!(transfer.latency(), Some(Duration::from_millis(90)));
+        transfer.record_hash_failure();
+        assert_eq!(transfer.stats(start).uploaded, 500);
+        assert_eq!(transfer.hash_failures(), 1);
+    }
+}
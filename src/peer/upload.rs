@@ -0,0 +1,219 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use super::connection::BlockRequest;
+
+/// Order in which peers with queued requests are served.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum UploadOrder {
+    /// One block per peer in turn.
+    #[default]
+    RoundRobin,
+    /// The peer with the fewest queued bytes first, so small requests finish quickly.
+    ShortestQueue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UploadSchedulerConfig {
+    pub order: UploadOrder,
+    /// Requests queued per peer; further ones are dropped until the queue drains, as BEP 3
+    /// lets a peer silently ignore requests.
+    pub max_queued_per_peer: usize,
+    /// Bytes of requests queued across all peers of the torrent; `None` for no cap.
+    pub max_queued_bytes: Option<u64>,
+}
+
+impl Default for UploadSchedulerConfig {
+    fn default() -> Self {
+        Self {
+            order: UploadOrder::default(),
+            max_queued_per_peer: 250,
+            max_queued_bytes: None,
+        }
+    }
+}
+
+/// Peer requests waiting to be served, queued per peer so one peer asking for hundreds of
+/// blocks cannot starve the others.
+///
+/// Requests are moved here from [`super::PeerConnection::next_peer_request`]; cancels and
+/// chokes that follow have to be passed on with [`Self::cancel`] and [`Self::remove_peer`].
+#[derive(Debug, Clone)]
+pub struct UploadScheduler<P> {
+    config: UploadSchedulerConfig,
+    queues: HashMap<P, VecDeque<BlockRequest>>,
+    /// Peers with queued requests, next to serve in round-robin order first.
+    turn: VecDeque<P>,
+    queued_bytes: u64,
+}
+
+impl<P: Clone + Eq + Hash> UploadScheduler<P> {
+    pub fn new(config: UploadSchedulerConfig) -> Self {
+        Self {
+            config,
+            queues: HashMap::new(),
+            turn: VecDeque::new(),
+            queued_bytes: 0,
+        }
+    }
+
+    /// Queues a request; returns `false` if it was dropped because the peer's queue or the
+    /// byte cap is full, or it is already queued.
+    pub fn push(&mut self, peer: P, request: BlockRequest) -> bool {
+        let length = u64::from(request.length);
+        if self
+            .config
+            .max_queued_bytes
+            .is_some_and(|max| self.queued_bytes + length > max)
+        {
+            return false;
+        }
+        let queue = self.queues.entry(peer.clone()).or_default();
+        if queue.len() >= self.config.max_queued_per_peer || queue.contains(&request) {
+            return false;
+        }
+        if queue.is_empty() {
+            self.turn.push_back(peer);
+        }
+        queue.push_back(request);
+        self.queued_bytes += length;
+        true
+    }
+
+    /// Drops a request the peer cancelled.
+    pub fn cancel(&mut self, peer: &P, request: &BlockRequest) {
+        if let Some(queue) = self.queues.get_mut(peer)
+            && let Some(position) = queue.iter().position(|queued| queued == request)
+        {
+            queue.remove(position);
+            self.queued_bytes -= u64::from(request.length);
+        }
+        self.drop_if_empty(peer);
+    }
+
+    /// Drops every request of a peer we choked or lost.
+    pub fn remove_peer(&mut self, peer: &P) {
+        if let Some(queue) = self.queues.remove(peer) {
+            self.queued_bytes -= queue
+                .iter()
+                .map(|request| u64::from(request.length))
+                .sum::<u64>();
+        }
+        self.turn.retain(|queued| queued != peer);
+    }
+
+    /// Requests queued for `peer`.
+    pub fn queued(&self, peer: &P) -> usize {
+        self.queues.get(peer).map_or(0, VecDeque::len)
+    }
+
+    /// Bytes of all queued requests.
+    pub fn queued_bytes(&self) -> u64 {
+        self.queued_bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.queues.values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.turn.is_empty()
+    }
+
+    /// The next request to serve and the peer it is for.
+    pub fn pop(&mut self) -> Option<(P, BlockRequest)> {
+        let position = match self.config.order {
+            UploadOrder::RoundRobin => 0,
+            // Ties keep round-robin order, so equal queues still take turns.
+            UploadOrder::ShortestQueue => self
+                .turn
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, peer)| {
+                    self.queues[*peer]
+                        .iter()
+                        .map(|request| u64::from(request.length))
+                        .sum::<u64>()
+                })
+                .map(|(position, _)| position)?,
+        };
+        let peer = self.turn.remove(position)?;
+        let queue = self.queues.get_mut(&peer)?;
+        let request = queue.pop_front()?;
+        self.queued_bytes -= u64::from(request.length);
+        if queue.is_empty() {
+            self.queues.remove(&peer);
+        } else {
+            self.turn.push_back(peer.clone());
+        }
+        Some((peer, request))
+    }
+
+    fn drop_if_empty(&mut self, peer: &P) {
+        if self.queues.get(peer).is_some_and(VecDeque::is_empty) {
+            self.remove_peer(peer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(piece: u32) -> BlockRequest {
+        BlockRequest {
+            piece,
+            offset: 0,
+            length: 16 * 1024,
+        }
+    }
+
+    #[test]
+    fn takes_turns_between_peers() {
+        let mut scheduler = UploadScheduler::new(UploadSchedulerConfig::default());
+        for piece in 0..3 {
+            scheduler.push("greedy", block(piece));
+        }
+        scheduler.push("modest", block(9));
+
+        let order = std::iter::from_fn(|| scheduler.pop())
+            .map(|(peer, request)| (peer, request.piece))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            [("greedy", 0), ("modest", 9), ("greedy", 1), ("greedy", 2)]
+        );
+    }
+
+    #[test]
+    fn caps_each_peer_queue() {
+        let mut scheduler = UploadScheduler::new(UploadSchedulerConfig {
+            max_queued_per_peer: 2,
+            ..UploadSchedulerConfig::default()
+        });
+        assert!(scheduler.push("peer", block(0)));
+        assert!(!scheduler.push("peer", block(0)));
+        assert!(scheduler.push("peer", block(1)));
+        assert!(!scheduler.push("peer", block(2)));
+        assert_eq!(scheduler.queued(&"peer"), 2);
+    }
+
+    #[test]
+    fn shortest_queue_goes_first() {
+        let mut scheduler = UploadScheduler::new(UploadSchedulerConfig {
+            order: UploadOrder::ShortestQueue,
+            ..UploadSchedulerConfig::default()
+        });
+        scheduler.push("long", block(0));
+        scheduler.push("long", block(1));
+        scheduler.push("short", block(5));
+        assert_eq!(scheduler.pop(), Some(("short", block(5))));
+        scheduler.cancel(&"long", &block(0));
+        assert_eq!(scheduler.pop(), Some(("long", block(1))));
+        assert!(scheduler.is_empty());
+    }
+}
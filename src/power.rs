@@ -0,0 +1,199 @@
+use std::env;
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+/// Environment variable that marks the connection as metered for one run, e.g. from a
+/// NetworkManager dispatcher script.
+pub const METERED_ENV: &str = "TERRENT_METERED";
+
+/// What to do with transfers while a power or network condition holds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerAction {
+    #[default]
+    Ignore,
+    /// Apply the rate limits of [`PowerConfig::throttle`].
+    Throttle,
+    Pause,
+}
+
+/// Rates in bytes per second; `None` leaves that direction unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    pub download: Option<u64>,
+    pub upload: Option<u64>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            download: Some(256 * 1024),
+            upload: Some(32 * 1024),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    pub on_battery: PowerAction,
+    pub on_metered: PowerAction,
+    /// Treat the connection as metered; setting `TERRENT_METERED=1` does the same for one run.
+    pub metered: bool,
+    /// Restrictive profile used by [`PowerAction::Throttle`].
+    pub throttle: RateLimits,
+}
+
+/// Conditions the power rules react to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PowerState {
+    pub on_battery: bool,
+    pub metered: bool,
+}
+
+impl PowerState {
+    pub fn detect(config: &PowerConfig) -> Self {
+        Self {
+            on_battery: on_battery(),
+            metered: config.metered
+                || env::var(METERED_ENV).is_ok_and(|value| !matches!(value.as_str(), "" | "0")),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TransferMode {
+    #[default]
+    Normal,
+    Throttled(RateLimits),
+    Paused,
+}
+
+impl PowerConfig {
+    /// Mode for `state`; when several conditions hold the most restrictive action wins.
+    pub fn mode(&self, state: PowerState) -> TransferMode {
+        let battery = state.on_battery.then_some(self.on_battery);
+        let metered = state.metered.then_some(self.on_metered);
+        match battery.max(metered).unwrap_or_default() {
+            PowerAction::Ignore => TransferMode::Normal,
+            PowerAction::Throttle => TransferMode::Throttled(self.throttle),
+            PowerAction::Pause => TransferMode::Paused,
+        }
+    }
+}
+
+/// Re-evaluates the power rules periodically and reports when the transfer mode changes, so
+/// transfers resume by themselves once back on mains or an unmetered connection.
+#[derive(Debug, Default, Clone)]
+pub struct PowerMonitor {
+    mode: TransferMode,
+}
+
+impl PowerMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(&self) -> TransferMode {
+        self.mode
+    }
+
+    /// Detects the current state; returns the new mode when it differs from the last one.
+    pub fn poll(&mut self, config: &PowerConfig) -> Option<TransferMode> {
+        self.update(config.mode(PowerState::detect(config)))
+    }
+
+    pub fn update(&mut self, mode: TransferMode) -> Option<TransferMode> {
+        (mode != self.mode).then(|| {
+            self.mode = mode;
+            mode
+        })
+    }
+}
+
+/// Whether the machine runs on battery; an online mains adapter always wins.
+#[cfg(target_os = "linux")]
+pub fn on_battery() -> bool {
+    supplies_on_battery(Path::new("/sys/class/power_supply"))
+}
+
+/// [`on_battery`] for the supplies under `dir`. Batteries of devices such as a wireless
+/// mouse report `scope=Device` and are skipped, since they do not power the machine.
+#[cfg(target_os = "linux")]
+fn supplies_on_battery(dir: &Path) -> bool {
+    let Ok(supplies) = fs::read_dir(dir) else {
+        return false;
+    };
+
+    let read = |path: &Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    let mut discharging = false;
+    for supply in supplies.flatten() {
+        let path = supply.path();
+        if read(&path, "scope") == "Device" {
+            continue;
+        }
+        match read(&path, "type").as_str() {
+            "Mains" | "USB" if read(&path, "online") == "1" => return false,
+            "Battery" => discharging |= read(&path, "status") == "Discharging",
+            _ => {}
+        }
+    }
+    discharging
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn on_battery() -> bool {
+    false
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn supply(dir: &Path, name: &str, attributes: &[(&str, &str)]) {
+        let path = dir.join(name);
+        fs::create_dir_all(&path).unwrap();
+        for (attribute, value) in attributes {
+            fs::write(path.join(attribute), format!("{value}\n")).unwrap();
+        }
+    }
+
+    #[test]
+    fn device_batteries_do_not_count() {
+        let dir = env::temp_dir().join(format!("terrent-power-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        supply(
+            &dir,
+            "hidpp_battery_0",
+            &[
+                ("type", "Battery"),
+                ("scope", "Device"),
+                ("status", "Discharging"),
+            ],
+        );
+        assert!(!supplies_on_battery(&dir));
+
+        supply(
+            &dir,
+            "BAT0",
+            &[("type", "Battery"), ("status", "Discharging")],
+        );
+        assert!(supplies_on_battery(&dir));
+
+        supply(&dir, "AC", &[("type", "Mains"), ("online", "1")]);
+        assert!(!supplies_on_battery(&dir));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+#[cfg(unix)]
+use anyhow::Context;
+use anyhow::Result;
+
+/// Niceness applied when running in the background.
+#[cfg(unix)]
+const BACKGROUND_NICENESS: libc::c_int = 10;
+
+/// Lowers the CPU and disk I/O priority of the whole process so hashing and transfers
+/// yield to interactive work.
+pub fn lower_priority() -> Result<()> {
+    lower_cpu_priority(0)?;
+    lower_io_priority(0)
+}
+
+/// Lowers the priority of the calling thread only, e.g. one rechecking data. Only Linux
+/// sets priorities per thread; elsewhere this does nothing rather than slow down the
+/// whole process.
+pub fn lower_thread_priority() -> Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        // SAFETY: gettid has no preconditions.
+        let tid = unsafe { libc::gettid() } as u32;
+        lower_cpu_priority(tid)?;
+        lower_io_priority(tid)?;
+    }
+    Ok(())
+}
+
+/// `who` is a process or, on Linux, a thread id; 0 means the calling process.
+#[cfg(unix)]
+fn lower_cpu_priority(who: u32) -> Result<()> {
+    // SAFETY: setpriority has no memory-safety preconditions.
+    let result =
+        unsafe { libc::setpriority(libc::PRIO_PROCESS as _, who as _, BACKGROUND_NICENESS) };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to lower CPU priority");
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn lower_cpu_priority(_who: u32) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn lower_io_priority(who: u32) -> Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_BE: libc::c_long = 2;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+    // Lowest best-effort level; the idle class can starve rechecks forever on a busy disk.
+    const IOPRIO_LOWEST_LEVEL: libc::c_long = 7;
+
+    let ioprio = (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | IOPRIO_LOWEST_LEVEL;
+    // SAFETY: ioprio_set only takes integer arguments.
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            libc::c_long::from(who),
+            ioprio,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error()).context("Failed to lower I/O priority");
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn lower_io_priority(_who: u32) -> Result<()> {
+    Ok(())
+}
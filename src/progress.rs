@@ -0,0 +1,180 @@
+//! Optional sidecar file with the progress of every torrent, so dashboards and scripts can
+//! follow downloads without talking to the client.
+//!
+//! The file is JSON, replaced atomically so readers never see half of it, and only
+//! rewritten when a torrent's progress or state actually changed.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::format::hex;
+use crate::metadata::Metadata;
+use crate::session;
+
+/// Name of the sidecar file when no path is configured.
+pub const PROGRESS_FILE: &str = ".terrent-progress.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProgressFileConfig {
+    pub enabled: bool,
+    /// Where to write the file; defaults to [`PROGRESS_FILE`] in the data directory.
+    pub path: Option<PathBuf>,
+    /// Least number of seconds between two writes.
+    pub interval_secs: u64,
+}
+
+impl Default for ProgressFileConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: None,
+            interval_secs: 5,
+        }
+    }
+}
+
+impl ProgressFileConfig {
+    pub fn path(&self) -> Option<PathBuf> {
+        self.path
+            .clone()
+            .or_else(|| session::dir().map(|dir| dir.join(PROGRESS_FILE)))
+    }
+}
+
+/// Keeps the sidecar file in step with the torrents.
+#[derive(Debug, Clone)]
+pub struct ProgressFile {
+    /// `None` while the file is turned off.
+    path: Option<PathBuf>,
+    interval: Duration,
+    last_written: Option<Instant>,
+    /// The torrent entries last written, to skip writes that would change nothing.
+    written: String,
+}
+
+impl ProgressFile {
+    pub fn new(config: &ProgressFileConfig) -> Self {
+        Self {
+            path: config.enabled.then(|| config.path()).flatten(),
+            interval: Duration::from_secs(config.interval_secs),
+            last_written: None,
+            written: String::new(),
+        }
+    }
+
+    /// Rewrites the file if the interval passed and anything changed since the last write;
+    /// returns whether it did. `unix_now` stamps the file.
+    pub fn update(&mut self, torrents: &[Metadata], now: Instant, unix_now: u64) -> Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+        if self
+            .last_written
+            .is_some_and(|at| now.duration_since(at) < self.interval)
+        {
+            return Ok(false);
+        }
+        let entries = torrents.iter().map(entry).collect::<Vec<_>>().join(",\n");
+        if self.last_written.is_some() && entries == self.written {
+            return Ok(false);
+        }
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {parent:?}"))?;
+        }
+        let content = format!("{{\"updated\": {unix_now}, \"torrents\": [\n{entries}\n]}}\n");
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, content).with_context(|| format!("Failed to write {tmp:?}"))?;
+        fs::rename(&tmp, path).with_context(|| format!("Failed to replace {path:?}"))?;
+
+        self.last_written = Some(now);
+        self.written = entries;
+        Ok(true)
+    }
+}
+
+/// One torrent as a JSON object on a single line. Fields that are not known yet are left
+/// out rather than written as `null`.
+fn entry(torrent: &Metadata) -> String {
+    let mut fields = vec![
+        format!("\"info_hash\": \"{}\"", hex(&torrent.info_hash)),
+        format!("\"name\": {}", json_string(&torrent.name)),
+        format!("\"state\": {}", json_string(&torrent.state.describe())),
+        format!("\"size\": {}", torrent.length),
+        format!("\"downloaded\": {}", torrent.stats.downloaded),
+        format!("\"uploaded\": {}", torrent.stats.uploaded),
+    ];
+    if let Some(label) = &torrent.label {
+        fields.push(format!("\"label\": {}", json_string(label)));
+    }
+    if let Some(left) = torrent.left {
+        let done = torrent.length.saturating_sub(left);
+        let progress = if torrent.length == 0 {
+            1.0
+        } else {
+            done as f64 / torrent.length as f64
+        };
+        fields.push(format!("\"done\": {done}"));
+        fields.push(format!("\"progress\": {progress:.4}"));
+    }
+    format!("  {{{}}}", fields.join(", "))
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if u32::from(c) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", u32::from(c));
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn writes_only_when_progress_changes() {
+        let dir = env::temp_dir().join(format!("terrent-progress-{}", process::id()));
+        let path = dir.join(PROGRESS_FILE);
+        let mut file = ProgressFile::new(&ProgressFileConfig {
+            enabled: true,
+            path: Some(path.clone()),
+            interval_secs: 0,
+        });
+        let mut torrent = Metadata {
+            name: "a \"quoted\" name".to_string(),
+            ..Metadata::from(&SyntheticTorrent::single("data", 200, 16 * 1024).torrent)
+        };
+
+        let now = Instant::now();
+        assert!(file.update(&[torrent.clone()], now, 1).unwrap());
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains(r#""name": "a \"quoted\" name""#));
+        assert!(!content.contains("progress"));
+        assert!(!file.update(&[torrent.clone()], now, 2).unwrap());
+
+        torrent.left = Some(50);
+        assert!(file.update(&[torrent], now, 3).unwrap());
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains(r#""done": 150, "progress": 0.7500"#));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+//! Starting and stopping torrents, including starts held back until a set time, e.g. for
+//! off-peak hours.
+
+use anyhow::{Context, Result, bail};
+
+use crate::format::{format_time, local_offset};
+use crate::metadata::Metadata;
+
+/// Whether a torrent transfers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TorrentState {
+    #[default]
+    Active,
+    Stopped,
+    /// Stopped until the Unix time `at`, then started by [`start_due`].
+    Scheduled {
+        at: u64,
+    },
+}
+
+impl TorrentState {
+    /// Short status for the torrent list, e.g. `Scheduled 02:00`.
+    pub fn describe(&self) -> String {
+        match self {
+            TorrentState::Active => "Active".to_string(),
+            TorrentState::Stopped => "Stopped".to_string(),
+            TorrentState::Scheduled { at } => format!("Scheduled {}", format_time(*at)),
+        }
+    }
+}
+
+/// Holds `torrent` back until `at`.
+pub fn schedule(torrent: &mut Metadata, at: u64) {
+    torrent.state = TorrentState::Scheduled { at };
+}
+
+/// Drops a pending start, leaving the torrent stopped; returns whether one was pending.
+pub fn cancel(torrent: &mut Metadata) -> bool {
+    let scheduled = matches!(torrent.state, TorrentState::Scheduled { .. });
+    if scheduled {
+        torrent.state = TorrentState::Stopped;
+    }
+    scheduled
+}
+
+/// Starts a stopped or scheduled torrent right away; returns whether it was not running.
+pub fn start_now(torrent: &mut Metadata) -> bool {
+    let started = torrent.state != TorrentState::Active;
+    torrent.state = TorrentState::Active;
+    started
+}
+
+/// Starts every torrent whose scheduled time has come and returns their indices.
+pub fn start_due(torrents: &mut [Metadata], now: u64) -> Vec<usize> {
+    let mut started = Vec::new();
+    for (index, torrent) in torrents.iter_mut().enumerate() {
+        if let TorrentState::Scheduled { at } = torrent.state
+            && at <= now
+        {
+            torrent.state = TorrentState::Active;
+            started.push(index);
+        }
+    }
+    started
+}
+
+/// Parses a local `HH:MM` into the next Unix time showing it, today or tomorrow.
+pub fn parse_start_time(text: &str, now: u64) -> Result<u64> {
+    let (hour, minute) = text
+        .trim()
+        .split_once(':')
+        .context("Expected a time like 02:00")?;
+    let (hour, minute) = (
+        hour.parse::<u64>().context("Invalid hour")?,
+        minute.parse::<u64>().context("Invalid minute")?,
+    );
+    if hour > 23 || minute > 59 {
+        bail!("{text} is not a time of day");
+    }
+    Ok(next_time_of_day(hour, minute, now, local_offset(now)))
+}
+
+/// The first Unix time after `now` that reads `hour:minute` in a zone `offset` seconds
+/// ahead of UTC.
+fn next_time_of_day(hour: u64, minute: u64, now: u64, offset: i64) -> u64 {
+    let local = now as i64 + offset;
+    let midnight = local - local.rem_euclid(86_400);
+    let mut at = midnight + (hour * 3600 + minute * 60) as i64;
+    if at <= local {
+        at += 86_400;
+    }
+    (at - offset) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_next_occurrence_in_local_time() {
+        // 2024-01-01 23:30 UTC.
+        let now = 1_704_151_800;
+        // 02:00 UTC is tomorrow.
+        assert_eq!(next_time_of_day(2, 0, now, 0), 1_704_160_800);
+        // It is 02:30 at UTC+3, so 02:00 there comes again in 23.5 hours.
+        assert_eq!(next_time_of_day(2, 0, now, 3 * 3600), 1_704_236_400);
+        // 23:45 UTC is still ahead today.
+        assert_eq!(next_time_of_day(23, 45, now, 0), 1_704_152_700);
+    }
+}
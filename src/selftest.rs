@@ -0,0 +1,472 @@
+use anyhow::{Context, Result, bail, ensure};
+use sha1::{Digest, Sha1};
+
+use crate::file::bencode::BencodeTorrent;
+use crate::file::encoder::Value;
+use crate::file::{DecodeError, DecodeLimits, merkle};
+use crate::format::hex;
+use crate::peer::extension::set_extensions_bit;
+use crate::peer::fast::set_fast_bit;
+use crate::peer::message::MAX_MESSAGE_LEN;
+use crate::peer::metadata::METADATA_PIECE_SIZE;
+use crate::peer::mse::{self, Rc4};
+use crate::peer::{
+    AuthMessage, AuthState, Bitfield, ExtendedHandshake, Handshake, Message, MetadataAssembler,
+    MetadataMessage, MetadataServer, SwarmAuth, SwarmSecret,
+};
+
+/// A named check run against fixed vectors.
+pub type Check = (&'static str, fn() -> Result<()>);
+
+/// Known-answer checks for the primitives that corrupt data silently when they misbehave on a
+/// platform, e.g. because of a miscompiled hash backend.
+pub const CHECKS: &[Check] = &[
+    ("bencode encoder", bencode_encoder),
+    ("bencode decoder", bencode_decoder),
+    ("bencode limits", bencode_limits),
+    ("SHA-1", sha1_vectors),
+    ("SHA-256", sha256_vectors),
+    ("merkle tree", merkle_tree),
+    ("peer handshake codec", peer_handshake),
+    ("peer message codec", peer_messages),
+    ("bitfield codec", bitfield),
+    ("extension handshake codec", extension_handshake),
+    ("ut_metadata codec", metadata_messages),
+    ("ut_metadata exchange", metadata_exchange),
+    ("swarm authentication", swarm_auth),
+    ("RC4", rc4_vectors),
+    ("MSE key exchange", mse_key_exchange),
+];
+
+/// Runs every check, returning each name with its outcome.
+pub fn run() -> Vec<(&'static str, Result<()>)> {
+    CHECKS
+        .iter()
+        .map(|(name, check)| (*name, check()))
+        .collect()
+}
+
+fn bencode_encoder() -> Result<()> {
+    // Examples from BEP 3.
+    let cases: [(Value, &[u8]); 5] = [
+        (Value::from("spam"), b"4:spam"),
+        (Value::from(3i64), b"i3e"),
+        (Value::from(-3i64), b"i-3e"),
+        (Value::from(vec!["spam", "eggs"]), b"l4:spam4:eggse"),
+        (
+            Value::dict().with("spam", "eggs").with("cow", "moo"),
+            b"d3:cow3:moo4:spam4:eggse",
+        ),
+    ];
+    for (value, expected) in cases {
+        let encoded = value.encode();
+        ensure!(
+            encoded == expected,
+            "{value:?} encoded to {:?}",
+            String::from_utf8_lossy(&encoded)
+        );
+    }
+    Ok(())
+}
+
+fn bencode_decoder() -> Result<()> {
+    let info = Value::dict()
+        .with("length", 5u64)
+        .with("name", "hello.txt")
+        .with("piece length", 16384u64)
+        .with("pieces", Sha1::digest(b"hello").as_slice());
+    let torrent = Value::dict()
+        .with("announce", "http://tracker.example/announce")
+        .with("comment", "self-test")
+        .with("info", info.clone())
+        .encode();
+
+    let decoded = BencodeTorrent::from_bytes(&torrent).context("Failed to decode vector")?;
+    ensure!(
+        decoded.info.total_length() == Some(5),
+        "Wrong length {:?}",
+        decoded.info.total_length()
+    );
+    ensure!(decoded.info.name() == "hello.txt", "Wrong name");
+    ensure!(
+        decoded.comment.as_deref() == Some("self-test"),
+        "Wrong comment"
+    );
+    ensure!(decoded.info_bytes == info.encode(), "Raw info bytes differ");
+    ensure!(
+        decoded.info_hash() == <[u8; 20]>::from(Sha1::digest(info.encode())),
+        "Info hash differs"
+    );
+    Ok(())
+}
+
+fn bencode_limits() -> Result<()> {
+    let limits = DecodeLimits {
+        max_depth: 2,
+        ..DecodeLimits::default()
+    };
+    ensure!(limits.check(b"lli1eee").is_ok(), "Rejected valid nesting");
+    ensure!(
+        matches!(limits.check(b"llli1eeee"), Err(DecodeError::TooDeep { .. })),
+        "Accepted nesting beyond the limit"
+    );
+    ensure!(
+        matches!(limits.check(b"5:abc"), Err(DecodeError::Malformed { .. })),
+        "Accepted a truncated string"
+    );
+    Ok(())
+}
+
+fn sha1_vectors() -> Result<()> {
+    // FIPS 180 test vectors.
+    let cases: [(&[u8], &str); 2] = [
+        (b"abc", "a9993e364706816aba3e25717850c26c9cd0d89d"),
+        (b"", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+    ];
+    for (input, expected) in cases {
+        let hash = hex(&Sha1::digest(input));
+        ensure!(hash == expected, "SHA-1({input:?}) = {hash}");
+    }
+    Ok(())
+}
+
+fn sha256_vectors() -> Result<()> {
+    let cases: [(&[u8], &str); 2] = [
+        (
+            b"abc",
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+        ),
+        (
+            b"",
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+        ),
+    ];
+    for (input, expected) in cases {
+        let hash = hex(&merkle::sha256(input));
+        ensure!(hash == expected, "SHA-256({input:?}) = {hash}");
+    }
+    Ok(())
+}
+
+fn merkle_tree() -> Result<()> {
+    let leaves = vec![
+        merkle::sha256(b"a"),
+        merkle::sha256(b"b"),
+        merkle::sha256(b"c"),
+    ];
+    let layers = merkle::layers(leaves.clone());
+
+    let left = merkle::sha256(&[leaves[0], leaves[1]].concat());
+    let right = merkle::sha256(&[leaves[2], [0; 32]].concat());
+    let root = merkle::sha256(&[left, right].concat());
+    ensure!(layers.len() == 3, "Expected 3 layers, got {}", layers.len());
+    ensure!(layers[2] == [root], "Root differs");
+    Ok(())
+}
+
+fn peer_handshake() -> Result<()> {
+    let mut handshake = Handshake::new([0xaa; 20], *b"-TT0100-abcdefghijkl");
+    set_extensions_bit(&mut handshake.reserved);
+    set_fast_bit(&mut handshake.reserved);
+    let encoded = handshake.encode();
+
+    let mut expected = vec![19];
+    expected.extend_from_slice(b"BitTorrent protocol");
+    expected.extend_from_slice(&[0, 0, 0, 0, 0, 0x10, 0, 0x04]);
+    expected.extend_from_slice(&[0xaa; 20]);
+    expected.extend_from_slice(b"-TT0100-abcdefghijkl");
+    ensure!(encoded[..] == expected[..], "Encoding differs");
+    ensure!(
+        Handshake::decode(&encoded)? == handshake,
+        "Decoding differs"
+    );
+    ensure!(handshake.supports_extensions(), "Extension bit was not set");
+    ensure!(handshake.supports_fast(), "Fast extension bit was not set");
+
+    let mut garbage = encoded;
+    garbage[1] = b'b';
+    ensure!(
+        Handshake::decode(&garbage).is_err(),
+        "Wrong protocol string was accepted"
+    );
+    Ok(())
+}
+
+fn peer_messages() -> Result<()> {
+    let cases: [(Message, &[u8]); 8] = [
+        (Message::KeepAlive, &[0, 0, 0, 0]),
+        (Message::Interested, &[0, 0, 0, 1, 2]),
+        (Message::Have { piece: 258 }, &[0, 0, 0, 5, 4, 0, 0, 1, 2]),
+        (
+            Message::Request {
+                piece: 1,
+                offset: 0x4000,
+                length: 0x4000,
+            },
+            &[0, 0, 0, 13, 6, 0, 0, 0, 1, 0, 0, 0x40, 0, 0, 0, 0x40, 0],
+        ),
+        (Message::Port(6881), &[0, 0, 0, 3, 9, 0x1a, 0xe1]),
+        (Message::HaveAll, &[0, 0, 0, 1, 0x0e]),
+        (
+            Message::RejectRequest {
+                piece: 1,
+                offset: 0,
+                length: 0x4000,
+            },
+            &[0, 0, 0, 13, 0x10, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0x40, 0],
+        ),
+        (
+            Message::AllowedFast { piece: 7 },
+            &[0, 0, 0, 5, 0x11, 0, 0, 0, 7],
+        ),
+    ];
+    for (message, expected) in cases {
+        ensure!(message.encode() == expected, "{message:?} encoding differs");
+        ensure!(
+            Message::parse(expected, MAX_MESSAGE_LEN)? == Some((message.clone(), expected.len())),
+            "{message:?} decoding differs"
+        );
+    }
+
+    let piece = Message::Piece {
+        piece: 3,
+        offset: 16,
+        data: b"block".to_vec(),
+    };
+    let encoded = piece.encode();
+    ensure!(
+        Message::parse(&encoded[..encoded.len() - 1], MAX_MESSAGE_LEN)?.is_none(),
+        "Incomplete message was decoded"
+    );
+    ensure!(
+        Message::parse(&encoded, MAX_MESSAGE_LEN)? == Some((piece, encoded.len())),
+        "Piece decoding differs"
+    );
+    ensure!(
+        Message::parse(&[0, 0x10, 0, 1], MAX_MESSAGE_LEN).is_err(),
+        "Oversized length was accepted"
+    );
+    ensure!(
+        Message::parse(&[0, 0, 0, 2, 4, 0], MAX_MESSAGE_LEN).is_err(),
+        "Truncated have was accepted"
+    );
+    Ok(())
+}
+
+fn bitfield() -> Result<()> {
+    let mut bitfield = Bitfield::new(10);
+    for piece in [0, 1, 9] {
+        ensure!(bitfield.set_piece(piece), "Piece {piece} was out of range");
+    }
+    ensure!(!bitfield.set_piece(10), "Piece past the end was set");
+    ensure!(bitfield.as_bytes() == [0xc0, 0x40], "Encoding differs");
+    ensure!(
+        bitfield.pieces().collect::<Vec<_>>() == [0, 1, 9],
+        "Iteration differs"
+    );
+    ensure!(
+        Bitfield::from_bytes(&[0xc0, 0x40], 10)? == bitfield,
+        "Decoding differs"
+    );
+    ensure!(
+        Bitfield::from_bytes(&[0xc0, 0x60], 10).is_err(),
+        "Spare bits were accepted"
+    );
+    ensure!(
+        Bitfield::from_bytes(&[0xc0], 10).is_err(),
+        "Short bitfield was accepted"
+    );
+    ensure!(
+        Bitfield::full(10).as_bytes() == [0xff, 0xc0] && Bitfield::full(10).is_complete(),
+        "Full bitfield differs"
+    );
+    Ok(())
+}
+
+fn extension_handshake() -> Result<()> {
+    // Example from BEP 9.
+    let expected: &[u8] = b"d1:md11:ut_metadatai3ee13:metadata_sizei31235ee";
+    let handshake = ExtendedHandshake {
+        extensions: [("ut_metadata".to_string(), 3)].into(),
+        metadata_size: Some(31235),
+        ..ExtendedHandshake::default()
+    };
+
+    ensure!(handshake.encode()? == expected, "Encoding differs");
+    ensure!(
+        ExtendedHandshake::decode(expected)? == handshake,
+        "Decoding differs"
+    );
+    Ok(())
+}
+
+fn metadata_messages() -> Result<()> {
+    // Examples from BEP 9.
+    let request: &[u8] = b"d8:msg_typei0e5:piecei0ee";
+    ensure!(
+        MetadataMessage::Request { piece: 0 }.encode()? == request,
+        "Request encoding differs"
+    );
+
+    let data = MetadataMessage::Data {
+        piece: 0,
+        total_size: 3425,
+        data: b"xxxx".to_vec(),
+    };
+    let encoded = data.encode()?;
+    ensure!(
+        encoded == b"d8:msg_typei1e5:piecei0e10:total_sizei3425eexxxx",
+        "Data encoding differs"
+    );
+    ensure!(
+        MetadataMessage::decode(&encoded)? == data,
+        "Data decoding differs"
+    );
+    Ok(())
+}
+
+fn metadata_exchange() -> Result<()> {
+    let info = Value::dict()
+        .with("name", "a")
+        .with("pieces", vec![7u8; 2 * METADATA_PIECE_SIZE])
+        .encode();
+    let server = MetadataServer::new(Some(info.clone()));
+    let mut assembler = MetadataAssembler::new(Sha1::digest(&info).into(), info.len())?;
+
+    for piece in 0..assembler.piece_count() {
+        let request = MetadataMessage::Request { piece }.encode()?;
+        let reply = server
+            .respond(&MetadataMessage::decode(&request)?)
+            .context("No reply to a request")?;
+        let MetadataMessage::Data { data, .. } = MetadataMessage::decode(&reply.encode()?)? else {
+            bail!("Piece {piece} was rejected");
+        };
+        assembler.receive(piece, data)?;
+    }
+    ensure!(assembler.finish()? == info, "Reassembled metadata differs");
+
+    let past_end = assembler.piece_count();
+    ensure!(
+        server.respond(&MetadataMessage::Request { piece: past_end })
+            == Some(MetadataMessage::Reject { piece: past_end }),
+        "Out of range request was not rejected"
+    );
+    ensure!(
+        MetadataServer::default().respond(&MetadataMessage::Request { piece: 0 })
+            == Some(MetadataMessage::Reject { piece: 0 }),
+        "Request without metadata was not rejected"
+    );
+    Ok(())
+}
+
+fn swarm_auth() -> Result<()> {
+    let info_hash = [5; 20];
+    let (alice, bob) = (*b"-TT0100-aaaaaaaaaaaa", *b"-TT0100-bbbbbbbbbbbb");
+    let secret = SwarmSecret::new("correct horse");
+    let mut ours = SwarmAuth::new(&secret, info_hash, alice, bob)?;
+    let mut theirs = SwarmAuth::new(&secret, info_hash, bob, alice)?;
+
+    let mut handshake = ExtendedHandshake::default();
+    SwarmAuth::advertise(&mut handshake, 5);
+    let challenge = ours.start(&handshake).context("No challenge")?;
+    let response = theirs
+        .handle(&AuthMessage::decode(&challenge.encode()?)?)
+        .context("No response")?;
+    ours.handle(&AuthMessage::decode(&response.encode()?)?);
+    ensure!(ours.may_serve(), "Correct proof was refused");
+
+    let mut impostor = SwarmAuth::new(&SwarmSecret::new("guess"), info_hash, bob, alice)?;
+    let mut ours = SwarmAuth::new(&secret, info_hash, alice, bob)?;
+    let challenge = ours.start(&handshake).context("No challenge")?;
+    let response = impostor.handle(&challenge).context("No response")?;
+    ours.handle(&response);
+    ensure!(
+        ours.state() == AuthState::Failed,
+        "Proof without the secret was accepted"
+    );
+
+    let mut ours = SwarmAuth::new(&secret, info_hash, alice, bob)?;
+    ensure!(
+        ours.start(&ExtendedHandshake::default()).is_none() && !ours.may_serve(),
+        "Peer without tt_auth was accepted"
+    );
+    ensure!(
+        SwarmAuth::new(&secret, info_hash, alice, alice).is_err(),
+        "Peer with our own id was accepted"
+    );
+    Ok(())
+}
+
+fn rc4_vectors() -> Result<()> {
+    let cases: [(&[u8], &[u8], &str); 2] = [
+        (b"Key", b"Plaintext", "bbf316e8d940af0ad3"),
+        (b"Secret", b"Attack at dawn", "45a01f645fc35b383552544b9bf5"),
+    ];
+    for (key, plaintext, expected) in cases {
+        let mut data = plaintext.to_vec();
+        Rc4::new(key).apply(&mut data);
+        ensure!(hex(&data) == expected, "RC4({key:?}) = {}", hex(&data));
+        Rc4::new(key).apply(&mut data);
+        ensure!(data == plaintext, "RC4({key:?}) did not decrypt");
+    }
+    Ok(())
+}
+
+fn mse_key_exchange() -> Result<()> {
+    let (alice, bob) = ([7; 20], [9; 20]);
+    let public = mse::public_key(&alice);
+    ensure!(
+        hex(&public)
+            == "ef73bd17b32dc4378e4f3d64b1b7692650f67cce83e48b902f304e26e139ba934cbd8d8260c5baf4\
+                25e9bafd171b535e67340464866f90768f458ecc206d067eef265ccc96da8b36cf9a573a89cc87e4\
+                53920fda0c48d967703be3f13a6247dc",
+        "Public key is {}",
+        hex(&public)
+    );
+    let secret = mse::shared_secret(&alice, &mse::public_key(&bob))?;
+    ensure!(
+        hex(&secret)
+            == "b917099a54285e158249fac45c1064d53dee3c626c69808fbf903495f239f4cbdbbbd1f1582bdd0d\
+                50209bd162e0334bbf4793e7684e77593dcad97888b791819c66650d2564f44be218fd7eb83adc68\
+                e413051e2f1a28fcb92f18cff6fc81f1",
+        "Shared secret is {}",
+        hex(&secret)
+    );
+    ensure!(
+        mse::shared_secret(&bob, &public)? == secret,
+        "Both sides derived different secrets"
+    );
+    ensure!(
+        mse::shared_secret(&alice, &[0xff; mse::KEY_LEN]).is_err(),
+        "Public key above the prime was accepted"
+    );
+
+    let (mut outgoing, _) = mse::stream_ciphers(&secret, &[5; 20], true);
+    let (_, mut incoming) = mse::stream_ciphers(&secret, &[5; 20], false);
+    let mut keystream = [0; 8];
+    outgoing.apply(&mut keystream);
+    ensure!(
+        hex(&keystream) == "9a5b37b865e47d35",
+        "keyA stream starts with {}",
+        hex(&keystream)
+    );
+    let mut message = *b"BitTorrent protocol";
+    outgoing.apply(&mut message);
+    incoming.apply(&mut [0; 8]);
+    incoming.apply(&mut message);
+    ensure!(
+        &message == b"BitTorrent protocol",
+        "The receiving side did not decrypt"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn every_check_passes() {
+        for (name, result) in super::run() {
+            assert!(result.is_ok(), "{name}: {result:?}");
+        }
+    }
+}
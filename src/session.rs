@@ -0,0 +1,168 @@
+//! Startup check of the files kept in the data directory between runs.
+//!
+//! Damaged files are moved into `corrupt/` instead of stopping the client, so one bad entry
+//! only costs that entry. The check covers the removed-torrent history with its kept
+//! `.torrent` copies, torrents unloaded by the low-memory mode, the block-level resume
+//! files in `resume/`, and the piece hash cache.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use crate::download::hash_cache::{HASH_CACHE_FILE, check_hash_cache};
+use crate::download::partial::check_partials;
+use crate::file::TorrentFile;
+use crate::format::hex;
+use crate::history::{History, unix_now};
+
+/// A damaged file that was moved out of the way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Quarantined {
+    /// Where the file was, relative to the data directory.
+    pub path: PathBuf,
+    pub moved_to: PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Files that were read and found intact.
+    pub intact: usize,
+    pub quarantined: Vec<Quarantined>,
+    /// Damaged files that could not be moved either, left where they are.
+    pub failed: Vec<(PathBuf, String)>,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.quarantined.is_empty() && self.failed.is_empty()
+    }
+}
+
+pub fn dir() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("terrent"))
+}
+
+/// Writes a file only the owner can read, e.g. one holding a secret. The contents go to a
+/// temporary file created that way and replace `path` once complete, so they are never
+/// readable by others, not even for a moment.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    // A leftover temporary file keeps the mode it was created with.
+    let _ = fs::remove_file(&tmp);
+    let mut file = options
+        .open(&tmp)
+        .with_context(|| format!("Failed to create {tmp:?}"))?;
+    file.write_all(contents)
+        .and_then(|()| file.sync_all())
+        .with_context(|| format!("Failed to write {tmp:?}"))?;
+    fs::rename(&tmp, path).with_context(|| format!("Failed to replace {path:?}"))?;
+    Ok(())
+}
+
+/// Checks the data directory, if there is one.
+pub fn check_data_dir() -> IntegrityReport {
+    dir().map(|dir| check(&dir)).unwrap_or_default()
+}
+
+/// Checks every known kind of file below `dir`, quarantining the damaged ones.
+pub fn check(dir: &Path) -> IntegrityReport {
+    let mut report = IntegrityReport::default();
+
+    let history = dir.join("history").join("history.toml");
+    if history.is_file() {
+        check_file(dir, &history, &mut report, |path| {
+            let content = fs::read_to_string(path)?;
+            toml::from_str::<History>(&content)?;
+            Ok(())
+        });
+    }
+    for kept in ["history", "unloaded"] {
+        for path in files_with_extension(&dir.join(kept), "torrent") {
+            check_file(dir, &path, &mut report, check_kept_torrent);
+        }
+    }
+    for path in files_with_extension(&dir.join("resume"), "resume") {
+        check_file(dir, &path, &mut report, check_partials);
+    }
+    let hash_cache = dir.join(HASH_CACHE_FILE);
+    if hash_cache.is_file() {
+        check_file(dir, &hash_cache, &mut report, check_hash_cache);
+    }
+    report
+}
+
+/// Kept copies are named after their info hash, which must still match.
+fn check_kept_torrent(path: &Path) -> Result<()> {
+    let torrent = TorrentFile::open(path)?;
+    let info_hash = hex(&torrent.info_hash());
+    if path.file_stem().and_then(|stem| stem.to_str()) != Some(info_hash.as_str()) {
+        bail!("Info hash {info_hash} does not match the file name");
+    }
+    Ok(())
+}
+
+fn check_file(
+    dir: &Path,
+    path: &Path,
+    report: &mut IntegrityReport,
+    check: impl FnOnce(&Path) -> Result<()>,
+) {
+    let Err(err) = check(path) else {
+        report.intact += 1;
+        return;
+    };
+    let relative = path.strip_prefix(dir).unwrap_or(path).to_path_buf();
+    match quarantine(dir, &relative) {
+        Ok(moved_to) => report.quarantined.push(Quarantined {
+            path: relative,
+            moved_to,
+            reason: format!("{err:#}"),
+        }),
+        Err(move_err) => report
+            .failed
+            .push((relative, format!("{err:#}; {move_err:#}"))),
+    }
+}
+
+/// Moves `relative` into `corrupt/`, flattening its path and stamping it with the time so
+/// repeated failures do not overwrite each other.
+fn quarantine(dir: &Path, relative: &Path) -> Result<PathBuf> {
+    let corrupt = dir.join("corrupt");
+    fs::create_dir_all(&corrupt)?;
+    let flat = relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("_");
+    let target = corrupt.join(format!("{}-{flat}", unix_now()));
+    fs::rename(dir.join(relative), &target)
+        .with_context(|| format!("Failed to move {relative:?} to {target:?}"))?;
+    Ok(target)
+}
+
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == extension))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
@@ -0,0 +1,272 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use crate::metadata::Metadata;
+use crate::peer::PeerTransfer;
+
+/// Transfer counters of a single torrent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TransferStats {
+    pub downloaded: u64,
+    pub uploaded: u64,
+    /// Current download rate in bytes per second.
+    pub download_rate: u64,
+    /// Current upload rate in bytes per second.
+    pub upload_rate: u64,
+}
+
+impl TransferStats {
+    pub fn is_active(&self) -> bool {
+        self.download_rate > 0 || self.upload_rate > 0
+    }
+
+    /// Uploaded over downloaded bytes; `None` until something was downloaded.
+    pub fn ratio(&self) -> Option<f64> {
+        (self.downloaded > 0).then(|| self.uploaded as f64 / self.downloaded as f64)
+    }
+
+    fn add(&mut self, other: &TransferStats) {
+        self.downloaded += other.downloaded;
+        self.uploaded += other.uploaded;
+        self.download_rate += other.download_rate;
+        self.upload_rate += other.upload_rate;
+    }
+}
+
+/// A peer connected for one torrent.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PeerStats {
+    pub addr: SocketAddr,
+    /// Client name from the extension handshake, if the peer sent one.
+    pub client: Option<String>,
+    /// Short connection flags, e.g. `E` for encrypted or `I` for incoming.
+    pub flags: String,
+    pub download_rate: u64,
+    pub upload_rate: u64,
+    /// Bytes of piece data received from the peer on this connection.
+    pub downloaded: u64,
+    /// Bytes of piece data sent to the peer on this connection.
+    pub uploaded: u64,
+    /// Round trip of our block requests; `None` until one was answered.
+    pub latency: Option<Duration>,
+    /// Requests of the peer's waiting in our upload queue.
+    pub upload_queue: usize,
+    /// Pieces with data from the peer that failed their hash check.
+    pub hash_failures: u32,
+}
+
+impl PeerStats {
+    /// A peer that just connected, with nothing transferred yet.
+    pub fn new(addr: SocketAddr, flags: &str) -> Self {
+        Self {
+            addr,
+            client: None,
+            flags: flags.to_string(),
+            download_rate: 0,
+            upload_rate: 0,
+            downloaded: 0,
+            uploaded: 0,
+            latency: None,
+            upload_queue: 0,
+            hash_failures: 0,
+        }
+    }
+
+    /// Takes over the counters, rates and latency of the peer's connection at `now`.
+    pub fn update(&mut self, transfer: &PeerTransfer, now: Instant) {
+        self.download_rate = transfer.download_rate(now);
+        self.upload_rate = transfer.upload_rate(now);
+        self.downloaded = transfer.downloaded();
+        self.uploaded = transfer.uploaded();
+        self.latency = transfer.latency();
+        self.hash_failures = transfer.hash_failures();
+    }
+}
+
+/// Torrent metadata held in memory.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub loaded: usize,
+    /// Torrents unloaded to disk until they are needed again.
+    pub unloaded: usize,
+    /// Estimated bytes used by the loaded ones.
+    pub bytes: u64,
+}
+
+/// Totals of every torrent in a group, e.g. sharing a label.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GroupStats {
+    pub torrents: usize,
+    pub active: usize,
+    pub transfer: TransferStats,
+}
+
+impl GroupStats {
+    pub fn add(&mut self, stats: &TransferStats) {
+        self.torrents += 1;
+        self.active += usize::from(stats.is_active());
+        self.transfer.add(stats);
+    }
+}
+
+/// Rolls torrents up per label; unlabeled torrents are grouped under `None`, which sorts first.
+pub fn by_label(torrents: &[Metadata]) -> BTreeMap<Option<String>, GroupStats> {
+    let mut groups = BTreeMap::<Option<String>, GroupStats>::new();
+    for torrent in torrents {
+        groups
+            .entry(torrent.label.clone())
+            .or_default()
+            .add(&torrent.stats);
+    }
+    groups
+}
+
+/// Totals across every torrent.
+pub fn total(torrents: &[Metadata]) -> GroupStats {
+    let mut total = GroupStats::default();
+    for torrent in torrents {
+        total.add(&torrent.stats);
+    }
+    total
+}
+
+/// Estimated memory used by the metadata of every torrent in the list.
+pub fn memory_usage(torrents: &[Metadata]) -> MemoryUsage {
+    MemoryUsage {
+        loaded: torrents.len(),
+        unloaded: 0,
+        bytes: torrents
+            .iter()
+            .map(|torrent| torrent.heap_size() as u64)
+            .sum(),
+    }
+}
+
+/// Every connected peer with its torrent, ordered by address so an IP connected to several
+/// torrents is listed together.
+pub fn all_peers(torrents: &[Metadata]) -> Vec<(&Metadata, &PeerStats)> {
+    let mut peers = torrents
+        .iter()
+        .flat_map(|torrent| torrent.peers.iter().map(move |peer| (torrent, peer)))
+        .collect::<Vec<_>>();
+    peers.sort_by(|(a_torrent, a), (b_torrent, b)| {
+        (a.addr.ip(), &a_torrent.name, a.addr.port()).cmp(&(
+            b.addr.ip(),
+            &b_torrent.name,
+            b.addr.port(),
+        ))
+    });
+    peers
+}
+
+/// How many torrents each IP address is connected for.
+pub fn torrents_per_ip(torrents: &[Metadata]) -> HashMap<IpAddr, usize> {
+    let mut counts = HashMap::new();
+    for torrent in torrents {
+        let mut ips = torrent
+            .peers
+            .iter()
+            .map(|peer| peer.addr.ip())
+            .collect::<Vec<_>>();
+        ips.sort();
+        ips.dedup();
+        for ip in ips {
+            *counts.entry(ip).or_default() += 1;
+        }
+    }
+    counts
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TorrentSnapshot {
+    downloaded: u64,
+    uploaded: u64,
+    complete: bool,
+    /// Failing trackers with their error at the time.
+    errors: BTreeSet<(String, String)>,
+}
+
+/// Counters of every torrent at one moment, to tell what changed since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    taken: Instant,
+    torrents: HashMap<[u8; 20], TorrentSnapshot>,
+}
+
+/// What happened between a [`Snapshot`] and now.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ChangeSummary {
+    pub elapsed: Duration,
+    pub downloaded: u64,
+    pub uploaded: u64,
+    /// Names of torrents that finished.
+    pub completed: Vec<String>,
+    /// Torrent name and message of every error that was not there before.
+    pub errors: Vec<(String, String)>,
+}
+
+impl ChangeSummary {
+    pub fn is_empty(&self) -> bool {
+        self.downloaded == 0
+            && self.uploaded == 0
+            && self.completed.is_empty()
+            && self.errors.is_empty()
+    }
+}
+
+impl Snapshot {
+    pub fn take(torrents: &[Metadata], now: Instant) -> Self {
+        Self {
+            taken: now,
+            torrents: torrents
+                .iter()
+                .map(|torrent| (torrent.info_hash, Self::torrent(torrent)))
+                .collect(),
+        }
+    }
+
+    /// Changes since the snapshot; torrents added in the meantime count from zero.
+    pub fn changes(&self, torrents: &[Metadata], now: Instant) -> ChangeSummary {
+        let mut summary = ChangeSummary {
+            elapsed: now.saturating_duration_since(self.taken),
+            ..ChangeSummary::default()
+        };
+        for torrent in torrents {
+            let current = Self::torrent(torrent);
+            let before = self.torrents.get(&torrent.info_hash);
+
+            summary.downloaded += current
+                .downloaded
+                .saturating_sub(before.map_or(0, |before| before.downloaded));
+            summary.uploaded += current
+                .uploaded
+                .saturating_sub(before.map_or(0, |before| before.uploaded));
+            if current.complete && !before.is_some_and(|before| before.complete) {
+                summary.completed.push(torrent.name.clone());
+            }
+            for error in &current.errors {
+                if !before.is_some_and(|before| before.errors.contains(error)) {
+                    let (url, message) = error;
+                    summary
+                        .errors
+                        .push((torrent.name.clone(), format!("{url}: {message}")));
+                }
+            }
+        }
+        summary
+    }
+
+    fn torrent(torrent: &Metadata) -> TorrentSnapshot {
+        TorrentSnapshot {
+            downloaded: torrent.stats.downloaded,
+            uploaded: torrent.stats.uploaded,
+            complete: torrent.left == Some(0),
+            errors: torrent
+                .trackers
+                .iter()
+                .filter_map(|state| Some((state.url.clone(), state.last_error.clone()?)))
+                .collect(),
+        }
+    }
+}
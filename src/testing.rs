@@ -0,0 +1,253 @@
+//! Synthetic torrents with matching in-memory data, for tests that need real piece hashes
+//! without hand-written bencode.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use sha1::{Digest, Sha1};
+
+use crate::file::TorrentFile;
+use crate::file::encoder::Value;
+
+/// A generated torrent and the content it describes.
+#[derive(Debug, Clone)]
+pub struct SyntheticTorrent {
+    pub torrent: TorrentFile,
+    /// The encoded `.torrent` file.
+    pub encoded: Vec<u8>,
+    /// The concatenated piece stream.
+    pub data: Vec<u8>,
+}
+
+/// Builds a [`SyntheticTorrent`]; one file of 64 KiB in 16 KiB pieces unless told otherwise.
+#[derive(Debug, Clone)]
+pub struct SyntheticBuilder {
+    name: String,
+    piece_length: u64,
+    /// Paths below the torrent name with their lengths; `None` makes a single-file torrent.
+    files: Option<Vec<(Vec<String>, u64)>>,
+    length: u64,
+    seed: u64,
+    private: bool,
+    trackers: Vec<String>,
+}
+
+impl SyntheticTorrent {
+    pub fn builder(name: impl Into<String>) -> SyntheticBuilder {
+        SyntheticBuilder {
+            name: name.into(),
+            piece_length: 16 * 1024,
+            files: None,
+            length: 64 * 1024,
+            seed: 1,
+            private: false,
+            trackers: Vec::new(),
+        }
+    }
+
+    /// Single-file torrent of `length` bytes.
+    pub fn single(name: impl Into<String>, length: u64, piece_length: u64) -> Self {
+        Self::builder(name)
+            .length(length)
+            .piece_length(piece_length)
+            .build()
+    }
+
+    /// Bytes of piece `index`.
+    pub fn piece(&self, index: usize) -> &[u8] {
+        let piece_length = self.torrent.piece_length() as usize;
+        let start = index * piece_length;
+        &self.data[start..(start + piece_length).min(self.data.len())]
+    }
+
+    /// Writes the content under `root` the way it would be downloaded.
+    pub fn write_to(&self, root: &Path) -> Result<()> {
+        for file in self.torrent.files() {
+            let path = root.join(&file.path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let start = file.offset as usize;
+            fs::write(&path, &self.data[start..start + file.length as usize])?;
+        }
+        Ok(())
+    }
+}
+
+impl SyntheticBuilder {
+    /// Any non-zero length; torrents that are not a power of two only carry a warning.
+    pub fn piece_length(mut self, piece_length: u64) -> Self {
+        self.piece_length = piece_length;
+        self
+    }
+
+    /// Total length of a single-file torrent.
+    pub fn length(mut self, length: u64) -> Self {
+        self.length = length;
+        self
+    }
+
+    /// Adds a file, making this a multi-file torrent; `path` is relative to the torrent name.
+    pub fn file(mut self, path: &str, length: u64) -> Self {
+        let components = path.split('/').map(str::to_string).collect();
+        self.files
+            .get_or_insert_default()
+            .push((components, length));
+        self
+    }
+
+    /// Seed of the generated content; different seeds give different info hashes.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    pub fn tracker(mut self, url: impl Into<String>) -> Self {
+        self.trackers.push(url.into());
+        self
+    }
+
+    pub fn build(&self) -> SyntheticTorrent {
+        let length = match &self.files {
+            Some(files) => files.iter().map(|(_, length)| length).sum(),
+            None => self.length,
+        };
+        let data = content(self.seed, length as usize);
+        let pieces = data
+            .chunks(self.piece_length as usize)
+            .flat_map(|piece| <[u8; 20]>::from(Sha1::digest(piece)))
+            .collect::<Vec<_>>();
+
+        let mut info = Value::dict()
+            .with("name", self.name.as_str())
+            .with("piece length", self.piece_length)
+            .with("pieces", pieces);
+        match &self.files {
+            Some(files) => {
+                let entries = files
+                    .iter()
+                    .map(|(path, length)| {
+                        Value::dict()
+                            .with("length", *length)
+                            .with("path", path.clone())
+                    })
+                    .collect::<Vec<_>>();
+                info.insert("files", entries);
+            }
+            None => info.insert("length", length),
+        }
+        if self.private {
+            info.insert("private", 1i64);
+        }
+
+        let mut torrent = Value::dict().with("info", info);
+        if let Some(announce) = self.trackers.first() {
+            torrent.insert("announce", announce.as_str());
+        }
+        if self.trackers.len() > 1 {
+            let tiers = self
+                .trackers
+                .iter()
+                .map(|tracker| vec![tracker.as_str()])
+                .collect::<Vec<_>>();
+            torrent.insert("announce-list", tiers);
+        }
+
+        let encoded = torrent.encode();
+        let torrent =
+            TorrentFile::from_bytes(&encoded).expect("synthetic torrents are always valid");
+        SyntheticTorrent {
+            torrent,
+            encoded,
+            data,
+        }
+    }
+}
+
+/// Deterministic pseudo-random bytes (xorshift64), so failures reproduce.
+fn content(seed: u64, length: usize) -> Vec<u8> {
+    let mut state = seed.max(1);
+    let mut data = Vec::with_capacity(length + 8);
+    while data.len() < length {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        data.extend_from_slice(&state.to_le_bytes());
+    }
+    data.truncate(length);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::process;
+
+    use super::*;
+    use crate::download::inspect::read_piece;
+    use crate::download::partial::{BLOCK_SIZE, PartialPiece};
+
+    #[test]
+    fn piece_hashes_match_the_data() {
+        let synthetic = SyntheticTorrent::single("a.bin", 100_000, 16 * 1024);
+        let torrent = &synthetic.torrent;
+        assert_eq!(torrent.total_length(), 100_000);
+        assert_eq!(torrent.piece_count(), 7);
+        for (index, expected) in torrent.piece_hashes().iter().enumerate() {
+            assert_eq!(
+                <[u8; 20]>::from(Sha1::digest(synthetic.piece(index))),
+                *expected
+            );
+        }
+        assert_eq!(synthetic.piece(6).len(), 100_000 - 6 * 16 * 1024);
+    }
+
+    #[test]
+    fn seeds_give_distinct_torrents() {
+        let a = SyntheticTorrent::builder("a").seed(1).build();
+        let b = SyntheticTorrent::builder("a").seed(2).build();
+        assert_ne!(a.torrent.info_hash(), b.torrent.info_hash());
+        assert_eq!(
+            a.torrent.info_hash(),
+            SyntheticTorrent::builder("a").build().torrent.info_hash()
+        );
+    }
+
+    #[test]
+    fn multi_file_pieces_read_back_from_disk() {
+        let synthetic = SyntheticTorrent::builder("d")
+            .piece_length(32 * 1024)
+            .file("a.bin", 10_000)
+            .file("sub/b.bin", 50_000)
+            .file("c.bin", 1)
+            .build();
+        let root = env::temp_dir().join(format!("terrent-synthetic-{}", process::id()));
+        synthetic.write_to(&root).unwrap();
+
+        for index in 0..synthetic.torrent.piece_count() {
+            let piece = read_piece(&synthetic.torrent, &root, index).unwrap();
+            assert_eq!(piece, synthetic.piece(index));
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn blocks_assemble_into_verified_pieces() {
+        let synthetic = SyntheticTorrent::single("a.bin", 40_000, 32 * 1024);
+        for (index, expected) in synthetic.torrent.piece_hashes().iter().enumerate() {
+            let data = synthetic.piece(index);
+            let mut piece = PartialPiece::new(index, data.len());
+            for (block, bytes) in data.chunks(BLOCK_SIZE).enumerate().rev() {
+                piece.add_block(block * BLOCK_SIZE, bytes).unwrap();
+            }
+            assert!(piece.is_complete());
+            assert!(piece.verify(expected));
+        }
+    }
+}
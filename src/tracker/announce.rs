@@ -0,0 +1,698 @@
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use reqwest::{Client, Response};
+use serde::Deserialize;
+use serde_bytes::ByteBuf;
+use tokio::net::lookup_host;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+use url::Url;
+
+use super::scrape::ScrapeStats;
+use super::udp::ConnectionIds;
+use crate::file::bencode::some;
+use crate::peer::address::{COMPACT_V4_LEN, COMPACT_V6_LEN};
+use crate::peer::external::ip_from_bytes;
+use crate::peer::{Peer, PeerId};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnnounceEvent {
+    /// Regular re-announce at the tracker's interval.
+    #[default]
+    None,
+    Started,
+    Stopped,
+    Completed,
+}
+
+impl AnnounceEvent {
+    pub(super) fn as_str(&self) -> Option<&'static str> {
+        match self {
+            AnnounceEvent::None => None,
+            AnnounceEvent::Started => Some("started"),
+            AnnounceEvent::Stopped => Some("stopped"),
+            AnnounceEvent::Completed => Some("completed"),
+        }
+    }
+}
+
+/// Tracker responses larger than this are refused; even thousands of peers in dictionary
+/// form fit easily.
+const MAX_RESPONSE_SIZE: usize = 2 * 1024 * 1024;
+/// Peers named by host instead of address that are looked up per announce.
+const MAX_PEER_HOSTS: usize = 32;
+/// Longest the lookups of those peers may take together.
+const PEER_LOOKUP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Every parameter [`AnnounceRequest`] may add to the announce URL.
+const QUERY_KEYS: &[&str] = &[
+    "info_hash",
+    "peer_id",
+    "port",
+    "uploaded",
+    "downloaded",
+    "left",
+    "compact",
+    "no_peer_id",
+    "event",
+    "numwant",
+    "trackerid",
+    "key",
+    "ip",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceRequest {
+    pub info_hash: [u8; 20],
+    pub peer_id: PeerId,
+    pub port: u16,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: AnnounceEvent,
+    /// Peers wanted; the tracker picks its own default when unset.
+    pub numwant: Option<u32>,
+    /// `tracker id` from an earlier response, echoed back as trackers expect.
+    pub tracker_id: Option<String>,
+    /// Per-session value that lets trackers recognize us after an IP change.
+    pub key: Option<u32>,
+    /// Asks trackers that ignore `compact` to leave peer ids out of the peer list.
+    pub no_peer_id: bool,
+    /// Address to hand out instead of the one the request comes from.
+    pub ip: Option<IpAddr>,
+}
+
+/// Random `key` for this session; like the peer id it only has to be unlikely to collide.
+pub fn generate_key() -> Result<u32> {
+    getrandom::u32().context("No randomness for a tracker key")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceResponse {
+    pub interval: Duration,
+    /// Trackers may ask clients not to re-announce sooner than this.
+    pub min_interval: Option<Duration>,
+    /// Seeders in the swarm.
+    pub complete: Option<u64>,
+    /// Leechers in the swarm.
+    pub incomplete: Option<u64>,
+    /// Completed downloads the tracker has seen, which only some trackers announce.
+    pub downloaded: Option<u64>,
+    pub tracker_id: Option<String>,
+    pub peers: Vec<Peer>,
+    /// Non-fatal notice from the tracker, worth showing to the user.
+    pub warning: Option<String>,
+    /// Our address as the tracker sees it (BEP 24).
+    pub external_ip: Option<IpAddr>,
+    /// Announce URL the tracker redirected us to; later announces should go there.
+    pub redirected_to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawPeer {
+    #[serde(default, rename = "peer id", deserialize_with = "some")]
+    peer_id: Option<ByteBuf>,
+    /// An IP literal or, from some trackers, a host name.
+    ip: String,
+    port: u16,
+}
+
+/// A dictionary-model peer given by host name; [`announce`] resolves it.
+#[derive(Debug)]
+struct HostPeer {
+    host: String,
+    port: u16,
+    id: Option<PeerId>,
+}
+
+/// Trackers send peers as a compact string (BEP 23) or, for old clients, a list of dicts.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum RawPeers {
+    Compact(ByteBuf),
+    List(Vec<RawPeer>),
+}
+
+impl Default for RawPeers {
+    fn default() -> Self {
+        RawPeers::Compact(ByteBuf::new())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawAnnounceResponse {
+    #[serde(default, rename = "external ip", deserialize_with = "some")]
+    external_ip: Option<ByteBuf>,
+    #[serde(default, rename = "failure reason", deserialize_with = "some")]
+    failure_reason: Option<ByteBuf>,
+    #[serde(default, rename = "warning message", deserialize_with = "some")]
+    warning_message: Option<ByteBuf>,
+    #[serde(default, deserialize_with = "some")]
+    interval: Option<u64>,
+    #[serde(default, rename = "min interval", deserialize_with = "some")]
+    min_interval: Option<u64>,
+    #[serde(default, rename = "tracker id", deserialize_with = "some")]
+    tracker_id: Option<ByteBuf>,
+    #[serde(default, deserialize_with = "some")]
+    complete: Option<u64>,
+    #[serde(default, deserialize_with = "some")]
+    incomplete: Option<u64>,
+    #[serde(default, deserialize_with = "some")]
+    downloaded: Option<u64>,
+    #[serde(default)]
+    peers: RawPeers,
+    /// IPv6 peers, always compact (BEP 7).
+    #[serde(default, deserialize_with = "some")]
+    peers6: Option<ByteBuf>,
+}
+
+impl AnnounceResponse {
+    /// Stores the swarm counts of this announce in `swarm`, keeping the completed count from
+    /// an earlier scrape when the tracker left it out.
+    pub fn update_swarm(&self, swarm: &mut Option<ScrapeStats>) {
+        let (Some(complete), Some(incomplete)) = (self.complete, self.incomplete) else {
+            return;
+        };
+        let downloaded = self
+            .downloaded
+            .or(swarm.map(|swarm| swarm.downloaded))
+            .unwrap_or(0);
+        *swarm = Some(ScrapeStats {
+            complete,
+            incomplete,
+            downloaded,
+        });
+    }
+
+    /// Decodes a tracker's bencoded reply; a `failure reason` becomes the error.
+    ///
+    /// Peers given by host name are left out, as resolving them needs DNS; [`announce`]
+    /// resolves and adds them.
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        Ok(Self::decode_with_hosts(bytes)?.0)
+    }
+
+    fn decode_with_hosts(bytes: &[u8]) -> Result<(Self, Vec<HostPeer>)> {
+        let raw: RawAnnounceResponse =
+            bendy::serde::from_bytes(bytes).context("Malformed tracker response")?;
+        if let Some(reason) = raw.failure_reason {
+            bail!("Tracker failure: {}", String::from_utf8_lossy(&reason));
+        }
+
+        let mut hosts = Vec::new();
+        let mut peers = match raw.peers {
+            RawPeers::Compact(bytes) => Peer::compact_list(&bytes, COMPACT_V4_LEN)
+                .with_context(|| format!("Compact peer list has {} bytes", bytes.len()))?,
+            RawPeers::List(peers) => peers
+                .into_iter()
+                .filter_map(|peer| {
+                    let id = peer
+                        .peer_id
+                        .and_then(|id| PeerId::try_from(id.as_slice()).ok());
+                    match peer.ip.parse::<IpAddr>() {
+                        Ok(ip) => Some(Peer {
+                            addr: SocketAddr::new(ip, peer.port),
+                            id,
+                        }),
+                        Err(_) => {
+                            hosts.push(HostPeer {
+                                host: peer.ip,
+                                port: peer.port,
+                                id,
+                            });
+                            None
+                        }
+                    }
+                })
+                .collect(),
+        };
+
+        if let Some(bytes) = raw.peers6 {
+            peers
+                .extend(Peer::compact_list(&bytes, COMPACT_V6_LEN).with_context(|| {
+                    format!("Compact IPv6 peer list has {} bytes", bytes.len())
+                })?);
+        }
+
+        let response = AnnounceResponse {
+            interval: Duration::from_secs(
+                raw.interval.context("Tracker response has no interval")?,
+            ),
+            min_interval: raw.min_interval.map(Duration::from_secs),
+            complete: raw.complete,
+            incomplete: raw.incomplete,
+            downloaded: raw.downloaded,
+            tracker_id: raw
+                .tracker_id
+                .map(|id| String::from_utf8_lossy(&id).into_owned()),
+            peers,
+            warning: raw
+                .warning_message
+                .map(|warning| String::from_utf8_lossy(&warning).into_owned()),
+            external_ip: raw.external_ip.and_then(|ip| ip_from_bytes(&ip)),
+            redirected_to: None,
+        };
+        Ok((response, hosts))
+    }
+}
+
+/// Builds the GET URL for an HTTP tracker announce (BEP 3).
+///
+/// Parameters already in the announce URL, such as a private tracker's passkey, are kept and
+/// the announce parameters appended after them.
+pub fn build_tracker_url(announce: &str, request: &AnnounceRequest) -> Result<Url> {
+    let mut url =
+        Url::parse(announce).with_context(|| format!("Invalid tracker URL {announce}"))?;
+    let parameters = encode_query(&request.query_pairs());
+    let query = match url.query().filter(|query| !query.is_empty()) {
+        Some(existing) => format!("{}&{parameters}", existing.trim_end_matches('&')),
+        None => parameters,
+    };
+    url.set_query(Some(&query));
+    Ok(url)
+}
+
+/// The announce URL behind a request URL: its query without the parameters
+/// [`build_tracker_url`] adds, as a tracker that redirects usually keeps them.
+fn announce_base(url: &Url) -> String {
+    let mut base = url.clone();
+    let kept = url
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| {
+            let key = pair.split_once('=').map_or(*pair, |(key, _)| key);
+            !pair.is_empty() && !QUERY_KEYS.contains(&key)
+        })
+        .collect::<Vec<_>>();
+    base.set_query((!kept.is_empty()).then(|| kept.join("&")).as_deref());
+    base.to_string()
+}
+
+impl AnnounceRequest {
+    /// Query parameters as raw bytes; binary values such as the info hash must not be
+    /// encoded before they reach [`encode_query`].
+    fn query_pairs(&self) -> Vec<(&'static str, Vec<u8>)> {
+        let number = |value: u64| value.to_string().into_bytes();
+        let mut pairs = vec![
+            ("info_hash", self.info_hash.to_vec()),
+            ("peer_id", self.peer_id.to_vec()),
+            ("port", number(self.port.into())),
+            ("uploaded", number(self.uploaded)),
+            ("downloaded", number(self.downloaded)),
+            ("left", number(self.left)),
+            ("compact", number(1)),
+        ];
+        if self.no_peer_id {
+            pairs.push(("no_peer_id", number(1)));
+        }
+        if let Some(event) = self.event.as_str() {
+            pairs.push(("event", event.as_bytes().to_vec()));
+        }
+        if let Some(numwant) = self.numwant {
+            pairs.push(("numwant", number(numwant.into())));
+        }
+        if let Some(tracker_id) = &self.tracker_id {
+            pairs.push(("trackerid", tracker_id.as_bytes().to_vec()));
+        }
+        if let Some(key) = self.key {
+            pairs.push(("key", format!("{key:08X}").into_bytes()));
+        }
+        if let Some(ip) = self.ip {
+            pairs.push(("ip", ip.to_string().into_bytes()));
+        }
+        pairs
+    }
+}
+
+/// Joins the pairs into a query string, percent-encoding every value exactly once.
+///
+/// `form_urlencoded` is deliberately not used: it expects text, and feeding it an already
+/// encoded info hash escapes the `%` signs a second time.
+pub(super) fn encode_query(pairs: &[(&str, Vec<u8>)]) -> String {
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{key}={}", percent_encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Percent-encodes everything except RFC 3986 unreserved characters.
+fn percent_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() * 3);
+    for byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(char::from(*byte))
+            }
+            _ => encoded.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    encoded
+}
+
+/// Reads a tracker's response body, giving up as soon as it grows past
+/// [`MAX_RESPONSE_SIZE`].
+pub(super) async fn read_response(mut response: Response) -> Result<Vec<u8>> {
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_RESPONSE_SIZE as u64)
+    {
+        bail!("Tracker response is larger than {MAX_RESPONSE_SIZE} bytes");
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if bytes.len() + chunk.len() > MAX_RESPONSE_SIZE {
+            bail!("Tracker response is larger than {MAX_RESPONSE_SIZE} bytes");
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(bytes)
+}
+
+/// Announces to an HTTP(S) or UDP tracker, or a WebTorrent one with the `webtorrent` feature,
+/// and returns its reply. `udp` keeps the connection ids UDP trackers hand out.
+///
+/// When `client` goes through a proxy, `proxied` keeps everything else off the direct
+/// route: UDP trackers are refused and peers the tracker names by host are dropped instead
+/// of resolved, since either would reveal our address or what we download.
+pub async fn announce(
+    client: &Client,
+    udp: &ConnectionIds,
+    announce: &str,
+    request: &AnnounceRequest,
+    proxied: bool,
+) -> Result<AnnounceResponse> {
+    #[cfg(feature = "webtorrent")]
+    if announce.starts_with("ws://") || announce.starts_with("wss://") {
+        return super::websocket::announce(client, announce, request).await;
+    }
+    if announce.starts_with("udp://") {
+        if proxied {
+            bail!("UDP tracker {announce} cannot be reached through the proxy");
+        }
+        let url =
+            Url::parse(announce).with_context(|| format!("Invalid tracker URL {announce}"))?;
+        return super::udp::announce(&url, udp, request)
+            .await
+            .with_context(|| format!("Announce to {announce} failed"));
+    }
+
+    let url = build_tracker_url(announce, request)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!("Unsupported tracker scheme {}", url.scheme());
+    }
+
+    // The client follows redirects and gives up on loops and overly long chains.
+    let reply = client
+        .get(url.clone())
+        .send()
+        .await
+        .with_context(|| format!("Failed to announce to {announce}"))?
+        .error_for_status()?;
+    let redirected_to = (*reply.url() != url).then(|| announce_base(reply.url()));
+    let bytes = read_response(reply)
+        .await
+        .with_context(|| format!("Failed to read the response of {announce}"))?;
+    let (mut response, hosts) = AnnounceResponse::decode_with_hosts(&bytes)
+        .with_context(|| format!("Announce to {announce} failed"))?;
+    response.redirected_to = redirected_to.filter(|base| base != announce);
+
+    if proxied {
+        return Ok(response);
+    }
+    // A peer whose name does not resolve in time is just skipped, like any unreachable
+    // peer. Only the first few are looked up, all at once, so a reply full of host names
+    // cannot stall the announce.
+    let mut lookups = JoinSet::new();
+    for host in hosts.into_iter().take(MAX_PEER_HOSTS) {
+        lookups.spawn(async move {
+            let addr = lookup_host((host.host.as_str(), host.port))
+                .await
+                .ok()?
+                .next()?;
+            Some(Peer { addr, id: host.id })
+        });
+    }
+    let _ = timeout(PEER_LOOKUP_TIMEOUT, async {
+        while let Some(result) = lookups.join_next().await {
+            if let Ok(Some(peer)) = result {
+                response.peers.push(peer);
+            }
+        }
+    })
+    .await;
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Info hash from the BEP 3 tracker example.
+    const INFO_HASH: [u8; 20] = [
+        0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf1, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef,
+        0x12, 0x34, 0x56, 0x78, 0x9a,
+    ];
+    const ENCODED_INFO_HASH: &str = "%124Vx%9A%BC%DE%F1%23Eg%89%AB%CD%EF%124Vx%9A";
+
+    fn request() -> AnnounceRequest {
+        AnnounceRequest {
+            info_hash: INFO_HASH,
+            peer_id: *b"-TT0100-abcdefghijkl",
+            port: 6881,
+            uploaded: 0,
+            downloaded: 0,
+            left: 1024,
+            event: AnnounceEvent::Started,
+            numwant: None,
+            tracker_id: None,
+            key: None,
+            no_peer_id: false,
+            ip: None,
+        }
+    }
+
+    #[test]
+    fn percent_encodes_bep3_example() {
+        assert_eq!(percent_encode(&INFO_HASH), ENCODED_INFO_HASH);
+    }
+
+    #[test]
+    fn keeps_unreserved_and_escapes_the_rest() {
+        assert_eq!(percent_encode(b"aZ09-._~"), "aZ09-._~");
+        assert_eq!(percent_encode(b" %&=+/"), "%20%25%26%3D%2B%2F");
+        assert_eq!(percent_encode(&[0x00, 0xff]), "%00%FF");
+    }
+
+    #[test]
+    fn encodes_info_hash_exactly_once() {
+        let url = build_tracker_url("http://tracker.example/announce", &request()).unwrap();
+        assert_eq!(
+            url.as_str(),
+            format!(
+                "http://tracker.example/announce?info_hash={ENCODED_INFO_HASH}\
+                 &peer_id=-TT0100-abcdefghijkl&port=6881&uploaded=0&downloaded=0&left=1024\
+                 &compact=1&event=started"
+            )
+        );
+        assert!(!url.as_str().contains("%25"));
+    }
+
+    #[test]
+    fn keeps_existing_query_parameters() {
+        let url = build_tracker_url(
+            "http://tracker.example/announce.php?passkey=XYZ",
+            &request(),
+        )
+        .unwrap();
+        let query = url.query().unwrap();
+        assert!(query.starts_with(&format!("passkey=XYZ&info_hash={ENCODED_INFO_HASH}&")));
+        assert_eq!(url.path(), "/announce.php");
+
+        let url = build_tracker_url("http://tracker.example/announce?", &request()).unwrap();
+        assert!(url.query().unwrap().starts_with("info_hash="));
+    }
+
+    #[test]
+    fn strips_request_parameters_from_redirect_targets() {
+        let url = build_tracker_url(
+            "https://mirror.example/tracker/announce?passkey=XYZ",
+            &AnnounceRequest {
+                key: Some(0xbeef),
+                ..request()
+            },
+        )
+        .unwrap();
+        assert_eq!(
+            announce_base(&url),
+            "https://mirror.example/tracker/announce?passkey=XYZ"
+        );
+
+        let url = build_tracker_url("http://mirror.example/announce", &request()).unwrap();
+        assert_eq!(announce_base(&url), "http://mirror.example/announce");
+    }
+
+    #[test]
+    fn appends_optional_parameters() {
+        let url = build_tracker_url(
+            "http://tracker.example/announce",
+            &AnnounceRequest {
+                event: AnnounceEvent::None,
+                numwant: Some(80),
+                tracker_id: Some("a b".to_string()),
+                key: Some(0xbeef),
+                no_peer_id: true,
+                ip: Some("2001:db8::1".parse().unwrap()),
+                ..request()
+            },
+        )
+        .unwrap();
+        assert!(url.query().unwrap().ends_with(
+            "&left=1024&compact=1&no_peer_id=1&numwant=80&trackerid=a%20b&key=0000BEEF\
+             &ip=2001%3Adb8%3A%3A1"
+        ));
+    }
+
+    #[test]
+    fn round_trips_binary_info_hash() {
+        let hashes = [[0u8; 20], [0xff; 20], *b"%%%%%%%%%%%%%%%%%%%%", INFO_HASH];
+        for info_hash in hashes {
+            let url = build_tracker_url(
+                "http://tracker.example/announce",
+                &AnnounceRequest {
+                    info_hash,
+                    ..request()
+                },
+            )
+            .unwrap();
+            let value = url
+                .query()
+                .unwrap()
+                .split('&')
+                .find_map(|pair| pair.strip_prefix("info_hash="))
+                .unwrap();
+            assert_eq!(percent_decode(value), info_hash);
+        }
+    }
+
+    fn addrs(response: &AnnounceResponse) -> Vec<String> {
+        response
+            .peers
+            .iter()
+            .map(|peer| peer.addr.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn decodes_compact_v4_and_v6_peers() {
+        let mut body = b"d8:intervali1800e5:peers6:\x7f\x00\x00\x01\x1a\xe16:peers618:".to_vec();
+        body.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        body.extend_from_slice(&6882u16.to_be_bytes());
+        body.push(b'e');
+
+        let response = AnnounceResponse::decode(&body).unwrap();
+        assert_eq!(addrs(&response), ["127.0.0.1:6881", "[2001:db8::1]:6882"]);
+    }
+
+    #[test]
+    fn decodes_times_completed() {
+        let body = b"d8:completei5e10:downloadedi42e10:incompletei3e8:intervali1800e5:peers0:e";
+        let response = AnnounceResponse::decode(body).unwrap();
+        assert_eq!(response.downloaded, Some(42));
+        let mut swarm = None;
+        response.update_swarm(&mut swarm);
+        assert_eq!(
+            swarm,
+            Some(ScrapeStats {
+                complete: 5,
+                incomplete: 3,
+                downloaded: 42,
+            })
+        );
+
+        let body = b"d8:completei6e10:incompletei2e8:intervali1800e5:peers0:e";
+        AnnounceResponse::decode(body)
+            .unwrap()
+            .update_swarm(&mut swarm);
+        assert_eq!(
+            swarm.map(|swarm| (swarm.complete, swarm.downloaded)),
+            Some((6, 42))
+        );
+    }
+
+    #[test]
+    fn decodes_external_ip() {
+        let body = b"d11:external ip4:\xcb\x00\x71\x078:intervali1800e5:peers0:e";
+        let response = AnnounceResponse::decode(body).unwrap();
+        assert_eq!(response.external_ip, Some("203.0.113.7".parse().unwrap()));
+
+        let body = b"d11:external ip3:abc8:intervali1800e5:peers0:e";
+        assert_eq!(AnnounceResponse::decode(body).unwrap().external_ip, None);
+    }
+
+    #[test]
+    fn decodes_v6_literals_in_dictionary_peers() {
+        let mut body = b"d8:intervali1800e5:peersld2:ip3:::14:porti6881eed2:ip8:10.0.0.14:porti6882eee6:peers618:".to_vec();
+        body.extend_from_slice(&[0xfe, 0x80, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        body.extend_from_slice(&51413u16.to_be_bytes());
+        body.push(b'e');
+
+        let response = AnnounceResponse::decode(&body).unwrap();
+        assert_eq!(
+            addrs(&response),
+            ["[::1]:6881", "10.0.0.1:6882", "[fe80::2]:51413"]
+        );
+    }
+
+    #[test]
+    fn rejects_truncated_v6_peers() {
+        let body = b"d8:intervali1800e5:peers0:6:peers66:\x7f\x00\x00\x01\x1a\xe1e";
+        let err = AnnounceResponse::decode(body).unwrap_err();
+        assert!(err.to_string().contains("IPv6"), "{err}");
+    }
+
+    fn percent_decode(value: &str) -> Vec<u8> {
+        let bytes = value.as_bytes();
+        let mut decoded = Vec::new();
+        let mut index = 0;
+        while index < bytes.len() {
+            if bytes[index] == b'%' {
+                let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap();
+                decoded.push(u8::from_str_radix(hex, 16).unwrap());
+                index += 3;
+            } else {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+        decoded
+    }
+
+    #[tokio::test]
+    async fn refuses_oversized_responses() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let _ = stream.read(&mut [0; 4096]).await;
+            let chunk = vec![b'0'; 64 * 1024];
+            let _ = stream
+                .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+                .await;
+            // Without a length, only counting the body can stop it.
+            while stream.write_all(&chunk).await.is_ok() {}
+        });
+
+        let response = Client::new()
+            .get(format!("http://{addr}/announce"))
+            .send()
+            .await
+            .unwrap();
+        assert!(read_response(response).await.is_err());
+    }
+}
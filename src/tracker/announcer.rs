@@ -0,0 +1,341 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+
+use reqwest::Client;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep_until, timeout};
+
+use super::lifecycle::AnnounceLifecycle;
+use super::schedule::AnnounceSchedule;
+use super::tiers::{TrackerState, TrackerTiers};
+use super::udp::ConnectionIds;
+use crate::metadata::Metadata;
+use crate::peer::{ExternalSource, Peer};
+
+/// How long a `stopped` announce waits for the torrent to resume, which makes both it and
+/// the following `started` unnecessary. Quick restarts would otherwise announce twice in a
+/// row, which trackers with a `min interval` may count as hammering.
+pub const STOP_COALESCE_WINDOW: Duration = Duration::from_secs(30);
+/// Longest the final `stopped` may take across all tiers before the task ends anyway.
+pub const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the announcer needs to know about its torrent for the next announce.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TorrentProgress {
+    pub metadata: Metadata,
+    /// Bytes still missing.
+    pub left: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnnouncerCommand {
+    /// Announce as soon as the tracker's `min interval` allows.
+    AnnounceNow,
+    /// Announce to this tracker right away, even while it is backed off.
+    AnnounceTo(String),
+    /// Add a tracker as a new last tier.
+    AddTracker(String),
+    RemoveTracker(String),
+    /// Stop announcing while the torrent is paused. `stopped` goes out once
+    /// [`STOP_COALESCE_WINDOW`] passes without a [`Self::Resume`].
+    Pause,
+    /// Announce again after a pause; `started` only if `stopped` was sent.
+    Resume,
+    /// Send `stopped` and end the task.
+    Stop,
+}
+
+/// Where a paused announcer is in sending `stopped`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Paused {
+    No,
+    /// `stopped` is held back until then.
+    Holding(Instant),
+    Stopped,
+}
+
+/// Sent after every announce attempt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnouncerUpdate {
+    pub trackers: Vec<TrackerState>,
+    /// Peers no earlier announce returned.
+    pub new_peers: Vec<Peer>,
+    pub next_announce: Option<Instant>,
+    pub error: Option<String>,
+    /// The edited `announce-list`, after a tracker was added or removed, to be stored with
+    /// the torrent.
+    pub announce_list: Option<Vec<Vec<String>>>,
+    /// Our address as reported by the tracker that answered.
+    pub external_ip: Option<(IpAddr, ExternalSource)>,
+}
+
+/// Re-announces one torrent in the background, honoring `interval` and `min interval`.
+pub struct Announcer {
+    client: Client,
+    udp: ConnectionIds,
+    tiers: TrackerTiers,
+    lifecycle: AnnounceLifecycle,
+    schedule: AnnounceSchedule,
+    peers: BTreeSet<SocketAddr>,
+    paused: Paused,
+}
+
+impl Announcer {
+    pub fn new(client: Client, tiers: TrackerTiers, lifecycle: AnnounceLifecycle) -> Self {
+        Self {
+            client,
+            udp: ConnectionIds::default(),
+            tiers,
+            lifecycle,
+            schedule: AnnounceSchedule::default(),
+            peers: BTreeSet::new(),
+            paused: Paused::No,
+        }
+    }
+
+    /// Shares the connection ids of UDP trackers with the announcers of other torrents.
+    pub fn with_udp(mut self, ids: ConnectionIds) -> Self {
+        self.udp = ids;
+        self
+    }
+
+    /// Runs until [`AnnouncerCommand::Stop`] arrives or the command sender is dropped.
+    ///
+    /// A stop is noticed even while an announce is in progress; the final `stopped` gets
+    /// at most [`STOP_TIMEOUT`] so it cannot hold up shutdown.
+    pub async fn run(
+        mut self,
+        mut progress: watch::Receiver<TorrentProgress>,
+        mut commands: mpsc::Receiver<AnnouncerCommand>,
+        updates: mpsc::Sender<AnnouncerUpdate>,
+    ) {
+        // Commands that arrived during an announce, handled before any new ones.
+        let mut deferred = VecDeque::new();
+        let mut progress_open = true;
+        loop {
+            let due = match self.paused {
+                Paused::No => Some(self.schedule.next().unwrap_or_else(Instant::now)),
+                Paused::Holding(until) => Some(until),
+                Paused::Stopped => None,
+            };
+            let next_command = async {
+                match deferred.pop_front() {
+                    Some(command) => Some(command),
+                    None => commands.recv().await,
+                }
+            };
+            let update = tokio::select! {
+                _ = sleep_until(due.unwrap_or_else(Instant::now).into()), if due.is_some() => {
+                    let snapshot = progress.borrow().clone();
+                    let stopping = self.paused != Paused::No;
+                    if stopping {
+                        self.paused = Paused::Stopped;
+                    }
+                    let announce = self.announce(&snapshot, None, stopping);
+                    match until_stopped(announce, &mut commands, &mut deferred).await {
+                        Some(update) => update,
+                        None => break,
+                    }
+                }
+                changed = progress.changed(), if progress_open => {
+                    progress_open = changed.is_ok();
+                    // `completed` goes out as soon as the last byte is in.
+                    if progress.borrow().left == 0
+                        && self.lifecycle.is_started()
+                        && !self.lifecycle.is_completed()
+                        && self.paused == Paused::No
+                    {
+                        self.schedule.announce_at(Instant::now());
+                    }
+                    continue;
+                }
+                command = next_command => match command {
+                    Some(AnnouncerCommand::AnnounceNow) => {
+                        self.schedule.request_now(Instant::now());
+                        continue;
+                    }
+                    Some(AnnouncerCommand::Pause) => {
+                        if self.paused == Paused::No {
+                            self.paused = if self.lifecycle.is_started() {
+                                Paused::Holding(Instant::now() + STOP_COALESCE_WINDOW)
+                            } else {
+                                Paused::Stopped
+                            };
+                        }
+                        continue;
+                    }
+                    Some(AnnouncerCommand::Resume) => {
+                        // A pause still holding `stopped` back ends without any announce.
+                        if self.paused == Paused::Stopped {
+                            self.schedule.request_now(Instant::now());
+                        }
+                        self.paused = Paused::No;
+                        continue;
+                    }
+                    Some(AnnouncerCommand::AnnounceTo(url)) => {
+                        let snapshot = progress.borrow().clone();
+                        let announce = self.announce(&snapshot, Some(&url), false);
+                        match until_stopped(announce, &mut commands, &mut deferred).await {
+                            Some(update) => update,
+                            None => break,
+                        }
+                    }
+                    Some(AnnouncerCommand::AddTracker(url)) => {
+                        let added = self.tiers.add_tracker(url);
+                        self.edited(added)
+                    }
+                    Some(AnnouncerCommand::RemoveTracker(url)) => {
+                        let removed = self.tiers.remove_tracker(&url);
+                        self.edited(removed)
+                    }
+                    Some(AnnouncerCommand::Stop) | None => break,
+                },
+            };
+            if updates.send(update).await.is_err() {
+                break;
+            }
+        }
+
+        if self.lifecycle.is_started() {
+            let snapshot = progress.borrow().clone();
+            if let Ok(update) = timeout(STOP_TIMEOUT, self.announce(&snapshot, None, true)).await {
+                // The receiver may already be gone during shutdown.
+                let _ = updates.send(update).await;
+            }
+        }
+    }
+
+    /// Announces to the tiers in order, or only to `target`.
+    async fn announce(
+        &mut self,
+        progress: &TorrentProgress,
+        target: Option<&str>,
+        stopping: bool,
+    ) -> AnnouncerUpdate {
+        let request = self
+            .lifecycle
+            .request(&progress.metadata, progress.left, stopping);
+        let result = match target {
+            Some(url) => self
+                .tiers
+                .announce_to(&self.client, &self.udp, url, &request)
+                .await
+                .map(|response| (url.to_string(), response)),
+            None => self.tiers.announce(&self.client, &self.udp, &request).await,
+        };
+
+        let now = Instant::now();
+        let (new_peers, error, external_ip) = match result {
+            Ok((url, response)) => {
+                self.lifecycle.record(&request);
+                self.schedule.record(&response, now);
+                let new_peers = response
+                    .peers
+                    .iter()
+                    .copied()
+                    .filter(|peer| self.peers.insert(peer.addr))
+                    .collect();
+                let external_ip = response
+                    .external_ip
+                    .map(|ip| (ip, ExternalSource::Tracker(url)));
+                (new_peers, None, external_ip)
+            }
+            Err(err) => {
+                self.schedule.record_failure(self.tiers.next_retry(), now);
+                (Vec::new(), Some(format!("{err:#}")), None)
+            }
+        };
+
+        AnnouncerUpdate {
+            trackers: self
+                .tiers
+                .states()
+                .map(|(_, state)| state.clone())
+                .collect(),
+            new_peers,
+            next_announce: self.schedule.next(),
+            error,
+            announce_list: None,
+            external_ip,
+        }
+    }
+
+    /// Update after the tracker list changed, or did not because the edit was a no-op.
+    fn edited(&self, changed: bool) -> AnnouncerUpdate {
+        AnnouncerUpdate {
+            trackers: self
+                .tiers
+                .states()
+                .map(|(_, state)| state.clone())
+                .collect(),
+            new_peers: Vec::new(),
+            next_announce: self.schedule.next(),
+            error: None,
+            announce_list: changed.then(|| self.tiers.urls()),
+            external_ip: None,
+        }
+    }
+}
+
+/// Runs `announce` unless a stop arrives first, in which case `None` is returned. Other
+/// commands received meanwhile are kept in `deferred`.
+async fn until_stopped(
+    announce: impl Future<Output = AnnouncerUpdate>,
+    commands: &mut mpsc::Receiver<AnnouncerCommand>,
+    deferred: &mut VecDeque<AnnouncerCommand>,
+) -> Option<AnnouncerUpdate> {
+    tokio::pin!(announce);
+    loop {
+        tokio::select! {
+            update = &mut announce => return Some(update),
+            command = commands.recv() => match command {
+                Some(AnnouncerCommand::Stop) | None => return None,
+                Some(command) => deferred.push_back(command),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+
+    #[tokio::test]
+    async fn stop_ends_an_announce_the_tracker_never_answers() {
+        // Accepts the connection and then stays silent.
+        let tracker = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/announce", tracker.local_addr().unwrap());
+        let silent = tokio::spawn(async move {
+            let (stream, _) = tracker.accept().await.unwrap();
+            std::future::pending::<()>().await;
+            drop(stream);
+        });
+
+        let torrent = SyntheticTorrent::single("announced", 1024, 16 * 1024).torrent;
+        let (_progress, progress_rx) = watch::channel(TorrentProgress {
+            metadata: Metadata::from(&torrent),
+            left: 1024,
+        });
+        let (commands, commands_rx) = mpsc::channel(4);
+        let (updates, mut updates_rx) = mpsc::channel(4);
+        let announcer = Announcer::new(
+            Client::new(),
+            TrackerTiers::new(vec![vec![url]]),
+            AnnounceLifecycle::new(*b"-TT0100-abcdefghijkl", 6881),
+        );
+        let running = tokio::spawn(announcer.run(progress_rx, commands_rx, updates));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        commands.send(AnnouncerCommand::Stop).await.unwrap();
+        timeout(Duration::from_secs(5), running)
+            .await
+            .expect("Stop was not noticed during the announce")
+            .unwrap();
+        assert!(updates_rx.recv().await.is_none());
+        silent.abort();
+    }
+}
@@ -0,0 +1,89 @@
+//! Tracker lists changed while running, kept in the data directory so added and removed
+//! trackers outlive the session.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::format::hex;
+use crate::metadata::Metadata;
+use crate::session;
+
+/// Announce URLs by hex info hash, for every torrent whose trackers were edited.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrackerEdits {
+    #[serde(default)]
+    pub torrents: BTreeMap<String, Vec<String>>,
+}
+
+impl TrackerEdits {
+    pub fn path() -> Option<PathBuf> {
+        session::dir().map(|dir| dir.join("trackers.toml"))
+    }
+
+    /// Loads the edits, which are empty until the first tracker is added or removed.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content =
+            fs::read_to_string(&path).with_context(|| format!("Failed to read {path:?}"))?;
+        toml::from_str(&content).with_context(|| format!("Invalid tracker list {path:?}"))
+    }
+
+    /// Saves the edits; announce URLs may carry private tracker passkeys, so on Unix only
+    /// the owner can read them.
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path().context("No data directory available")?;
+        session::write_private(&path, toml::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Remembers the current trackers of `torrent`.
+    pub fn record(&mut self, torrent: &Metadata) {
+        self.torrents
+            .insert(hex(&torrent.info_hash), torrent.announce.clone());
+    }
+
+    /// Replaces the trackers of `torrent` with its edited ones, if any.
+    pub fn apply(&self, torrent: &mut Metadata) {
+        if let Some(announce) = self.torrents.get(&hex(&torrent.info_hash)) {
+            torrent.announce = announce.clone();
+        }
+    }
+
+    /// Records the trackers of `torrent` in the saved edits.
+    pub fn persist(torrent: &Metadata) -> Result<()> {
+        let mut edits = Self::load()?;
+        edits.record(torrent);
+        edits.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+
+    #[test]
+    fn edited_trackers_replace_the_torrents_own() {
+        let synthetic = SyntheticTorrent::builder("edited")
+            .tracker("http://old.example/announce")
+            .build();
+        let mut torrent = Metadata::from(&synthetic.torrent);
+        torrent.announce = vec!["http://new.example/announce".to_string()];
+        let mut edits = TrackerEdits::default();
+        edits.record(&torrent);
+
+        let encoded = toml::to_string_pretty(&edits).unwrap();
+        let edits: TrackerEdits = toml::from_str(&encoded).unwrap();
+        let mut reloaded = Metadata::from(&synthetic.torrent);
+        edits.apply(&mut reloaded);
+        assert_eq!(reloaded.announce, torrent.announce);
+    }
+}
@@ -0,0 +1,105 @@
+use std::collections::HashMap;
+
+use url::Url;
+
+/// Consecutive failures after which a scheme is considered persistently broken for a host.
+const DEFAULT_FAILURE_THRESHOLD: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TrackerScheme {
+    Udp,
+    Http,
+}
+
+impl TrackerScheme {
+    fn of(url: &Url) -> Option<Self> {
+        match url.scheme() {
+            "udp" => Some(TrackerScheme::Udp),
+            "http" | "https" => Some(TrackerScheme::Http),
+            _ => None,
+        }
+    }
+}
+
+/// Learns, per tracker host, whether its UDP or HTTP endpoint works for this session.
+///
+/// Torrents often list `udp://` and `http(s)://` variants of the same tracker; once one of them
+/// fails repeatedly while the other answers, announces should go to the working one first.
+#[derive(Debug, Clone)]
+pub struct SchemeFallback {
+    failure_threshold: u32,
+    failures: HashMap<(String, TrackerScheme), u32>,
+    preferred: HashMap<String, TrackerScheme>,
+}
+
+impl Default for SchemeFallback {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD)
+    }
+}
+
+impl SchemeFallback {
+    pub fn new(failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            failures: HashMap::new(),
+            preferred: HashMap::new(),
+        }
+    }
+
+    pub fn record_success(&mut self, url: &str) {
+        if let Some((host, scheme)) = host_and_scheme(url) {
+            self.failures.remove(&(host.clone(), scheme));
+            self.preferred.insert(host, scheme);
+        }
+    }
+
+    pub fn record_failure(&mut self, url: &str) {
+        let Some((host, scheme)) = host_and_scheme(url) else {
+            return;
+        };
+
+        let failures = self.failures.entry((host.clone(), scheme)).or_default();
+        *failures += 1;
+        if *failures >= self.failure_threshold && self.preferred.get(&host) == Some(&scheme) {
+            self.preferred.remove(&host);
+        }
+    }
+
+    pub fn preferred(&self, host: &str) -> Option<TrackerScheme> {
+        self.preferred.get(host).copied()
+    }
+
+    pub fn is_failing(&self, url: &str) -> bool {
+        host_and_scheme(url).is_some_and(|key| {
+            self.failures.get(&key).copied().unwrap_or_default() >= self.failure_threshold
+        })
+    }
+
+    /// Orders announce URLs so that, per host, the working scheme is tried first and
+    /// persistently failing variants last. Relative order is otherwise preserved.
+    pub fn order<'a>(&self, urls: &'a [String]) -> Vec<&'a String> {
+        let rank = |url: &String| -> u8 {
+            let Some((host, scheme)) = host_and_scheme(url) else {
+                return 1;
+            };
+            if self.is_failing(url) {
+                2
+            } else if self.preferred.get(&host) == Some(&scheme) {
+                0
+            } else {
+                1
+            }
+        };
+
+        let mut ordered: Vec<&String> = urls.iter().collect();
+        ordered.sort_by_key(|url| rank(url));
+        ordered
+    }
+}
+
+fn host_and_scheme(url: &str) -> Option<(String, TrackerScheme)> {
+    let url = Url::parse(url).ok()?;
+    let scheme = TrackerScheme::of(&url)?;
+    Some((url.host_str()?.to_ascii_lowercase(), scheme))
+}
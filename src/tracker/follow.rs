@@ -0,0 +1,185 @@
+//! Announces of a torrent held in a shared session, e.g. the daemon's: its announcer starts
+//! once the torrent is first active, then pauses and resumes with it and stops when the
+//! torrent is removed.
+
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch};
+
+use super::announcer::{Announcer, AnnouncerCommand, TorrentProgress};
+use crate::metadata::Metadata;
+use crate::queue::TorrentState;
+
+/// How often the torrent's state and progress are read from the session.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+/// Commands and updates buffered between the torrent and its announcer.
+const QUEUE_LEN: usize = 16;
+
+/// Runs `announcer` for the torrent with `info_hash` in `torrents` until the torrent is
+/// removed, forwarding `commands` to it and keeping the torrent's tracker states current.
+///
+/// Commands sent before the torrent was first active are dropped, since no announce is due.
+pub async fn follow_torrent(
+    info_hash: [u8; 20],
+    torrents: Arc<Mutex<Vec<Metadata>>>,
+    announcer: Announcer,
+    mut commands: mpsc::Receiver<AnnouncerCommand>,
+) {
+    let mut announcer = Some(announcer);
+    let mut running: Option<(
+        watch::Sender<TorrentProgress>,
+        mpsc::Sender<AnnouncerCommand>,
+    )> = None;
+    let mut paused = false;
+    let (updates_tx, mut updates) = mpsc::channel(QUEUE_LEN);
+    let mut refresh = tokio::time::interval(REFRESH_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = refresh.tick() => {
+                let torrent = lock(&torrents)
+                    .iter()
+                    .find(|torrent| torrent.info_hash == info_hash)
+                    .cloned();
+                let Some(torrent) = torrent else {
+                    if let Some((_, commands)) = &running {
+                        let _ = commands.send(AnnouncerCommand::Stop).await;
+                    }
+                    return;
+                };
+                let active = torrent.state == TorrentState::Active;
+                let progress = TorrentProgress {
+                    left: torrent.left.unwrap_or(torrent.length),
+                    metadata: torrent,
+                };
+                match &running {
+                    Some((current, commands)) => {
+                        // The announcer only wakes up for a finished download.
+                        current.send_if_modified(|current| {
+                            let finished = current.left != progress.left;
+                            *current = progress;
+                            finished
+                        });
+                        if active == paused {
+                            paused = !active;
+                            let command = if active {
+                                AnnouncerCommand::Resume
+                            } else {
+                                AnnouncerCommand::Pause
+                            };
+                            let _ = commands.send(command).await;
+                        }
+                    }
+                    None if active => {
+                        let Some(announcer) = announcer.take() else {
+                            return;
+                        };
+                        let (progress, progress_rx) = watch::channel(progress);
+                        let (commands, commands_rx) = mpsc::channel(QUEUE_LEN);
+                        tokio::spawn(announcer.run(progress_rx, commands_rx, updates_tx.clone()));
+                        running = Some((progress, commands));
+                    }
+                    None => {}
+                }
+            }
+            Some(command) = commands.recv() => {
+                if let Some((_, announcer)) = &running {
+                    let _ = announcer.send(command).await;
+                }
+            }
+            Some(update) = updates.recv() => {
+                // Only inbound peers are served so far; announcing is what lets them find
+                // us, and the peers trackers return are not dialed.
+                if let Some(torrent) = lock(&torrents)
+                    .iter_mut()
+                    .find(|torrent| torrent.info_hash == info_hash)
+                {
+                    torrent.trackers = update.trackers;
+                }
+            }
+        }
+    }
+}
+
+fn lock(torrents: &Mutex<Vec<Metadata>>) -> MutexGuard<'_, Vec<Metadata>> {
+    torrents
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::testing::SyntheticTorrent;
+    use crate::tracker::{AnnounceLifecycle, TrackerStatus, TrackerTiers};
+
+    /// Answers every announce with an empty peer list; returns its URL and the requests.
+    async fn fake_tracker() -> (String, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/announce", listener.local_addr().unwrap());
+        let (requests, received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0; 4096];
+                let read = stream.read(&mut buffer).await.unwrap();
+                let _ = requests.send(String::from_utf8_lossy(&buffer[..read]).into_owned());
+                let body = b"d8:intervali1800e5:peers0:e";
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                stream.write_all(head.as_bytes()).await.unwrap();
+                stream.write_all(body).await.unwrap();
+            }
+        });
+        (url, received)
+    }
+
+    #[tokio::test]
+    async fn announces_once_the_torrent_starts_and_stops_when_removed() {
+        let (url, mut requests) = fake_tracker().await;
+        let synthetic = SyntheticTorrent::single("followed", 1024, 16 * 1024);
+        let mut metadata = Metadata::from(&synthetic.torrent);
+        metadata.state = TorrentState::Stopped;
+        let info_hash = metadata.info_hash;
+        let torrents = Arc::new(Mutex::new(vec![metadata]));
+        let announcer = Announcer::new(
+            Client::new(),
+            TrackerTiers::new(vec![vec![url.clone()]]),
+            AnnounceLifecycle::new(*b"-TT0100-abcdefghijkl", 6881),
+        );
+        let (_commands, commands_rx) = mpsc::channel(4);
+        let following = tokio::spawn(follow_torrent(
+            info_hash,
+            Arc::clone(&torrents),
+            announcer,
+            commands_rx,
+        ));
+
+        tokio::time::sleep(REFRESH_INTERVAL + Duration::from_millis(200)).await;
+        assert!(requests.try_recv().is_err());
+
+        torrents.lock().unwrap()[0].state = TorrentState::Active;
+        assert!(requests.recv().await.unwrap().contains("event=started"));
+        let mut working = false;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            working = torrents.lock().unwrap()[0]
+                .trackers
+                .iter()
+                .any(|tracker| tracker.url == url && tracker.status == TrackerStatus::Working);
+            if working {
+                break;
+            }
+        }
+        assert!(working);
+
+        torrents.lock().unwrap().clear();
+        assert!(requests.recv().await.unwrap().contains("event=stopped"));
+        following.await.unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use super::announce::{AnnounceEvent, AnnounceRequest};
+use crate::metadata::Metadata;
+use crate::peer::PeerId;
+
+/// Optional announce parameters sent to every tracker, and how the tiers are walked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnnounceConfig {
+    /// Peers to ask for; trackers pick their own default, usually 50, when unset.
+    pub numwant: Option<u32>,
+    pub no_peer_id: bool,
+    /// External address to announce, for when trackers see a different one, e.g. behind
+    /// a proxy or with several uplinks.
+    pub ip: Option<IpAddr>,
+    /// Announce to every tier at once and merge their peers instead of falling back from
+    /// tier to tier; see [`TrackerTiers::with_all_tiers`](super::TrackerTiers::with_all_tiers).
+    pub all_tiers: bool,
+}
+
+impl Default for AnnounceConfig {
+    fn default() -> Self {
+        Self {
+            numwant: None,
+            no_peer_id: true,
+            ip: None,
+            all_tiers: false,
+        }
+    }
+}
+
+/// Which announce events one tracker has acknowledged for one torrent.
+///
+/// Events are only marked as sent once the tracker answered, so a failed `started` or
+/// `completed` announce is retried with the same event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnnounceLifecycle {
+    peer_id: PeerId,
+    port: u16,
+    started: bool,
+    /// Set once `completed` was sent, or when the torrent was already complete on `started`;
+    /// trackers count a completion only for downloads they saw happen.
+    completed: bool,
+    key: Option<u32>,
+    config: AnnounceConfig,
+}
+
+impl AnnounceLifecycle {
+    pub fn new(peer_id: PeerId, port: u16) -> Self {
+        Self {
+            peer_id,
+            port,
+            started: false,
+            completed: false,
+            key: None,
+            config: AnnounceConfig::default(),
+        }
+    }
+
+    /// Sends the session's `key`, shared by every torrent and tracker.
+    pub fn with_key(mut self, key: u32) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    pub fn with_config(mut self, config: AnnounceConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn is_started(&self) -> bool {
+        self.started
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.completed
+    }
+
+    /// Next announce for `torrent`, with `left` bytes still missing; `stopping` on pause or
+    /// shutdown.
+    pub fn request(&self, torrent: &Metadata, left: u64, stopping: bool) -> AnnounceRequest {
+        let event = if stopping {
+            AnnounceEvent::Stopped
+        } else if !self.started {
+            AnnounceEvent::Started
+        } else if left == 0 && !self.completed {
+            AnnounceEvent::Completed
+        } else {
+            AnnounceEvent::None
+        };
+
+        AnnounceRequest {
+            info_hash: torrent.info_hash,
+            peer_id: self.peer_id,
+            port: self.port,
+            uploaded: torrent.stats.uploaded,
+            downloaded: torrent.stats.downloaded,
+            left,
+            event,
+            // A stopping client has no use for peers.
+            numwant: if stopping {
+                Some(0)
+            } else {
+                self.config.numwant
+            },
+            // Each tracker's own id is added by the tiers, which know where the request goes.
+            tracker_id: None,
+            key: self.key,
+            no_peer_id: self.config.no_peer_id,
+            ip: self.config.ip,
+        }
+    }
+
+    /// Records a successful announce.
+    pub fn record(&mut self, request: &AnnounceRequest) {
+        match request.event {
+            AnnounceEvent::Started => {
+                self.started = true;
+                self.completed = request.left == 0;
+            }
+            AnnounceEvent::Completed => self.completed = true,
+            // A later restart is a new session for the tracker.
+            AnnounceEvent::Stopped => self.started = false,
+            AnnounceEvent::None => {}
+        }
+    }
+}
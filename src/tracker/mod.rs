@@ -0,0 +1,26 @@
+pub mod announce;
+pub mod announcer;
+pub mod edits;
+pub mod fallback;
+pub mod follow;
+pub mod lifecycle;
+pub mod rewrite;
+pub mod schedule;
+pub mod scrape;
+pub mod tiers;
+pub mod udp;
+#[cfg(feature = "webtorrent")]
+pub mod websocket;
+
+pub use announce::{
+    AnnounceEvent, AnnounceRequest, AnnounceResponse, announce, build_tracker_url, generate_key,
+};
+pub use announcer::{Announcer, AnnouncerCommand, AnnouncerUpdate, TorrentProgress};
+pub use edits::TrackerEdits;
+pub use fallback::{SchemeFallback, TrackerScheme};
+pub use follow::follow_torrent;
+pub use lifecycle::{AnnounceConfig, AnnounceLifecycle};
+pub use rewrite::RewriteRule;
+pub use schedule::AnnounceSchedule;
+pub use scrape::{ScrapeStats, scrape};
+pub use tiers::{AnnouncePace, TrackerState, TrackerStatus, TrackerTiers};
@@ -0,0 +1,72 @@
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// Replaces `from` at the start of matching announce URLs with `to`, e.g.
+/// `from = "http://tracker.example/"` and `to = "https://tracker.example/"` to force HTTPS.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewriteRule {
+    pub from: String,
+    pub to: String,
+}
+
+impl RewriteRule {
+    pub fn apply(&self, url: &str) -> Option<String> {
+        url.strip_prefix(&self.from)
+            .map(|rest| format!("{}{rest}", self.to))
+    }
+}
+
+/// `url` after the first rule that matches it, or `None` when no rule changes it.
+pub fn rewrite(url: &str, rules: &[RewriteRule]) -> Option<String> {
+    rules
+        .iter()
+        .find_map(|rule| rule.apply(url))
+        .filter(|rewritten| rewritten != url)
+}
+
+/// Moves `url` from the host `from` to `to`, keeping scheme, port, path, and any passkey, as
+/// needed when a tracker migrates domains. Hosts compare ignoring case; `None` when `url`
+/// is on another host or `to` is not a valid host.
+pub fn replace_host(url: &str, from: &str, to: &str) -> Option<String> {
+    let mut parsed = Url::parse(url).ok()?;
+    if !parsed
+        .host_str()
+        .is_some_and(|host| host.eq_ignore_ascii_case(from))
+    {
+        return None;
+    }
+    parsed.set_host(Some(to)).ok()?;
+    Some(parsed.to_string())
+}
+
+/// Applies [`replace_host`] to every URL in place, returning how many changed.
+pub fn replace_hosts(urls: &mut [String], from: &str, to: &str) -> usize {
+    let mut replaced = 0;
+    for url in urls {
+        if let Some(moved) = replace_host(url, from, to) {
+            *url = moved;
+            replaced += 1;
+        }
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_only_the_matching_host() {
+        let mut urls = vec![
+            "https://Old.example:8443/abc123/announce".to_string(),
+            "udp://other.example:6969/announce".to_string(),
+        ];
+        assert_eq!(replace_hosts(&mut urls, "old.example", "new.example"), 1);
+        assert_eq!(urls[0], "https://new.example:8443/abc123/announce");
+        assert_eq!(urls[1], "udp://other.example:6969/announce");
+        assert_eq!(
+            replace_host("http://old.example/announce", "old.example", "bad host"),
+            None
+        );
+    }
+}
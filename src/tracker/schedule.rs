@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+use super::announce::AnnounceResponse;
+
+/// Interval used until a tracker tells us its own.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(30 * 60);
+
+/// When the next regular or manual announce of one torrent may go out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AnnounceSchedule {
+    interval: Duration,
+    min_interval: Option<Duration>,
+    last: Option<Instant>,
+    next: Option<Instant>,
+}
+
+impl Default for AnnounceSchedule {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            min_interval: None,
+            last: None,
+            next: None,
+        }
+    }
+}
+
+impl AnnounceSchedule {
+    /// Next regular announce; `None` until the first one went out, which is due right away.
+    pub fn next(&self) -> Option<Instant> {
+        self.next
+    }
+
+    pub fn is_due(&self, now: Instant) -> bool {
+        self.next.is_none_or(|next| next <= now)
+    }
+
+    /// Earliest time the tracker's `min interval` allows another announce.
+    pub fn earliest(&self) -> Option<Instant> {
+        Some(self.last? + self.min_interval?)
+    }
+
+    /// Moves the next announce forward for an "announce now", but never before `earliest`.
+    pub fn request_now(&mut self, now: Instant) -> Instant {
+        let at = self.earliest().map_or(now, |earliest| earliest.max(now));
+        self.next = Some(self.next.map_or(at, |next| next.min(at)));
+        at
+    }
+
+    /// Makes the next announce due at `now`, for events the tracker should hear about
+    /// right away, such as `completed`.
+    pub fn announce_at(&mut self, now: Instant) {
+        self.next = Some(now);
+    }
+
+    pub fn record(&mut self, response: &AnnounceResponse, now: Instant) {
+        self.interval = response.interval;
+        self.min_interval = response.min_interval;
+        self.last = Some(now);
+        self.next = Some(now + self.interval);
+    }
+
+    /// Schedules the retry after no tracker answered; `retry_at` is when one may be asked again.
+    pub fn record_failure(&mut self, retry_at: Option<Instant>, now: Instant) {
+        self.next = Some(retry_at.unwrap_or(now + self.interval));
+    }
+}
@@ -0,0 +1,105 @@
+use anyhow::{Context, Result, bail};
+use bendy::decoding::{Decoder, Object};
+use reqwest::Client;
+use url::Url;
+
+use super::announce::{encode_query, read_response};
+use super::udp::ConnectionIds;
+
+/// Swarm counts a tracker reports for one torrent.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ScrapeStats {
+    /// Seeders.
+    pub complete: u64,
+    /// Leechers.
+    pub incomplete: u64,
+    /// Completed downloads the tracker has seen.
+    pub downloaded: u64,
+}
+
+/// Asks the tracker behind `announce` for swarm counts without announcing.
+pub async fn scrape(
+    client: &Client,
+    udp: &ConnectionIds,
+    announce: &str,
+    info_hash: [u8; 20],
+) -> Result<ScrapeStats> {
+    let url = Url::parse(announce).with_context(|| format!("Invalid tracker URL {announce}"))?;
+    match url.scheme() {
+        "http" | "https" => scrape_http(client, &url, info_hash).await,
+        "udp" => super::udp::scrape(&url, udp, info_hash).await,
+        #[cfg(feature = "webtorrent")]
+        "ws" | "wss" => super::websocket::scrape(client, announce, info_hash).await,
+        scheme => bail!("Unsupported tracker scheme {scheme}"),
+    }
+    .with_context(|| format!("Scrape of {announce} failed"))
+}
+
+/// Scrape URL of an HTTP tracker: the `announce` at the start of the last path segment
+/// replaced by `scrape` (BEP 48); trackers without that segment do not support scraping.
+pub fn scrape_url(announce: &Url) -> Result<Url> {
+    let path = announce.path();
+    let (directory, last) = path.rsplit_once('/').unwrap_or(("", path));
+    let Some(rest) = last.strip_prefix("announce") else {
+        bail!("Tracker does not support scraping");
+    };
+
+    let mut url = announce.clone();
+    url.set_path(&format!("{directory}/scrape{rest}"));
+    Ok(url)
+}
+
+async fn scrape_http(client: &Client, announce: &Url, info_hash: [u8; 20]) -> Result<ScrapeStats> {
+    let mut url = scrape_url(announce)?;
+    let parameters = encode_query(&[("info_hash", info_hash.to_vec())]);
+    let query = match url.query().filter(|query| !query.is_empty()) {
+        Some(existing) => format!("{}&{parameters}", existing.trim_end_matches('&')),
+        None => parameters,
+    };
+    url.set_query(Some(&query));
+
+    let response = client.get(url).send().await?.error_for_status()?;
+    let bytes = read_response(response).await?;
+    decode_http(&bytes, &info_hash)
+}
+
+/// Reads `files[info_hash]` from a bencoded scrape response.
+fn decode_http(bytes: &[u8], info_hash: &[u8; 20]) -> Result<ScrapeStats> {
+    let mut decoder = Decoder::new(bytes);
+    let mut response = decoder
+        .next_object()?
+        .context("Empty scrape response")?
+        .try_into_dictionary()?;
+
+    while let Some((key, value)) = response.next_pair()? {
+        match (key, value) {
+            (b"failure reason", Object::Bytes(reason)) => {
+                bail!("Tracker failure: {}", String::from_utf8_lossy(reason))
+            }
+            (b"files", Object::Dict(mut files)) => {
+                while let Some((hash, file)) = files.next_pair()? {
+                    if hash != info_hash {
+                        continue;
+                    }
+                    let mut file = file.try_into_dictionary()?;
+                    let mut stats = ScrapeStats::default();
+                    while let Some((key, value)) = file.next_pair()? {
+                        let Object::Integer(value) = value else {
+                            continue;
+                        };
+                        let value = value.parse().unwrap_or_default();
+                        match key {
+                            b"complete" => stats.complete = value,
+                            b"incomplete" => stats.incomplete = value,
+                            b"downloaded" => stats.downloaded = value,
+                            _ => {}
+                        }
+                    }
+                    return Ok(stats);
+                }
+            }
+            _ => {}
+        }
+    }
+    bail!("Tracker does not know this torrent")
+}
@@ -0,0 +1,591 @@
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use reqwest::Client;
+use tokio::task::JoinSet;
+use tokio::time::timeout;
+
+use super::announce::{AnnounceRequest, AnnounceResponse, announce};
+use super::rewrite::{RewriteRule, rewrite};
+use super::udp::ConnectionIds;
+
+/// Wait before the first retry of a failed tracker; doubles with every further failure.
+const INITIAL_BACKOFF: Duration = Duration::from_secs(15);
+const MAX_BACKOFF: Duration = Duration::from_secs(30 * 60);
+/// Longest one tracker may take to answer before the next one is tried.
+pub const ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TrackerStatus {
+    #[default]
+    NotContacted,
+    Working,
+    Failing,
+}
+
+/// How the gap between our last two announces to a tracker compares to its `min interval`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AnnouncePace {
+    #[default]
+    Relaxed,
+    /// Within a quarter of the limit; a few more early announces may get us banned.
+    Close,
+    /// Sooner than the tracker allows.
+    TooSoon,
+}
+
+/// Announce status of one tracker, kept for display.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TrackerState {
+    pub url: String,
+    pub status: TrackerStatus,
+    /// Why the last announce failed, including the tracker's `failure reason`.
+    pub last_error: Option<String>,
+    /// `warning message` of the last successful announce.
+    pub warning: Option<String>,
+    /// Peers the last successful announce returned.
+    pub peers: Option<usize>,
+    /// Failures since the last successful announce.
+    pub failures: u32,
+    pub retry_at: Option<Instant>,
+    /// `url` after the configured rewrite rules, when they change it.
+    pub rewritten_to: Option<String>,
+    /// Where the tracker last redirected our announces; used until it fails.
+    pub redirected_to: Option<String>,
+    /// `min interval` of the last successful announce.
+    pub min_interval: Option<Duration>,
+    pub last_announce: Option<Instant>,
+    pub pace: AnnouncePace,
+    /// Announces that answered sooner than the `min interval` allowed.
+    pub too_soon: u32,
+    /// `tracker id` the tracker asked to get back; other trackers never see it.
+    pub tracker_id: Option<String>,
+}
+
+impl TrackerState {
+    fn new(url: String) -> Self {
+        Self {
+            url,
+            status: TrackerStatus::default(),
+            last_error: None,
+            warning: None,
+            peers: None,
+            failures: 0,
+            retry_at: None,
+            rewritten_to: None,
+            redirected_to: None,
+            min_interval: None,
+            last_announce: None,
+            pace: AnnouncePace::default(),
+            too_soon: 0,
+            tracker_id: None,
+        }
+    }
+
+    /// Where announces for this tracker actually go.
+    pub fn announce_url(&self) -> &str {
+        self.redirected_to
+            .as_deref()
+            .or(self.rewritten_to.as_deref())
+            .unwrap_or(&self.url)
+    }
+
+    fn is_ready(&self, now: Instant) -> bool {
+        self.retry_at.is_none_or(|retry_at| retry_at <= now)
+    }
+}
+
+/// Walks a torrent's `announce-list` tiers per BEP 12.
+///
+/// Tiers are tried in order and trackers within a tier from the front; a tracker that
+/// answers moves to the front of its tier so it is asked first next time. Failed trackers
+/// are skipped until their backoff runs out. Trackers within a tier are shuffled on load,
+/// so the swarm's announces spread over all of them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackerTiers {
+    tiers: Vec<Vec<TrackerState>>,
+    rewrites: Vec<RewriteRule>,
+    /// Announces go through a proxy, so UDP trackers are left out.
+    proxied: bool,
+    /// Every tier is announced to at once instead of one after the other.
+    all_tiers: bool,
+}
+
+impl TrackerTiers {
+    pub fn new(tiers: Vec<Vec<String>>) -> Self {
+        Self {
+            tiers: tiers
+                .into_iter()
+                .map(|tier| {
+                    let mut tier = tier.into_iter().map(TrackerState::new).collect::<Vec<_>>();
+                    shuffle(&mut tier);
+                    tier
+                })
+                .filter(|tier| !tier.is_empty())
+                .collect(),
+            rewrites: Vec::new(),
+            proxied: false,
+            all_tiers: false,
+        }
+    }
+
+    /// Announces to every tier at once and merges the peers they return, for faster peer
+    /// discovery than BEP 12's tier by tier fallback. Within a tier, trackers still stand in
+    /// for each other.
+    pub fn with_all_tiers(mut self, all_tiers: bool) -> Self {
+        self.all_tiers = all_tiers;
+        self
+    }
+
+    /// Announces through a proxy: UDP trackers are skipped, since they would bypass it.
+    pub fn with_proxy(mut self, proxied: bool) -> Self {
+        self.proxied = proxied;
+        self
+    }
+
+    /// Applies `rules` to every tracker, including ones added later.
+    pub fn with_rewrites(mut self, rules: Vec<RewriteRule>) -> Self {
+        for state in self.tiers.iter_mut().flatten() {
+            state.rewritten_to = rewrite(&state.url, &rules);
+        }
+        self.rewrites = rules;
+        self
+    }
+
+    /// Every tracker with its tier index, in the order they would be tried.
+    pub fn states(&self) -> impl Iterator<Item = (usize, &TrackerState)> {
+        self.tiers
+            .iter()
+            .enumerate()
+            .flat_map(|(tier, trackers)| trackers.iter().map(move |state| (tier, state)))
+    }
+
+    /// Trackers that may be announced to at `now`, in the order they should be tried.
+    pub fn candidates(&self, now: Instant) -> Vec<String> {
+        self.states()
+            .filter(|(_, state)| self.may_announce(state, now))
+            .map(|(_, state)| state.url.clone())
+            .collect()
+    }
+
+    fn may_announce(&self, state: &TrackerState, now: Instant) -> bool {
+        state.is_ready(now) && !(self.proxied && state.announce_url().starts_with("udp://"))
+    }
+
+    /// Earliest time a currently backed-off tracker may be retried.
+    pub fn next_retry(&self) -> Option<Instant> {
+        self.states().filter_map(|(_, state)| state.retry_at).min()
+    }
+
+    /// Adds `url` as a tier of its own after the existing ones, unless it is already listed.
+    pub fn add_tracker(&mut self, url: impl Into<String>) -> bool {
+        let url = url.into();
+        if self.position(&url).is_some() {
+            return false;
+        }
+        let mut state = TrackerState::new(url);
+        state.rewritten_to = rewrite(&state.url, &self.rewrites);
+        self.tiers.push(vec![state]);
+        true
+    }
+
+    /// Removes `url`, dropping its tier if that leaves it empty.
+    pub fn remove_tracker(&mut self, url: &str) -> bool {
+        let Some((tier, index)) = self.position(url) else {
+            return false;
+        };
+        self.tiers[tier].remove(index);
+        if self.tiers[tier].is_empty() {
+            self.tiers.remove(tier);
+        }
+        true
+    }
+
+    /// The tiers as they are now, e.g. to store an edited `announce-list`.
+    pub fn urls(&self) -> Vec<Vec<String>> {
+        self.tiers
+            .iter()
+            .map(|tier| tier.iter().map(|state| state.url.clone()).collect())
+            .collect()
+    }
+
+    pub fn record_success(&mut self, url: &str, response: &AnnounceResponse, now: Instant) {
+        let Some((tier, index)) = self.position(url) else {
+            return;
+        };
+
+        let trackers = &mut self.tiers[tier];
+        let mut state = trackers.remove(index);
+        state.status = TrackerStatus::Working;
+        state.last_error = None;
+        state.warning = response.warning.clone();
+        state.peers = Some(response.peers.len());
+        state.failures = 0;
+        state.retry_at = None;
+        if let Some(redirected_to) = &response.redirected_to {
+            state.redirected_to = Some(redirected_to.clone());
+        }
+        if let Some(tracker_id) = &response.tracker_id {
+            state.tracker_id = Some(tracker_id.clone());
+        }
+        state.pace = match (state.last_announce, state.min_interval) {
+            (Some(last), Some(min_interval)) => {
+                let gap = now.duration_since(last);
+                if gap < min_interval {
+                    state.too_soon += 1;
+                    AnnouncePace::TooSoon
+                } else if gap < min_interval + min_interval / 4 {
+                    AnnouncePace::Close
+                } else {
+                    AnnouncePace::Relaxed
+                }
+            }
+            _ => AnnouncePace::Relaxed,
+        };
+        state.min_interval = response.min_interval;
+        state.last_announce = Some(now);
+        trackers.insert(0, state);
+    }
+
+    pub fn record_failure(&mut self, url: &str, error: String, now: Instant) {
+        let Some((tier, index)) = self.position(url) else {
+            return;
+        };
+
+        let state = &mut self.tiers[tier][index];
+        state.status = TrackerStatus::Failing;
+        state.last_error = Some(error);
+        state.failures += 1;
+        // The next attempt asks the tracker's own URL again, which may redirect elsewhere.
+        state.redirected_to = None;
+        let backoff = INITIAL_BACKOFF
+            .saturating_mul(2u32.saturating_pow(state.failures - 1))
+            .min(MAX_BACKOFF);
+        state.retry_at = Some(now + backoff);
+    }
+
+    /// Announces to the first tracker that answers, recording every attempt; see
+    /// [`Self::with_all_tiers`] for asking every tier.
+    pub async fn announce(
+        &mut self,
+        client: &Client,
+        udp: &ConnectionIds,
+        request: &AnnounceRequest,
+    ) -> Result<(String, AnnounceResponse)> {
+        if self.all_tiers {
+            return self.announce_all(client, udp, request).await;
+        }
+        let mut last_error = None;
+        for url in self.candidates(Instant::now()) {
+            match self.announce_once(client, udp, &url, request).await {
+                Ok(response) => {
+                    self.record_success(&url, &response, Instant::now());
+                    return Ok((url, response));
+                }
+                Err(err) => {
+                    self.record_failure(&url, format!("{err:#}"), Instant::now());
+                    last_error = Some(err);
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow!("No tracker is available to announce to")))
+    }
+
+    /// Announces to every tier at once, recording every attempt. The reply of the first tier
+    /// that answered is returned, with the peers of all the others added.
+    async fn announce_all(
+        &mut self,
+        client: &Client,
+        udp: &ConnectionIds,
+        request: &AnnounceRequest,
+    ) -> Result<(String, AnnounceResponse)> {
+        let now = Instant::now();
+        let mut tiers = JoinSet::new();
+        for (tier, trackers) in self.tiers.iter().enumerate() {
+            let candidates: Vec<(String, String, AnnounceRequest)> = trackers
+                .iter()
+                .filter(|state| self.may_announce(state, now))
+                .map(|state| {
+                    let request = self.request_for(&state.url, request);
+                    (state.url.clone(), state.announce_url().to_string(), request)
+                })
+                .collect();
+            let (client, udp) = (client.clone(), udp.clone());
+            let proxied = self.proxied;
+            tiers.spawn(async move {
+                let mut attempts = Vec::new();
+                for (url, announce_url, request) in candidates {
+                    let result =
+                        announce_in_time(&client, &udp, &announce_url, &request, proxied).await;
+                    let answered = result.is_ok();
+                    attempts.push((url, result));
+                    if answered {
+                        break;
+                    }
+                }
+                (tier, attempts)
+            });
+        }
+        let mut attempts = tiers.join_all().await;
+        attempts.sort_by_key(|(tier, _)| *tier);
+
+        let mut merged: Option<(String, AnnounceResponse)> = None;
+        let mut known = HashSet::new();
+        let mut last_error = None;
+        for (url, result) in attempts.into_iter().flat_map(|(_, attempts)| attempts) {
+            let mut response = match result {
+                Ok(response) => response,
+                Err(err) => {
+                    self.record_failure(&url, format!("{err:#}"), Instant::now());
+                    last_error = Some(err);
+                    continue;
+                }
+            };
+            self.record_success(&url, &response, Instant::now());
+            response.peers.retain(|peer| known.insert(peer.addr));
+            match &mut merged {
+                Some((_, merged)) => merged.peers.append(&mut response.peers),
+                None => merged = Some((url, response)),
+            }
+        }
+        merged.ok_or_else(|| {
+            last_error.unwrap_or_else(|| anyhow!("No tracker is available to announce to"))
+        })
+    }
+
+    /// Announces to `url` only, even while it is backed off, e.g. when the user asks for it.
+    pub async fn announce_to(
+        &mut self,
+        client: &Client,
+        udp: &ConnectionIds,
+        url: &str,
+        request: &AnnounceRequest,
+    ) -> Result<AnnounceResponse> {
+        if self.position(url).is_none() {
+            bail!("{url} is not a tracker of this torrent");
+        }
+        match self.announce_once(client, udp, url, request).await {
+            Ok(response) => {
+                self.record_success(url, &response, Instant::now());
+                Ok(response)
+            }
+            Err(err) => {
+                self.record_failure(url, format!("{err:#}"), Instant::now());
+                Err(err)
+            }
+        }
+    }
+
+    /// One announce to `url`, given up after [`ANNOUNCE_TIMEOUT`].
+    async fn announce_once(
+        &self,
+        client: &Client,
+        udp: &ConnectionIds,
+        url: &str,
+        request: &AnnounceRequest,
+    ) -> Result<AnnounceResponse> {
+        let request = self.request_for(url, request);
+        announce_in_time(client, udp, self.announce_url(url), &request, self.proxied).await
+    }
+
+    /// `request` with the `tracker id` that `url` handed out, if any.
+    fn request_for(&self, url: &str, request: &AnnounceRequest) -> AnnounceRequest {
+        let tracker_id = self
+            .position(url)
+            .and_then(|(tier, index)| self.tiers[tier][index].tracker_id.clone());
+        AnnounceRequest {
+            tracker_id,
+            ..request.clone()
+        }
+    }
+
+    fn announce_url<'a>(&'a self, url: &'a str) -> &'a str {
+        self.position(url)
+            .map_or(url, |(tier, index)| self.tiers[tier][index].announce_url())
+    }
+
+    fn position(&self, url: &str) -> Option<(usize, usize)> {
+        self.tiers.iter().enumerate().find_map(|(tier, trackers)| {
+            trackers
+                .iter()
+                .position(|state| state.url == url)
+                .map(|index| (tier, index))
+        })
+    }
+}
+
+/// [`announce`], given up after [`ANNOUNCE_TIMEOUT`].
+async fn announce_in_time(
+    client: &Client,
+    udp: &ConnectionIds,
+    announce_url: &str,
+    request: &AnnounceRequest,
+    proxied: bool,
+) -> Result<AnnounceResponse> {
+    timeout(
+        ANNOUNCE_TIMEOUT,
+        announce(client, udp, announce_url, request, proxied),
+    )
+    .await
+    .map_err(|_| anyhow!("Announce to {announce_url} timed out"))?
+}
+
+/// Shuffles `items` in place; without a source of randomness they keep their order.
+fn shuffle<T>(items: &mut [T]) {
+    for last in (1..items.len()).rev() {
+        let mut bytes = [0; 8];
+        if getrandom::fill(&mut bytes).is_err() {
+            return;
+        }
+        let other = u64::from_le_bytes(bytes) % (last as u64 + 1);
+        items.swap(last, other as usize);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Metadata;
+    use crate::testing::SyntheticTorrent;
+    use crate::tracker::AnnounceLifecycle;
+
+    fn response(min_interval: u64) -> AnnounceResponse {
+        AnnounceResponse {
+            interval: Duration::from_secs(1800),
+            min_interval: Some(Duration::from_secs(min_interval)),
+            complete: None,
+            incomplete: None,
+            downloaded: None,
+            tracker_id: None,
+            peers: Vec::new(),
+            warning: None,
+            external_ip: None,
+            redirected_to: None,
+        }
+    }
+
+    #[test]
+    fn tracks_announces_against_the_min_interval() {
+        let url = "http://tracker.example/announce";
+        let mut tiers = TrackerTiers::new(vec![vec![url.to_string()]]);
+        let start = Instant::now();
+        let pace = |tiers: &TrackerTiers| tiers.states().next().unwrap().1.pace;
+
+        tiers.record_success(url, &response(600), start);
+        assert_eq!(pace(&tiers), AnnouncePace::Relaxed);
+        tiers.record_success(url, &response(600), start + Duration::from_secs(60));
+        assert_eq!(pace(&tiers), AnnouncePace::TooSoon);
+        tiers.record_success(url, &response(600), start + Duration::from_secs(700));
+        assert_eq!(pace(&tiers), AnnouncePace::Close);
+        tiers.record_success(url, &response(600), start + Duration::from_secs(2000));
+        assert_eq!(pace(&tiers), AnnouncePace::Relaxed);
+        assert_eq!(tiers.states().next().unwrap().1.too_soon, 1);
+    }
+
+    #[test]
+    fn proxied_tiers_skip_udp_trackers() {
+        let udp = "udp://tracker.example:6969/announce".to_string();
+        let http = "http://tracker.example/announce".to_string();
+        let tiers = TrackerTiers::new(vec![vec![udp.clone(), http.clone()]]);
+        let now = Instant::now();
+        assert_eq!(tiers.candidates(now).len(), 2);
+        assert_eq!(tiers.with_proxy(true).candidates(now), vec![http]);
+    }
+
+    #[test]
+    fn shuffles_trackers_within_their_tier() {
+        let first: Vec<String> = (0..8)
+            .map(|n| format!("http://first{n}.example/announce"))
+            .collect();
+        let second = "http://second.example/announce".to_string();
+        let tiers = TrackerTiers::new(vec![first.clone(), vec![second.clone()]]);
+
+        let mut candidates = tiers.candidates(Instant::now());
+        assert_eq!(candidates.pop(), Some(second));
+        candidates.sort();
+        assert_eq!(candidates, first);
+    }
+
+    #[test]
+    fn tracker_ids_go_back_to_their_own_tracker() {
+        let (first, second) = ("http://one.example/announce", "http://two.example/announce");
+        let mut tiers = TrackerTiers::new(vec![vec![first.to_string(), second.to_string()]]);
+        let request = AnnounceLifecycle::new(*b"-TT0100-abcdefghijkl", 6881).request(
+            &Metadata::from(&SyntheticTorrent::single("ids", 1024, 16 * 1024).torrent),
+            1024,
+            false,
+        );
+        tiers.record_success(
+            first,
+            &AnnounceResponse {
+                tracker_id: Some("one".to_string()),
+                ..response(60)
+            },
+            Instant::now(),
+        );
+        tiers.record_success(second, &response(60), Instant::now());
+
+        assert_eq!(
+            tiers.request_for(first, &request).tracker_id.as_deref(),
+            Some("one")
+        );
+        assert_eq!(tiers.request_for(second, &request).tracker_id, None);
+    }
+
+    /// Serves `body` to every request, like a tracker that always gives the same reply.
+    async fn tracker_replying(body: Vec<u8>) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = stream.read(&mut [0; 4096]).await;
+                let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+                let _ = stream.write_all(head.as_bytes()).await;
+                let _ = stream.write_all(&body).await;
+            }
+        });
+        format!("http://{addr}/announce")
+    }
+
+    fn compact_reply(ports: &[u16]) -> Vec<u8> {
+        let mut peers = Vec::new();
+        for port in ports {
+            peers.extend_from_slice(&[10, 0, 0, 1]);
+            peers.extend_from_slice(&port.to_be_bytes());
+        }
+        let mut reply = format!("d8:intervali1800e5:peers{}:", peers.len()).into_bytes();
+        reply.extend_from_slice(&peers);
+        reply.push(b'e');
+        reply
+    }
+
+    #[tokio::test]
+    async fn all_tiers_merge_their_peers() {
+        let first = tracker_replying(compact_reply(&[1, 2])).await;
+        let second = tracker_replying(compact_reply(&[2, 3])).await;
+        let request = AnnounceLifecycle::new(*b"-TT0100-abcdefghijkl", 6881).request(
+            &Metadata::from(&SyntheticTorrent::single("tiers", 1024, 16 * 1024).torrent),
+            1024,
+            false,
+        );
+        let mut tiers =
+            TrackerTiers::new(vec![vec![first.clone()], vec![second.clone()]]).with_all_tiers(true);
+
+        let (url, response) = tiers
+            .announce(&Client::new(), &ConnectionIds::default(), &request)
+            .await
+            .unwrap();
+        assert_eq!(url, first);
+        let mut ports: Vec<u16> = response.peers.iter().map(|peer| peer.addr.port()).collect();
+        ports.sort();
+        assert_eq!(ports, [1, 2, 3]);
+        assert!(
+            tiers
+                .states()
+                .all(|(_, state)| state.status == TrackerStatus::Working)
+        );
+    }
+}
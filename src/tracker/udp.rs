@@ -0,0 +1,346 @@
+//! UDP trackers (BEP 15). Every request needs a connection id from a connect round trip;
+//! the id stays valid for a minute, so it is kept per tracker address in [`ConnectionIds`]
+//! and shared by the announces and scrapes of every torrent on that tracker.
+
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+use tokio::net::{UdpSocket, lookup_host};
+use tokio::time::timeout;
+use url::Url;
+
+use super::announce::{AnnounceEvent, AnnounceRequest, AnnounceResponse};
+use super::scrape::ScrapeStats;
+use crate::peer::Peer;
+use crate::peer::address::{COMPACT_V4_LEN, COMPACT_V6_LEN};
+
+/// Magic constant opening every connect request.
+const PROTOCOL_ID: u64 = 0x41727101980;
+const ACTION_CONNECT: u32 = 0;
+const ACTION_ANNOUNCE: u32 = 1;
+const ACTION_SCRAPE: u32 = 2;
+const ACTION_ERROR: u32 = 3;
+/// Wait for the first attempt; it doubles with every retry.
+const TIMEOUT: Duration = Duration::from_secs(5);
+const ATTEMPTS: u32 = 3;
+/// Room for any datagram, so a long peer list is not cut short.
+const MAX_DATAGRAM: usize = 65536;
+/// How long a tracker accepts a connection id after handing it out.
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Connection ids by tracker address, with when they were obtained. A tracker ties the id
+/// to our address rather than to a socket, so clones share them between torrents.
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionIds(Arc<Mutex<HashMap<SocketAddr, (u64, Instant)>>>);
+
+impl ConnectionIds {
+    /// The id for `addr`, unless it is older than [`CONNECTION_ID_LIFETIME`] at `now`.
+    fn get(&self, addr: SocketAddr, now: Instant) -> Option<u64> {
+        self.lock()
+            .get(&addr)
+            .filter(|(_, obtained)| now.duration_since(*obtained) < CONNECTION_ID_LIFETIME)
+            .map(|(id, _)| *id)
+    }
+
+    /// Keeps `id` for `addr`, dropping the ids that expired by `now`.
+    fn insert(&self, addr: SocketAddr, id: u64, obtained: Instant, now: Instant) {
+        let mut ids = self.lock();
+        ids.retain(|_, (_, obtained)| now.duration_since(*obtained) < CONNECTION_ID_LIFETIME);
+        ids.insert(addr, (id, obtained));
+    }
+
+    /// Drops the id for `addr` if it is still `id`, not one another request replaced it with.
+    fn forget(&self, addr: SocketAddr, id: u64) {
+        let mut ids = self.lock();
+        if ids.get(&addr).is_some_and(|(cached, _)| *cached == id) {
+            ids.remove(&addr);
+        }
+    }
+
+    /// The map stays consistent even if a holder panicked, so a poisoned lock is taken over.
+    fn lock(&self) -> MutexGuard<'_, HashMap<SocketAddr, (u64, Instant)>> {
+        self.0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub async fn announce(
+    announce: &Url,
+    ids: &ConnectionIds,
+    request: &AnnounceRequest,
+) -> Result<AnnounceResponse> {
+    let tracker = Tracker::open(announce, ids).await?;
+
+    let event: u32 = match request.event {
+        AnnounceEvent::None => 0,
+        AnnounceEvent::Completed => 1,
+        AnnounceEvent::Started => 2,
+        AnnounceEvent::Stopped => 3,
+    };
+    // The address field only fits IPv4; zero means the sender's address.
+    let ip = match request.ip {
+        Some(IpAddr::V4(ip)) => ip.octets(),
+        _ => [0; 4],
+    };
+    let numwant = request
+        .numwant
+        .map_or(-1, |numwant| i32::try_from(numwant).unwrap_or(i32::MAX));
+
+    let mut body = Vec::with_capacity(82);
+    body.extend_from_slice(&request.info_hash);
+    body.extend_from_slice(&request.peer_id);
+    body.extend_from_slice(&request.downloaded.to_be_bytes());
+    body.extend_from_slice(&request.left.to_be_bytes());
+    body.extend_from_slice(&request.uploaded.to_be_bytes());
+    body.extend_from_slice(&event.to_be_bytes());
+    body.extend_from_slice(&ip);
+    body.extend_from_slice(&request.key.unwrap_or_default().to_be_bytes());
+    body.extend_from_slice(&numwant.to_be_bytes());
+    body.extend_from_slice(&request.port.to_be_bytes());
+    let reply = tracker.request(ACTION_ANNOUNCE, &body, 20).await?;
+
+    // Trackers answer over IPv6 with IPv6 peers only.
+    let entry_len = if tracker.addr.is_ipv4() {
+        COMPACT_V4_LEN
+    } else {
+        COMPACT_V6_LEN
+    };
+    let peers = Peer::compact_list(&reply[20..], entry_len)
+        .with_context(|| format!("Compact peer list has {} bytes", reply.len() - 20))?;
+    Ok(AnnounceResponse {
+        interval: Duration::from_secs(u64::from(field(&reply, 8))),
+        min_interval: None,
+        complete: Some(u64::from(field(&reply, 16))),
+        incomplete: Some(u64::from(field(&reply, 12))),
+        downloaded: None,
+        tracker_id: None,
+        peers,
+        warning: None,
+        external_ip: None,
+        redirected_to: None,
+    })
+}
+
+pub async fn scrape(
+    announce: &Url,
+    ids: &ConnectionIds,
+    info_hash: [u8; 20],
+) -> Result<ScrapeStats> {
+    let tracker = Tracker::open(announce, ids).await?;
+    let reply = tracker.request(ACTION_SCRAPE, &info_hash, 20).await?;
+    Ok(ScrapeStats {
+        complete: u64::from(field(&reply, 8)),
+        downloaded: u64::from(field(&reply, 12)),
+        incomplete: u64::from(field(&reply, 16)),
+    })
+}
+
+/// A socket connected to one tracker.
+struct Tracker {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    ids: ConnectionIds,
+}
+
+impl Tracker {
+    async fn open(announce: &Url, ids: &ConnectionIds) -> Result<Self> {
+        let host = announce.host_str().context("Tracker URL has no host")?;
+        let port = announce.port().context("UDP tracker URL has no port")?;
+        let addr = lookup_host((host, port))
+            .await?
+            .next()
+            .with_context(|| format!("Could not resolve {host}"))?;
+        let local = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).await?;
+        socket.connect(addr).await?;
+        Ok(Self {
+            socket,
+            addr,
+            ids: ids.clone(),
+        })
+    }
+
+    /// Sends `body` as an `action` request and returns a reply of at least `min_len` bytes.
+    ///
+    /// A cached connection id the tracker refuses, e.g. after a restart, is replaced by a
+    /// fresh one and the request sent once more.
+    async fn request(&self, action: u32, body: &[u8], min_len: usize) -> Result<Vec<u8>> {
+        let cached = self.ids.get(self.addr, Instant::now());
+        let connection_id = match cached {
+            Some(id) => id,
+            None => self.connect().await?,
+        };
+        let reply = match self.exchange(connection_id, action, body, min_len).await? {
+            Err(_) if cached.is_some() => {
+                self.ids.forget(self.addr, connection_id);
+                let connection_id = self.connect().await?;
+                self.exchange(connection_id, action, body, min_len).await?
+            }
+            reply => reply,
+        };
+        reply.map_err(|message| anyhow!("Tracker failure: {message}"))
+    }
+
+    async fn connect(&self) -> Result<u64> {
+        let obtained = Instant::now();
+        let reply = self
+            .exchange(PROTOCOL_ID, ACTION_CONNECT, &[], 16)
+            .await?
+            .map_err(|message| anyhow!("Tracker failure: {message}"))?;
+        let connection_id = u64::from_be_bytes(reply[8..16].try_into().expect("slice is 8 bytes"));
+        self.ids
+            .insert(self.addr, connection_id, obtained, Instant::now());
+        Ok(connection_id)
+    }
+
+    /// Sends a request until the reply to it arrives: `Err` holds the message of an error
+    /// reply, which unlike a timeout may be down to the connection id alone.
+    async fn exchange(
+        &self,
+        connection_id: u64,
+        action: u32,
+        body: &[u8],
+        min_len: usize,
+    ) -> Result<Result<Vec<u8>, String>> {
+        let transaction_id = transaction_id()?;
+        let mut request = Vec::with_capacity(16 + body.len());
+        request.extend_from_slice(&connection_id.to_be_bytes());
+        request.extend_from_slice(&action.to_be_bytes());
+        request.extend_from_slice(&transaction_id.to_be_bytes());
+        request.extend_from_slice(body);
+
+        let mut buffer = vec![0; MAX_DATAGRAM];
+        for attempt in 0..ATTEMPTS {
+            self.socket.send(&request).await?;
+            let Ok(received) =
+                timeout(TIMEOUT * 2u32.pow(attempt), self.socket.recv(&mut buffer)).await
+            else {
+                continue;
+            };
+            let reply = &buffer[..received?];
+            if reply.len() < 8 || field(reply, 4) != transaction_id {
+                continue;
+            }
+
+            let reply_action = field(reply, 0);
+            if reply_action == ACTION_ERROR {
+                return Ok(Err(String::from_utf8_lossy(&reply[8..]).into_owned()));
+            }
+            if reply_action != action || reply.len() < min_len {
+                bail!("Unexpected UDP tracker reply");
+            }
+            return Ok(Ok(reply.to_vec()));
+        }
+        bail!("UDP tracker did not answer")
+    }
+}
+
+fn field(reply: &[u8], offset: usize) -> u32 {
+    u32::from_be_bytes(
+        reply[offset..offset + 4]
+            .try_into()
+            .expect("slice is 4 bytes"),
+    )
+}
+
+/// Random, so an off-path attacker cannot guess it and forge a reply (BEP 15).
+fn transaction_id() -> Result<u32> {
+    let mut bytes = [0; 4];
+    getrandom::fill(&mut bytes).context("No randomness for a transaction id")?;
+    Ok(u32::from_ne_bytes(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves connect and scrape requests on localhost; `reply` gets the action, the
+    /// transaction id and how many requests came before, and returns the datagram to send.
+    async fn fake_tracker<F>(reply: F) -> Url
+    where
+        F: Fn(u32, u32, usize) -> Vec<u8> + Send + 'static,
+    {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("udp://{}", socket.local_addr().unwrap())).unwrap();
+        tokio::spawn(async move {
+            let mut buffer = vec![0; MAX_DATAGRAM];
+            let mut count = 0;
+            while let Ok((read, from)) = socket.recv_from(&mut buffer).await {
+                let request = &buffer[..read];
+                let datagram = reply(field(request, 8), field(request, 12), count);
+                socket.send_to(&datagram, from).await.unwrap();
+                count += 1;
+            }
+        });
+        url
+    }
+
+    fn reply(action: u32, transaction_id: u32, body: &[u32]) -> Vec<u8> {
+        [action, transaction_id]
+            .iter()
+            .chain(body)
+            .flat_map(|value| value.to_be_bytes())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn skips_replies_to_other_transactions() {
+        let url = fake_tracker(|action, transaction_id, count| match (action, count) {
+            (ACTION_CONNECT, 0) => reply(action, transaction_id ^ 1, &[0, 7]),
+            (ACTION_CONNECT, _) => reply(action, transaction_id, &[0, 7]),
+            _ => reply(action, transaction_id, &[3, 5, 2]),
+        })
+        .await;
+        let ids = ConnectionIds::default();
+
+        let stats = scrape(&url, &ids, [1; 20]).await.unwrap();
+        assert_eq!(
+            (stats.complete, stats.downloaded, stats.incomplete),
+            (3, 5, 2)
+        );
+        let addr = url.socket_addrs(|| None).unwrap()[0];
+        assert_eq!(ids.get(addr, Instant::now()), Some(7));
+    }
+
+    #[tokio::test]
+    async fn rejects_replies_with_another_action() {
+        let url = fake_tracker(|action, transaction_id, _| match action {
+            ACTION_CONNECT => reply(action, transaction_id, &[0, 7]),
+            _ => reply(ACTION_ANNOUNCE, transaction_id, &[3, 5, 2]),
+        })
+        .await;
+        let error = scrape(&url, &ConnectionIds::default(), [1; 20])
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unexpected UDP tracker reply");
+    }
+
+    #[test]
+    fn connection_ids_expire_after_a_minute() {
+        let ids = ConnectionIds::default();
+        let first: SocketAddr = "10.0.0.1:6969".parse().unwrap();
+        let second: SocketAddr = "10.0.0.2:6969".parse().unwrap();
+        let obtained = Instant::now();
+        ids.insert(first, 7, obtained, obtained);
+
+        let almost = obtained + CONNECTION_ID_LIFETIME - Duration::from_millis(1);
+        assert_eq!(ids.get(first, almost), Some(7));
+        assert_eq!(ids.get(first, obtained + CONNECTION_ID_LIFETIME), None);
+
+        let later = obtained + CONNECTION_ID_LIFETIME;
+        ids.insert(second, 8, later, later);
+        assert_eq!(ids.lock().len(), 1);
+        ids.forget(second, 9);
+        assert_eq!(ids.get(second, later), Some(8));
+        ids.forget(second, 8);
+        assert_eq!(ids.get(second, later), None);
+    }
+}
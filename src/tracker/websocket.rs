@@ -0,0 +1,188 @@
+//! WebTorrent trackers: JSON announces over a WebSocket. Peers there are reached through
+//! WebRTC, which terrent cannot speak, so these trackers only contribute swarm counts.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use futures_util::{SinkExt, StreamExt};
+use reqwest::header::{CONNECTION, UPGRADE};
+use reqwest::{Client, StatusCode, Version};
+use serde_json::{Value, json};
+use tokio::time::timeout;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::client::generate_key;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::{Role, WebSocketConfig};
+use url::Url;
+
+use super::announce::{AnnounceRequest, AnnounceResponse};
+use super::scrape::ScrapeStats;
+
+/// WebTorrent trackers announce every two minutes unless told otherwise.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(120);
+/// Wait for the tracker's reply once connected.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(15);
+/// Larger messages are not tracker replies.
+const MAX_MESSAGE: usize = 1 << 20;
+
+/// Announces to a `ws://` or `wss://` tracker without offering WebRTC connections, which
+/// still gets us counted and tells us how large the swarm is. The reply never has peers.
+pub async fn announce(
+    client: &Client,
+    announce: &str,
+    request: &AnnounceRequest,
+) -> Result<AnnounceResponse> {
+    let mut message = json!({
+        "action": "announce",
+        "info_hash": binary_string(&request.info_hash),
+        "peer_id": binary_string(&request.peer_id),
+        "uploaded": request.uploaded,
+        "downloaded": request.downloaded,
+        "left": request.left,
+        "numwant": 0,
+        "offers": [],
+    });
+    if let Some(event) = request.event.as_str() {
+        message["event"] = event.into();
+    }
+
+    let reply = exchange(client, announce, &message, "announce")
+        .await
+        .with_context(|| format!("Announce to {announce} failed"))?;
+    let count = |key| reply.get(key).and_then(Value::as_u64);
+    let interval = count("interval").map_or(DEFAULT_INTERVAL, Duration::from_secs);
+    Ok(AnnounceResponse {
+        interval,
+        min_interval: count("min interval").map(Duration::from_secs),
+        complete: count("complete"),
+        incomplete: count("incomplete"),
+        downloaded: count("downloaded"),
+        tracker_id: None,
+        peers: Vec::new(),
+        warning: reply
+            .get("warning message")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        external_ip: None,
+        redirected_to: None,
+    })
+}
+
+/// Asks a WebTorrent tracker for swarm counts.
+pub async fn scrape(client: &Client, announce: &str, info_hash: [u8; 20]) -> Result<ScrapeStats> {
+    let message = json!({
+        "action": "scrape",
+        "info_hash": binary_string(&info_hash),
+    });
+    let reply = exchange(client, announce, &message, "scrape").await?;
+    let file = reply
+        .get("files")
+        .and_then(|files| files.get(binary_string(&info_hash)))
+        .context("Tracker does not know this torrent")?;
+    let count = |key| file.get(key).and_then(Value::as_u64).unwrap_or_default();
+    Ok(ScrapeStats {
+        complete: count("complete"),
+        incomplete: count("incomplete"),
+        downloaded: count("downloaded"),
+    })
+}
+
+/// Connects, sends `message` and returns the first reply with the same `action`; relayed
+/// WebRTC offers from other peers are skipped.
+async fn exchange(client: &Client, announce: &str, message: &Value, action: &str) -> Result<Value> {
+    let mut stream = connect(client, announce)
+        .await
+        .with_context(|| format!("Failed to connect to {announce}"))?;
+    stream.send(Message::text(message.to_string())).await?;
+
+    let reply = timeout(REPLY_TIMEOUT, async {
+        // Pings are answered by the stream while it is read.
+        while let Some(message) = stream.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => bail!("Tracker closed the WebSocket"),
+                _ => continue,
+            };
+            let reply = parse_reply(&text)?;
+            if let Some(reason) = reply.get("failure reason").and_then(Value::as_str) {
+                bail!("Tracker failure: {reason}");
+            }
+            let is_relayed = reply.get("offer").is_some() || reply.get("answer").is_some();
+            if reply.get("action").and_then(Value::as_str) == Some(action) && !is_relayed {
+                return Ok(reply);
+            }
+        }
+        bail!("Tracker closed the WebSocket")
+    })
+    .await
+    .context("Tracker did not answer")??;
+
+    // Being polite; the tracker forgets us either way once the socket closes.
+    let _ = stream.close(None).await;
+    Ok(reply)
+}
+
+/// Parses a tracker message. serde_json stops at 128 levels of nesting, so a reply of
+/// nothing but brackets is an error rather than a stack overflow.
+fn parse_reply(text: &str) -> Result<Value> {
+    serde_json::from_str(text).context("Tracker sent invalid JSON")
+}
+
+/// Opens the WebSocket through reqwest, so `wss://` uses the same TLS setup and proxy as
+/// HTTPS trackers.
+async fn connect(client: &Client, announce: &str) -> Result<WebSocketStream<reqwest::Upgraded>> {
+    let mut url =
+        Url::parse(announce).with_context(|| format!("Invalid tracker URL {announce}"))?;
+    let scheme = match url.scheme() {
+        "ws" => "http",
+        "wss" => "https",
+        scheme => bail!("Unsupported tracker scheme {scheme}"),
+    };
+    url.set_scheme(scheme)
+        .map_err(|()| anyhow::anyhow!("Invalid tracker URL {announce}"))?;
+
+    let key = generate_key();
+    let response = client
+        .get(url)
+        .version(Version::HTTP_11)
+        .header(UPGRADE, "websocket")
+        .header(CONNECTION, "Upgrade")
+        .header("Sec-WebSocket-Version", "13")
+        .header("Sec-WebSocket-Key", &key)
+        .send()
+        .await?;
+    if response.status() != StatusCode::SWITCHING_PROTOCOLS {
+        bail!("Tracker refused the WebSocket ({})", response.status());
+    }
+
+    let expected = derive_accept_key(key.as_bytes());
+    let accept = response.headers().get("Sec-WebSocket-Accept");
+    if accept.is_none_or(|accept| accept.as_bytes() != expected.as_bytes()) {
+        bail!("Tracker sent a bad WebSocket handshake");
+    }
+    let config = WebSocketConfig::default()
+        .max_message_size(Some(MAX_MESSAGE))
+        .max_frame_size(Some(MAX_MESSAGE));
+    let upgraded = response.upgrade().await?;
+    Ok(WebSocketStream::from_raw_socket(upgraded, Role::Client, Some(config)).await)
+}
+
+/// WebTorrent sends hashes and peer ids as strings with one code point per byte.
+fn binary_string(bytes: &[u8]) -> String {
+    bytes.iter().copied().map(char::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deeply_nested_replies_are_refused() {
+        let brackets = "[".repeat(MAX_MESSAGE);
+        assert!(parse_reply(&brackets).is_err());
+
+        let reply = parse_reply(r#"{"action":"scrape","files":{}}"#).unwrap();
+        assert_eq!(reply["action"], "scrape");
+    }
+}